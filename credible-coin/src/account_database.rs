@@ -0,0 +1,437 @@
+//! An embedded SQLite database of the exchange's accounts, kept by [`SqliteLedgerStore`].
+//!
+//! The schema has three tables:
+//! 1. `accounts`: Every row of the ledger, in ledger order (by `id`), with its normalized address
+//!    and when it was opened
+//! 2. `balances`: The current value of every account
+//! 3. `history`: Every change made to an account: when it was opened, updated or closed, with
+//!    its old and new values
+//!
+//! Every change is made in a single transaction which also records it in `history`, so the
+//! liabilities of the exchange are never left half updated, even when several processes share
+//! the database. Databases convert to and from the CSV format with
+//! [`SqliteLedgerStore::import_csv`] and [`SqliteLedgerStore::export_csv`].
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, Transaction, TransactionBehavior, params};
+use serde::Serialize;
+
+use crate::errors::{AddressValidationError, LedgerStoreError};
+use crate::ledger_store::{LedgerStore, normalized};
+use crate::utils::address_utils::normalize;
+use crate::utils::csv_utils::{
+    make_address_vector, make_value_vector, validate_csv_addresses, write_csv_columns,
+};
+use crate::utils::duplicates::split_key;
+
+/// The extensions of the files `exchange load` opens as SQLite databases
+pub const SQLITE_EXTENSIONS: [&str; 2] = ["db", "sqlite"];
+/// The version of the schema, kept in the database's `user_version`
+pub const SCHEMA_VERSION: i32 = 1;
+/// The first bytes of every SQLite database
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+/// How long a change waits for another process to finish its own
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address TEXT NOT NULL,
+    opened_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS accounts_by_address ON accounts (address);
+CREATE TABLE IF NOT EXISTS balances (
+    account_id INTEGER PRIMARY KEY REFERENCES accounts (id) ON DELETE CASCADE,
+    value INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    change TEXT NOT NULL CHECK (change IN ('open', 'update', 'close')),
+    old_value INTEGER,
+    new_value INTEGER,
+    changed_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS history_by_address ON history (address);
+";
+/// The address and value of every account, in ledger order
+const ROWS: &str = "SELECT accounts.id, accounts.address, balances.value FROM accounts
+    JOIN balances ON balances.account_id = accounts.id ORDER BY accounts.id";
+
+/// Check whether the file at `path` is a SQLite database by looking at its header
+pub fn is_sqlite_database<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0u8; 16];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == MAGIC
+}
+/// What happened to an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The account was added to the ledger
+    Open,
+    /// The balance of the account was set
+    Update,
+    /// The account was removed from the ledger
+    Close,
+}
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Update => "update",
+            Self::Close => "close",
+        }
+    }
+    fn parse(change: &str) -> Option<Self> {
+        match change {
+            "open" => Some(Self::Open),
+            "update" => Some(Self::Update),
+            "close" => Some(Self::Close),
+            _ => None,
+        }
+    }
+}
+/// A change recorded in the `history` table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountChange {
+    /// The id of the account, which tells apart the rows of a repeated address
+    pub account: i64,
+    /// The normalized address of the account
+    pub address: String,
+    /// What happened to the account
+    pub change: ChangeKind,
+    /// The balance before the change, `None` when the account was opened
+    pub old_value: Option<i64>,
+    /// The balance after the change, `None` when the account was closed
+    pub new_value: Option<i64>,
+    /// Seconds since the Unix epoch
+    pub timestamp: i64,
+}
+/// A ledger kept in a SQLite database, see the [module documentation](self) for its schema
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::account_database::{ChangeKind, SqliteLedgerStore};
+/// use credible_coin::ledger_store::LedgerStore;
+///
+/// let address = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+/// let ledger = SqliteLedgerStore::in_memory().unwrap();
+/// ledger.append(vec![address.to_owned()], vec![10]).unwrap();
+/// ledger.update(address, 20).unwrap();
+/// assert_eq!(ledger.get_by_address(address).unwrap().1.entry_value(), 20);
+/// let changes: Vec<ChangeKind> = ledger
+///     .history(address)
+///     .unwrap()
+///     .iter()
+///     .map(|change| change.change)
+///     .collect();
+/// assert_eq!(changes, [ChangeKind::Open, ChangeKind::Update]);
+/// ```
+#[derive(Debug)]
+pub struct SqliteLedgerStore {
+    path: String,
+    connection: Mutex<Connection>,
+}
+impl SqliteLedgerStore {
+    /// Open the database at `path`, creating it (and its tables) if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be opened as a SQLite database, or if its schema is
+    /// newer than [`SCHEMA_VERSION`].
+    pub fn open(path: &str) -> Result<Self, LedgerStoreError> {
+        Self::with_connection(path, Connection::open(path)?)
+    }
+    /// A database kept in memory, gone with the store, so it is meant for tests
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if SQLite can't allocate the database.
+    pub fn in_memory() -> Result<Self, LedgerStoreError> {
+        Self::with_connection(":memory:", Connection::open_in_memory()?)
+    }
+    fn with_connection(path: &str, mut connection: Connection) -> Result<Self, LedgerStoreError> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let found: i32 = transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if found > SCHEMA_VERSION {
+            return Err(LedgerStoreError::UnsupportedSchema {
+                found,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        transaction.execute_batch(SCHEMA)?;
+        transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        transaction.commit()?;
+        Ok(Self {
+            path: path.to_owned(),
+            connection: Mutex::new(connection),
+        })
+    }
+    /// The path of the database, `:memory:` for [`SqliteLedgerStore::in_memory`]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    /// Run `change` in a transaction which holds the database's write lock, committing it only
+    /// if `change` succeeds. `change` is given the time of the transaction.
+    fn transaction<T>(
+        &self,
+        change: impl FnOnce(&Transaction<'_>, i64) -> Result<T, LedgerStoreError>,
+    ) -> Result<T, LedgerStoreError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let result = change(&transaction, now())?;
+        transaction.commit()?;
+        Ok(result)
+    }
+    /// Check that every address in the database is valid for the active network, returning the
+    /// first one that isn't. The line of the error is the row of the account, counting from 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an address is invalid or the database can't be read.
+    pub fn validate_addresses(&self) -> Result<(), LedgerStoreError> {
+        for (row, address) in self.columns()?.0.iter().enumerate() {
+            normalize(address).map_err(|err| AddressValidationError::InFile {
+                file: self.path.clone(),
+                line: row + 1,
+                source: Box::new(err),
+            })?;
+        }
+        Ok(())
+    }
+    /// Every change recorded for the address of a (possibly disambiguated) key, oldest first.
+    /// The changes of every row of a repeated address are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database can't be read.
+    pub fn history(&self, key: &str) -> Result<Vec<AccountChange>, LedgerStoreError> {
+        let (address, _) = split_key(key);
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT account_id, address, change, old_value, new_value, changed_at FROM history
+                WHERE address = ?1 ORDER BY id",
+        )?;
+        let changes = statement.query_map([address], |row| {
+            let change: String = row.get(2)?;
+            Ok(AccountChange {
+                account: row.get(0)?,
+                address: row.get(1)?,
+                change: ChangeKind::parse(&change).ok_or_else(|| {
+                    rusqlite::Error::InvalidColumnType(
+                        2,
+                        "change".to_owned(),
+                        rusqlite::types::Type::Text,
+                    )
+                })?,
+                old_value: row.get(3)?,
+                new_value: row.get(4)?,
+                timestamp: row.get(5)?,
+            })
+        })?;
+        Ok(changes.collect::<Result<_, _>>()?)
+    }
+    /// Replace every account of the database with the rows of a CSV file or
+    /// [binary ledger](crate::utils::binary_ledger), in one transaction, returning the number of
+    /// accounts opened. Repeated addresses are kept as separate accounts, as they are in the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the addresses of the file is invalid for the active network, or
+    /// if the database can't be changed, in which case it is left untouched.
+    pub fn import_csv(&self, csv_file: &str) -> Result<usize, LedgerStoreError> {
+        validate_csv_addresses(csv_file)?;
+        let addresses = make_address_vector(csv_file);
+        let values = make_value_vector(csv_file);
+        self.transaction(|transaction, now| {
+            for (id, address, value) in accounts(transaction)? {
+                close_account(transaction, id, &address, value, now)?;
+            }
+            for (address, value) in addresses.iter().zip(&values) {
+                open_account(transaction, address, *value, now)?;
+            }
+            Ok(addresses.len())
+        })
+    }
+    /// Write every account to a CSV file, in ledger order, returning the number of rows written.
+    /// The file is replaced atomically if it already exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database can't be read or the file can't be written.
+    pub fn export_csv(&self, csv_file: &str) -> Result<usize, LedgerStoreError> {
+        let (addresses, values) = self.columns()?;
+        write_csv_columns(csv_file, &addresses, &values)?;
+        Ok(addresses.len())
+    }
+}
+impl LedgerStore for SqliteLedgerStore {
+    fn columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError> {
+        let connection = self.connection.lock().unwrap();
+        Ok(accounts(&connection)?
+            .into_iter()
+            .map(|(_, address, value)| (address, value))
+            .unzip())
+    }
+    fn update(&self, key: &str, value: i64) -> Result<(), LedgerStoreError> {
+        let (address, occurrence) = split_key(key);
+        self.transaction(|transaction, now| {
+            let accounts = accounts_of(transaction, address)?;
+            let index = occurrence.map_or(Some(0), |occurrence| occurrence.checked_sub(1));
+            let Some(&(id, old_value)) = index.and_then(|index| accounts.get(index)) else {
+                return Err(LedgerStoreError::NotFound(key.to_owned()));
+            };
+            transaction.execute(
+                "UPDATE balances SET value = ?2, updated_at = ?3 WHERE account_id = ?1",
+                params![id, value, now],
+            )?;
+            record(
+                transaction,
+                id,
+                address,
+                ChangeKind::Update,
+                Some(old_value),
+                Some(value),
+                now,
+            )?;
+            // A bare address merges its rows into the first one, as it does in a file
+            if occurrence.is_none() {
+                for &(id, old_value) in &accounts[1..] {
+                    close_account(transaction, id, address, old_value, now)?;
+                }
+            }
+            Ok(())
+        })
+    }
+    fn append(&self, addresses: Vec<String>, values: Vec<i64>) -> Result<usize, LedgerStoreError> {
+        let addresses = normalized(&addresses)?;
+        self.transaction(|transaction, now| {
+            for (index, address) in addresses.iter().enumerate() {
+                if addresses[..index].contains(address)
+                    || !accounts_of(transaction, address)?.is_empty()
+                {
+                    return Err(LedgerStoreError::Exists(address.clone()));
+                }
+            }
+            for (address, value) in addresses.iter().zip(&values) {
+                open_account(transaction, address, *value, now)?;
+            }
+            Ok(addresses.len())
+        })
+    }
+    fn remove(&self, key: &str) -> Result<usize, LedgerStoreError> {
+        let (address, occurrence) = split_key(key);
+        self.transaction(|transaction, now| {
+            let mut accounts = accounts_of(transaction, address)?;
+            if let Some(occurrence) = occurrence {
+                accounts = occurrence
+                    .checked_sub(1)
+                    .and_then(|index| accounts.get(index).copied())
+                    .into_iter()
+                    .collect();
+            }
+            if accounts.is_empty() {
+                return Err(LedgerStoreError::NotFound(key.to_owned()));
+            }
+            for &(id, value) in &accounts {
+                close_account(transaction, id, address, value, now)?;
+            }
+            Ok(accounts.len())
+        })
+    }
+}
+/// Seconds since the Unix epoch
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            i64::try_from(elapsed.as_secs()).unwrap_or(i64::MAX)
+        })
+}
+/// The id, address and value of every account, in ledger order
+fn accounts(connection: &Connection) -> rusqlite::Result<Vec<(i64, String, i64)>> {
+    let mut statement = connection.prepare_cached(ROWS)?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+/// The id and value of every account of `address`, in ledger order
+fn accounts_of(connection: &Connection, address: &str) -> rusqlite::Result<Vec<(i64, i64)>> {
+    let mut statement = connection.prepare_cached(
+        "SELECT accounts.id, balances.value FROM accounts
+            JOIN balances ON balances.account_id = accounts.id
+            WHERE accounts.address = ?1 ORDER BY accounts.id",
+    )?;
+    let rows = statement.query_map([address], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+fn record(
+    connection: &Connection,
+    account: i64,
+    address: &str,
+    change: ChangeKind,
+    old_value: Option<i64>,
+    new_value: Option<i64>,
+    now: i64,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO history (account_id, address, change, old_value, new_value, changed_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![account, address, change.as_str(), old_value, new_value, now],
+    )?;
+    Ok(())
+}
+fn open_account(
+    connection: &Connection,
+    address: &str,
+    value: i64,
+    now: i64,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO accounts (address, opened_at) VALUES (?1, ?2)",
+        params![address, now],
+    )?;
+    let id = connection.last_insert_rowid();
+    connection.execute(
+        "INSERT INTO balances (account_id, value, updated_at) VALUES (?1, ?2, ?3)",
+        params![id, value, now],
+    )?;
+    record(
+        connection,
+        id,
+        address,
+        ChangeKind::Open,
+        None,
+        Some(value),
+        now,
+    )
+}
+/// Remove an account, its balance going with it
+fn close_account(
+    connection: &Connection,
+    id: i64,
+    address: &str,
+    value: i64,
+    now: i64,
+) -> rusqlite::Result<()> {
+    connection.execute("DELETE FROM accounts WHERE id = ?1", [id])?;
+    record(
+        connection,
+        id,
+        address,
+        ChangeKind::Close,
+        Some(value),
+        None,
+        now,
+    )
+}
@@ -0,0 +1,133 @@
+use super::{AbstractAccumulator, MembershipProof};
+use crate::{
+    ledger_store::LedgerStore,
+    merkle_tree_entry::MerkleTreeEntry,
+    utils::{csv_utils::position_in_columns, merkle_utils::leaves_from_vectors},
+};
+use anyhow::Result;
+use rs_merkle::{MerkleTree, algorithms::Sha256};
+use std::sync::Arc;
+/// An accumulator which proves membership with the merkle path of an entry's leaf, checked
+/// against the root of the exchange's secret set.
+///
+/// Unlike the [`DeltaAccumulator`](super::value_delta::DeltaAccumulator) it doesn't need the
+/// emp-zk toolchain. The proofs it makes reveal nothing but whether the entry is a member.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use credible_coin::accumulator::AbstractAccumulator;
+/// use credible_coin::accumulator::merkle_path::MerklePathAccumulator;
+/// use credible_coin::ledger_store::MemoryLedgerStore;
+/// use credible_coin::merkle_tree_entry::MerkleTreeEntry;
+///
+/// let address = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+/// let ledger = MemoryLedgerStore::from_columns(vec![address.to_owned()], vec![10]).unwrap();
+/// let accumulator = MerklePathAccumulator::new(Arc::new(ledger));
+/// let member = MerkleTreeEntry::new(address.to_owned(), 10);
+/// assert!(accumulator.prove_member(&member, None).unwrap().is_member());
+/// let inflated = MerkleTreeEntry::new(address.to_owned(), 11);
+/// assert!(!accumulator.prove_member(&inflated, Some(0)).unwrap().is_member());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MerklePathAccumulator {
+    /// The exchange's secret set
+    pub exchange_secrets: Arc<dyn LedgerStore>,
+}
+impl MerklePathAccumulator {
+    /// An accumulator over the exchange's secret set
+    pub fn new(exchange_secrets: Arc<dyn LedgerStore>) -> Self {
+        Self { exchange_secrets }
+    }
+}
+/// The leaves of the secret set and their tree, built once per proof or aggregation. The proof
+/// server keeps its own, see [`crate::proof_server`].
+struct SecretTree {
+    addresses: Vec<String>,
+    values: Vec<i64>,
+    leaves: Vec<[u8; 32]>,
+    tree: MerkleTree<Sha256>,
+}
+impl SecretTree {
+    fn new(ledger: &dyn LedgerStore) -> Result<Self> {
+        let (addresses, values) = ledger.leaf_columns()?;
+        let leaves = leaves_from_vectors(addresses.clone(), values.clone());
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        Ok(Self {
+            addresses,
+            values,
+            leaves,
+            tree,
+        })
+    }
+    fn proves(&self, element: &MerkleTreeEntry, index: usize) -> bool {
+        path_proves(&self.tree, &self.leaves, element, index)
+    }
+}
+/// Whether the merkle path of the leaf at `index` of `tree`, built from `leaves`, proves
+/// `element` is committed to by its root
+pub(crate) fn path_proves(
+    tree: &MerkleTree<Sha256>,
+    leaves: &[[u8; 32]],
+    element: &MerkleTreeEntry,
+    index: usize,
+) -> bool {
+    let leaf = MerkleTreeEntry::hash_bytes(element.serialize_entry());
+    let Some(root) = tree.root() else {
+        return false;
+    };
+    leaves.get(index) == Some(&leaf)
+        && tree
+            .proof(&[index])
+            .verify(root, &[index], &[leaf], leaves.len())
+}
+impl AbstractAccumulator for MerklePathAccumulator {
+    fn prove_member(
+        &self,
+        element: &MerkleTreeEntry,
+        pos: Option<usize>,
+    ) -> Result<MembershipProof> {
+        let secrets = SecretTree::new(self.exchange_secrets.as_ref())?;
+        let index = match pos {
+            Some(pos) => pos,
+            None => position_in_columns(
+                &secrets.addresses,
+                &secrets.values,
+                &element.entry_address(),
+                Some(element.entry_value()),
+            )?,
+        };
+        Ok(MembershipProof::new(secrets.proves(element, index)))
+    }
+    /// Membership proofs of this accumulator are checked as they are made, so there is nothing
+    /// left to verify
+    fn verify(&self, _element_proof: MembershipProof) {}
+    fn search(&self, entry: &MerkleTreeEntry) -> Result<usize> {
+        Ok(self
+            .exchange_secrets
+            .position(&entry.entry_address(), Some(entry.entry_value()))?)
+    }
+    fn aggregate(
+        &self,
+        _ledger: &dyn LedgerStore,
+        ledger_entries: Vec<MerkleTreeEntry>,
+    ) -> Result<i64> {
+        // Only the members of the secret set count, whatever the ledger claims
+        let secrets = SecretTree::new(self.exchange_secrets.as_ref())?;
+        Ok(ledger_entries
+            .iter()
+            .filter(|entry| {
+                position_in_columns(
+                    &secrets.addresses,
+                    &secrets.values,
+                    &entry.entry_address(),
+                    Some(entry.entry_value()),
+                )
+                .is_ok_and(|index| secrets.proves(entry, index))
+            })
+            .map(MerkleTreeEntry::entry_value)
+            .sum())
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! This crate currently implements:
 //! 1. [`Delta Accumulator`](crate::accumulator::value_delta::DeltaAccumulator)
+//! 2. [`Merkle Path Accumulator`](crate::accumulator::merkle_path::MerklePathAccumulator)
 
+use crate::ledger_store::LedgerStore;
 use crate::merkle_tree_entry::MerkleTreeEntry;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -10,10 +12,19 @@ use serde::{Deserialize, Serialize};
 /// A membership proof is an interactive proof for a statement of the form x in L, where L is some formal language.
 /// The only information held in the type is whether the statement is a member of the language (ie the element is
 /// part of the secret set, if considering from the perspective of a membership proof)
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MembershipProof {
     is_member: bool,
 }
+impl MembershipProof {
+    pub(crate) fn new(is_member: bool) -> Self {
+        Self { is_member }
+    }
+    /// Whether the element is a member of the set
+    pub fn is_member(&self) -> bool {
+        self.is_member
+    }
+}
 /// Common Functionality an accumulator should have
 pub trait AbstractAccumulator {
     /// Prove that a [`Merkle Tree
@@ -30,7 +41,7 @@ pub trait AbstractAccumulator {
     /// Verify the provided [`Membership Proof`](MembershipProof)
     fn verify(&self, element_proof: MembershipProof);
     /// Search for a particular [`Merkle Tree Entry`](MerkleTreeEntry) and
-    /// return it's position in the file
+    /// return it's position in the secret set
     fn search(&self, entry: &MerkleTreeEntry) -> anyhow::Result<usize>;
     /// Aggregate the final delta using the public ledger's entries and the
     /// entries from the exchange's secret set This function follows a 2-step
@@ -41,8 +52,11 @@ pub trait AbstractAccumulator {
     /// using the above prove_member function The delta is only accumulated for
     /// those addresses in which the membership proof is true (ie they are part
     /// of the set)
-    fn aggregate(&self, ledger: String, ledger_entries: Vec<MerkleTreeEntry>) -> Result<i64>;
+    fn aggregate(&self, ledger: &dyn LedgerStore, ledger_entries: Vec<MerkleTreeEntry>)
+        -> Result<i64>;
 }
+/// Proving membership with the merkle paths of the leaves, without a zero-knowledge backend
+pub mod merkle_path;
 /// Our custom implementation of a delta accumulation proof using emp-zk as a
 /// zero-knowledge backend
 pub mod value_delta;
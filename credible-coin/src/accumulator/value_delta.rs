@@ -6,8 +6,9 @@ use crate::{
         executor::{execute_compiled_binary, execute_make_install, retrieve_membership_string},
     },
     handle_status,
+    ledger_store::{CsvLedgerStore, LedgerStore},
     merkle_tree_entry::MerkleTreeEntry,
-    utils::{csv_utils::get_address_position, get_project_root},
+    utils::get_project_root,
 };
 use anyhow::Result;
 use rayon::{
@@ -25,8 +26,8 @@ use std::collections::HashMap;
 /// of the exchange's liabilities
 #[derive(Debug)]
 pub struct DeltaAccumulator {
-    /// The path to the exchange secrets file
-    pub exchange_secrets_path: String,
+    /// The exchange's secret set
+    pub exchange_secrets: Box<dyn LedgerStore>,
 }
 impl AbstractAccumulator for DeltaAccumulator {
     fn prove_member(
@@ -65,22 +66,25 @@ impl AbstractAccumulator for DeltaAccumulator {
         panic!("This function should not be called");
     }
     fn search(&self, entry: &MerkleTreeEntry) -> Result<usize> {
+        let pos = self
+            .exchange_secrets
+            .position(&entry.entry_address(), None)?;
         println!(
             "{:?}, {:?}: {:?}",
             entry.entry_address(),
             entry.entry_value(),
-            get_address_position(&self.exchange_secrets_path, entry.entry_address(), None)?
+            pos
         );
         // TODO: Implement what I am calling a ColumnCache:
-        // It is basically a glorified multimap where the key is the filename and the value is a pair
-        // of the address and value vectors (which are the columns in the csv file)
-        Ok(get_address_position(
-            &self.exchange_secrets_path,
-            entry.entry_address(),
-            None,
-        )?)
+        // It is basically a glorified multimap where the key is the store and the value is a pair
+        // of the address and value vectors (which are the columns of the ledger)
+        Ok(pos)
     }
-    fn aggregate(&self, ledger_file: String, ledger_entries: Vec<MerkleTreeEntry>) -> Result<i64> {
+    fn aggregate(
+        &self,
+        ledger: &dyn LedgerStore,
+        ledger_entries: Vec<MerkleTreeEntry>,
+    ) -> Result<i64> {
         let matching_entries_map = self.precompute_matching_entries(&ledger_entries);
         let delta = std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0));
 
@@ -95,7 +99,6 @@ impl AbstractAccumulator for DeltaAccumulator {
             .for_each(|(entry_index, entry_match)| {
                 let delta_clone = std::sync::Arc::clone(&delta);
                 let file_mutex_clone = std::sync::Arc::clone(&file_mutex);
-                let ledger_file_clone = ledger_file.clone();
                 let self_clone = self.clone(); // Assuming YourStruct is Cloneable
 
                 // Lock for exclusive file access
@@ -103,9 +106,8 @@ impl AbstractAccumulator for DeltaAccumulator {
 
                 println!("Index {}: Processing entry.", entry_index);
 
-                let pos = match get_address_position(
-                    &ledger_file_clone,
-                    entry_match.entry_address(),
+                let pos = match ledger.position(
+                    &entry_match.entry_address(),
                     Some(entry_match.entry_value()),
                 ) {
                     Ok(p) => p,
@@ -134,11 +136,13 @@ impl AbstractAccumulator for DeltaAccumulator {
     }
 }
 impl DeltaAccumulator {
-    /// Make a new `DeltaAccumulator` from the provided exchange_path string
-    pub fn new(exchange_path: String) -> Self {
-        return Self {
-            exchange_secrets_path: exchange_path.into(),
-        };
+    /// Make a new `DeltaAccumulator` over the provided secret set
+    pub fn new(exchange_secrets: Box<dyn LedgerStore>) -> Self {
+        Self { exchange_secrets }
+    }
+    /// Make a new `DeltaAccumulator` over the secret set in the file at `exchange_path`
+    pub fn from_path(exchange_path: &str) -> Self {
+        Self::new(Box::new(CsvLedgerStore::new(exchange_path)))
     }
     /// Returns all `MerkleTreeEntry` items from the provided ledger entries that match the specified address.
     ///
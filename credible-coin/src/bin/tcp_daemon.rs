@@ -1,20 +1,87 @@
-use std::{
-    io::Read,
-    net::{TcpListener, TcpStream},
-};
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
+//! Answer the questions of verifiers and customers about the exchange's ledger over TCP: its
+//! merkle root, membership and inclusion proofs, and the solvency report of that root. See
+//! [`credible_coin::proof_server`] for the protocol.
+//!
+//! Accounts are served by ID too if `CREDIBLE_ACCOUNT_ID_SECRET` holds the secret the IDs are
+//! derived with.
+use std::sync::Arc;
+use std::time::Duration;
 
-    stream.read_exact(&mut buffer).unwrap();
+use anyhow::{Context, Result};
+use bitcoin::Network;
+use clap::{Parser, ValueEnum};
+use credible_coin::accumulator::value_delta::DeltaAccumulator;
+use credible_coin::ledger_store::{LedgerStore, open_ledger};
+use credible_coin::proof_server::{
+    DEFAULT_ADDRESS, MembershipProver, ProofServer, account_id_secret,
+};
+use credible_coin::proof_store::{ProofStore, RedisProofStore};
+use credible_coin::utils::address_utils::set_active_network;
+use credible_coin::utils::duplicates::{DuplicatePolicy, set_active_duplicate_policy};
+use tokio::net::TcpListener;
 
-    println!("Request: {}", String::from_utf8_lossy(&buffer[..]));
+/// How membership is proven
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AccumulatorKind {
+    /// The merkle path of the account's leaf
+    MerklePath,
+    /// The emp-zk circuit, which needs the emp toolchain
+    Emp,
 }
-
-pub fn main() {
-    let listener = TcpListener::bind("127.0.0.1:2300").unwrap();
-
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        handle_connection(stream);
+/// Answer proof requests about the exchange's ledger over TCP
+#[derive(Parser, Debug)]
+#[command(version)]
+struct DaemonArgs {
+    /// The exchange's ledger: a CSV file, binary ledger or SQLite database
+    ledger: String,
+    /// The address to listen on
+    #[arg(long, default_value = DEFAULT_ADDRESS)]
+    listen: String,
+    /// The network every address of the ledger must belong to
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    /// What to do with addresses that appear on more than one row of the ledger
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::KeepAll)]
+    duplicates: DuplicatePolicy,
+    /// How membership is proven
+    #[arg(long, value_enum, default_value_t = AccumulatorKind::MerklePath)]
+    accumulator: AccumulatorKind,
+    /// Don't serve the solvency reports of the proof Redis instance
+    #[arg(long)]
+    no_reports: bool,
+    /// Seconds between reloads of the ledger, 0 to never reload it
+    #[arg(long, default_value_t = 60)]
+    refresh: u64,
+}
+#[tokio::main]
+pub async fn main() -> Result<()> {
+    let args = DaemonArgs::parse();
+    set_active_network(args.network);
+    set_active_duplicate_policy(args.duplicates);
+    let ledger: Arc<dyn LedgerStore> = Arc::from(open_ledger(&args.ledger)?);
+    let prover = match args.accumulator {
+        AccumulatorKind::MerklePath => MembershipProver::MerklePath,
+        AccumulatorKind::Emp => MembershipProver::Accumulator(Arc::new(DeltaAccumulator::new(
+            open_ledger(&args.ledger)?,
+        ))),
+    };
+    let reports: Option<Arc<dyn ProofStore>> = if args.no_reports {
+        None
+    } else {
+        Some(Arc::new(RedisProofStore::new()?))
+    };
+    let mut server = ProofServer::new(ledger, prover, reports)?;
+    if let Some(secret) = account_id_secret() {
+        server = server.with_account_ids(secret)?;
+    }
+    let server = Arc::new(server);
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    println!("Listening on {}", listener.local_addr()?);
+    if args.refresh > 0 {
+        server.reload_every(Duration::from_secs(args.refresh));
     }
+    server.serve(listener).await?;
+    Ok(())
 }
@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Once;
+
+use anyhow::{Context, Result, bail, ensure};
+use clap::Args;
+use flexi_logger::{AdaptiveFormat, Duplicate, FileSpec, Logger};
+
+use crate::cli::output::{OutputFormat, Status, output_format};
+use crate::cli::shell::{Flow, Shell};
+use crate::cli::transcript::{TranscriptEntry, read_transcript, start_transcript};
+
+/// Start the logger every shell command reports through, unless it was already started. Messages
+/// go to the `credible.log` file, and to stdout too unless the output format is JSON, where
+/// stdout is kept for the command reports alone.
+pub(crate) fn start_logger() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        let duplicate = match output_format() {
+            OutputFormat::Human => Duplicate::All,
+            OutputFormat::Json => Duplicate::None,
+        };
+        //TODO: Eventually swap WriteMode::Default with WriteMode::Async
+        Logger::try_with_str("info")
+            .expect("Could not create logger object")
+            .duplicate_to_stdout(duplicate)
+            .log_to_file(
+                FileSpec::default()
+                    .basename("credible")
+                    .suffix("log")
+                    .suppress_timestamp(),
+            )
+            .adaptive_format_for_stderr(AdaptiveFormat::Default)
+            .adaptive_format_for_stdout(AdaptiveFormat::Default)
+            .append()
+            .start()
+            .unwrap();
+    });
+}
+/// The options shared by the `load` commands of both clis to run the shell without a TTY, and to
+/// record or replay what a session did.
+///
+/// # Fields
+///
+/// * `script`: A file of shell commands to run, one per line, instead of starting the
+///   interactive shell. `-` reads the commands from stdin, which is also what happens when stdin
+///   is not a terminal.
+/// * `keep_going`: Keep running the remaining commands after one fails, and only exit with a
+///   non-zero status at the end.
+/// * `transcript`: A file every command of the session is appended to, with its arguments, its
+///   result and the Merkle root before and after it ran. See
+///   [`TranscriptEntry`](crate::cli::transcript::TranscriptEntry).
+/// * `replay`: A transcript whose successful commands are run again instead of starting the
+///   interactive shell, checking that each one leaves the tree with the root it recorded.
+#[derive(Args, Debug, Clone, Default)]
+pub struct BatchArgs {
+    /// Run the shell commands in this file (or `-` for stdin) instead of starting the shell
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+    /// Keep going after a command fails and report the failures at the end
+    #[arg(long)]
+    keep_going: bool,
+    /// Append every command, its result and the Merkle roots around it to this file
+    #[arg(long, value_name = "FILE")]
+    transcript: Option<PathBuf>,
+    /// Run the successful commands of this transcript again, checking the roots they recorded
+    #[arg(long, value_name = "TRANSCRIPT", conflicts_with = "script")]
+    replay: Option<PathBuf>,
+}
+impl BatchArgs {
+    /// Run `shell` interactively, in batch mode if a script was provided or stdin is not a
+    /// terminal, or replay a transcript
+    pub(crate) fn run<S: Shell>(&self, shell: &mut S) -> Result<()> {
+        start_logger();
+        if let Some(path) = &self.transcript {
+            start_transcript(path, S::ROLE)?;
+        }
+        if let Some(path) = &self.replay {
+            return run_replay(shell, &read_transcript(path)?, self.keep_going);
+        }
+        match &self.script {
+            Some(path) if path.as_os_str() != "-" => {
+                let file = File::open(path)
+                    .with_context(|| format!("Failed to open script {}", path.display()))?;
+                run_batch(shell, BufReader::new(file), self.keep_going)
+            }
+            Some(_) => run_batch(shell, io::stdin().lock(), self.keep_going),
+            None if !io::stdin().is_terminal() => {
+                run_batch(shell, io::stdin().lock(), self.keep_going)
+            }
+            None => shell.start(),
+        }
+    }
+}
+/// Run every command read from `input` through `shell`.
+///
+/// Blank lines and lines starting with `#` are skipped. Each failure is reported on stderr with
+/// the line it came from. Unless `keep_going` is set the first failure stops the batch, otherwise
+/// every command is run and the number of failures is reported at the end. Either way an error is
+/// returned if any command failed, so the binary exits with a non-zero status.
+pub(crate) fn run_batch<S: Shell, R: BufRead>(
+    shell: &mut S,
+    input: R,
+    keep_going: bool,
+) -> Result<()> {
+    let mut failures = 0;
+    for (index, line) in input.lines().enumerate() {
+        let line = line.context("Failed to read the next command")?;
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        match shell.execute(command) {
+            Ok(Flow::Exit) => break,
+            Ok(Flow::Continue | Flow::Clear) => {}
+            Err(err) if !keep_going => {
+                return Err(err.context(format!("line {}: `{}` failed", index + 1, command)));
+            }
+            Err(err) => {
+                eprintln!("line {}: `{}` failed: {:#}", index + 1, command, err);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        bail!("{} command(s) failed", failures);
+    }
+    Ok(())
+}
+/// Run the successful commands of a transcript through `shell` again, in the order they were
+/// recorded.
+///
+/// Before and after each command the root of the shell's tree is checked against the one in the
+/// transcript, so a replay fails as soon as the file it runs against has drifted from the one
+/// the session was recorded on. Commands that pick fresh keys or addresses, like `addCoinToDB`,
+/// can't reproduce their recorded root. Failures are reported like in [`run_batch`], with the
+/// number of the entry they came from.
+pub(crate) fn run_replay<S: Shell>(
+    shell: &mut S,
+    entries: &[TranscriptEntry],
+    keep_going: bool,
+) -> Result<()> {
+    let mut failures = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.report.status != Status::Ok {
+            continue;
+        }
+        match replay_entry(shell, entry) {
+            Ok(Flow::Exit) => break,
+            Ok(Flow::Continue | Flow::Clear) => {}
+            Err(err) if !keep_going => {
+                return Err(err.context(format!(
+                    "entry {}: `{}` failed",
+                    index + 1,
+                    entry.report.command
+                )));
+            }
+            Err(err) => {
+                eprintln!(
+                    "entry {}: `{}` failed: {:#}",
+                    index + 1,
+                    entry.report.command,
+                    err
+                );
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        bail!("{} command(s) failed", failures);
+    }
+    Ok(())
+}
+/// Run a single transcript entry, checking the roots around it
+fn replay_entry<S: Shell>(shell: &mut S, entry: &TranscriptEntry) -> Result<Flow> {
+    ensure!(
+        entry.role == S::ROLE,
+        "It was recorded in the {} shell, not the {} shell",
+        entry.role,
+        S::ROLE
+    );
+    let root = shell.root();
+    ensure!(
+        root == entry.root_before,
+        "The root before it is {}, but the transcript recorded {}",
+        describe_root(&root),
+        describe_root(&entry.root_before)
+    );
+    let args: Vec<String> = std::iter::once(entry.report.command.clone())
+        .chain(entry.args.iter().cloned())
+        .collect();
+    let flow = shell.execute_args(&args)?;
+    let root = shell.root();
+    ensure!(
+        root == entry.root_after,
+        "The root after it is {}, but the transcript recorded {}",
+        describe_root(&root),
+        describe_root(&entry.root_after)
+    );
+    Ok(flow)
+}
+fn describe_root(root: &Option<String>) -> &str {
+    root.as_deref().unwrap_or("none")
+}
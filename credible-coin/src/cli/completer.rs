@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use reedline::{Completer, Span, Suggestion};
+
+use crate::cli::shell::ArgKind;
+
+/// Completes the word under the cursor from what the shell knows about it: a command name for the
+/// first word, then an address or file path depending on the [`ArgKind`] of the argument being
+/// typed.
+#[derive(Debug)]
+pub(crate) struct ShellCompleter {
+    /// The name of every command and the kinds of its arguments, in order
+    commands: Vec<(String, Vec<ArgKind>)>,
+    /// The keys of the loaded file, shared with the shell so it can refresh them when a command
+    /// changes the file
+    addresses: Arc<RwLock<Vec<String>>>,
+}
+impl ShellCompleter {
+    pub(crate) fn new(
+        commands: Vec<(String, Vec<ArgKind>)>,
+        addresses: Arc<RwLock<Vec<String>>>,
+    ) -> Self {
+        Self {
+            commands,
+            addresses,
+        }
+    }
+    /// The candidates for a word starting with `partial`, and whether selecting one ends the word
+    fn candidates(&self, words: &[&str], partial: &str) -> Vec<(String, bool)> {
+        let Some((command, previous)) = words.split_first() else {
+            return self
+                .commands
+                .iter()
+                .map(|(name, _)| name)
+                .filter(|name| name.starts_with(partial))
+                .map(|name| (name.clone(), true))
+                .collect();
+        };
+        let kind = self
+            .commands
+            .iter()
+            .find(|(name, _)| name == command)
+            .and_then(|(_, kinds)| kinds.get(previous.len()));
+        match kind {
+            Some(ArgKind::Address) => {
+                let mut addresses: Vec<&str> = Vec::new();
+                let keys = self.addresses.read().unwrap();
+                for key in keys.iter() {
+                    // Plain addresses don't take the `#N` of a repeated address
+                    let address = key
+                        .split_once('#')
+                        .map_or(key.as_str(), |(address, _)| address);
+                    if address.starts_with(partial) && !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+                addresses
+                    .into_iter()
+                    .map(|address| (address.to_owned(), true))
+                    .collect()
+            }
+            Some(ArgKind::Key) => self
+                .addresses
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|key| key.starts_with(partial))
+                .map(|key| (key.clone(), true))
+                .collect(),
+            Some(ArgKind::Path) => complete_path(partial),
+            Some(ArgKind::I64 | ArgKind::U64 | ArgKind::Text) | None => Vec::new(),
+        }
+    }
+}
+impl Completer for ShellCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |index| {
+            index + line[index..].chars().next().unwrap().len_utf8()
+        });
+        let words: Vec<&str> = line[..start].split_whitespace().collect();
+        self.candidates(&words, &line[start..])
+            .into_iter()
+            .map(|(value, append_whitespace)| Suggestion {
+                value,
+                description: None,
+                extra: None,
+                span: Span::new(start, pos),
+                append_whitespace,
+            })
+            .collect()
+    }
+}
+/// The files and directories whose path starts with `partial`, relative to the working directory
+/// unless `partial` is absolute. Directories end with a `/` so completion can carry on into them,
+/// and hidden entries are only offered once `partial` names them with a leading `.`.
+fn complete_path(partial: &str) -> Vec<(String, bool)> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(index) => partial.split_at(index + 1),
+        None => ("", partial),
+    };
+    let Ok(entries) = fs::read_dir(if directory.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(directory)
+    }) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<(String, bool)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if entry.file_type().ok()?.is_dir() {
+                Some((format!("{}{}/", directory, name), false))
+            } else {
+                Some((format!("{}{}", directory, name), true))
+            }
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+
+    fn completer() -> ShellCompleter {
+        ShellCompleter::new(
+            vec![
+                ("exit".to_owned(), vec![]),
+                ("getCoinInfo".to_owned(), vec![ArgKind::Key]),
+                ("import".to_owned(), vec![ArgKind::Path]),
+                ("send".to_owned(), vec![ArgKind::Address, ArgKind::U64]),
+            ],
+            Arc::new(RwLock::new(vec![
+                format!("{FIRST}#0"),
+                SECOND.to_owned(),
+                format!("{FIRST}#1"),
+            ])),
+        )
+    }
+    fn values(line: &str) -> Vec<String> {
+        completer()
+            .complete(line, line.len())
+            .into_iter()
+            .map(|suggestion| suggestion.value)
+            .collect()
+    }
+    #[test]
+    fn the_first_word_completes_command_names() {
+        assert_eq!(values("ge"), ["getCoinInfo"]);
+        assert_eq!(values(""), ["exit", "getCoinInfo", "import", "send"]);
+    }
+    #[test]
+    fn addresses_complete_from_the_loaded_keys() {
+        assert_eq!(
+            values("getCoinInfo 17"),
+            [format!("{FIRST}#0"), format!("{FIRST}#1")]
+        );
+        assert_eq!(values("send  17"), [FIRST]);
+        let suggestions = completer().complete("getCoinInfo bc", 14);
+        assert_eq!(suggestions[0].span, Span::new(12, 14));
+        // Values and extra arguments have nothing to complete
+        assert!(values("send bc1 1").is_empty());
+        assert!(values("exit 1").is_empty());
+    }
+    #[test]
+    fn paths_complete_from_the_file_system() {
+        let directory = std::env::temp_dir().join("completer_paths");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("nested")).unwrap();
+        fs::write(directory.join("ledger.csv"), "").unwrap();
+        fs::write(directory.join(".hidden"), "").unwrap();
+        let prefix = format!("{}/", directory.display());
+        assert_eq!(
+            values(&format!("import {}", prefix)),
+            [
+                format!("{}ledger.csv", prefix),
+                format!("{}nested/", prefix)
+            ]
+        );
+        assert_eq!(
+            values(&format!("import {}.", prefix)),
+            [format!("{}.hidden", prefix)]
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use bitcoin::Network;
+use clap::Parser;
+use serde::Serialize;
+
+use crate::account_database::{SQLITE_EXTENSIONS, SqliteLedgerStore, is_sqlite_database};
+use crate::cli::exchange::asset_database::get_extension_from_filename;
+use crate::cli::output::{CommandOutput, emit};
+use crate::utils::address_utils::set_active_network;
+use crate::utils::binary_ledger::is_binary_ledger;
+use crate::utils::csv_utils::{convert_csv_to_ledger, convert_ledger_to_csv};
+
+/// Represents the CLI command for converting a ledger between the CSV, binary and SQLite formats.
+///
+/// The direction of the conversion is picked from the files: a
+/// [binary ledger](crate::utils::binary_ledger) or [SQLite database](crate::account_database) is
+/// converted to CSV, and anything else is read as CSV (or a binary ledger) and converted to a
+/// SQLite database if the output has one of the [`SQLITE_EXTENSIONS`], or to a binary ledger.
+///
+/// # Fields
+///
+/// * `input`: The path to the file to convert.
+/// * `output`: The path to write the converted file to. It must not already exist.
+/// * `network`: The network every address in a CSV input must belong to.
+
+#[derive(Parser, Debug)]
+#[command(infer_subcommands = true)]
+pub struct ConvertCmd {
+    input: String,
+    output: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+}
+/// The result of a conversion, as reported by `convert`
+#[derive(Debug, Serialize)]
+struct Conversion {
+    input: String,
+    output: String,
+    /// The format of the written file, `csv`, `ledger` or `sqlite`
+    to: &'static str,
+    rows: usize,
+}
+impl fmt::Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.to {
+            "csv" => "rows",
+            "sqlite" => "accounts",
+            _ => "entries",
+        };
+        write!(
+            f,
+            "Wrote {} {} from {} to {}",
+            self.rows, unit, self.input, self.output
+        )
+    }
+}
+impl ConvertCmd {
+    /// Convert the file
+    pub fn run(self) -> Result<()> {
+        let result = self.convert();
+        emit("convert", &result);
+        result.map(|_| ())
+    }
+    fn convert(&self) -> Result<CommandOutput> {
+        if !Path::new(&self.input).exists() {
+            bail!("{} not found", self.input);
+        }
+        if Path::new(&self.output).exists() {
+            bail!("{} already exists", self.output);
+        }
+        set_active_network(self.network);
+        let (to, rows) = if is_binary_ledger(&self.input) {
+            ("csv", convert_ledger_to_csv(&self.input, &self.output)?)
+        } else if is_sqlite_database(&self.input) {
+            let database = SqliteLedgerStore::open(&self.input)?;
+            ("csv", database.export_csv(&self.output)?)
+        } else if get_extension_from_filename(&self.output)
+            .is_some_and(|extension| SQLITE_EXTENSIONS.contains(&extension))
+        {
+            let imported = SqliteLedgerStore::open(&self.output)
+                .and_then(|database| database.import_csv(&self.input));
+            if imported.is_err() {
+                // Don't leave a half made database behind
+                let _ = fs::remove_file(&self.output);
+            }
+            ("sqlite", imported?)
+        } else {
+            ("ledger", convert_csv_to_ledger(&self.input, &self.output)?)
+        };
+        CommandOutput::new(&Conversion {
+            input: self.input.clone(),
+            output: self.output.clone(),
+            to,
+            rows,
+        })
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::fs;
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use bitcoin::Network;
+use clap::Parser;
+use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
+use serde::Serialize;
+
+use crate::cli::exchange::key_manager::KeyManager;
+use crate::cli::output::{CommandOutput, emit};
+use crate::utils::address_utils::set_active_network;
+use crate::utils::atomic_file::write_atomically;
+use crate::utils::change_set::{
+    ChangeSet, SignedChangeSet, apply_change_set, diff_ledgers, verify_change_set,
+};
+
+/// Represents the CLI command for comparing two versions of a ledger.
+///
+/// Both files may be CSV files or [binary ledgers](crate::utils::binary_ledger). The change set
+/// is printed as a table, or as JSON with `--output json`, and can be signed with one of the
+/// exchange's keys and saved so it can be reviewed and re-applied with [`ApplyCmd`].
+///
+/// # Fields
+///
+/// * `old`: The path to the earlier version of the ledger.
+/// * `new`: The path to the later version of the ledger.
+/// * `network`: The network every address in both files must belong to, and the one the
+///   signing key's address is encoded for.
+/// * `sign`: The name of the key in the configured keystore to sign the change set with, see
+///   [`ChangeSet::sign`].
+/// * `save`: The path to write the change set to as JSON, signed if `sign` is given.
+
+#[derive(Parser, Debug)]
+#[command(infer_subcommands = true)]
+pub struct DiffCmd {
+    old: String,
+    new: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long, value_name = "KEY_NAME")]
+    sign: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    save: Option<String>,
+}
+/// Represents the CLI command for re-applying a signed change set saved by `diff`.
+///
+/// The signature is checked against `address` before anything is changed, and the change set
+/// is then applied with [`apply_change_set`], which checks the file is the one it was made from.
+///
+/// # Fields
+///
+/// * `file`: The path to the copy of the old ledger to apply the changes to.
+/// * `changes`: The path to the signed change set, as saved by `diff --sign <KEY_NAME> --save`.
+/// * `address`: The address of the exchange's key the change set must be signed by.
+/// * `network`: The network every address in the file and `address` belong to.
+
+#[derive(Parser, Debug)]
+#[command(infer_subcommands = true)]
+pub struct ApplyCmd {
+    file: String,
+    changes: String,
+    #[arg(long)]
+    address: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+}
+/// A change set, as reported by `diff`
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+struct ChangeReport(ChangeSet);
+impl fmt::Display for ChangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_changes(f, &self.0)
+    }
+}
+/// A signed change set, as reported by `diff --sign`
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+struct SignedChangeReport(SignedChangeSet);
+impl fmt::Display for SignedChangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_changes(f, &self.0.changes)?;
+        write!(f, "\nSigned by {}: {}", self.0.address, self.0.signature)
+    }
+}
+/// The result of applying a change set, as reported by `apply`
+#[derive(Debug, Serialize)]
+struct Application {
+    file: String,
+    /// The address the change set was signed by
+    address: String,
+    /// The number of entries added, removed and modified
+    changes: usize,
+    root: Option<String>,
+}
+impl fmt::Display for Application {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Applied {} changes signed by {} to {}\nRoot:{}",
+            self.changes,
+            self.address,
+            self.file,
+            self.root.as_deref().unwrap_or_default()
+        )
+    }
+}
+/// Write the roots and the table of the changes of `changes`
+fn write_changes(f: &mut fmt::Formatter<'_>, changes: &ChangeSet) -> fmt::Result {
+    writeln!(
+        f,
+        "Old root:{}\nNew root:{}",
+        changes.old_root.as_deref().unwrap_or_default(),
+        changes.new_root.as_deref().unwrap_or_default()
+    )?;
+    if changes.is_empty() {
+        return f.write_str("No changes");
+    }
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Change").add_attribute(Attribute::Bold),
+            Cell::new("Address").add_attribute(Attribute::Bold),
+            Cell::new("Old value").add_attribute(Attribute::Bold),
+            Cell::new("New value").add_attribute(Attribute::Bold),
+            Cell::new("Delta").add_attribute(Attribute::Bold),
+        ]);
+    for entry in &changes.removed {
+        table.add_row(vec![
+            Cell::new("removed"),
+            Cell::new(&entry.address),
+            Cell::new(entry.value),
+            Cell::new(""),
+            Cell::new(-i128::from(entry.value)),
+        ]);
+    }
+    for entry in &changes.modified {
+        table.add_row(vec![
+            Cell::new("modified"),
+            Cell::new(&entry.address),
+            Cell::new(entry.old_value),
+            Cell::new(entry.new_value),
+            Cell::new(format!("{:+}", entry.delta)),
+        ]);
+    }
+    for entry in &changes.added {
+        table.add_row(vec![
+            Cell::new("added"),
+            Cell::new(&entry.address),
+            Cell::new(""),
+            Cell::new(entry.value),
+            Cell::new(format!("{:+}", entry.value)),
+        ]);
+    }
+    write!(
+        f,
+        "{table}\n{} added, {} removed, {} modified, net change {:+}",
+        changes.added.len(),
+        changes.removed.len(),
+        changes.modified.len(),
+        changes.net_change
+    )
+}
+impl DiffCmd {
+    /// Compare the files
+    pub fn run(self) -> Result<()> {
+        set_active_network(self.network);
+        let result = self.diff();
+        emit("diff", &result);
+        result.map(|_| ())
+    }
+    fn diff(&self) -> Result<CommandOutput> {
+        let changes = diff_ledgers(&self.old, &self.new)?;
+        let Some(name) = &self.sign else {
+            self.save(&changes)?;
+            return CommandOutput::new(&ChangeReport(changes));
+        };
+        let signed = changes.sign(&KeyManager::from_config()?, name)?;
+        self.save(&signed)?;
+        CommandOutput::new(&SignedChangeReport(signed))
+    }
+    fn save<T: Serialize>(&self, changes: &T) -> Result<()> {
+        if let Some(path) = &self.save {
+            let contents = serde_json::to_vec_pretty(changes)?;
+            write_atomically(path, |file| file.write_all(&contents))
+                .with_context(|| format!("Failed to save the change set to {path}"))?;
+        }
+        Ok(())
+    }
+}
+impl ApplyCmd {
+    /// Apply the change set
+    pub fn run(self) -> Result<()> {
+        set_active_network(self.network);
+        let result = self.apply();
+        emit("apply", &result);
+        result.map(|_| ())
+    }
+    fn apply(&self) -> Result<CommandOutput> {
+        let contents = fs::read(&self.changes)
+            .with_context(|| format!("Failed to read the change set {}", self.changes))?;
+        let signed: SignedChangeSet = serde_json::from_slice(&contents)
+            .with_context(|| format!("{} is not a signed change set", self.changes))?;
+        if !verify_change_set(&signed, &self.address)? {
+            bail!(
+                "The change set {} is not signed by {}",
+                self.changes,
+                self.address
+            );
+        }
+        apply_change_set(&self.file, &signed.changes)?;
+        let changes = signed.changes;
+        CommandOutput::new(&Application {
+            file: self.file.clone(),
+            address: signed.address,
+            changes: changes.added.len() + changes.removed.len() + changes.modified.len(),
+            root: changes.new_root,
+        })
+    }
+}
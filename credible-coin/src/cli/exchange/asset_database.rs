@@ -1,13 +1,20 @@
-use std::{collections::HashSet, ffi::OsStr, path::Path, sync::Mutex};
+use std::{collections::HashSet, ffi::OsStr, path::Path, sync::Arc, sync::Mutex};
 
+use bitcoin::Network;
 use clap::Parser;
 use csv::Writer;
-use rs_merkle::{algorithms::Sha256, MerkleTree};
+use rs_merkle::{MerkleTree, algorithms::Sha256};
 
+use crate::account_database::SQLITE_EXTENSIONS;
+use crate::cli::batch::BatchArgs;
+use crate::cli::enforce_duplicate_policy;
+use crate::ledger_store::{LedgerStore, open_ledger};
 use crate::utils::{
-    bitcoin_utils::generate_address,
-    csv_utils::{make_value_vector, CSVRecord},
-    merkle_utils::load_merkle_leaves_from_csv,
+    address_utils::set_active_network,
+    binary_ledger::LEDGER_EXTENSION,
+    bitcoin_utils::{AddressMix, generate_mixed_address},
+    csv_utils::{CSVRecord, make_value_vector},
+    duplicates::DuplicatePolicy,
 };
 
 use super::shell::ExchangeShell;
@@ -33,6 +40,11 @@ pub(crate) fn get_extension_from_filename(filename: &str) -> Option<&str> {
 /// * `row_count`: The number of rows to consider from the publisher file when generating
 /// the exchange database.
 ///
+/// * `network`: The network the generated addresses are encoded for.
+///
+/// * `mix`: The relative weights of each script type, e.g. `p2pkh=40,p2wpkh=60`. When it isn't
+///   provided the default [`AddressMix`] is used.
+///
 
 #[derive(Parser, Debug)]
 #[command(infer_subcommands = true)]
@@ -40,6 +52,10 @@ pub struct CreateCmd {
     publisher_filename: String,
     exchange_filename: String,
     row_count: usize,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long)]
+    mix: Option<AddressMix>,
 }
 /// Represents the CLI command for loading an exchange database.
 ///
@@ -47,48 +63,66 @@ pub struct CreateCmd {
 ///
 /// # Fields
 ///
-/// * `filename`: The path to the csv file from which the data should be loaded.
+/// * `filename`: The path to the csv file, binary ledger or SQLite database (`.db` or
+///   `.sqlite`, see [`crate::account_database`]) from which the data should be loaded.
+///
+/// * `network`: The network every address in the file (and every address given to the shell)
+///   must belong to.
+/// * `duplicates`: What to do with addresses that appear on more than one row of the file.
+/// * `batch`: Run shell commands from a script or stdin instead of the interactive shell, see
+///   [`BatchArgs`].
 
 #[derive(Parser, Debug)]
 #[command(infer_subcommands = true)]
 pub struct LoadCmd {
     filename: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::KeepAll)]
+    duplicates: DuplicatePolicy,
+    #[command(flatten)]
+    batch: BatchArgs,
 }
 impl CreateCmd {
     pub(crate) fn run(&self) {
-        create_exchange_database(
+        let mix = self
+            .mix
+            .clone()
+            .unwrap_or_default()
+            .with_network(self.network);
+        create_exchange_database_with_mix(
             &self.publisher_filename,
             &self.exchange_filename,
             self.row_count,
+            &mix,
         );
     }
 }
 impl LoadCmd {
-    pub(crate) fn run(&self) {
+    pub(crate) fn run(&self) -> anyhow::Result<()> {
         if !std::path::Path::new(&self.filename).exists() {
             panic!("Exchange file: {} not found", self.filename)
         }
-        if get_extension_from_filename(&self.filename).unwrap() == "csv" {
-            let merkle_leaves = load_merkle_leaves_from_csv(&self.filename);
-            let coin_tree = load_exchange_db(merkle_leaves);
+        set_active_network(self.network);
+        let extension = get_extension_from_filename(&self.filename);
+        let is_ledger = matches!(extension, Some("csv" | LEDGER_EXTENSION))
+            || extension.is_some_and(|extension| SQLITE_EXTENSIONS.contains(&extension));
+        if is_ledger {
+            let ledger: Arc<dyn LedgerStore> = Arc::from(open_ledger(&self.filename)?);
+            enforce_duplicate_policy(&self.filename, ledger.as_ref(), self.duplicates)?;
             // I think the clone is unavoidable, hopefully it doesn't bite us
-            let mut exchange_shell = ExchangeShell::new(Some(coin_tree), self.filename.clone());
-            match exchange_shell.start() {
-                Ok(_) => {}
-                Err(err) => log::error!("{}", err),
-            }
+            let mut exchange_shell = ExchangeShell::new(Some(ledger), self.filename.clone())?;
+            self.batch.run(&mut exchange_shell)?;
         } else if get_extension_from_filename(&self.filename).unwrap() == "txt" {
-            let mut exchange_shell = ExchangeShell::new(None, self.filename.clone());
-            match exchange_shell.start() {
-                Ok(_) => {}
-                Err(err) => log::error!("{}", err),
-            }
+            let mut exchange_shell = ExchangeShell::new(None, self.filename.clone())?;
+            self.batch.run(&mut exchange_shell)?;
         } else {
             panic!(
                 "Unrecognized extension: {} ",
                 get_extension_from_filename(&self.filename).unwrap()
             )
         }
+        Ok(())
     }
 }
 /// Counts the number of rows in a given CSV file.
@@ -140,6 +174,31 @@ pub fn create_exchange_database(
     publisher_filename: &str,
     exchange_filename: &str,
     row_count: usize,
+) {
+    create_exchange_database_with_mix(
+        publisher_filename,
+        exchange_filename,
+        row_count,
+        &AddressMix::default(),
+    );
+}
+/// Creates an exchange database file whose addresses follow the script type and network
+/// distribution of the provided [`AddressMix`].
+///
+/// See [`create_exchange_database`] for the checks that are performed before the file is
+/// written.
+///
+/// # Panics
+///
+/// * If the publisher file doesn't exist.
+/// * If the exchange file already exists.
+/// * If the provided row count exceeds the max rows in the publisher CSV.
+///
+pub fn create_exchange_database_with_mix(
+    publisher_filename: &str,
+    exchange_filename: &str,
+    row_count: usize,
+    mix: &AddressMix,
 ) {
     if !std::path::Path::new(&publisher_filename).exists() {
         panic!("Publisher file: {} not found", publisher_filename)
@@ -171,7 +230,7 @@ pub fn create_exchange_database(
 
     // This should ensure that we do not have any repeated addresses making the file shorter
     while selected_addresses.len() != row_count {
-        selected_addresses.insert(generate_address());
+        selected_addresses.insert(generate_mixed_address(mix));
     }
     assert_eq!(selected_addresses.len(), row_count);
     let selected_values: Vec<i64> = make_value_vector(publisher_filename)[0..row_count].to_vec();
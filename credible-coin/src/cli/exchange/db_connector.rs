@@ -1,57 +1,330 @@
-use anyhow::{Context, Result};
-use redis::Commands;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
 
+use redis::{Commands, ConnectionInfo, IntoConnectionInfo};
+
+use crate::credible_config::{RedisInstanceConfig, get_redis_config};
+use crate::errors::DBConnectorError;
+
+/// A pool of connections to one Redis instance, cheap to clone
+pub type RedisPool = r2d2::Pool<redis::Client>;
+/// A connection borrowed from a [`RedisPool`], given back to the pool when dropped. It derefs
+/// to a `redis::Connection`, so `&mut *conn` can be passed to the functions of this module.
+pub type PooledConnection = r2d2::PooledConnection<redis::Client>;
+
+/// How long to wait for a connection before giving up on the instance
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    /// The pools opened so far, so every part of the process shares the connections to an
+    /// instance
+    static ref POOLS: Mutex<HashMap<RedisInstanceConfig, RedisPool>> = Mutex::new(HashMap::new());
+}
+
+/// The address and credentials of an instance, with the username, password and database index
+/// of the configuration taking precedence over the ones of its URL
+///
+/// # Errors
+///
+/// Returns an error if the URL of the instance is invalid.
+pub fn connection_info(instance: &RedisInstanceConfig) -> Result<ConnectionInfo, DBConnectorError> {
+    let mut info = instance.url.as_str().into_connection_info()?;
+    if let Some(username) = &instance.username {
+        info.redis.username = Some(username.clone());
+    }
+    if let Some(password) = &instance.password {
+        info.redis.password = Some(password.clone());
+    }
+    if let Some(db) = instance.db {
+        info.redis.db = db;
+    }
+    Ok(info)
+}
+/// The pool of connections to an instance, opened on first use and shared afterwards. No
+/// connection is made until one is asked for.
+///
+/// # Errors
+///
+/// Returns an error if the URL of the instance is invalid or its pool size is 0.
+pub fn pool(instance: &RedisInstanceConfig) -> Result<RedisPool, DBConnectorError> {
+    if instance.pool_size == 0 {
+        return Err(DBConnectorError::InvalidPoolSize(instance.url.clone()));
+    }
+    let mut pools = POOLS.lock().unwrap();
+    if let Some(pool) = pools.get(instance) {
+        return Ok(pool.clone());
+    }
+    let client = redis::Client::open(connection_info(instance)?)?;
+    let pool = r2d2::Pool::builder()
+        .max_size(instance.pool_size)
+        .min_idle(Some(0))
+        .connection_timeout(CONNECTION_TIMEOUT)
+        .build_unchecked(client);
+    pools.insert(instance.clone(), pool.clone());
+    Ok(pool)
+}
+/// The pool of connections to the exchange's Redis instance, as set in `credible_config`
+///
+/// # Errors
+///
+/// Returns an error if the configuration is invalid.
+pub fn exchange_pool() -> Result<RedisPool, DBConnectorError> {
+    pool(&get_redis_config()?.exchange)
+}
+/// The pool of connections to the Redis instance holding the proofs, as set in
+/// `credible_config`
+///
+/// # Errors
+///
+/// Returns an error if the configuration is invalid.
+pub fn proofs_pool() -> Result<RedisPool, DBConnectorError> {
+    pool(&get_redis_config()?.proofs)
+}
+/// The keys of one exchange in its Redis instance, all under `exchange:{id}:`, so several
+/// exchanges or environments can share the instance:
+///
+/// * `exchange:{id}:keys:{name}`: The bytes of the secret key stored under `name`
+/// * `exchange:{id}:indices:{counter}`: The next index the derivation counter hands out
+/// * `exchange:{id}:ledgers:{name}`: The rows of the ledger called `name`
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::cli::exchange::db_connector::ExchangeNamespace;
+///
+/// let namespace = ExchangeNamespace::new("eu-prod").unwrap();
+/// assert_eq!(namespace.secret_key("default"), "exchange:eu-prod:keys:default");
+/// assert!(ExchangeNamespace::new("eu:prod").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExchangeNamespace {
+    id: String,
+}
+impl ExchangeNamespace {
+    /// The namespace of the exchange called `id`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error unless `id` is made of letters, digits, '-', '_' and '.', which keeps it
+    /// from reaching into another namespace or matching more than its keys.
+    pub fn new(id: &str) -> Result<Self, DBConnectorError> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(DBConnectorError::InvalidNamespace(id.to_owned()));
+        }
+        Ok(Self { id: id.to_owned() })
+    }
+    /// The namespace of the exchange set by `exchange_id` in `credible_config`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration or the id is invalid.
+    pub fn from_config() -> Result<Self, DBConnectorError> {
+        Self::new(&get_redis_config()?.exchange_id)
+    }
+    /// The id of the exchange
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    /// The key the secret key called `name` is stored under
+    pub fn secret_key(&self, name: &str) -> String {
+        format!("{}{}", self.secret_key_prefix(), name)
+    }
+    fn secret_key_prefix(&self) -> String {
+        format!("exchange:{}:keys:", self.id)
+    }
+    /// The key the derivation counter called `counter` is stored under
+    pub fn index(&self, counter: &str) -> String {
+        format!("exchange:{}:indices:{}", self.id, counter)
+    }
+    /// The key the rows of the ledger called `name` are stored under
+    pub fn ledger(&self, name: &str) -> String {
+        format!("exchange:{}:ledgers:{}", self.id, name)
+    }
+    /// The key of the hash indexing the rows of the ledger called `name` by address
+    pub fn ledger_index(&self, name: &str) -> String {
+        format!("exchange:{}:ledger-indices:{}", self.id, name)
+    }
+}
+/// The prefix of every key of the instance holding the proofs
+pub const PROOF_KEY_PREFIX: &str = "proofs:";
+/// The key `field` of the proofs of the ledger whose merkle root is `root` is stored under,
+/// `proofs:{root}:{field}`
+pub fn proof_key(root: &str, field: &str) -> String {
+    format!("{PROOF_KEY_PREFIX}{root}:{field}")
+}
 /// A connector module responsible for creating and managing all of the information the
 /// exchange needs.
 ///
 /// The database is a redis server instance managing:
-/// 1. Private Keys for address generation (our fallback is to generate a key on the fly)
+/// 1. The secret keys of the exchange, stored under the names they were created with
+///
+/// Keys are never overwritten: if a key with the same name already exists nothing is written,
+/// so a key which addresses were already derived from can't be lost by reusing its name.
+///
+/// # Arguments
 ///
-/// The current setup for the Random Number Generator and Private Key force there to be only
-/// 1 record present at a time. If the user calls the function to generate a private key multiple
-/// times, for example, the value gets overwritten every time
+/// * `conn` - A connection to the exchange's Redis instance
+/// * `namespace` - The namespace of the exchange
+/// * `name` - The name to store the key under
+/// * `secret_key_bytes` - The 32 bytes of the secret key
 ///
 /// # Returns
 ///
-/// * `Ok()`: Successfully inserted or updated the public key.
-/// * `Err(anyhow::Error)`: An error context containing the reason for failure, e.g., connection issues, key not found, etc.
+/// * `Ok(true)`: The key was inserted.
+/// * `Ok(false)`: A key with this name already exists and was left untouched.
+/// * `Err(DBConnectorError)`: The Redis server could not be reached or refused the command.
+pub fn insert_secret_key(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+    name: &str,
+    secret_key_bytes: &[u8],
+) -> Result<bool, DBConnectorError> {
+    Ok(conn.set_nx(namespace.secret_key(name), secret_key_bytes)?)
+}
+/// Retrieves the bytes of the secret key stored under `name` from the exchange's Redis instance.
 ///
-/// # Panics
+/// # Returns
 ///
-/// The function may panic if the Redis server is not running or if there are other unforeseen issues.
-
-pub fn insert_key_or_update(key_bytes: Vec<u8>) -> Result<()> {
-    let client =
-        redis::Client::open("redis://127.0.0.1:6380/").context("Failed to open Redis client")?;
-    let mut conn = client
-        .get_connection()
-        .context("Failed to establish connection to Redis")?;
-    conn.set("private_key", key_bytes)
-        .context("Failed to set private key")?;
+/// * `Ok(Some(Vec<u8>))`: The bytes of the secret key.
+/// * `Ok(None)`: No key was stored under this name.
+/// * `Err(DBConnectorError)`: The Redis server could not be reached or refused the command.
+pub fn retrieve_secret_key(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+    name: &str,
+) -> Result<Option<Vec<u8>>, DBConnectorError> {
+    Ok(conn.get(namespace.secret_key(name))?)
+}
+/// The names of every key the exchange stored in its Redis instance, in no particular order
+///
+/// # Errors
+///
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn list_key_names(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+) -> Result<Vec<String>, DBConnectorError> {
+    let prefix = namespace.secret_key_prefix();
+    Ok(scan_keys(conn, &format!("{prefix}*"))?
+        .iter()
+        .filter_map(|key| key.strip_prefix(&prefix))
+        .map(str::to_owned)
+        .collect())
+}
+/// Removes the key stored under `name`, returning whether there was one
+///
+/// # Errors
+///
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn remove_secret_key(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+    name: &str,
+) -> Result<bool, DBConnectorError> {
+    Ok(conn.del(namespace.secret_key(name))?)
+}
+/// Hands out the next index of the derivation counter called `counter`, starting at 0.
+///
+/// The counter is incremented atomically, so no two callers are ever given the same index.
+///
+/// # Errors
+///
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn reserve_index(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+    counter: &str,
+) -> Result<u32, DBConnectorError> {
+    let next: u32 = conn.incr(namespace.index(counter), 1)?;
+    Ok(next - 1)
+}
+/// The index the derivation counter called `counter` will hand out next, without reserving it
+///
+/// # Errors
+///
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn peek_index(
+    conn: &mut redis::Connection,
+    namespace: &ExchangeNamespace,
+    counter: &str,
+) -> Result<u32, DBConnectorError> {
+    let next: Option<u32> = conn.get(namespace.index(counter))?;
+    Ok(next.unwrap_or_default())
+}
+/// `ttl` in milliseconds, as Redis expects it, rounded up so a short but non-zero `ttl` doesn't
+/// become 0
+fn milliseconds(ttl: Duration) -> usize {
+    usize::try_from(ttl.as_millis())
+        .unwrap_or(usize::MAX)
+        .max(1)
+}
+/// Stores `value` under `key`, replacing whatever was there. The key is dropped after `ttl`, or
+/// kept until it is replaced if there is none.
+///
+/// # Errors
+///
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn set_value(
+    conn: &mut redis::Connection,
+    key: &str,
+    value: &str,
+    ttl: Option<Duration>,
+) -> Result<(), DBConnectorError> {
+    match ttl {
+        Some(ttl) => conn.pset_ex(key, value, milliseconds(ttl))?,
+        None => conn.set(key, value)?,
+    }
     Ok(())
 }
-/// Retrieves the public key bytes from a Redis instance.
+/// The value stored under `key`, `None` if there is none (or it expired)
 ///
-/// This function connects to a Redis instance at the provided URI and fetches the bytes associated with the key "private_key".
-/// Note: The function name suggests retrieving a public key, but the Redis key is named "private_key". Ensure that the correct key is being fetched.
+/// # Errors
 ///
-/// # Returns
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn get_value(
+    conn: &mut redis::Connection,
+    key: &str,
+) -> Result<Option<String>, DBConnectorError> {
+    Ok(conn.get(key)?)
+}
+/// Every key matching the glob-style `pattern`, in no particular order. The keys are scanned in
+/// batches, so the server isn't blocked the way `KEYS` would block it.
+///
+/// # Errors
 ///
-/// * `Ok(Vec<u8>)`: A vector of bytes representing the public key.
-/// * `Err(anyhow::Error)`: An error context containing the reason for failure, e.g., connection issues, key not found, etc.
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn scan_keys(
+    conn: &mut redis::Connection,
+    pattern: &str,
+) -> Result<Vec<String>, DBConnectorError> {
+    Ok(conn.scan_match(pattern)?.collect())
+}
+/// Drops `key` after `ttl`, or right away if `ttl` is zero, returning whether there was such a
+/// key
+///
+/// # Errors
 ///
-/// # Panics
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn expire_key(
+    conn: &mut redis::Connection,
+    key: &str,
+    ttl: Duration,
+) -> Result<bool, DBConnectorError> {
+    if ttl.is_zero() {
+        return Ok(conn.del(key)?);
+    }
+    Ok(conn.pexpire(key, milliseconds(ttl))?)
+}
+/// Every item of the list stored under `key`, in order, empty if there is no such list
 ///
-/// The function may panic if the Redis server is not running or if there are other unforeseen issues.
+/// # Errors
 ///
-pub fn retrieve_public_key_bytes() -> Result<Vec<u8>> {
-    let client =
-        redis::Client::open("redis://127.0.0.1:6380/").context("Failed to open Redis client")?;
-    let mut conn = client
-        .get_connection()
-        .context("Failed to establish connection to Redis")?;
-    let key_bytes: Vec<u8> = conn
-        .get("private_key")
-        .context("Failed to retrieve key from Redis")?;
-    Ok(key_bytes)
+/// Returns an error if the Redis server could not be reached or refused the command.
+pub fn list_items(conn: &mut redis::Connection, key: &str) -> Result<Vec<String>, DBConnectorError> {
+    Ok(conn.lrange(key, 0, -1)?)
 }
@@ -1,24 +1,213 @@
-use bitcoin::PublicKey;
-use comfy_table::{presets::UTF8_FULL, Attribute, Cell, ContentArrangement, Table};
+use std::fmt;
+
+use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
 use rand::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 use rs_merkle::{algorithms::Sha256, MerkleTree};
-use secp256k1::Secp256k1;
+use serde::Serialize;
 
 use crate::{
-    cli::exchange::db_connector::insert_key_or_update, merkle_tree_entry::MerkleTreeEntry,
-    utils::csv_utils::addresses_and_values_as_vectors,
+    cli::exchange::key_manager::{KeyManager, ManagedKey},
+    errors::KeyError,
+    merkle_tree_entry::MerkleTreeEntry,
+    utils::{
+        address_utils::active_network, bitcoin_utils::ScriptType,
+        csv_utils::addresses_and_values_as_vectors,
+    },
 };
-/// Create a new SECP256K1 Private Key
-pub fn create_private_key() -> PublicKey {
-    let s = Secp256k1::new();
-    let key = PublicKey::new(s.generate_keypair(&mut rand::thread_rng()).1);
-    println!("{:?}", key.to_bytes());
-    match insert_key_or_update(key.to_bytes()) {
-        Ok(_) => {}
-        Err(err) => log::error!("{:?}", err),
-    };
-    key
+/// The result of proving an address is a member of the exchange's ledger with emp, as reported by
+/// `proveMembership`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExchangeMembership {
+    /// The address (or disambiguated key) which was proven
+    pub address: String,
+    /// The index of the address' leaf
+    pub index: usize,
+    /// The verdict printed by the emp circuit
+    pub result: String,
+    /// Any extra line the emp circuit printed after its verdict
+    pub details: Option<String>,
+}
+impl fmt::Display for ExchangeMembership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.result)?;
+        if let Some(details) = &self.details {
+            write!(f, "\n{:?}", details)?;
+        }
+        Ok(())
+    }
+}
+/// The ID the exchange hands the customer of an account, as reported by `accountId`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountId {
+    /// The address (or disambiguated key) of the account
+    pub address: String,
+    /// The ID customers fetch the inclusion proof of the account with
+    pub id: String,
+}
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address:{:?}\nID:{}", self.address, self.id)
+    }
+}
+/// A key of the exchange, as reported by `createPrivateKey` and `listKeys`. The secret key is
+/// never part of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyInfo {
+    /// The name the key is stored under
+    pub name: String,
+    /// The hex encoded compressed public key
+    pub public_key: String,
+    /// The mainnet P2PKH address of the key, the one `addCoinToDB` uses
+    pub address: String,
+}
+impl KeyInfo {
+    /// Describe the provided key, with its P2PKH address on the
+    /// [`active_network`](crate::utils::address_utils::active_network)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address of the key can't be encoded.
+    pub fn new(key: &ManagedKey) -> Result<Self, KeyError> {
+        Ok(Self {
+            name: key.name().to_owned(),
+            public_key: key.public_key().to_string(),
+            address: key.address(ScriptType::P2pkh, active_network())?,
+        })
+    }
+}
+impl fmt::Display for KeyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Key:{}\nPublic key:{}\nAddress:{}",
+            self.name, self.public_key, self.address
+        )
+    }
+}
+/// Every key of the exchange, as listed by `listKeys`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct KeyTable(pub Vec<KeyInfo>);
+impl fmt::Display for KeyTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("No keys created");
+        }
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Name").add_attribute(Attribute::Bold),
+                Cell::new("Public key").add_attribute(Attribute::Bold),
+                Cell::new("Address").add_attribute(Attribute::Bold),
+            ]);
+        for key in &self.0 {
+            table.add_row(vec![
+                Cell::new(&key.name).add_attribute(Attribute::Bold),
+                Cell::new(&key.public_key),
+                Cell::new(&key.address),
+            ]);
+        }
+        write!(f, "{table}")
+    }
+}
+/// A message signed with one of the exchange's keys by `signMessage`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SignedMessage {
+    /// The name of the key the message was signed with
+    pub key: String,
+    /// The mainnet P2PKH address the signature can be checked against
+    pub address: String,
+    /// The message which was signed
+    pub message: String,
+    /// The base64 encoded `signmessage` signature
+    pub signature: String,
+}
+impl fmt::Display for SignedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Address:{}\nMessage:{:?}\nSignature:{}",
+            self.address, self.message, self.signature
+        )
+    }
+}
+/// A coin appended to the exchange's ledger by `addCoinToDB`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddedCoin {
+    /// The newly derived deposit address of the coin
+    pub address: String,
+    /// The BIP32 path the address was derived along from the exchange's default key
+    pub path: String,
+    /// The value of the coin
+    pub value: u64,
+    /// The hex encoded merkle root of the updated ledger
+    pub root: String,
+}
+impl fmt::Display for AddedCoin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Address:{:?}\nPath:{}\nValue:{:?}",
+            self.address, self.path, self.value
+        )
+    }
+}
+/// An account removed from the exchange's ledger by `removeAccount`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemovedAccount {
+    /// The address (or disambiguated key) of the removed account
+    pub address: String,
+    /// The number of rows removed from the ledger
+    pub rows: usize,
+    /// The hex encoded merkle root of the updated ledger, empty if no account is left
+    pub root: String,
+}
+impl fmt::Display for RemovedAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Removed {} row(s) of {:?}", self.rows, self.address)
+    }
+}
+/// An account whose balance was set by `setBalance`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BalanceUpdate {
+    /// The address (or disambiguated key) of the account
+    pub address: String,
+    /// The new balance of the account
+    pub value: u64,
+    /// The hex encoded merkle root of the updated ledger
+    pub root: String,
+}
+impl fmt::Display for BalanceUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address:{:?}\nNew Value:{:?}", self.address, self.value)
+    }
+}
+/// The accounts appended to the exchange's ledger by `importAccounts`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedAccounts {
+    /// The CSV file the accounts were read from
+    pub file: String,
+    /// The number of accounts imported
+    pub count: usize,
+    /// The hex encoded merkle root of the updated ledger
+    pub root: String,
+}
+impl fmt::Display for ImportedAccounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Imported {} account(s) from {}", self.count, self.file)
+    }
+}
+/// Create a new SECP256K1 secret key and save it to the keystore of `keys` under `name`
+///
+/// # Errors
+///
+/// Returns an error if the name is invalid or already taken, or if the keystore can't be
+/// written.
+pub fn create_private_key(keys: &KeyManager, name: &str) -> Result<ManagedKey, KeyError> {
+    keys.create_key(name)
 }
 /// Create a Random Number Generator (RNG) from a provided
 /// seed value
@@ -44,56 +233,3 @@ pub fn create_new_tree_from_file(filename: &str) -> MerkleTree<Sha256> {
     }
     MerkleTree::<Sha256>::from_leaves(&new_leaves)
 }
-
-/// The table of commands, descriptions, and usage
-pub fn cmd_table() {
-    let mut table = Table::new();
-    table
-            .load_preset(UTF8_FULL)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_width(80)
-            .set_header(vec![
-                Cell::new("Command").add_attribute(Attribute::Bold),
-                Cell::new("Description").add_attribute(Attribute::Bold),
-                Cell::new("Usage").add_attribute(Attribute::Bold),
-            ])
-            .add_row(vec![
-                Cell::new("?").add_attribute(Attribute::Bold),
-                Cell::new("Print this command table"),
-                Cell::new("Usage: `?`"),
-            ])
-            .add_row(vec![
-                Cell::new("addCoinToDB").add_attribute(Attribute::Bold),
-                Cell::new("Append a new coin to the CSV or SQL table given a particular value by autogenerating a new address"),
-                Cell::new("Usage: `addCoinToDB <VALUE>`"),
-            ]).add_row(vec![
-                Cell::new("clear").add_attribute(Attribute::Bold),
-                Cell::new("Clear the screen"),
-                Cell::new("Usage: `clear`"),
-            ]).add_row(vec![
-                Cell::new("createPrivateKey").add_attribute(Attribute::Bold),
-                Cell::new("Create a private key to be saved to the database"),
-                Cell::new("Usage: `createPrivateKey`"),
-            ]).add_row(vec![
-                Cell::new("createRNG").add_attribute(Attribute::Bold),
-                Cell::new("Given a seed value, create a RNG and save it to the database"),
-                Cell::new("Usage: `createRNG <SEED>`"),
-            ]).add_row(vec![
-                Cell::new("exit").add_attribute(Attribute::Bold),
-                Cell::new("Exit the shell"),
-                Cell::new("Usage: `exit`"),
-            ]).add_row(vec![
-                Cell::new("help").add_attribute(Attribute::Bold),
-                Cell::new("Print this command table"),
-                Cell::new("Usage: `help`"),
-            ]).add_row(vec![
-                Cell::new("proveMembership").add_attribute(Attribute::Bold),
-                Cell::new("Prove that the provided address is/isn't a member of the merkle tree"),
-                Cell::new("Usage: `proveMembership <ADDRESS>`"),
-            ]).add_row(vec![
-                Cell::new("showFile").add_attribute(Attribute::Bold),
-                Cell::new("Preview the file loaded into the shell"),
-                Cell::new("Usage: `showFile`"),
-            ]);
-    println!("{table}");
-}
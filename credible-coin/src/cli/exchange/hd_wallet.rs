@@ -0,0 +1,214 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::Network;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv, Xpub};
+use bitcoin::secp256k1::Secp256k1;
+use serde::Serialize;
+
+use crate::cli::exchange::key_manager::ManagedKey;
+use crate::errors::KeyError;
+use crate::utils::bitcoin_utils::ScriptType;
+
+/// The BIP43 purposes the exchange derives addresses under, one for each [`ScriptType`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Purpose {
+    /// BIP44, `m/44'/...`, for P2PKH addresses
+    Bip44,
+    /// BIP49, `m/49'/...`, for P2SH-P2WPKH addresses
+    Bip49,
+    /// BIP84, `m/84'/...`, for P2WPKH addresses
+    Bip84,
+    /// BIP86, `m/86'/...`, for single key P2TR addresses
+    Bip86,
+}
+impl Purpose {
+    /// Every purpose, in the order of [`ScriptType::ALL`]
+    pub const ALL: [Purpose; 4] = [
+        Purpose::Bip44,
+        Purpose::Bip49,
+        Purpose::Bip84,
+        Purpose::Bip86,
+    ];
+    /// The purpose addresses of the given script type are derived under
+    pub fn for_script_type(script_type: ScriptType) -> Self {
+        match script_type {
+            ScriptType::P2pkh => Purpose::Bip44,
+            ScriptType::P2shP2wpkh => Purpose::Bip49,
+            ScriptType::P2wpkh => Purpose::Bip84,
+            ScriptType::P2tr => Purpose::Bip86,
+        }
+    }
+    /// The script type of the addresses derived under the purpose
+    pub fn script_type(self) -> ScriptType {
+        match self {
+            Purpose::Bip44 => ScriptType::P2pkh,
+            Purpose::Bip49 => ScriptType::P2shP2wpkh,
+            Purpose::Bip84 => ScriptType::P2wpkh,
+            Purpose::Bip86 => ScriptType::P2tr,
+        }
+    }
+    /// The number of the purpose, the first (hardened) level of its paths
+    pub fn number(self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+        }
+    }
+}
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bip{}", self.number())
+    }
+}
+impl FromStr for Purpose {
+    type Err = KeyError;
+
+    /// Parse a purpose from its name (`bip84`) or from the script type of its addresses
+    /// (`p2wpkh`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        if let Some(purpose) = Purpose::ALL
+            .into_iter()
+            .find(|purpose| purpose.to_string() == name)
+        {
+            return Ok(purpose);
+        }
+        match name.parse::<ScriptType>() {
+            Ok(script_type) => Ok(Purpose::for_script_type(script_type)),
+            Err(_) => Err(KeyError::UnknownPurpose(s.to_owned())),
+        }
+    }
+}
+/// An address derived from an [`HdWallet`], along with where it was derived from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedAddress {
+    /// The full path of the address' key, e.g. `m/84'/0'/0'/0/7`
+    pub path: DerivationPath,
+    /// The index of the address in its chain, the last level of its path
+    pub index: u32,
+    /// The encoded address
+    pub address: String,
+    /// The key of the address, which can sign for it
+    pub key: ManagedKey,
+}
+/// A BIP32 wallet rooted at one of the exchange's keys.
+///
+/// The 32 bytes of the key are used as the BIP32 seed, so every key the exchange stores is also
+/// the root of a tree of keys. Addresses are derived along the standard paths of their
+/// [`Purpose`]: `m/purpose'/coin'/account'/change/index`, where `coin` is 0 on mainnet and 1 on
+/// every test network. Deposit addresses are on the external chain (`change` = 0).
+///
+/// # Examples
+///
+/// ```
+/// use bitcoin::Network;
+/// use credible_coin::cli::exchange::hd_wallet::{HdWallet, Purpose};
+/// use credible_coin::cli::exchange::key_manager::ManagedKey;
+///
+/// let wallet = HdWallet::new(&ManagedKey::generate("default"), Network::Bitcoin).unwrap();
+/// let first = wallet.derive(Purpose::Bip84, 0, 0).unwrap();
+/// let second = wallet.derive(Purpose::Bip84, 0, 1).unwrap();
+/// assert_eq!(first.path.to_string(), "m/84'/0'/0'/0/0");
+/// assert!(first.address.starts_with("bc1q"));
+/// assert_ne!(first.address, second.address);
+/// ```
+#[derive(Clone)]
+pub struct HdWallet {
+    name: String,
+    master: Xpriv,
+}
+impl fmt::Debug for HdWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdWallet")
+            .field("name", &self.name)
+            .field("network", &self.master.network)
+            .finish_non_exhaustive()
+    }
+}
+impl HdWallet {
+    /// The wallet rooted at `key`, deriving addresses for `network`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the astronomically unlikely case that the key isn't a valid seed.
+    pub fn new(key: &ManagedKey, network: Network) -> Result<Self, KeyError> {
+        Ok(Self {
+            name: key.name().to_owned(),
+            master: Xpriv::new_master(network, &key.secret_key().secret_bytes())?,
+        })
+    }
+    /// The network the wallet derives addresses for
+    pub fn network(&self) -> Network {
+        self.master.network
+    }
+    /// The path of an account, `m/purpose'/coin'/account'`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `account` doesn't fit in 31 bits.
+    pub fn account_path(&self, purpose: Purpose, account: u32) -> Result<DerivationPath, KeyError> {
+        let coin = if self.master.network == Network::Bitcoin {
+            0
+        } else {
+            1
+        };
+        Ok(DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(purpose.number())?,
+            ChildNumber::from_hardened_idx(coin)?,
+            ChildNumber::from_hardened_idx(account)?,
+        ]))
+    }
+    /// The extended public key of an account, which watch-only wallets can derive its
+    /// addresses from
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `account` doesn't fit in 31 bits.
+    pub fn account_xpub(&self, purpose: Purpose, account: u32) -> Result<Xpub, KeyError> {
+        let secp = Secp256k1::signing_only();
+        let xpriv = self
+            .master
+            .derive_priv(&secp, &self.account_path(purpose, account)?)?;
+        Ok(Xpub::from_priv(&secp, &xpriv))
+    }
+    /// Derive the deposit address at `index` of an account
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `account` or `index` doesn't fit in 31 bits.
+    pub fn derive(
+        &self,
+        purpose: Purpose,
+        account: u32,
+        index: u32,
+    ) -> Result<DerivedAddress, KeyError> {
+        let path = self.account_path(purpose, account)?.extend([
+            ChildNumber::from_normal_idx(0)?,
+            ChildNumber::from_normal_idx(index)?,
+        ]);
+        let xpriv = self.master.derive_priv(&Secp256k1::signing_only(), &path)?;
+        let key = ManagedKey::new(&format!("{}:{}", self.name, path), xpriv.private_key);
+        Ok(DerivedAddress {
+            address: key.address(purpose.script_type(), self.network())?,
+            index,
+            path,
+            key,
+        })
+    }
+    /// The name of the counter handing out the indices of an account's deposit addresses, e.g.
+    /// `default:m/84'/0'/0'/0`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `account` doesn't fit in 31 bits.
+    pub fn counter(&self, purpose: Purpose, account: u32) -> Result<String, KeyError> {
+        Ok(format!(
+            "{}:{}/0",
+            self.name,
+            self.account_path(purpose, account)?
+        ))
+    }
+}
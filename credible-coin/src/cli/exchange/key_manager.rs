@@ -0,0 +1,306 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use bitcoin::{Address, Network, PublicKey};
+
+use crate::cli::exchange::hd_wallet::{DerivedAddress, HdWallet, Purpose};
+use crate::cli::exchange::keystore::{Keystore, open_configured_keystore};
+use crate::errors::KeyError;
+use crate::utils::bitcoin_utils::{ScriptType, address_from_public_key};
+
+/// The name of the key `addCoinToDB` derives the addresses of new coins from
+pub const DEFAULT_KEY: &str = "default";
+
+/// A secret key of the exchange along with the name it is stored under.
+///
+/// The public key, the addresses, and the signatures of the key are all derived from the secret
+/// key, so the exchange can prove it controls every address it reports. The [`fmt::Debug`]
+/// implementation never prints the secret key.
+///
+/// # Examples
+///
+/// ```
+/// use bitcoin::Network;
+/// use credible_coin::cli::exchange::key_manager::{ManagedKey, verify_message};
+/// use credible_coin::utils::bitcoin_utils::ScriptType;
+///
+/// let key = ManagedKey::generate("cold-wallet");
+/// let address = key.address(ScriptType::P2pkh, Network::Bitcoin).unwrap();
+/// let signature = key.sign_message("We control this address");
+/// assert!(verify_message(&address, "We control this address", &signature).unwrap());
+/// assert!(!verify_message(&address, "Something else", &signature).unwrap());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ManagedKey {
+    name: String,
+    secret_key: SecretKey,
+}
+impl fmt::Debug for ManagedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagedKey")
+            .field("name", &self.name)
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+impl ManagedKey {
+    /// Wrap an existing secret key under `name`
+    pub fn new(name: &str, secret_key: SecretKey) -> Self {
+        Self {
+            name: name.to_owned(),
+            secret_key,
+        }
+    }
+    /// Generate a new random secret key under `name`
+    pub fn generate(name: &str) -> Self {
+        Self::new(name, SecretKey::new(&mut rand::thread_rng()))
+    }
+    /// Read a secret key from the bytes it is stored as
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Corrupt`] if the bytes aren't a valid secret key.
+    pub fn from_slice(name: &str, bytes: &[u8]) -> Result<Self, KeyError> {
+        let secret_key =
+            SecretKey::from_slice(bytes).map_err(|err| KeyError::Corrupt(name.to_owned(), err))?;
+        Ok(Self::new(name, secret_key))
+    }
+    /// The name the key is stored under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The secret key itself
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+    /// The compressed public key of the secret key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.secret_key.public_key(&Secp256k1::signing_only()))
+    }
+    /// Encode the public key of the key as an address of the given script type on the given
+    /// network
+    ///
+    /// # Errors
+    ///
+    /// The public key is always compressed, so this only fails if `bitcoin` can't encode it.
+    pub fn address(&self, script_type: ScriptType, network: Network) -> Result<String, KeyError> {
+        Ok(address_from_public_key(
+            &self.public_key(),
+            script_type,
+            network,
+        )?)
+    }
+    /// Sign `message` the way Bitcoin Core's `signmessage` does, so the signature can be checked
+    /// against the P2PKH address of the key with [`verify_message`] or `verifymessage`
+    pub fn sign_message(&self, message: &str) -> MessageSignature {
+        let digest = Message::from_digest(signed_msg_hash(message).to_byte_array());
+        let signature = Secp256k1::signing_only().sign_ecdsa_recoverable(&digest, &self.secret_key);
+        MessageSignature::new(signature, true)
+    }
+}
+/// Check that `signature` is a `signmessage` signature of `message` by the key of `address`.
+///
+/// # Errors
+///
+/// Returns an error if the address can't be parsed, or if it isn't a P2PKH address, the only
+/// kind `signmessage` signatures can be checked against.
+pub fn verify_message(
+    address: &str,
+    message: &str,
+    signature: &MessageSignature,
+) -> Result<bool, KeyError> {
+    let address = Address::from_str(address)?.assume_checked();
+    Ok(signature.is_signed_by_address(
+        &Secp256k1::verification_only(),
+        &address,
+        signed_msg_hash(message),
+    )?)
+}
+/// Encode a signature as base64, the format `signmessage` prints
+pub fn encode_signature(signature: &MessageSignature) -> String {
+    BASE64_STANDARD.encode(signature.serialize())
+}
+/// Decode a base64 signature as printed by `signmessage`
+///
+/// # Errors
+///
+/// Returns an error if the string isn't base64 or doesn't hold a valid signature.
+pub fn decode_signature(signature: &str) -> Result<MessageSignature, KeyError> {
+    let bytes = BASE64_STANDARD.decode(signature)?;
+    Ok(MessageSignature::from_slice(&bytes)?)
+}
+/// Names become Redis hash fields and show up in shell commands, so they are kept to a safe set
+/// of characters
+fn check_name(name: &str) -> Result<(), KeyError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(KeyError::InvalidName(name.to_owned()));
+    }
+    Ok(())
+}
+/// Stores the secret keys of the exchange in a [`Keystore`] under the names they were created
+/// with, and loads them back to derive addresses and sign with.
+///
+/// A key which isn't stored is always reported as [`KeyError::Missing`]: nothing generates a key
+/// on the fly in its place, since the exchange couldn't sign for the addresses derived from it.
+#[derive(Debug)]
+pub struct KeyManager {
+    keystore: Box<dyn Keystore>,
+}
+impl KeyManager {
+    /// A key manager for the keys in the provided keystore
+    pub fn new(keystore: Box<dyn Keystore>) -> Self {
+        Self { keystore }
+    }
+    /// A key manager for the keystore selected in `credible_config`, see
+    /// [`open_configured_keystore`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be opened.
+    pub fn from_config() -> Result<Self, KeyError> {
+        Ok(Self::new(open_configured_keystore()?))
+    }
+    /// Generate a new key and store it under `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or already taken, or if the key can't be stored.
+    pub fn create_key(&self, name: &str) -> Result<ManagedKey, KeyError> {
+        let key = ManagedKey::generate(name);
+        self.store(&key)?;
+        Ok(key)
+    }
+    /// Store an existing secret key under `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or already taken, or if the key can't be stored.
+    pub fn import_key(&self, name: &str, secret_key: SecretKey) -> Result<ManagedKey, KeyError> {
+        let key = ManagedKey::new(name, secret_key);
+        self.store(&key)?;
+        Ok(key)
+    }
+    fn store(&self, key: &ManagedKey) -> Result<(), KeyError> {
+        check_name(key.name())?;
+        if !self
+            .keystore
+            .insert(key.name(), &key.secret_key().secret_bytes())?
+        {
+            return Err(KeyError::Exists(key.name().to_owned()));
+        }
+        Ok(())
+    }
+    /// Load the key stored under `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn key(&self, name: &str) -> Result<ManagedKey, KeyError> {
+        check_name(name)?;
+        let bytes = self
+            .keystore
+            .get(name)?
+            .ok_or_else(|| KeyError::Missing(name.to_owned()))?;
+        ManagedKey::from_slice(name, &bytes)
+    }
+    /// The public key of the key stored under `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn public_key(&self, name: &str) -> Result<PublicKey, KeyError> {
+        Ok(self.key(name)?.public_key())
+    }
+    /// The address of the given script type and network of the key stored under `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn address(
+        &self,
+        name: &str,
+        script_type: ScriptType,
+        network: Network,
+    ) -> Result<String, KeyError> {
+        self.key(name)?.address(script_type, network)
+    }
+    /// Sign `message` with the key stored under `name`, see [`ManagedKey::sign_message`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn sign_message(&self, name: &str, message: &str) -> Result<MessageSignature, KeyError> {
+        Ok(self.key(name)?.sign_message(message))
+    }
+    /// The names of every stored key, sorted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys can't be listed.
+    pub fn list_keys(&self) -> Result<Vec<String>, KeyError> {
+        let mut names = self.keystore.names()?;
+        names.sort();
+        Ok(names)
+    }
+    /// The BIP32 wallet rooted at the key stored under `name`, see [`HdWallet`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn wallet(&self, name: &str, network: Network) -> Result<HdWallet, KeyError> {
+        HdWallet::new(&self.key(name)?, network)
+    }
+    /// Derive the next unused deposit address of an account of the wallet rooted at the key
+    /// stored under `name`.
+    ///
+    /// The index of the address is reserved in the keystore before the address is derived, so it
+    /// is never handed out twice, even to two shells sharing the keystore.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if the index can't be
+    /// reserved.
+    pub fn next_address(
+        &self,
+        name: &str,
+        purpose: Purpose,
+        account: u32,
+        network: Network,
+    ) -> Result<DerivedAddress, KeyError> {
+        let wallet = self.wallet(name, network)?;
+        let index = self
+            .keystore
+            .reserve_index(&wallet.counter(purpose, account)?)?;
+        wallet.derive(purpose, account, index)
+    }
+    /// Every deposit address handed out by [`KeyManager::next_address`] for an account, in the
+    /// order they were handed out
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if the keystore can't
+    /// be read.
+    pub fn issued_addresses(
+        &self,
+        name: &str,
+        purpose: Purpose,
+        account: u32,
+        network: Network,
+    ) -> Result<Vec<DerivedAddress>, KeyError> {
+        let wallet = self.wallet(name, network)?;
+        let issued = self
+            .keystore
+            .next_index(&wallet.counter(purpose, account)?)?;
+        (0..issued)
+            .map(|index| wallet.derive(purpose, account, index))
+            .collect()
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::cli::exchange::db_connector::{
+    ExchangeNamespace, PooledConnection, RedisPool, exchange_pool, insert_secret_key,
+    list_key_names, peek_index, remove_secret_key, reserve_index, retrieve_secret_key,
+};
+use crate::credible_config::{KeystoreConfig, get_keystore_config};
+use crate::errors::{DBConnectorError, KeyError};
+use crate::utils::atomic_file::{FileLock, write_atomically};
+
+/// The environment variable the passphrase of an [`EncryptedFileKeystore`] is read from
+pub const PASSPHRASE_VAR: &str = "CREDIBLE_KEYSTORE_PASSPHRASE";
+
+/// Somewhere the secret keys of the exchange are kept, by name.
+///
+/// Keys are never overwritten, so that a key which addresses were already derived from can't be
+/// lost by reusing its name. The [`KeyManager`](crate::cli::exchange::key_manager::KeyManager)
+/// works on top of any keystore; which one the exchange uses is chosen by the `keystore` section
+/// of `credible_config`, see [`open_configured_keystore`].
+pub trait Keystore: fmt::Debug + Send {
+    /// Store `secret_key` under `name`, returning `false` without writing anything if a key
+    /// with this name already exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be written.
+    fn insert(&self, name: &str, secret_key: &[u8]) -> Result<bool, KeyError>;
+    /// The secret key stored under `name`, if there is one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be read or the key can't be decrypted.
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, KeyError>;
+    /// The names of every stored key, in no particular order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be read.
+    fn names(&self) -> Result<Vec<String>, KeyError>;
+    /// Remove the key stored under `name`, returning whether there was one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be written.
+    fn remove(&self, name: &str) -> Result<bool, KeyError>;
+    /// Hand out the next index of the counter called `counter` (starting at 0), so that every
+    /// caller is given a different one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be written.
+    fn reserve_index(&self, counter: &str) -> Result<u32, KeyError>;
+    /// The index the counter called `counter` will hand out next, i.e. how many it handed out
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keystore can't be read.
+    fn next_index(&self, counter: &str) -> Result<u32, KeyError>;
+}
+/// Open the keystore selected in `credible_config`, an encrypted file by default.
+///
+/// The passphrase of an encrypted file is read from [`PASSPHRASE_VAR`], or asked for if the
+/// exchange is run from a terminal. The Redis keystore holds the keys in plaintext, so it is only
+/// opened if the configuration sets `allow_plaintext`.
+///
+/// # Errors
+///
+/// Returns [`KeyError::PlaintextKeystore`] if the Redis keystore wasn't allowed, or an error if
+/// the configuration is invalid, if no passphrase is available, or if the keystore can't be
+/// opened.
+pub fn open_configured_keystore() -> Result<Box<dyn Keystore>, KeyError> {
+    match get_keystore_config()? {
+        KeystoreConfig::Redis {
+            allow_plaintext: true,
+        } => Ok(Box::new(RedisKeystore::new()?)),
+        KeystoreConfig::Redis {
+            allow_plaintext: false,
+        } => Err(KeyError::PlaintextKeystore),
+        KeystoreConfig::File { path } => {
+            let passphrase = read_passphrase(&path)?;
+            Ok(Box::new(EncryptedFileKeystore::open(path, &passphrase)?))
+        }
+    }
+}
+fn read_passphrase(path: &Path) -> Result<Zeroizing<String>, KeyError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(KeyError::NoPassphrase);
+    }
+    let prompt = format!("Passphrase for {}: ", path.display());
+    Ok(Zeroizing::new(rpassword::prompt_password(prompt)?))
+}
+/// The keys in the exchange's Redis instance, stored as they are under the namespace of the
+/// exchange. Redis should only be used when the instance itself is protected, e.g. with disk
+/// encryption.
+#[derive(Debug, Clone)]
+pub struct RedisKeystore {
+    pool: RedisPool,
+    namespace: ExchangeNamespace,
+}
+impl RedisKeystore {
+    /// A keystore for the exchange's Redis instance and namespace, as set in `credible_config`.
+    /// The instance is only connected to when a key is stored or loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `redis` section of the configuration is invalid.
+    pub fn new() -> Result<Self, KeyError> {
+        Ok(Self::with_pool(
+            exchange_pool()?,
+            ExchangeNamespace::from_config()?,
+        ))
+    }
+    /// A keystore for the keys of `namespace` in the instance of `pool`
+    pub fn with_pool(pool: RedisPool, namespace: ExchangeNamespace) -> Self {
+        Self { pool, namespace }
+    }
+    fn connection(&self) -> Result<PooledConnection, KeyError> {
+        Ok(self.pool.get().map_err(DBConnectorError::from)?)
+    }
+}
+impl Keystore for RedisKeystore {
+    fn insert(&self, name: &str, secret_key: &[u8]) -> Result<bool, KeyError> {
+        Ok(insert_secret_key(
+            &mut *self.connection()?,
+            &self.namespace,
+            name,
+            secret_key,
+        )?)
+    }
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, KeyError> {
+        Ok(
+            retrieve_secret_key(&mut *self.connection()?, &self.namespace, name)?
+                .map(Zeroizing::new),
+        )
+    }
+    fn names(&self) -> Result<Vec<String>, KeyError> {
+        Ok(list_key_names(&mut *self.connection()?, &self.namespace)?)
+    }
+    fn remove(&self, name: &str) -> Result<bool, KeyError> {
+        Ok(remove_secret_key(
+            &mut *self.connection()?,
+            &self.namespace,
+            name,
+        )?)
+    }
+    fn reserve_index(&self, counter: &str) -> Result<u32, KeyError> {
+        Ok(reserve_index(
+            &mut *self.connection()?,
+            &self.namespace,
+            counter,
+        )?)
+    }
+    fn next_index(&self, counter: &str) -> Result<u32, KeyError> {
+        Ok(peek_index(
+            &mut *self.connection()?,
+            &self.namespace,
+            counter,
+        )?)
+    }
+}
+/// The prefix of the associated data of the sealed check value, which tells a wrong passphrase
+/// apart from a damaged key. It is followed by the file's [`KdfParams`], so they can't be changed
+/// without the passphrase either.
+const CHECK_AAD: &[u8] = b"credible-coin keystore";
+/// The prefix of the associated data of the value sealed over the derivation indices, which are
+/// appended to it
+const INDICES_AAD: &[u8] = b"credible-coin keystore indices";
+/// The version of the file format. Version 1 didn't authenticate the KDF parameters and indices.
+const FILE_VERSION: u32 = 2;
+
+/// The Argon2id parameters the encryption key of a file was derived with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct KdfParams {
+    /// The base64 encoded random salt of the file
+    salt: String,
+    /// The memory cost, in KiB
+    memory_kib: u32,
+    /// The number of passes over the memory
+    iterations: u32,
+    /// The number of lanes
+    parallelism: u32,
+}
+/// A value encrypted with XChaCha20-Poly1305
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Sealed {
+    /// The base64 encoded random 24 byte nonce
+    nonce: String,
+    /// The base64 encoded ciphertext, followed by its 16 byte tag
+    ciphertext: String,
+}
+/// The contents of an encrypted keystore file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u32,
+    kdf: KdfParams,
+    /// An empty value sealed with [`CHECK_AAD`] and `kdf`, which only opens with the right
+    /// passphrase
+    check: Sealed,
+    /// The secret keys, each sealed with its name as associated data so it can't be moved to
+    /// another name
+    keys: BTreeMap<String, Sealed>,
+    /// The next index of each derivation counter. These aren't secret, so they are kept in
+    /// plaintext.
+    #[serde(default)]
+    indices: BTreeMap<String, u32>,
+    /// An empty value sealed with [`INDICES_AAD`] and `indices`, so a counter can't be edited
+    /// back to hand out an address twice
+    indices_check: Sealed,
+}
+/// The keys of the exchange in a JSON file, each encrypted with XChaCha20-Poly1305 under a key
+/// derived from a passphrase with Argon2id. Only the names of the keys are stored in plaintext.
+///
+/// The file is rewritten atomically and under its [`FileLock`], so two shells can add keys to it
+/// at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::cli::exchange::keystore::{EncryptedFileKeystore, Keystore};
+///
+/// let path = std::env::temp_dir().join("keystore_doctest.json");
+/// # let _ = std::fs::remove_file(&path);
+/// let keystore = EncryptedFileKeystore::open(&path, "correct horse").unwrap();
+/// assert!(keystore.insert("hot", &[7; 32]).unwrap());
+/// assert_eq!(keystore.get("hot").unwrap().unwrap().as_slice(), &[7; 32]);
+/// assert!(!std::fs::read(&path).unwrap().windows(32).any(|bytes| bytes == [7; 32]));
+///
+/// assert!(EncryptedFileKeystore::open(&path, "wrong horse").is_err());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct EncryptedFileKeystore {
+    path: PathBuf,
+    cipher: XChaCha20Poly1305,
+}
+impl fmt::Debug for EncryptedFileKeystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileKeystore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+impl EncryptedFileKeystore {
+    /// Open the keystore file at `path` with `passphrase`, creating an empty one if there is no
+    /// file yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::WrongPassphrase`] if the file was created with another passphrase, or
+    /// an error if it can't be read, parsed, or created.
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self, KeyError> {
+        let path = path.as_ref().to_owned();
+        let _lock = FileLock::exclusive(&path)?;
+        if let Some(file) = read_file(&path)? {
+            let cipher = derive_cipher(passphrase, &file.kdf)?;
+            open_sealed(&cipher, &file.check, &check_aad(&file.kdf)?)
+                .ok_or(KeyError::WrongPassphrase)?;
+            return Ok(Self { path, cipher });
+        }
+        let mut salt = [0; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let kdf = KdfParams {
+            salt: BASE64_STANDARD.encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        };
+        let cipher = derive_cipher(passphrase, &kdf)?;
+        let indices = BTreeMap::new();
+        let file = KeystoreFile {
+            version: FILE_VERSION,
+            check: seal(&cipher, &[], &check_aad(&kdf)?)?,
+            kdf,
+            keys: BTreeMap::new(),
+            indices_check: seal(&cipher, &[], &indices_aad(&indices)?)?,
+            indices,
+        };
+        write_file(&path, &file)?;
+        Ok(Self { path, cipher })
+    }
+    /// The path of the keystore file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Read the file, which must exist once the keystore is open, checking its indices
+    fn read(&self) -> Result<KeystoreFile, KeyError> {
+        let file = read_file(&self.path)?.ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} was removed", self.path.display()),
+            )
+        })?;
+        open_sealed(
+            &self.cipher,
+            &file.indices_check,
+            &indices_aad(&file.indices)?,
+        )
+        .ok_or(KeyError::TamperedIndices)?;
+        Ok(file)
+    }
+}
+impl Keystore for EncryptedFileKeystore {
+    fn insert(&self, name: &str, secret_key: &[u8]) -> Result<bool, KeyError> {
+        let _lock = FileLock::exclusive(&self.path)?;
+        let mut file = self.read()?;
+        if file.keys.contains_key(name) {
+            return Ok(false);
+        }
+        file.keys.insert(
+            name.to_owned(),
+            seal(&self.cipher, secret_key, name.as_bytes())?,
+        );
+        write_file(&self.path, &file)?;
+        Ok(true)
+    }
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, KeyError> {
+        let file = self.read()?;
+        let Some(sealed) = file.keys.get(name) else {
+            return Ok(None);
+        };
+        open_sealed(&self.cipher, sealed, name.as_bytes())
+            .map(Some)
+            .ok_or_else(|| KeyError::Undecryptable(name.to_owned()))
+    }
+    fn names(&self) -> Result<Vec<String>, KeyError> {
+        Ok(self.read()?.keys.into_keys().collect())
+    }
+    fn remove(&self, name: &str) -> Result<bool, KeyError> {
+        let _lock = FileLock::exclusive(&self.path)?;
+        let mut file = self.read()?;
+        if file.keys.remove(name).is_none() {
+            return Ok(false);
+        }
+        write_file(&self.path, &file)?;
+        Ok(true)
+    }
+    fn reserve_index(&self, counter: &str) -> Result<u32, KeyError> {
+        let _lock = FileLock::exclusive(&self.path)?;
+        let mut file = self.read()?;
+        let next = file.indices.entry(counter.to_owned()).or_default();
+        let index = *next;
+        *next = index
+            .checked_add(1)
+            .ok_or_else(|| KeyError::IndicesExhausted(counter.to_owned()))?;
+        file.indices_check = seal(&self.cipher, &[], &indices_aad(&file.indices)?)?;
+        write_file(&self.path, &file)?;
+        Ok(index)
+    }
+    fn next_index(&self, counter: &str) -> Result<u32, KeyError> {
+        Ok(self
+            .read()?
+            .indices
+            .get(counter)
+            .copied()
+            .unwrap_or_default())
+    }
+}
+fn read_file(path: &Path) -> Result<Option<KeystoreFile>, KeyError> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let file: KeystoreFile = serde_json::from_slice(&contents)?;
+    if file.version != FILE_VERSION {
+        return Err(KeyError::UnsupportedVersion(file.version));
+    }
+    Ok(Some(file))
+}
+fn write_file(path: &Path, file: &KeystoreFile) -> Result<(), KeyError> {
+    let contents = serde_json::to_vec_pretty(file)?;
+    write_atomically(path, |out| out.write_all(&contents))?;
+    Ok(())
+}
+/// The associated data of the check value of a file with these parameters
+fn check_aad(kdf: &KdfParams) -> Result<Vec<u8>, KeyError> {
+    Ok([CHECK_AAD, &serde_json::to_vec(kdf)?].concat())
+}
+/// The associated data of the value sealed over these indices
+fn indices_aad(indices: &BTreeMap<String, u32>) -> Result<Vec<u8>, KeyError> {
+    Ok([INDICES_AAD, &serde_json::to_vec(indices)?].concat())
+}
+/// The most each Argon2 parameter of a keystore file may be, as a multiple of its default. The
+/// parameters are only authenticated once the key is derived, so without a bound a tampered
+/// file could ask for more memory or time than the exchange has.
+const MAX_KDF_COST_FACTOR: u32 = 4;
+fn derive_cipher(passphrase: &str, kdf: &KdfParams) -> Result<XChaCha20Poly1305, KeyError> {
+    let bounds = [
+        ("memory_kib", kdf.memory_kib, Params::DEFAULT_M_COST),
+        ("iterations", kdf.iterations, Params::DEFAULT_T_COST),
+        ("parallelism", kdf.parallelism, Params::DEFAULT_P_COST),
+    ];
+    for (name, value, default) in bounds {
+        let max = default * MAX_KDF_COST_FACTOR;
+        if value > max {
+            return Err(KeyError::KdfOutOfBounds(format!(
+                "{name} is {value}, at most {max} is allowed"
+            )));
+        }
+    }
+    let salt = BASE64_STANDARD.decode(&kdf.salt)?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))?;
+    let mut key = Zeroizing::new([0; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+        passphrase.as_bytes(),
+        &salt,
+        key.as_mut(),
+    )?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(key.as_ref())))
+}
+fn seal(cipher: &XChaCha20Poly1305, plaintext: &[u8], aad: &[u8]) -> Result<Sealed, KeyError> {
+    let mut nonce = [0; 24];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(KeyError::Encryption)?;
+    Ok(Sealed {
+        nonce: BASE64_STANDARD.encode(nonce),
+        ciphertext: BASE64_STANDARD.encode(ciphertext),
+    })
+}
+/// Decrypt a sealed value, or `None` if it was sealed with another key or associated data, or
+/// was tampered with
+fn open_sealed(
+    cipher: &XChaCha20Poly1305,
+    sealed: &Sealed,
+    aad: &[u8],
+) -> Option<Zeroizing<Vec<u8>>> {
+    let nonce = BASE64_STANDARD.decode(&sealed.nonce).ok()?;
+    let ciphertext = BASE64_STANDARD.decode(&sealed.ciphertext).ok()?;
+    if nonce.len() != 24 {
+        return None;
+    }
+    cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad,
+            },
+        )
+        .ok()
+        .map(Zeroizing::new)
+}
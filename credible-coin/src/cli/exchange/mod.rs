@@ -1,6 +1,9 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
+use crate::cli::{convert, diff};
+use crate::cli::output::{OutputFormat, set_output_format};
+
 /// The "Asset Database" represents the CSV file the exchange uses for
 /// its secret storage
 /// This module holds all of the functions to be able to create and
@@ -10,8 +13,22 @@ pub mod asset_database;
 /// A helper module to connect to a running Redis instance to
 /// store exchange private keys and other sensitive data
 pub mod db_connector;
+/// BIP32 wallets rooted at the exchange's keys, deriving a distinct deposit address for every
+/// account
+pub mod hd_wallet;
+/// The secret keys of the exchange: creating and storing them under names, deriving their public
+/// keys and addresses, and signing with them
+pub mod key_manager;
+/// Where the secret keys of the exchange are kept: its Redis instance, or a file encrypted with a
+/// passphrase
+pub mod keystore;
+/// Proving the exchange controls the addresses of its ledger by signing an auditor's challenge
+/// with the key of each of them
+pub mod proof_of_reserves;
 /// Various helper utilities for the exchange shell
 pub mod exchange_functions;
+/// Serving the inclusion proofs of the exchange's customers over HTTP
+pub mod serve;
 /// The core logic of the exchange shell
 mod shell;
 const VERSION: &str = "0.0.1";
@@ -34,29 +51,49 @@ static HELP_TEMPLATE: &str = "\
 /// The main field is the cmd, which represent the type of command
 /// to be run:
 /// - Create: Create a new database csv file based on the publisher's database csv but with new addresses
-/// - Load: Load the database from a csv file into a merkle tree in memory
+/// - Load: Load the database from a csv file or SQLite database into a merkle tree in memory
+/// - Convert: Convert the database between the csv, binary ledger and SQLite formats
+/// - Diff: Compare two versions of the database
+/// - Apply: Apply a signed change set saved by `diff` to a copy of the old database
+/// - Serve: Serve the customers' inclusion proofs and the latest solvency report over HTTP
 #[derive(Debug)]
 pub struct ExchangeCLI {
     #[command(subcommand)]
     cmd: ExchangeCmd,
+    /// How command results are reported: human readable text, or one JSON object per command
+    #[arg(long = "output", global = true, value_enum, default_value_t = OutputFormat::Human)]
+    output_format: OutputFormat,
 }
 impl ExchangeCLI {
     /// Runs the exchange cli and executes the command
     pub fn run(self) -> Result<()> {
+        set_output_format(self.output_format);
         self.cmd.run()
     }
 }
 /// The CLI subcommand to execute:
 /// - Create: Create a new database csv file based on the publisher's database csv but with new addresses
-/// - Load: Load the database from a csv file into a merkle tree in memory
+/// - Load: Load the database from a csv file or SQLite database into a merkle tree in memory
+/// - Convert: Convert the database between the csv, binary ledger and SQLite formats
+/// - Diff: Compare two versions of the database
+/// - Apply: Apply a signed change set saved by `diff` to a copy of the old database
+/// - Serve: Serve the customers' inclusion proofs and the latest solvency report over HTTP
 #[derive(Subcommand, Debug)]
 pub enum ExchangeCmd {
     /// Create a new database csv file based on the publisher's database csv but with new addresses
     // TODO: This perhaps should call out to the python scripts that we made, since we have the constants for file
     //  generation pretty well defined in there
     Create(asset_database::CreateCmd),
-    /// Load the database from a csv file into a merkle tree in memory
+    /// Load the database from a csv file or SQLite database into a merkle tree in memory
     Load(asset_database::LoadCmd),
+    /// Convert the database between the csv, binary ledger and SQLite formats
+    Convert(convert::ConvertCmd),
+    /// Compare two versions of the database, listing the added, removed and modified entries
+    Diff(diff::DiffCmd),
+    /// Apply a signed change set saved by `diff` to a copy of the old database
+    Apply(diff::ApplyCmd),
+    /// Serve the customers' inclusion proofs and the latest solvency report over HTTP
+    Serve(serve::ServeCmd),
 }
 impl ExchangeCmd {
     pub(crate) fn run(self) -> Result<()> {
@@ -67,7 +104,19 @@ impl ExchangeCmd {
                 cmd.run();
             }
             Self::Load(cmd) => {
-                cmd.run();
+                cmd.run()?;
+            }
+            Self::Convert(cmd) => {
+                cmd.run()?;
+            }
+            Self::Diff(cmd) => {
+                cmd.run()?;
+            }
+            Self::Apply(cmd) => {
+                cmd.run()?;
+            }
+            Self::Serve(cmd) => {
+                cmd.run()?;
             }
         }
         Ok(())
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::address::AddressType;
+use bitcoin::{Address, Network};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::exchange::hd_wallet::Purpose;
+use crate::cli::exchange::key_manager::{
+    KeyManager, ManagedKey, decode_signature, encode_signature, verify_message,
+};
+use crate::errors::KeyError;
+use crate::ledger_store::LedgerStore;
+use crate::utils::address_utils::active_network;
+use crate::utils::bip322;
+use crate::utils::bitcoin_utils::ScriptType;
+
+/// How the ownership of an address was signed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    /// A Bitcoin Core `signmessage` signature, used for P2PKH addresses
+    Legacy,
+    /// A BIP-322 signature, used for P2WPKH, P2SH-P2WPKH and P2TR addresses
+    Bip322,
+}
+impl fmt::Display for SignatureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureFormat::Legacy => "legacy",
+            SignatureFormat::Bip322 => "bip322",
+        })
+    }
+}
+/// A signature of the challenge by the key of one of the exchange's addresses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipProof {
+    /// The address which was signed for
+    pub address: String,
+    /// The balance of the address in the ledger
+    pub value: i64,
+    /// The format of the signature
+    pub format: SignatureFormat,
+    /// The base64 encoded signature of the challenge
+    pub signature: String,
+}
+impl OwnershipProof {
+    /// Sign `challenge` for `address` with `key`, using a `signmessage` signature for P2PKH
+    /// addresses and a BIP-322 one for every other type
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `address` can't be parsed, isn't an address of `key`, or is of a type
+    /// messages can't be signed for.
+    pub fn sign(
+        key: &ManagedKey,
+        address: &str,
+        value: i64,
+        challenge: &str,
+    ) -> Result<Self, KeyError> {
+        let parsed = Address::from_str(address)?.assume_checked();
+        let (format, signature) = if parsed.address_type() == Some(AddressType::P2pkh) {
+            if !parsed.is_related_to_pubkey(&key.public_key()) {
+                return Err(KeyError::NotOwned(address.to_owned()));
+            }
+            (
+                SignatureFormat::Legacy,
+                encode_signature(&key.sign_message(challenge)),
+            )
+        } else {
+            (
+                SignatureFormat::Bip322,
+                bip322::sign(key.secret_key(), address, challenge)?,
+            )
+        };
+        Ok(Self {
+            address: address.to_owned(),
+            value,
+            format,
+            signature,
+        })
+    }
+    /// Check the signature against the address
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address or the signature can't be decoded, or if the address is
+    /// of a type the format can't be checked against.
+    pub fn verify(&self, challenge: &str) -> Result<bool, KeyError> {
+        match self.format {
+            SignatureFormat::Legacy => verify_message(
+                &self.address,
+                challenge,
+                &decode_signature(&self.signature)?,
+            ),
+            SignatureFormat::Bip322 => bip322::verify(&self.address, challenge, &self.signature),
+        }
+    }
+}
+/// The evidence that the exchange controls the addresses of its ledger: a signature of an
+/// auditor's challenge for every address it holds the key of, along with the balance of the
+/// address, as produced by `proveReserves`.
+///
+/// Auditors check it with [`verify_reserves`], and compare the balances against the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveProof {
+    /// The message every address signed, chosen by the auditor so old proofs can't be replayed
+    pub challenge: String,
+    /// The hex encoded merkle root of the ledger the balances were read from
+    pub root: String,
+    /// The sum of the balances of the proven addresses
+    pub total: i64,
+    /// A proof for every address of the ledger the exchange holds the key of
+    pub proofs: Vec<OwnershipProof>,
+    /// The addresses of the ledger the exchange holds no key of, and so can't prove
+    pub unproven: Vec<String>,
+}
+impl fmt::Display for ReserveProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Proved {} address(es) holding {} for challenge {:?}",
+            self.proofs.len(),
+            self.total,
+            self.challenge
+        )?;
+        if !self.unproven.is_empty() {
+            write!(
+                f,
+                "\n{} address(es) have no key and can't be proven",
+                self.unproven.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+/// Every address the exchange can sign for on `network`: the addresses of each stored key, and
+/// the deposit addresses handed out from its wallet
+///
+/// # Errors
+///
+/// Returns an error if the keystore can't be read.
+pub fn controlled_addresses(
+    keys: &KeyManager,
+    network: Network,
+) -> Result<HashMap<String, ManagedKey>, KeyError> {
+    let mut addresses = HashMap::new();
+    for name in keys.list_keys()? {
+        let key = keys.key(&name)?;
+        for script_type in ScriptType::ALL {
+            addresses.insert(key.address(script_type, network)?, key.clone());
+        }
+        for purpose in Purpose::ALL {
+            for derived in keys.issued_addresses(&name, purpose, 0, network)? {
+                addresses.insert(derived.address, derived.key);
+            }
+        }
+    }
+    Ok(addresses)
+}
+/// Sign `challenge` for every address of `ledger` the exchange holds the key of, on the
+/// [`active_network`].
+///
+/// The rows of an address repeated in the ledger are proven together, with their balances
+/// summed.
+///
+/// # Errors
+///
+/// Returns an error if the keystore or the ledger can't be read, if an address can't be signed
+/// for, or if the balances add up to more than an `i64` holds.
+pub fn prove_reserves(
+    keys: &KeyManager,
+    ledger: &dyn LedgerStore,
+    challenge: &str,
+) -> Result<ReserveProof, KeyError> {
+    let controlled = controlled_addresses(keys, active_network())?;
+    let (addresses, values) = ledger.leaf_columns()?;
+    let mut balances: IndexMap<String, i64> = IndexMap::new();
+    for (address, value) in addresses.into_iter().zip(values) {
+        // Inserting an address again keeps its place, so the proofs follow the ledger's order
+        let balance = balances
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+            .checked_add(value)
+            .ok_or_else(|| KeyError::Overflow(format!("the balance of {address}")))?;
+        balances.insert(address, balance);
+    }
+    let mut proofs = Vec::new();
+    let mut unproven = Vec::new();
+    for (address, value) in balances {
+        match controlled.get(&address) {
+            Some(key) => proofs.push(OwnershipProof::sign(key, &address, value, challenge)?),
+            None => unproven.push(address),
+        }
+    }
+    let total = proofs
+        .iter()
+        .try_fold(0i64, |total, proof| total.checked_add(proof.value))
+        .ok_or_else(|| KeyError::Overflow("the total of the reserves".to_owned()))?;
+    Ok(ReserveProof {
+        challenge: challenge.to_owned(),
+        root: ledger.root()?.unwrap_or_default(),
+        total,
+        proofs,
+        unproven,
+    })
+}
+/// A proof of a [`ReserveProof`] which didn't hold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProofFailure {
+    /// The address of the proof
+    pub address: String,
+    /// Why the proof was rejected
+    pub reason: String,
+}
+/// The outcome of checking a [`ReserveProof`] with [`verify_reserves`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReserveVerification {
+    /// The challenge the proof was checked against
+    pub challenge: String,
+    /// The merkle root of the ledger the proof was made from
+    pub root: String,
+    /// Whether every signature held and the total adds up
+    pub valid: bool,
+    /// The number of addresses whose signature held
+    pub verified: usize,
+    /// The sum of the balances of the addresses whose signature held
+    pub verified_total: i64,
+    /// The total the proof claims
+    pub claimed_total: i64,
+    /// The proofs which didn't hold
+    pub failures: Vec<ProofFailure>,
+}
+impl fmt::Display for ReserveVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} address(es) holding {} of the claimed {} verified",
+            self.verified, self.verified_total, self.claimed_total
+        )?;
+        for failure in &self.failures {
+            write!(f, "\n{}: {}", failure.address, failure.reason)?;
+        }
+        Ok(())
+    }
+}
+/// Check every signature of `proof` against its address.
+///
+/// An address proven twice only counts once, so its balance can't be claimed twice, and a
+/// balance which would overflow the verified total is a failure rather than wrapping it. The
+/// proof is valid when every signature holds and the balances of the addresses add up to its
+/// total.
+///
+/// # Examples
+///
+/// ```
+/// use bitcoin::Network;
+/// use credible_coin::cli::exchange::key_manager::ManagedKey;
+/// use credible_coin::cli::exchange::proof_of_reserves::{
+///     OwnershipProof, ReserveProof, verify_reserves,
+/// };
+/// use credible_coin::utils::bitcoin_utils::ScriptType;
+///
+/// let key = ManagedKey::generate("cold-wallet");
+/// let address = key.address(ScriptType::P2wpkh, Network::Bitcoin).unwrap();
+/// let mut proof = ReserveProof {
+///     challenge: "audit 2024-Q1".to_owned(),
+///     root: String::new(),
+///     total: 50,
+///     proofs: vec![OwnershipProof::sign(&key, &address, 50, "audit 2024-Q1").unwrap()],
+///     unproven: Vec::new(),
+/// };
+/// assert!(verify_reserves(&proof).valid);
+///
+/// proof.challenge = "audit 2023-Q4".to_owned();
+/// assert!(!verify_reserves(&proof).valid);
+/// ```
+pub fn verify_reserves(proof: &ReserveProof) -> ReserveVerification {
+    let mut seen = HashSet::new();
+    let mut verified = 0;
+    let mut verified_total: i64 = 0;
+    let mut failures = Vec::new();
+    for ownership in &proof.proofs {
+        let outcome = if seen.insert(ownership.address.as_str()) {
+            ownership.verify(&proof.challenge)
+        } else {
+            Err(KeyError::Duplicate(ownership.address.clone()))
+        };
+        match outcome {
+            Ok(true) => match verified_total.checked_add(ownership.value) {
+                Some(total) => {
+                    verified += 1;
+                    verified_total = total;
+                }
+                None => failures.push(ProofFailure {
+                    address: ownership.address.clone(),
+                    reason: KeyError::Overflow("the verified total".to_owned()).to_string(),
+                }),
+            },
+            Ok(false) => failures.push(ProofFailure {
+                address: ownership.address.clone(),
+                reason: format!("the {} signature doesn't hold", ownership.format),
+            }),
+            Err(err) => failures.push(ProofFailure {
+                address: ownership.address.clone(),
+                reason: err.to_string(),
+            }),
+        }
+    }
+    ReserveVerification {
+        challenge: proof.challenge.clone(),
+        root: proof.root.clone(),
+        valid: failures.is_empty() && verified_total == proof.total,
+        verified,
+        verified_total,
+        claimed_total: proof.total,
+        failures,
+    }
+}
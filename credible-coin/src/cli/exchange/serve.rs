@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bitcoin::Network;
+use clap::Parser;
+use tokio::net::TcpListener;
+
+use crate::cli::enforce_duplicate_policy;
+use crate::ledger_store::{LedgerStore, open_ledger};
+use crate::proof_api::{DEFAULT_HTTP_ADDRESS, serve_http};
+use crate::proof_server::{MembershipProver, ProofServer, account_id_secret};
+use crate::proof_store::{ProofStore, RedisProofStore};
+use crate::utils::address_utils::set_active_network;
+use crate::utils::duplicates::DuplicatePolicy;
+
+/// Represents the CLI command for serving the customers' inclusion proofs over HTTP.
+///
+/// See [`crate::proof_api`] for the endpoints. Accounts are served by ID too if
+/// `CREDIBLE_ACCOUNT_ID_SECRET` holds the secret the IDs are derived with.
+///
+/// # Fields
+///
+/// * `filename`: The path to the csv file, binary ledger or SQLite database whose accounts are
+///   proven.
+/// * `listen`: The address to listen on.
+/// * `network`: The network every address in the file must belong to.
+/// * `duplicates`: What to do with addresses that appear on more than one row of the file.
+/// * `no_reports`: Don't serve the solvency reports of the proof Redis instance.
+/// * `refresh`: Seconds between reloads of the ledger, 0 to never reload it.
+
+#[derive(Parser, Debug)]
+pub struct ServeCmd {
+    filename: String,
+    #[arg(long, default_value = DEFAULT_HTTP_ADDRESS)]
+    listen: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::KeepAll)]
+    duplicates: DuplicatePolicy,
+    #[arg(long)]
+    no_reports: bool,
+    #[arg(long, default_value_t = 60)]
+    refresh: u64,
+}
+impl ServeCmd {
+    pub(crate) fn run(&self) -> Result<()> {
+        set_active_network(self.network);
+        let ledger: Arc<dyn LedgerStore> = Arc::from(open_ledger(&self.filename)?);
+        enforce_duplicate_policy(&self.filename, ledger.as_ref(), self.duplicates)?;
+        let reports: Option<Arc<dyn ProofStore>> = if self.no_reports {
+            None
+        } else {
+            Some(Arc::new(RedisProofStore::new()?))
+        };
+        // Customers only fetch inclusion proofs, which don't go through the prover
+        let mut server = ProofServer::new(ledger, MembershipProver::MerklePath, reports)?;
+        if let Some(secret) = account_id_secret() {
+            server = server.with_account_ids(secret)?;
+        }
+        let server = Arc::new(server);
+        tokio::runtime::Runtime::new()?.block_on(async {
+            let listener = TcpListener::bind(&self.listen)
+                .await
+                .with_context(|| format!("Failed to listen on {}", self.listen))?;
+            println!("Listening on http://{}", listener.local_addr()?);
+            if self.refresh > 0 {
+                server.reload_every(Duration::from_secs(self.refresh));
+            }
+            serve_http(server, listener).await?;
+            Ok(())
+        })
+    }
+}
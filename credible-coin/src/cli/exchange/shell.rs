@@ -1,243 +1,473 @@
-use crate::cli::exchange::db_connector::retrieve_public_key_bytes;
 use crate::cli::exchange::exchange_functions::{
-    cmd_table, create_new_tree_from_file, create_private_key, create_rng,
+    self, AccountId, AddedCoin, BalanceUpdate, ExchangeMembership, ImportedAccounts, KeyInfo,
+    KeyTable, RemovedAccount, SignedMessage, create_rng,
 };
-use crate::cli::{arg_sanitizer, convert_to_string_vec, ArgsList, CliError};
-use crate::credible_config::get_emp_copy_path;
-use crate::emp::cpp_gen::{copy_to_directory, CppFileGenerator};
+use crate::cli::exchange::hd_wallet::Purpose;
+use crate::cli::exchange::key_manager::{DEFAULT_KEY, KeyManager, encode_signature};
+use crate::cli::exchange::proof_of_reserves::{ReserveProof, prove_reserves, verify_reserves};
+use crate::cli::output::CommandOutput;
+use crate::cli::renderer::show_file;
+use crate::cli::shell::{ArgKind, ArgSpec, Args, Registry, Shell};
+use crate::cli::snapshots;
+use crate::credible_config::{get_deposit_script_type, get_emp_copy_path};
+use crate::emp::cpp_gen::{CppFileGenerator, copy_to_directory};
 use crate::emp::executor::{execute_compiled_binary, execute_make_install};
-use crate::utils::csv_utils::get_address_position;
-use crate::utils::get_project_root;
-use crate::utils::{
-    bitcoin_utils::generate_address_with_provided_public_key, csv_utils::append_record,
-};
-use crate::{handle_status, render_file_preview};
-use bitcoin::PublicKey;
-use flexi_logger::{AdaptiveFormat, Duplicate, FileSpec, Logger};
-use nu_ansi_term::Color;
-use reedline::{
-    default_emacs_keybindings, ColumnarMenu, DefaultCompleter, DefaultHinter, DefaultPrompt,
-    DefaultValidator, Emacs, ExampleHighlighter, KeyCode, KeyModifiers, Reedline, ReedlineEvent,
-    ReedlineMenu, Signal,
+use crate::errors::KeyError;
+use crate::handle_status;
+use crate::ledger_store::LedgerStore;
+use crate::proof_server::{ACCOUNT_ID_SECRET_VAR, account_id, account_id_secret};
+use crate::proof_store::{ProofStore, RedisProofStore, publish_proofs};
+use crate::utils::address_utils::active_network;
+use crate::utils::atomic_file::write_atomically;
+use crate::utils::bitcoin_utils::ScriptType;
+use crate::utils::csv_utils::{
+    make_address_vector, make_value_vector, position_in_columns, validate_csv_addresses,
 };
-use rs_merkle::algorithms::Sha256;
+use crate::utils::duplicates::account_keys;
+use crate::utils::get_project_root;
+use anyhow::{Context, bail};
 use rs_merkle::MerkleTree;
-use std::collections::HashMap;
+use rs_merkle::algorithms::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Default)]
 pub(crate) struct ExchangeShell {
     tree: Option<MerkleTree<Sha256>>,
+    /// The accounts of the exchange, `None` for the emp `txt` inputs
+    ledger: Option<Arc<dyn LedgerStore>>,
     filename: String,
-}
-pub(crate) fn shell_commands() -> Vec<String> {
-    vec![
-        "exit".into(),
-        "createPrivateKey".into(),
-        "proveMembership".into(),
-        "addCoinToDB".into(),
-        "createRNG".into(),
-        "clear".into(),
-        "showFile".into(),
-        "help".into(),
-        "?".into(),
-    ]
+    /// The exchange's keys, opened the first time a command needs them
+    keys: Option<KeyManager>,
 }
 
 /// The user is automatically brought into the exchange shell once they
 /// provide a valid CSV file of their coin addresses and values and it
 /// gets created into an in-memory merkle tree.
 impl ExchangeShell {
-    pub(crate) fn new(tree: Option<MerkleTree<Sha256>>, filename: String) -> Self {
-        Self { tree, filename }
-    }
-    pub(crate) fn start(&mut self) -> anyhow::Result<()> {
-        println!("Ctrl-D or Ctrl-C to quit");
-        let commands = shell_commands();
-        let completer: Box<DefaultCompleter> =
-            Box::new(DefaultCompleter::new_with_wordlen(commands.clone(), 2));
-        // Use the interactive menu to select options from the completer
-        let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
-        // Set up the required keybindings
-        let mut keybindings = default_emacs_keybindings();
-        keybindings.add_binding(
-            KeyModifiers::NONE,
-            KeyCode::Tab,
-            ReedlineEvent::UntilFound(vec![
-                ReedlineEvent::Menu("completion_menu".to_string()),
-                ReedlineEvent::MenuNext,
-            ]),
-        );
-
-        let edit_mode = Box::new(Emacs::new(keybindings));
-
-        let mut line_editor = Reedline::create()
-            .with_highlighter(Box::new(ExampleHighlighter::new(commands)))
-            .with_completer(completer)
-            .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
-            .with_ansi_colors(true)
-            .with_quick_completions(true)
-            .with_partial_completions(true)
-            .with_hinter(Box::new(DefaultHinter::default().with_style(
-                nu_ansi_term::Style::new().italic().fg(Color::LightGray),
-            )))
-            .with_validator(Box::new(DefaultValidator))
-            .with_edit_mode(edit_mode);
-        let prompt = DefaultPrompt::default();
-        //TODO: Eventually swap WriteMode::Default with WriteMode::Async
-        Logger::try_with_str("info")
-            .expect("Could not create logger object")
-            .duplicate_to_stdout(Duplicate::All)
-            .log_to_file(
-                FileSpec::default()
-                    .basename("credible")
-                    .suffix("log")
-                    .suppress_timestamp(),
-            )
-            .adaptive_format_for_stderr(AdaptiveFormat::Default)
-            .adaptive_format_for_stdout(AdaptiveFormat::Default)
-            .append()
-            .start()
-            .unwrap();
-        loop {
-            let sig = line_editor.read_line(&prompt)?;
-            match sig {
-                reedline::Signal::Success(buffer) => {
-                    let args: Vec<&str> = buffer.split(' ').collect();
-                    let args: Vec<String> = convert_to_string_vec(args);
-                    if args[0] == "exit" {
-                        log::info!("Exiting Shell");
-                        break;
-                    }
-                    if args[0] == "clear" {
-                        line_editor.clear_scrollback()?;
-                        continue;
-                    }
-                    if args[0] == "proveMembership" {
-                        arg_sanitizer::sanitize_args!(args, 1, "No public address provided");
-                        // It should be safe to unwrap here because of all of the previous checking
-                        let public_address = args.get(1).unwrap();
-                        println!("Public address{:?}", public_address);
-                        // NOTE: I think running like this a lot breaks my run script for some reason
-                        // so we need to be careful
-                        // FIXME: Use the arguments
-                        let mutex = std::sync::Mutex::new(());
-                        let _guard = mutex.lock().unwrap();
-                        let mut sub_map: HashMap<String, String> = HashMap::new();
-                        // TODO: The value needs to be the address position
-                        match get_address_position(&self.filename, public_address.to_string(), None)
-                        {
-                            Ok(pos) => {
-                                log::info!("Address position {:?}", pos);
-                                sub_map.insert("actual_leaf_index".to_string(), pos.to_string());
-                            }
-                            Err(e) => {
-                                log::error!(
-                                    "Error getting index for address {}: {:?}",
-                                    public_address,
-                                    e
-                                );
-                                continue;
-                            }
-                        }
-                        let generator =
-                            CppFileGenerator::new(&get_project_root().unwrap(), sub_map);
-                        if let Err(err) = generator.generate("gen") {
-                            eprintln!("Error generating C++ file: {:?}", err);
-                        }
-                        tokio::runtime::Runtime::new().unwrap().block_on(async {
-                            let _ = copy_to_directory("gen.cpp", &get_emp_copy_path()).await;
-                        });
-                        let output = execute_make_install();
-                        handle_status!(output);
-                        let output = execute_compiled_binary("bin/test_bool_gen".to_owned());
-                        let out = output.unwrap();
-                        if out.status.code().unwrap() != 0 {
-                            log::error!("Error proving membership");
-                            break;
-                        } else {
-                            let string: String = std::str::from_utf8(&out.stdout)?.to_owned();
-                            let strings: Vec<&str> = string.split("\n").collect();
-                            println!("{:?}", strings[1]);
-                            if strings[2].len() == 0 {
-                                continue;
-                            }
-                            println!("{:?}", strings[2]);
-                        }
-                        // handle_output!(output);
-                        // println!("{}", retrieve_membership_string(output)?);
-                    }
-                    if args[0] == "createPrivateKey" {
-                        create_private_key();
-                    }
-                    if args[0] == "createRNG" {
-                        arg_sanitizer::sanitize_args!(args, 1, "No seed provided");
-                        // It is safe to do unwrap the get() here because, sanitize_args! ensures that the value is not empty,
-                        // but we still need a match case for parsing the value to a string
-                        let seed = if let Ok(value) = args.get(1).unwrap().parse::<u64>() {
-                            value
-                        } else {
-                            log::error!("Invalid seed provided");
-                            continue;
-                        };
-
-                        // FIXME: This function call does not save the generated RNG anywhere, but we
-                        // should have another function responsible for that
-                        // FIXME: We may also need to change the code so that it uses the RNG that we generate
-                        // and give to it rather than making a thread_rng every time when generating the private key
-                        create_rng(seed);
-                    }
-                    if args[0] == "addCoinToDB" {
-                        arg_sanitizer::sanitize_args!(args, 1, "No value provided");
-                        // It is safe to do unwrap the get() here because, sanitize_args! ensures that the value is not empty,
-                        // but we still need a match case for parsing the value to a string
-                        let value = if let Ok(value) = args.get(1).unwrap().parse::<u64>() {
-                            value
-                        } else {
-                            log::error!("Invalid value provided");
-                            continue;
-                        };
-                        let mut retrieved_bytes: Vec<u8> = Vec::default();
-                        match retrieve_public_key_bytes() {
-                            Ok(key_bytes) => {
-                                retrieved_bytes = key_bytes;
-                            }
-                            Err(err) => {
-                                log::error!("{:?}", err);
-                                log::info!("Generating key ad-hoc");
-                                let s = secp256k1::Secp256k1::new();
-                                let key = bitcoin::PublicKey::new(
-                                    s.generate_keypair(&mut rand::thread_rng()).1,
-                                );
-                                retrieved_bytes = key.to_bytes();
-                            }
-                        };
+    pub(crate) fn new(
+        ledger: Option<Arc<dyn LedgerStore>>,
+        filename: String,
+    ) -> anyhow::Result<Self> {
+        let tree = ledger.as_deref().map(LedgerStore::tree).transpose()?;
+        Ok(Self {
+            tree,
+            ledger,
+            filename,
+            keys: None,
+        })
+    }
+    /// The exchange's keys, opening the configured keystore (and asking for its passphrase) on
+    /// first use
+    fn key_manager(&mut self) -> Result<&KeyManager, KeyError> {
+        let keys = match self.keys.take() {
+            Some(keys) => keys,
+            None => KeyManager::from_config()?,
+        };
+        Ok(self.keys.insert(keys))
+    }
+    fn prove_membership(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let public_address: String = args.get("ADDRESS");
+        log::debug!("Public address{:?}", public_address);
+        // NOTE: I think running like this a lot breaks my run script for some reason
+        // so we need to be careful
+        // FIXME: Use the arguments
+        let mutex = std::sync::Mutex::new(());
+        let _guard = mutex.lock().unwrap();
+        let mut sub_map: HashMap<String, String> = HashMap::new();
+        // TODO: The value needs to be the address position
+        let pos = self
+            .ledger()?
+            .position(&public_address, None)
+            .with_context(|| format!("Error getting index for address {}", public_address))?;
+        log::info!("Address position {:?}", pos);
+        sub_map.insert("actual_leaf_index".to_string(), pos.to_string());
+        let generator = CppFileGenerator::new(&get_project_root().unwrap(), sub_map);
+        if let Err(err) = generator.generate("gen") {
+            eprintln!("Error generating C++ file: {:?}", err);
+        }
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let _ = copy_to_directory("gen.cpp", &get_emp_copy_path()).await;
+        });
+        let output = execute_make_install();
+        handle_status!(output);
+        let output = execute_compiled_binary("bin/test_bool_gen".to_owned());
+        let out = output.unwrap();
+        if out.status.code().unwrap() != 0 {
+            bail!("Error proving membership");
+        }
+        let string: String = std::str::from_utf8(&out.stdout)?.to_owned();
+        let strings: Vec<&str> = string.split('\n').collect();
+        // handle_output!(output);
+        // println!("{}", retrieve_membership_string(output)?);
+        CommandOutput::new(&ExchangeMembership {
+            address: public_address,
+            index: pos,
+            result: strings[1].to_owned(),
+            details: Some(strings[2].to_owned()).filter(|line| !line.is_empty()),
+        })
+    }
+    fn create_private_key(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let key = exchange_functions::create_private_key(
+            self.key_manager()?,
+            &args.get::<String>("NAME"),
+        )?;
+        CommandOutput::new(&KeyInfo::new(&key)?)
+    }
+    fn list_keys(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        let manager = self.key_manager()?;
+        let keys = manager
+            .list_keys()?
+            .iter()
+            .map(|name| KeyInfo::new(&manager.key(name)?))
+            .collect::<Result<_, _>>()?;
+        CommandOutput::new(&KeyTable(keys))
+    }
+    fn sign_message(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let name: String = args.get("NAME");
+        let message: String = args.get("MESSAGE");
+        let key = self.key_manager()?.key(&name)?;
+        CommandOutput::new(&SignedMessage {
+            address: key.address(ScriptType::P2pkh, active_network())?,
+            signature: encode_signature(&key.sign_message(&message)),
+            key: name,
+            message,
+        })
+    }
+    fn prove_reserves(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let ledger = self.ledger()?;
+        let challenge: String = args.get("CHALLENGE");
+        let path: PathBuf = args.get("FILE");
+        let proof = prove_reserves(self.key_manager()?, ledger.as_ref(), &challenge)?;
+        let contents = serde_json::to_vec_pretty(&proof)?;
+        write_atomically(&path, |file| file.write_all(&contents))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        CommandOutput::new(&proof)
+    }
+    fn verify_reserves(_shell: &mut Self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let proof = read_reserve_proof(&args.get::<PathBuf>("FILE"))?;
+        CommandOutput::new(&verify_reserves(&proof))
+    }
+    fn publish_proofs(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let ledger = self.ledger()?;
+        let verification = verify_reserves(&read_reserve_proof(&args.get::<PathBuf>("RESERVES"))?);
+        if !verification.valid {
+            bail!("The proof of reserves doesn't hold:\n{verification}");
+        }
+        if Some(&verification.root) != self.tree.as_ref().and_then(MerkleTree::root_hex).as_ref() {
+            bail!(
+                "The proof of reserves was made for another ledger than {}",
+                self.filename
+            );
+        }
+        let hours: u64 = args.get("TTL");
+        let ttl = (hours > 0).then(|| Duration::from_secs(hours * 3600));
+        let report = publish_proofs(
+            &RedisProofStore::new()?,
+            ledger.as_ref(),
+            verification.verified_total,
+            &verification.challenge,
+            ttl,
+        )?;
+        CommandOutput::new(&report)
+    }
+    fn account_id(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let address: String = args.get("ADDRESS");
+        let Some(secret) = account_id_secret() else {
+            bail!("Set {ACCOUNT_ID_SECRET_VAR} to the secret account IDs are derived with");
+        };
+        let (addresses, values) = self.ledger()?.leaf_columns()?;
+        let index = position_in_columns(&addresses, &values, &address, None)?;
+        // The ID is derived from the disambiguated key, so the rows of a repeated address differ
+        let key = account_keys(&addresses).swap_remove(index);
+        CommandOutput::new(&AccountId {
+            id: account_id(&secret, &key),
+            address: key,
+        })
+    }
+    fn fetch_proof(_shell: &mut Self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let root: String = args.get("ROOT");
+        let address: String = args.get("ADDRESS");
+        let Some(proof) = RedisProofStore::new()?.get_proof(&root, &address)? else {
+            bail!("No proof of {address} was published for {root}");
+        };
+        if !proof.verify() {
+            bail!("The published proof of {address} doesn't hold against {root}");
+        }
+        CommandOutput::new(&proof)
+    }
+    fn create_rng(_shell: &mut Self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let seed: u64 = args.get("SEED");
 
-                        if retrieved_bytes.is_empty() {
-                            log::error!("Private key field not set. To set the private key call 'createPrivateKey <seed>'");
-                            continue;
-                        }
-                        let retrieved_key: PublicKey =
-                            PublicKey::from_slice(&retrieved_bytes).unwrap();
-                        let address = generate_address_with_provided_public_key(retrieved_key);
-                        append_record(&self.filename, address, value);
-                        self.tree = Some(create_new_tree_from_file(&self.filename));
-                        // TODO: how do we do a similar thing in emp's case????
-                    }
-                    if args[0] == "showFile" {
-                        render_file_preview!(&self.filename);
-                    }
-                    if args[0] == "help" || args[0] == "?" {
-                        cmd_table();
-                    }
-                }
-                Signal::CtrlD => {
-                    break;
-                }
-                Signal::CtrlC => {
-                    continue;
-                }
+        // FIXME: This function call does not save the generated RNG anywhere, but we
+        // should have another function responsible for that
+        // FIXME: We may also need to change the code so that it uses the RNG that we generate
+        // and give to it rather than making a thread_rng every time when generating the private key
+        create_rng(seed);
+        CommandOutput::data(&serde_json::json!({ "seed": seed }))
+    }
+    fn add_coin_to_db(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let value: u64 = args.get("VALUE");
+        let ledger = self.ledger()?;
+        let purpose = Purpose::for_script_type(get_deposit_script_type()?);
+        let existing: HashSet<String> = ledger.columns()?.0.into_iter().collect();
+        // Every coin is paid to a fresh address the exchange can sign for, so there is no
+        // fallback when the key can't be loaded. Indices whose address is already in the ledger
+        // (e.g. because it was imported) are skipped.
+        let deposit = loop {
+            let deposit = self
+                .key_manager()?
+                .next_address(DEFAULT_KEY, purpose, 0, active_network())
+                .context("Deposit addresses are derived from the exchange's default key")?;
+            if !existing.contains(&deposit.address) {
+                break deposit;
             }
+        };
+        ledger.append(vec![deposit.address.clone()], vec![i64::try_from(value)?])?;
+        let root = self.rebuild_tree()?;
+        // TODO: how do we do a similar thing in emp's case????
+        CommandOutput::new(&AddedCoin {
+            address: deposit.address,
+            path: deposit.path.to_string(),
+            value,
+            root,
+        })
+    }
+    /// The accounts of the exchange, failing unless the shell was loaded with a ledger rather
+    /// than an emp `txt` input
+    fn ledger(&self) -> anyhow::Result<Arc<dyn LedgerStore>> {
+        match &self.ledger {
+            Some(ledger) => Ok(Arc::clone(ledger)),
+            None => bail!(
+                "{} has no accounts to change, load a CSV file, binary ledger or SQLite database instead",
+                self.filename
+            ),
+        }
+    }
+    /// The CSV file or binary ledger the accounts are kept in, for the commands which copy or
+    /// show the file
+    fn ledger_file(&self) -> anyhow::Result<String> {
+        match self.ledger()?.file() {
+            Some(file) => Ok(file.to_owned()),
+            None => bail!(
+                "{} isn't a CSV file or binary ledger, export it to one first",
+                self.filename
+            ),
+        }
+    }
+    /// The file previewed by `showFile`: the ledger's file, or the emp `txt` input
+    fn show_file(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        if self.ledger.is_some() {
+            return show_file(&self.ledger_file()?);
         }
-        Ok(())
+        show_file(&self.filename)
     }
+    /// Rebuild the tree from the ledger after its accounts changed, returning the new root
+    fn rebuild_tree(&mut self) -> anyhow::Result<String> {
+        let tree = self.ledger()?.tree()?;
+        let root = tree.root_hex().unwrap_or_default();
+        self.tree = Some(tree);
+        Ok(root)
+    }
+    fn snapshot(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        snapshots::take(&self.ledger_file()?, &args.get::<String>("LABEL"))
+    }
+    fn snapshots(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        snapshots::list(&self.ledger_file()?)
+    }
+    fn rollback(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let (tree, output) =
+            snapshots::rollback(&self.ledger_file()?, &args.get::<String>("LABEL"))?;
+        self.tree = Some(tree);
+        Ok(output)
+    }
+    fn remove_account(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let address: String = args.get("ADDRESS");
+        let rows = self.ledger()?.remove(&address)?;
+        CommandOutput::new(&RemovedAccount {
+            address,
+            rows,
+            root: self.rebuild_tree()?,
+        })
+    }
+    fn set_balance(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let address: String = args.get("ADDRESS");
+        let value: u64 = args.get("VALUE");
+        self.ledger()?.update(
+            &address,
+            i64::try_from(value).context("The balance must fit in 63 bits")?,
+        )?;
+        CommandOutput::new(&BalanceUpdate {
+            address,
+            value,
+            root: self.rebuild_tree()?,
+        })
+    }
+    fn import_accounts(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let ledger = self.ledger()?;
+        let path: PathBuf = args.get("CSV");
+        let file = path.to_string_lossy().into_owned();
+        if !path.exists() {
+            bail!("{} not found", file);
+        }
+        validate_csv_addresses(&file)?;
+        let count = ledger
+            .append(make_address_vector(&file), make_value_vector(&file))
+            .with_context(|| format!("Failed to import {}", file))?;
+        CommandOutput::new(&ImportedAccounts {
+            file,
+            count,
+            root: self.rebuild_tree()?,
+        })
+    }
+}
+impl Shell for ExchangeShell {
+    const ROLE: &'static str = "exchange";
+    fn root(&self) -> Option<String> {
+        self.tree.as_ref().and_then(MerkleTree::root_hex)
+    }
+    fn addresses(&self) -> Vec<String> {
+        // Files without a ledger (the emp `txt` inputs) have no addresses to complete
+        self.ledger
+            .as_ref()
+            .and_then(|ledger| ledger.leaf_columns().ok())
+            .map(|(addresses, _)| addresses)
+            .unwrap_or_default()
+    }
+    fn registry() -> Registry<Self> {
+        Registry::new()
+            .command(
+                "accountId",
+                &[ArgSpec::new("ADDRESS", ArgKind::Key)],
+                "Show the ID the customer of an account fetches its inclusion proof with, derived with the secret in CREDIBLE_ACCOUNT_ID_SECRET",
+                Self::account_id,
+            )
+            .command(
+                "addCoinToDB",
+                &[ArgSpec::new("VALUE", ArgKind::U64)],
+                "Append a new coin to the CSV file or SQLite database given a particular value, paid to the next deposit address derived from the 'default' key",
+                Self::add_coin_to_db,
+            )
+            .command(
+                "createPrivateKey",
+                &[ArgSpec::new("NAME", ArgKind::Text)],
+                "Create a private key and save it to the database under the provided name",
+                Self::create_private_key,
+            )
+            .command(
+                "createRNG",
+                &[ArgSpec::new("SEED", ArgKind::U64)],
+                "Given a seed value, create a RNG and save it to the database",
+                Self::create_rng,
+            )
+            .command(
+                "fetchProof",
+                &[
+                    ArgSpec::new("ROOT", ArgKind::Text),
+                    ArgSpec::new("ADDRESS", ArgKind::Key),
+                ],
+                "Fetch and check the published proof that an account is committed to by a merkle root",
+                Self::fetch_proof,
+            )
+            .command(
+                "importAccounts",
+                &[ArgSpec::new("CSV", ArgKind::Path)],
+                "Append every account of a CSV file to the ledger, refusing the whole file if one of its addresses is already in the ledger",
+                Self::import_accounts,
+            )
+            .command(
+                "listKeys",
+                &[],
+                "List the name, public key and address of every key in the database",
+                Self::list_keys,
+            )
+            .command(
+                "proveMembership",
+                &[ArgSpec::new("ADDRESS", ArgKind::Key)],
+                "Prove that the provided address is/isn't a member of the merkle tree",
+                Self::prove_membership,
+            )
+            .command(
+                "proveReserves",
+                &[
+                    ArgSpec::new("CHALLENGE", ArgKind::Text),
+                    ArgSpec::new("FILE", ArgKind::Path),
+                ],
+                "Sign the challenge for every address of the ledger the exchange holds the key of, saving the proof of reserves to FILE",
+                Self::prove_reserves,
+            )
+            .command(
+                "publishProofs",
+                &[
+                    ArgSpec::new("RESERVES", ArgKind::Path),
+                    ArgSpec::new("TTL", ArgKind::U64),
+                ],
+                "Publish the proof of every account and the solvency report of the ledger, backed by a verified proof of reserves, for TTL hours (0 to keep them)",
+                Self::publish_proofs,
+            )
+            .command(
+                "removeAccount",
+                &[ArgSpec::new("ADDRESS", ArgKind::Key)],
+                "Remove every row of an account from the ledger, or only the Nth row of a repeated address",
+                Self::remove_account,
+            )
+            .command(
+                "rollback",
+                &[ArgSpec::new("LABEL", ArgKind::Text)],
+                "Restore the file and the tree to the snapshot with the provided label",
+                Self::rollback,
+            )
+            .command(
+                "setBalance",
+                &[
+                    ArgSpec::new("ADDRESS", ArgKind::Key),
+                    ArgSpec::new("VALUE", ArgKind::U64),
+                ],
+                "Set the balance of an account in the ledger",
+                Self::set_balance,
+            )
+            .command(
+                "showFile",
+                &[],
+                "Preview the file loaded into the shell",
+                Self::show_file,
+            )
+            .command(
+                "signMessage",
+                &[
+                    ArgSpec::new("NAME", ArgKind::Text),
+                    ArgSpec::new("MESSAGE", ArgKind::Text),
+                ],
+                "Sign a message with the named key, as Bitcoin Core's signmessage does",
+                Self::sign_message,
+            )
+            .command(
+                "snapshot",
+                &[ArgSpec::new("LABEL", ArgKind::Text)],
+                "Save a copy of the file, its hash and its merkle root under the provided label",
+                Self::snapshot,
+            )
+            .command(
+                "snapshots",
+                &[],
+                "List the snapshots taken of the file",
+                Self::snapshots,
+            )
+            .command(
+                "verifyReserves",
+                &[ArgSpec::new("FILE", ArgKind::Path)],
+                "Check every signature of a proof of reserves against its address",
+                Self::verify_reserves,
+            )
+    }
+}
+/// Read the proof of reserves saved to `path` by `proveReserves`
+fn read_reserve_proof(path: &Path) -> anyhow::Result<ReserveProof> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("{} is not a proof of reserves", path.display()))
 }
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+lazy_static! {
+    static ref OUTPUT_FORMAT: RwLock<OutputFormat> = RwLock::new(OutputFormat::default());
+}
+
+/// How the clis and shells report the result of each command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tables and plain text meant to be read by a person
+    #[default]
+    Human,
+    /// Exactly one [`CommandReport`] JSON object per line and per command, meant to be parsed by
+    /// scripts and dashboards. Log messages only go to the log file so they never end up mixed in
+    /// with the reports.
+    Json,
+}
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Human => "human",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+/// Set the format every command reports its result in for the rest of the program
+pub fn set_output_format(format: OutputFormat) {
+    *OUTPUT_FORMAT.write().unwrap() = format;
+}
+/// The format commands currently report their result in. Defaults to
+/// [`OutputFormat::Human`] until [`set_output_format`] is called.
+pub fn output_format() -> OutputFormat {
+    *OUTPUT_FORMAT.read().unwrap()
+}
+/// Whether a command succeeded, as reported in a [`CommandReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The command ran successfully and its result is in `data`
+    Ok,
+    /// The command failed and the reason is in `error`
+    Error,
+}
+/// The JSON object a command emits when the output format is [`OutputFormat::Json`].
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::cli::output::{CommandReport, Status};
+///
+/// let line = r#"{"command":"getCoinInfo","status":"ok","data":{"value":10},"error":null}"#;
+/// let report: CommandReport = serde_json::from_str(line).unwrap();
+/// assert_eq!(report.status, Status::Ok);
+/// assert_eq!(report.data["value"], 10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandReport {
+    /// The name of the command which was run, e.g. `getCoinInfo`
+    pub command: String,
+    /// Whether the command succeeded
+    pub status: Status,
+    /// The result of the command, `null` if it failed or has nothing to report
+    pub data: Value,
+    /// The reason the command failed (including its causes), `null` if it succeeded
+    pub error: Option<String>,
+}
+impl CommandReport {
+    /// Build the report for the result of running `command`
+    pub fn new(command: &str, result: &Result<CommandOutput>) -> Self {
+        match result {
+            Ok(output) => Self {
+                command: command.to_owned(),
+                status: Status::Ok,
+                data: output.data.clone(),
+                error: None,
+            },
+            Err(err) => Self {
+                command: command.to_owned(),
+                status: Status::Error,
+                data: Value::Null,
+                error: Some(format!("{:#}", err)),
+            },
+        }
+    }
+}
+/// What a successful command has to report, both as JSON data and as human readable text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    data: Value,
+    human: Option<String>,
+}
+impl CommandOutput {
+    /// A command which succeeded but has nothing to report
+    pub fn empty() -> Self {
+        Self::default()
+    }
+    /// A command whose result is `data`, which is shown to people through its [`Display`]
+    /// implementation
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` can't be serialized to JSON.
+    ///
+    /// [`Display`]: fmt::Display
+    pub fn new<T: Serialize + fmt::Display>(data: &T) -> Result<Self> {
+        Ok(Self {
+            data: serde_json::to_value(data)?,
+            human: Some(data.to_string()),
+        })
+    }
+    /// A command whose result is `data`, which prints nothing in the human format (usually because
+    /// the command already rendered it itself, like a table or a file preview)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` can't be serialized to JSON.
+    pub fn data<T: Serialize>(data: &T) -> Result<Self> {
+        Ok(Self {
+            data: serde_json::to_value(data)?,
+            human: None,
+        })
+    }
+    /// The JSON data of the result
+    pub fn json(&self) -> &Value {
+        &self.data
+    }
+}
+/// Report the result of running `command` in the active [`OutputFormat`].
+///
+/// In the human format only successful results are printed, errors are left to the caller so
+/// interactive shells can log them and batch runs can report the line they came from. In the JSON
+/// format a single [`CommandReport`] is printed either way.
+pub(crate) fn emit(command: &str, result: &Result<CommandOutput>) {
+    match output_format() {
+        OutputFormat::Human => {
+            if let Ok(CommandOutput {
+                human: Some(text), ..
+            }) = result
+            {
+                println!("{}", text);
+            }
+        }
+        OutputFormat::Json => match serde_json::to_string(&CommandReport::new(command, result)) {
+            Ok(line) => println!("{}", line),
+            Err(err) => log::error!("Failed to serialize the result of {}: {}", command, err),
+        },
+    }
+}
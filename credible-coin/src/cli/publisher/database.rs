@@ -1,11 +1,18 @@
+use bitcoin::Network;
 use clap::Parser;
 use csv::Writer;
-use rs_merkle::{algorithms::Sha256, MerkleTree};
+use rs_merkle::{MerkleTree, algorithms::Sha256};
 use std::path::Path;
 use std::sync::Mutex;
 
+use crate::cli::batch::BatchArgs;
+use crate::cli::enforce_duplicate_policy;
 use crate::cli::publisher::shell::PublisherShell;
-use crate::utils::bitcoin_utils::generate_n_address_value_pairs;
+use crate::ledger_store::CsvLedgerStore;
+use crate::utils::address_utils::set_active_network;
+use crate::utils::bitcoin_utils::{AddressMix, generate_n_address_value_pairs_with_mix};
+use crate::utils::csv_utils::validate_csv_addresses;
+use crate::utils::duplicates::DuplicatePolicy;
 use crate::utils::merkle_utils::load_merkle_leaves_from_csv;
 /// Represents the CLI command for creating a publisher database of a specific size.
 ///
@@ -17,6 +24,9 @@ use crate::utils::merkle_utils::load_merkle_leaves_from_csv;
 ///
 /// * `out_filename`: The path where the generated data should be saved.
 /// * `row_count`: The number of rows of data to generate.
+/// * `network`: The network the generated addresses are encoded for.
+/// * `mix`: The relative weights of each script type, e.g. `p2pkh=40,p2wpkh=60`. When it isn't
+///   provided the default [`AddressMix`] is used.
 ///
 
 #[derive(Parser, Debug)]
@@ -24,6 +34,10 @@ use crate::utils::merkle_utils::load_merkle_leaves_from_csv;
 pub struct CreateCmd {
     out_filename: String,
     row_count: u32,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long)]
+    mix: Option<AddressMix>,
 }
 /// Represents the CLI command for loading a publisher database.
 ///
@@ -32,38 +46,61 @@ pub struct CreateCmd {
 /// # Fields
 ///
 /// * `filename`: The path to the csv file from which the data should be loaded.
+/// * `network`: The network every address in the file (and every address given to the shell)
+///   must belong to.
+/// * `duplicates`: What to do with addresses that appear on more than one row of the file.
+/// * `batch`: Run shell commands from a script or stdin instead of the interactive shell, see
+///   [`BatchArgs`].
 
 #[derive(Parser, Debug)]
 #[command(infer_subcommands = true)]
 pub struct LoadCmd {
     filename: String,
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::KeepAll)]
+    duplicates: DuplicatePolicy,
+    #[command(flatten)]
+    batch: BatchArgs,
 }
 impl CreateCmd {
     /// Create the db
     pub fn run(self) -> anyhow::Result<()> {
         // 1. Check that the out_file doesn't already exist and handle errors
         // 2. Create the new file
-        return create_db(&self.out_filename, self.row_count);
+        let mix = self.mix.unwrap_or_default().with_network(self.network);
+        return create_db_with_mix(&self.out_filename, self.row_count, &mix);
     }
 }
 impl LoadCmd {
     /// Load the db
     pub fn run(self) -> anyhow::Result<()> {
         // 1. Check if the provided csv path exists and handle errors
-        assert!(Path::new(&self.filename)
-            .try_exists()
-            .expect("Can't find the file"));
+        assert!(
+            Path::new(&self.filename)
+                .try_exists()
+                .expect("Can't find the file")
+        );
         // 2. Try to read as dataframe and handle errors
+        set_active_network(self.network);
+        validate_csv_addresses(&self.filename)?;
+        let ledger = Box::new(CsvLedgerStore::new(&self.filename));
+        enforce_duplicate_policy(&self.filename, ledger.as_ref(), self.duplicates)?;
         // 3. Try to get the data from the addresses and values columns and handle errors
         // 4. Turn into merkle tree and handle errors
         let merkle_leaves = load_merkle_leaves_from_csv(&self.filename);
         let coin_tree = load_db(merkle_leaves.clone());
-        let mut publisher_shell = PublisherShell::new(coin_tree, self.filename);
-        publisher_shell.start()
+        let mut publisher_shell = PublisherShell::new(coin_tree, ledger);
+        self.batch.run(&mut publisher_shell)
     }
 }
 /// Creates csv file from random addresses and values
 pub fn create_db(filename: &str, row_count: u32) -> anyhow::Result<()> {
+    create_db_with_mix(filename, row_count, &AddressMix::default())
+}
+/// Creates csv file from random values and random addresses whose script types and network
+/// follow the provided [`AddressMix`]
+pub fn create_db_with_mix(filename: &str, row_count: u32, mix: &AddressMix) -> anyhow::Result<()> {
     assert!(
         !Path::new(filename)
             .try_exists()
@@ -85,7 +122,7 @@ pub fn create_db(filename: &str, row_count: u32) -> anyhow::Result<()> {
     let mutex = Mutex::new(());
     let _guard = mutex.lock().unwrap();
 
-    let (addresses, values) = generate_n_address_value_pairs(row_count);
+    let (addresses, values) = generate_n_address_value_pairs_with_mix(row_count, mix);
     // Create the file but don't save the handle
     std::fs::File::create(filename)?;
     let mut writer = Writer::from_path(filename).unwrap();
@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+
 use indexmap::IndexMap;
 
+use crate::utils::duplicates::{disambiguated_key, find_duplicates};
+
 /// A ``EntryMap`` is a mapping of address to value pairs. It is safe to keep these mappings in plain-text
 /// because this map is only used by the publisher. Internally, this just uses a IndexMap<String,i64>
 #[derive(Default, Debug)]
@@ -65,9 +69,10 @@ impl EntryMap {
     ///
     /// # Note
     ///
-    /// If a key occurs multiple times in the `key_vector`, only the last corresponding value from
-    /// `value_vector` will be stored in the resulting map. As a consequence, the returned map might have
-    /// fewer entries than the original vectors.
+    /// If a key occurs multiple times in the `key_vector`, every occurrence is stored under its
+    /// disambiguated key (`<KEY>#1`, `<KEY>#2`, ..., see
+    /// [`disambiguated_key`](crate::utils::duplicates::disambiguated_key)) so the map always has
+    /// one entry per element and the index of an entry is the index of its merkle leaf.
     ///
     /// # Returns
     ///
@@ -83,15 +88,27 @@ impl EntryMap {
     /// let entry_map = EntryMap::from_vectors(keys, values);
     /// assert_eq!(entry_map.inner.get("key1"), Some(&1));
     /// assert_eq!(entry_map.inner.get("key2"), Some(&2));
+    ///
+    /// let keys = vec!["key1".to_string(), "key2".to_string(), "key1".to_string()];
+    /// let entry_map = EntryMap::from_vectors(keys, vec![1, 2, 3]);
+    /// assert_eq!(entry_map.inner.get("key1#1"), Some(&1));
+    /// assert_eq!(entry_map.inner.get("key1#2"), Some(&3));
+    /// assert_eq!(entry_map.inner.len(), 3);
     /// ```
     pub fn from_vectors(key_vector: Vec<String>, value_vector: Vec<i64>) -> Self {
         assert_eq!(key_vector.len(), value_vector.len());
-        let mut map = IndexMap::new();
-        for (pos, element) in key_vector.iter().enumerate() {
-            // NOTE: If the address occurs multiple times, only the value will be updated
-            // in the map so the map will have fewer keys compared to rows in the csv file
-            // causing issues
-            map.insert(element.clone(), value_vector[pos]);
+        let repeated = find_duplicates(&key_vector);
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        let mut map = IndexMap::with_capacity(key_vector.len());
+        for (element, value) in key_vector.iter().zip(value_vector) {
+            let key = if repeated.rows.contains_key(element) {
+                let occurrence = occurrences.entry(element).or_default();
+                *occurrence += 1;
+                disambiguated_key(element, *occurrence)
+            } else {
+                element.clone()
+            };
+            map.insert(key, value);
         }
         Self { inner: map }
     }
@@ -109,7 +126,9 @@ impl EntryMap {
     ///
     /// # Note
     ///
-    /// If an address occurs multiple times in the CSV file, only the last corresponding value will be stored in the resulting map. As a consequence, the returned map might have fewer entries than the rows in the CSV file.
+    /// The [`active_duplicate_policy`](crate::utils::duplicates::active_duplicate_policy) is
+    /// applied to the file first. Under the `keep-all` policy every row of a repeated address is
+    /// stored under its disambiguated key (see [`EntryMap::from_vectors`]).
     ///
     /// # Returns
     ///
@@ -127,8 +146,8 @@ impl EntryMap {
     pub fn generate_address_value_map(filename: &str) -> Self {
         let (addresses, values) =
             crate::utils::csv_utils::addresses_and_values_as_vectors(filename);
-        println!("Address Length: {:?}", addresses.len());
-        println!("Values Length: {:?}", values.len());
+        log::debug!("Address Length: {:?}", addresses.len());
+        log::debug!("Values Length: {:?}", values.len());
         EntryMap::from_vectors(addresses, values)
     }
     /// Replaces the value associated with the given address key in the `EntryMap`.
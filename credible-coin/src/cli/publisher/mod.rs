@@ -1,5 +1,8 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+
+use crate::cli::{convert, diff};
+use crate::cli::output::{OutputFormat, set_output_format};
 /// The publisher database represents a CSV representation of the data
 /// a cryptocurrency provider would provide to the exchange
 /// This module holds all of the functions to be able to create and
@@ -34,26 +37,42 @@ static HELP_TEMPLATE: &str = "\
 /// to be run:
 /// - Create: Create a new database csv file from our test data
 /// - Load: Load the database from a csv file into a merkle tree in memory
+/// - Convert: Convert the database between the csv, binary ledger and SQLite formats
+/// - Diff: Compare two versions of the database
+/// - Apply: Apply a signed change set saved by `diff` to a copy of the old database
 #[derive(Debug)]
 pub struct PublisherCLI {
     #[command(subcommand)]
     cmd: PublisherCmd,
+    /// How command results are reported: human readable text, or one JSON object per command
+    #[arg(long = "output", global = true, value_enum, default_value_t = OutputFormat::Human)]
+    output_format: OutputFormat,
 }
 impl PublisherCLI {
     /// Run the cli
     pub fn run(self) -> Result<()> {
+        set_output_format(self.output_format);
         self.cmd.run()
     }
 }
 /// The CLI subcommand to execute:
 /// - Create: Create a new database csv file from our test data
 /// - Load: Load the database from a csv file into a merkle tree in memory
+/// - Convert: Convert the database between the csv, binary ledger and SQLite formats
+/// - Diff: Compare two versions of the database
+/// - Apply: Apply a signed change set saved by `diff` to a copy of the old database
 #[derive(Subcommand, Debug)]
 pub enum PublisherCmd {
     /// Save the database into a csv file
     Create(database::CreateCmd),
     /// Load the database from a csv file and load as a merkle tree into memory
     Load(database::LoadCmd),
+    /// Convert the database between the csv, binary ledger and SQLite formats
+    Convert(convert::ConvertCmd),
+    /// Compare two versions of the database, listing the added, removed and modified entries
+    Diff(diff::DiffCmd),
+    /// Apply a signed change set saved by `diff` to a copy of the old database
+    Apply(diff::ApplyCmd),
 }
 impl PublisherCmd {
     pub(crate) fn run(self) -> Result<()> {
@@ -66,6 +85,15 @@ impl PublisherCmd {
             Self::Load(cmd) => {
                 cmd.run()?;
             }
+            Self::Convert(cmd) => {
+                cmd.run()?;
+            }
+            Self::Diff(cmd) => {
+                cmd.run()?;
+            }
+            Self::Apply(cmd) => {
+                cmd.run()?;
+            }
         }
         Ok(())
     }
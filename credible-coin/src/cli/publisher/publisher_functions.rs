@@ -1,53 +1,243 @@
-use anyhow::{anyhow, Result};
-use comfy_table::{presets::UTF8_FULL, Attribute, Cell, ContentArrangement, Table};
-use rs_merkle::{algorithms::Sha256, MerkleTree};
+use std::cmp::Reverse;
+use std::fmt;
+
+use anyhow::{Result, anyhow};
+use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
+use rs_merkle::{MerkleTree, algorithms::Sha256};
+use serde::Serialize;
 
 use crate::{
     cli::publisher::entry_map::EntryMap,
+    ledger_store::LedgerStore,
     merkle_tree_entry::MerkleTreeEntry,
-    utils::csv_utils::{addresses_and_values_as_vectors, get_address_position, update_csv_value},
+    utils::duplicates::{find_duplicates, split_key},
 };
 
-/// Get all of the info for a coin in the merkle tree given its public address
-pub fn get_coin_info(filename: &str, public_address: &str, tree: &MerkleTree<Sha256>) {
+/// A coin of the merkle tree, as reported by `getCoinInfo`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CoinInfo {
+    /// The address (or disambiguated key) the coin was looked up by
+    pub address: String,
+    /// The value of the coin
+    pub value: i64,
+    /// The index of the coin's leaf in the merkle tree
+    pub index: usize,
+    /// The number of leaves in the merkle tree
+    pub leaf_count: usize,
+    /// The hex encoded merkle root the coin was verified against
+    pub root: String,
+}
+impl fmt::Display for CoinInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address:{:?}\nValue:{:?}", self.address, self.value)
+    }
+}
+/// A coin whose value was changed by `updateCoin`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CoinUpdate {
+    /// The address (or disambiguated key) of the updated coin
+    pub address: String,
+    /// The new value of the coin
+    pub value: i64,
+    /// The hex encoded merkle root of the updated tree
+    pub root: String,
+}
+impl fmt::Display for CoinUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address:{:?}\nNew Value:{:?}", self.address, self.value)
+    }
+}
+/// A coin proven to be a member of the merkle tree by `proveMembership`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Membership {
+    /// The address (or disambiguated key) of the coin
+    pub address: String,
+    /// The value the coin was proven to have
+    pub value: i64,
+    /// The hex encoded merkle root the coin was proven against
+    pub root: String,
+}
+impl fmt::Display for Membership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address {:?} found in merkle tree", self.address)
+    }
+}
+/// The root of the merkle tree, as reported by `root`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeRoot {
+    /// The hex encoded merkle root, empty if the tree has no leaves
+    pub root: String,
+    /// The number of leaves in the merkle tree
+    pub leaf_count: usize,
+}
+impl fmt::Display for TreeRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Root:{}\nLeaves:{}", self.root, self.leaf_count)
+    }
+}
+/// Totals over every entry of the loaded file, as reported by `stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LedgerStats {
+    /// The number of entries, which is the number of leaves in the merkle tree
+    pub count: usize,
+    /// The sum of every value. It is wider than the values themselves so it can't overflow.
+    pub sum: i128,
+    /// The smallest value, `None` if there are no entries
+    pub min: Option<i64>,
+    /// The largest value, `None` if there are no entries
+    pub max: Option<i64>,
+    /// The number of addresses which appear on more than one row of the file
+    pub duplicate_addresses: usize,
+    /// The number of rows beyond the first of every repeated address
+    pub duplicate_rows: usize,
+    /// The hex encoded merkle root the totals were taken at
+    pub root: String,
+}
+impl fmt::Display for LedgerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_none = |value: Option<i64>| value.map_or_else(|| "-".to_owned(), |v| v.to_string());
+        write!(
+            f,
+            "Entries:{}\nTotal value:{}\nMin value:{}\nMax value:{}\nRepeated addresses:{} ({} extra rows)\nRoot:{}",
+            self.count,
+            self.sum,
+            or_none(self.min),
+            or_none(self.max),
+            self.duplicate_addresses,
+            self.duplicate_rows,
+            self.root
+        )
+    }
+}
+/// An entry of the loaded file, as listed by `find` and `top`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LedgerEntry {
+    /// The index of the entry's leaf in the merkle tree
+    pub index: usize,
+    /// The address of the entry, or its disambiguated key if the address is repeated
+    pub address: String,
+    /// The value of the entry
+    pub value: i64,
+}
+/// The entries listed by `find` and `top`, shown as a table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct EntryTable(pub Vec<LedgerEntry>);
+impl fmt::Display for EntryTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("No matching entries");
+        }
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Index").add_attribute(Attribute::Bold),
+                Cell::new("Address").add_attribute(Attribute::Bold),
+                Cell::new("Value").add_attribute(Attribute::Bold),
+            ]);
+        for entry in &self.0 {
+            table.add_row(vec![
+                Cell::new(entry.index),
+                Cell::new(&entry.address),
+                Cell::new(entry.value),
+            ]);
+        }
+        write!(f, "{table}")
+    }
+}
+/// The entries of the ledger in the order of the leaves of its merkle tree
+fn ledger_entries(ledger: &dyn LedgerStore) -> Result<impl Iterator<Item = LedgerEntry>> {
+    let (addresses, values) = ledger.leaf_columns()?;
+    Ok(EntryMap::from_vectors(addresses, values)
+        .inner
+        .into_iter()
+        .enumerate()
+        .map(|(index, (address, value))| LedgerEntry {
+            index,
+            address,
+            value,
+        }))
+}
+/// Count, total, and bound the values of the ledger, and count its repeated addresses
+///
+/// The repeated addresses are counted on the rows of the ledger, before the active duplicate
+/// policy is applied, so they are reported even when the policy merged them into one entry.
+pub fn ledger_stats(ledger: &dyn LedgerStore, tree: &MerkleTree<Sha256>) -> Result<LedgerStats> {
+    let values: Vec<i64> = ledger_entries(ledger)?.map(|entry| entry.value).collect();
+    let duplicates = find_duplicates(&ledger.columns()?.0);
+    Ok(LedgerStats {
+        count: values.len(),
+        sum: values.iter().map(|&value| i128::from(value)).sum(),
+        min: values.iter().min().copied(),
+        max: values.iter().max().copied(),
+        duplicate_addresses: duplicates.address_count(),
+        duplicate_rows: duplicates.extra_row_count(),
+        root: tree.root_hex().unwrap_or_default(),
+    })
+}
+/// Every entry of the ledger whose address starts with `prefix`, in tree order
+pub fn find_entries(ledger: &dyn LedgerStore, prefix: &str) -> Result<EntryTable> {
+    Ok(EntryTable(
+        ledger_entries(ledger)?
+            .filter(|entry| entry.address.starts_with(prefix))
+            .collect(),
+    ))
+}
+/// The `count` entries of the ledger holding the largest values, largest first. Entries with
+/// the same value are kept in tree order.
+pub fn top_entries(ledger: &dyn LedgerStore, count: usize) -> Result<EntryTable> {
+    let mut entries: Vec<LedgerEntry> = ledger_entries(ledger)?.collect();
+    entries.sort_by_key(|entry| Reverse(entry.value));
+    entries.truncate(count);
+    Ok(EntryTable(entries))
+}
+/// Get all of the info for a coin in the merkle tree given its public address, or its
+/// disambiguated key (`<ADDRESS>#<N>`) if the address is repeated in the ledger
+pub fn get_coin_info(
+    ledger: &dyn LedgerStore,
+    public_address: &str,
+    tree: &MerkleTree<Sha256>,
+) -> Result<CoinInfo> {
     //let tree = PublisherShell::shell_tree();
     let tree_leaves = tree
         .leaves()
-        .ok_or("Could not get leaves to prove")
-        .unwrap();
-    let map = EntryMap::generate_address_value_map(filename);
-    let value = if let Some(v) = map.inner.get(public_address) {
-        v
-    } else {
-        log::error!("Could not find public address {:?}", public_address);
-        return;
-    };
-    let generated_entry = MerkleTreeEntry::new(public_address.to_owned(), *value);
-    let address_index = match get_address_position(filename, public_address.to_string(), None) {
-        Ok(addr) => addr,
-        Err(e) => {
-            log::error!("{:?}", e);
-            return;
-        }
-    };
+        .ok_or_else(|| anyhow!("Could not get leaves to prove"))?;
+    // The leaf is made from the bare address, even if it was asked for by its disambiguated key
+    let (address_index, generated_entry) = ledger.get_by_address(public_address)?;
+    let value = generated_entry.entry_value();
     // println!("Address Index:{:?}", address_index);
     // println!("Address Value:{:?}", value);
     let indices = vec![address_index];
     let proof = tree.proof(&indices);
-    let root = tree.root().ok_or("couldn't get the merkle root").unwrap();
+    let root = tree
+        .root()
+        .ok_or_else(|| anyhow!("couldn't get the merkle root"))?;
     let bytes = generated_entry.serialize_entry();
     let hashed_bytes = [MerkleTreeEntry::hash_bytes(bytes)];
-    println!("Indices:{:?}", indices);
-    println!("Leaf count:{:?}", tree_leaves.len());
+    log::debug!("Indices:{:?}", indices);
+    log::debug!("Leaf count:{:?}", tree_leaves.len());
 
-    assert!(proof.verify(root, &indices, &hashed_bytes, tree_leaves.len()));
-    println!("Address:{:?}", public_address);
-    println!("Value:{:?}", value);
+    if !proof.verify(root, &indices, &hashed_bytes, tree_leaves.len()) {
+        return Err(anyhow!(
+            "Proof verification failed for {:?}",
+            public_address
+        ));
+    }
+    Ok(CoinInfo {
+        address: public_address.to_owned(),
+        value,
+        index: address_index,
+        leaf_count: tree_leaves.len(),
+        root: tree.root_hex().unwrap_or_default(),
+    })
 }
-/// Update a coin in the merkle tree given its public address and its new value
+/// Update a coin in the merkle tree given its public address (or its disambiguated key
+/// `<ADDRESS>#<N>` if the address is repeated in the ledger) and its new value
 // TODO: _new_value should be an i64 not a u32
 pub fn update_coin(
-    filename: &str,
+    ledger: &dyn LedgerStore,
     _public_address: &str,
     _new_value: u32,
     tree: &MerkleTree<Sha256>,
@@ -56,15 +246,8 @@ pub fn update_coin(
         .leaves()
         .ok_or("Could not get leaves to prove")
         .unwrap();
-    let mut map = EntryMap::generate_address_value_map(filename);
-    let value = match map.inner.get(_public_address) {
-        Some(v) => v,
-        None => {
-            return Err(anyhow!("Public address not found"));
-        }
-    };
-    let generated_entry = MerkleTreeEntry::new(_public_address.to_owned(), *value);
-    let address_index = get_address_position(filename, _public_address.to_string(), None)?;
+    let (address_index, generated_entry) = ledger.get_by_address(_public_address)?;
+    let (address, _) = split_key(_public_address);
 
     let indices = vec![address_index];
     let proof = tree.proof(&indices);
@@ -73,22 +256,11 @@ pub fn update_coin(
     let hashed_bytes = [MerkleTreeEntry::hash_bytes(bytes)];
     assert!(proof.verify(root, &indices, &hashed_bytes, tree_leaves.len()));
 
-    //replace value in hashmap
-    let new_gen_coin = MerkleTreeEntry::new(_public_address.to_owned(), i64::from(_new_value));
-    map.replace(_public_address.to_string(), i64::from(_new_value));
-    let check = match map.inner.get(_public_address) {
-        Some(v) => v,
-        None => {
-            return Err(anyhow!("Public address not found"));
-        }
-    };
-    assert!(check == &i64::from(_new_value));
-    println!("Address:{:?}", _public_address);
-    println!("New Value:{:?}", _new_value);
+    let new_gen_coin = MerkleTreeEntry::new(address.to_owned(), i64::from(_new_value));
 
     //make new merkle tree
-    update_csv_value(filename, _public_address.to_owned(), i64::from(_new_value));
-    let (new_addr_vec, new_val_vec) = addresses_and_values_as_vectors(filename);
+    ledger.update(_public_address, i64::from(_new_value))?;
+    let (new_addr_vec, new_val_vec) = ledger.leaf_columns()?;
     assert!(new_val_vec.contains(&i64::from(_new_value)));
     let new_vec_coin = MerkleTreeEntry::create_entries_vector(new_addr_vec, new_val_vec);
     // println!("_______________________________________________________");
@@ -108,14 +280,14 @@ pub fn update_coin(
         new_leaves.push(MerkleTreeEntry::hash_bytes(u8s));
     }
     let new_tree = MerkleTree::<Sha256>::from_leaves(&new_leaves);
-    let new_address_index = get_address_position(filename, _public_address.to_string(), None)?;
+    let new_address_index = ledger.position(_public_address, None)?;
     let new_indices = vec![new_address_index];
     let new_proof = new_tree.proof(&new_indices);
     let new_root = new_tree
         .root()
         .ok_or("couldn't get the merkle root")
         .unwrap();
-    println!(
+    log::debug!(
         "{:?} {:?}",
         new_gen_coin.entry_address(),
         new_gen_coin.entry_value()
@@ -129,55 +301,3 @@ pub fn update_coin(
 
     Ok(new_tree)
 }
-/// The table of commands, descriptions, and usage
-pub fn cmd_table() {
-    let mut table = Table::new();
-    table
-            .load_preset(UTF8_FULL)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_width(80)
-            .set_header(vec![
-                Cell::new("Command").add_attribute(Attribute::Bold),
-                Cell::new("Description").add_attribute(Attribute::Bold),
-                Cell::new("Usage").add_attribute(Attribute::Bold),
-            ])
-            .add_row(vec![
-                Cell::new("?").add_attribute(Attribute::Bold),
-                Cell::new("Print this command table"),
-                Cell::new("Usage: `?`"),
-            ])
-            .add_row(vec![
-                Cell::new("clear").add_attribute(Attribute::Bold),
-                Cell::new("Clear the screen"),
-                Cell::new("Usage: `clear`"),
-            ])
-            .add_row(vec![
-                Cell::new("exit").add_attribute(Attribute::Bold),
-                Cell::new("Exit the shell"),
-                Cell::new("Usage: `exit`"),
-            ])
-            .add_row(vec![
-                Cell::new("getCoinInfo").add_attribute(Attribute::Bold),
-                Cell::new("Given an address, if the address is present in the CSV return basic information about it"),
-                Cell::new("Usage: `getCoinInfo <ADDRESS>`"),
-            ])
-            .add_row(vec![
-                Cell::new("help").add_attribute(Attribute::Bold),
-                Cell::new("Print this command table"),
-                Cell::new("Usage: `help`"),
-            ])
-            .add_row(vec![
-                Cell::new("proveMembership").add_attribute(Attribute::Bold),
-                Cell::new("Prove that the provided address is/isn't a member of the merkle tree"),
-                Cell::new("Usage: `proveMembership <ADDRESS> <VALUE>`"),
-            ]).add_row(vec![
-                Cell::new("showFile").add_attribute(Attribute::Bold),
-                Cell::new("Preview the file loaded into the shell"),
-                Cell::new("Usage: `showFile`"),
-            ]).add_row(vec![
-                Cell::new("updateCoin").add_attribute(Attribute::Bold),
-                Cell::new("Given an address, if the address is present in the CSV, update its value with the provided value"),
-                Cell::new("Usage: `updateCoin <ADDRESS> <NEW VALUE>`"),
-            ]);
-    println!("{table}");
-}
@@ -1,189 +1,186 @@
-use flexi_logger::{AdaptiveFormat, Duplicate, FileSpec, Logger};
-use nu_ansi_term::{Color, Style};
-use reedline::{
-    default_emacs_keybindings, ColumnarMenu, DefaultCompleter, DefaultHinter, DefaultPrompt,
-    DefaultValidator, Emacs, ExampleHighlighter, KeyCode, KeyModifiers, Reedline, ReedlineEvent,
-    ReedlineMenu, Signal,
-};
-use rs_merkle::algorithms::Sha256;
+use anyhow::{Context, anyhow};
 use rs_merkle::MerkleTree;
+use rs_merkle::algorithms::Sha256;
 
-use crate::cli::publisher::publisher_functions::{cmd_table, get_coin_info, update_coin};
-use crate::cli::{arg_sanitizer, convert_to_string_vec, ArgsList, CliError};
-use crate::render_file_preview;
+use crate::cli::output::CommandOutput;
+use crate::cli::publisher::entry_map::EntryMap;
+use crate::cli::publisher::publisher_functions::{
+    self, CoinUpdate, Membership, TreeRoot, find_entries, ledger_stats, top_entries, update_coin,
+};
+use crate::cli::renderer::show_file;
+use crate::cli::shell::{ArgKind, ArgSpec, Args, Registry, Shell};
+use crate::cli::snapshots;
+use crate::ledger_store::LedgerStore;
 use crate::utils::merkle_utils::prove_membership;
 
-#[derive(Default)]
 pub(crate) struct PublisherShell {
     tree: MerkleTree<Sha256>,
-    filename: String,
-}
-pub(crate) fn shell_commands() -> Vec<String> {
-    vec![
-        "clear".into(),
-        "exit".into(),
-        "getCoinInfo".into(),
-        "help".into(),
-        "proveMembership".into(),
-        "showFile".into(),
-        "updateCoin".into(),
-        "?".into(),
-    ]
+    ledger: Box<dyn LedgerStore>,
 }
 /// The user is automatically brought into the publisher shell once they
 /// provide a valid CSV file of their coin addresses and values and it
 /// gets created into an in-memory merkle tree.
 impl PublisherShell {
-    pub(crate) fn new(tree: MerkleTree<Sha256>, filename: String) -> Self {
-        Self { tree, filename }
+    pub(crate) fn new(tree: MerkleTree<Sha256>, ledger: Box<dyn LedgerStore>) -> Self {
+        Self { tree, ledger }
     }
-    pub(crate) fn start(&mut self) -> anyhow::Result<()> {
-        println!("Ctrl-D or Ctrl-C to quit");
-        let commands = shell_commands();
-        let completer: Box<DefaultCompleter> =
-            Box::new(DefaultCompleter::new_with_wordlen(commands.clone(), 2));
-        // Use the interactive menu to select options from the completer
-        let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
-        // Set up the required keybindings
-        let mut keybindings = default_emacs_keybindings();
-        keybindings.add_binding(
-            KeyModifiers::NONE,
-            KeyCode::Tab,
-            ReedlineEvent::UntilFound(vec![
-                ReedlineEvent::Menu("completion_menu".to_string()),
-                ReedlineEvent::MenuNext,
-            ]),
-        );
-
-        let edit_mode = Box::new(Emacs::new(keybindings));
-
-        let mut line_editor = Reedline::create()
-            .with_highlighter(Box::new(ExampleHighlighter::new(commands)))
-            .with_completer(completer)
-            .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
-            .with_ansi_colors(true)
-            .with_quick_completions(true)
-            .with_partial_completions(true)
-            .with_hinter(Box::new(
-                DefaultHinter::default().with_style(Style::new().italic().fg(Color::LightGray)),
-            ))
-            .with_validator(Box::new(DefaultValidator))
-            .with_edit_mode(edit_mode);
-        let prompt = DefaultPrompt::default();
-        //TODO: Eventually swap WriteMode::Default with WriteMode::Async
-        Logger::try_with_str("info")
-            .expect("Could not create logger object")
-            .duplicate_to_stdout(Duplicate::All)
-            .log_to_file(
-                FileSpec::default()
-                    .basename("credible")
-                    .suffix("log")
-                    .suppress_timestamp(),
+    /// The file the ledger is kept in, for the commands which work on the file itself
+    fn filename(&self) -> anyhow::Result<&str> {
+        self.ledger
+            .file()
+            .ok_or_else(|| anyhow!("The ledger isn't kept in a file"))
+    }
+    fn get_coin_info(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let public_address: String = args.get("ADDRESS");
+        CommandOutput::new(&publisher_functions::get_coin_info(
+            self.ledger.as_ref(),
+            &public_address,
+            &self.tree,
+        )?)
+    }
+    fn update_coin(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let public_address: String = args.get("ADDRESS");
+        let parsed_value = u32::try_from(args.get::<u64>("NEW VALUE"))
+            .context("The new value must fit in 32 bits")?;
+        self.tree = update_coin(self.ledger.as_ref(), &public_address, parsed_value, &self.tree)
+            .context("Failed to update coin")?;
+        CommandOutput::new(&CoinUpdate {
+            address: public_address,
+            value: i64::from(parsed_value),
+            root: self.tree.root_hex().unwrap_or_default(),
+        })
+    }
+    fn show_root(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        CommandOutput::new(&TreeRoot {
+            root: self.tree.root_hex().unwrap_or_default(),
+            leaf_count: self.tree.leaves_len(),
+        })
+    }
+    fn stats(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        CommandOutput::new(&ledger_stats(self.ledger.as_ref(), &self.tree)?)
+    }
+    fn find(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let prefix: String = args.get("PREFIX");
+        CommandOutput::new(&find_entries(self.ledger.as_ref(), &prefix)?)
+    }
+    fn top(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let count = usize::try_from(args.get::<u64>("N")).context("N is too large")?;
+        CommandOutput::new(&top_entries(self.ledger.as_ref(), count)?)
+    }
+    fn snapshot(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        snapshots::take(self.filename()?, &args.get::<String>("LABEL"))
+    }
+    fn snapshots(&mut self, _args: &Args) -> anyhow::Result<CommandOutput> {
+        snapshots::list(self.filename()?)
+    }
+    fn rollback(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let (tree, output) = snapshots::rollback(self.filename()?, &args.get::<String>("LABEL"))?;
+        self.tree = tree;
+        Ok(output)
+    }
+    fn prove_membership(&mut self, args: &Args) -> anyhow::Result<CommandOutput> {
+        let public_address: String = args.get("ADDRESS");
+        let delta_value: i64 = args.get("VALUE");
+        prove_membership(
+            self.ledger.as_ref(),
+            &public_address,
+            Some(delta_value),
+            &self.tree,
+        )?;
+        CommandOutput::new(&Membership {
+            address: public_address,
+            value: delta_value,
+            root: self.tree.root_hex().unwrap_or_default(),
+        })
+    }
+}
+impl Shell for PublisherShell {
+    const ROLE: &'static str = "publisher";
+    fn root(&self) -> Option<String> {
+        self.tree.root_hex()
+    }
+    fn addresses(&self) -> Vec<String> {
+        let Ok((addresses, values)) = self.ledger.leaf_columns() else {
+            return Vec::new();
+        };
+        EntryMap::from_vectors(addresses, values)
+            .inner
+            .into_keys()
+            .collect()
+    }
+    fn registry() -> Registry<Self> {
+        Registry::new()
+            .command(
+                "find",
+                &[ArgSpec::new("PREFIX", ArgKind::Text)],
+                "List every entry whose address starts with the provided prefix",
+                Self::find,
+            )
+            .command(
+                "getCoinInfo",
+                &[ArgSpec::new("ADDRESS", ArgKind::Key)],
+                "Given an address, if the address is present in the CSV return basic information about it",
+                Self::get_coin_info,
+            )
+            .command(
+                "proveMembership",
+                &[
+                    ArgSpec::new("ADDRESS", ArgKind::Key),
+                    ArgSpec::new("VALUE", ArgKind::I64),
+                ],
+                "Prove that the provided address is/isn't a member of the merkle tree",
+                Self::prove_membership,
+            )
+            .command(
+                "rollback",
+                &[ArgSpec::new("LABEL", ArgKind::Text)],
+                "Restore the file and the tree to the snapshot with the provided label",
+                Self::rollback,
+            )
+            .command(
+                "root",
+                &[],
+                "Show the merkle root and leaf count of the loaded tree",
+                Self::show_root,
+            )
+            .command(
+                "showFile",
+                &[],
+                "Preview the file loaded into the shell",
+                |shell, _| show_file(shell.filename()?),
+            )
+            .command(
+                "snapshot",
+                &[ArgSpec::new("LABEL", ArgKind::Text)],
+                "Save a copy of the file, its hash and its merkle root under the provided label",
+                Self::snapshot,
+            )
+            .command(
+                "snapshots",
+                &[],
+                "List the snapshots taken of the file",
+                Self::snapshots,
+            )
+            .command(
+                "stats",
+                &[],
+                "Show the number of entries, the total, smallest and largest values, and the repeated addresses of the loaded file",
+                Self::stats,
+            )
+            .command(
+                "top",
+                &[ArgSpec::new("N", ArgKind::U64)],
+                "List the N entries with the largest values",
+                Self::top,
+            )
+            .command(
+                "updateCoin",
+                &[
+                    ArgSpec::new("ADDRESS", ArgKind::Key),
+                    ArgSpec::new("NEW VALUE", ArgKind::U64),
+                ],
+                "Given an address, if the address is present in the CSV, update its value with the provided value",
+                Self::update_coin,
             )
-            .adaptive_format_for_stderr(AdaptiveFormat::Default)
-            .adaptive_format_for_stdout(AdaptiveFormat::Default)
-            .append()
-            .start()
-            .unwrap();
-
-        loop {
-            let sig = line_editor.read_line(&prompt)?;
-            match sig {
-                Signal::Success(buffer) => {
-                    // println!("We processed: {buffer}");
-
-                    // This is where command processing goes, see the reedline example demo for details
-                    let args: Vec<&str> = buffer.split(' ').collect();
-                    let args: Vec<String> = convert_to_string_vec(args);
-                    if args[0] == "exit" {
-                        log::info!("Exiting Shell");
-                        break;
-                    }
-                    if args[0] == "clear" {
-                        line_editor.clear_scrollback()?;
-                        continue;
-                    }
-                    if args[0] == "getCoinInfo" {
-                        arg_sanitizer::sanitize_args!(args, 1, "No public address provided");
-                        // It should be safe to unwrap here because of all of the previous checking
-                        let public_address = args.get(1).unwrap();
-                        get_coin_info(&self.filename, public_address, &self.tree);
-                    }
-                    if args[0] == "updateCoin" {
-                        arg_sanitizer::sanitize_args!(args, 2, "Invalid argument provided");
-                        let public_address = args.get(1).unwrap();
-                        if !public_address.chars().all(char::is_alphanumeric) {
-                            log::error!("Invalid public address provided");
-                            continue;
-                        }
-
-                        if let Some(value) = args.get(2) {
-                            if let Ok(parsed_value) = value.parse::<u32>() {
-                                // Perform additional operations on the parsed value if needed
-                                self.tree = match update_coin(
-                                    &self.filename,
-                                    public_address,
-                                    parsed_value,
-                                    &self.tree,
-                                ) {
-                                    Ok(updated_tree) => updated_tree,
-                                    Err(err) => {
-                                        log::error!("Failed to update coin {}", err);
-                                        continue;
-                                    }
-                                }
-                            } else {
-                                log::error!("Failed to parse value as a number");
-                                continue;
-                            }
-                        } else {
-                            log::error!("No new value provided");
-                            continue;
-                        }
-                    }
-
-                    if args[0] == "proveMembership" {
-                        arg_sanitizer::sanitize_args!(args, 2, "Invalid arguments provided");
-                        // It should be safe to unwrap here because of all of the previous checking
-                        let public_address = args.get(1).unwrap();
-                        let delta_value_result = args.get(2).and_then(|s| s.parse::<i64>().ok());
-
-                        match delta_value_result {
-                            Some(delta_value) => {
-                                match prove_membership(
-                                    &self.filename,
-                                    public_address,
-                                    Some(delta_value),
-                                    &self.tree,
-                                ) {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        log::error!("{:?}", e);
-                                        continue;
-                                    }
-                                }
-                            }
-                            None => {
-                                log::error!(
-                                    "Error: Could not parse the value {:?}.",
-                                    args.get(2).unwrap()
-                                );
-                            }
-                        }
-                    }
-                    if args[0] == "showFile" {
-                        render_file_preview!(&self.filename);
-                    }
-                    if args[0] == "help" || args[0] == "?" {
-                        cmd_table();
-                    }
-                }
-                Signal::CtrlD | Signal::CtrlC => {
-                    break;
-                }
-            }
-        }
-        println!();
-        Ok(())
     }
 }
@@ -1,4 +1,12 @@
 use bat::{Input, PrettyPrinter};
+use comfy_table::{presets::UTF8_FULL, Attribute, Cell, ContentArrangement, Table};
+
+use serde::Serialize;
+
+use crate::cli::exchange::asset_database::get_extension_from_filename;
+use crate::cli::output::{CommandOutput, OutputFormat, output_format};
+use crate::utils::binary_ledger::MappedLedger;
+use crate::utils::csv_utils::{make_address_vector, make_value_vector};
 /// Pretty-prints the content of a CSV file in the terminal.
 ///
 /// This function uses bat's `PrettyPrinter` module to format and display the
@@ -47,6 +55,46 @@ pub(crate) fn render_txt(path: &str) {
         .print()
         .unwrap();
 }
+/// Prints a summary of a binary ledger and a table of its first entries in the terminal.
+///
+/// Binary ledgers can't be shown by bat, so only the first `PREVIEW_ROWS` entries are read
+/// from the memory-mapped file.
+///
+/// # Arguments
+///
+/// * `path`: The path to the binary ledger to be previewed.
+///
+/// # Panics
+///
+/// The function will panic if the file is not a valid binary ledger.
+///
+pub(crate) fn render_ledger(path: &str) {
+    const PREVIEW_ROWS: usize = 20;
+    let ledger = MappedLedger::open(path).unwrap();
+    println!(
+        "{} (binary ledger v{}): {} entries, {} distinct addresses",
+        path,
+        ledger.header().version,
+        ledger.len(),
+        ledger.address_count()
+    );
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("#").add_attribute(Attribute::Bold),
+            Cell::new("addresses").add_attribute(Attribute::Bold),
+            Cell::new("value").add_attribute(Attribute::Bold),
+        ]);
+    for (row, (address, value)) in ledger.iter().take(PREVIEW_ROWS).enumerate() {
+        table.add_row(vec![Cell::new(row), Cell::new(address), Cell::new(value)]);
+    }
+    println!("{table}");
+    if ledger.len() > PREVIEW_ROWS {
+        println!("... {} more entries", ledger.len() - PREVIEW_ROWS);
+    }
+}
 #[macro_export]
 /// A macro to pretty print a file preview based on the file extension
 macro_rules! render_file_preview {
@@ -54,7 +102,36 @@ macro_rules! render_file_preview {
         match crate::cli::exchange::asset_database::get_extension_from_filename($path) {
             Some("csv") => crate::cli::renderer::render_csv($path),
             Some("txt") => crate::cli::renderer::render_txt($path),
+            Some(crate::utils::binary_ledger::LEDGER_EXTENSION) => {
+                crate::cli::renderer::render_ledger($path)
+            }
             _ => eprintln!("Unsupported file extension!"),
         }
     };
 }
+/// A row of the file loaded into a shell, as reported by `showFile` in the JSON format
+#[derive(Debug, Serialize)]
+struct FileEntry {
+    address: String,
+    value: i64,
+}
+/// The `showFile` command of both shells: previews the file in the human format, and reports
+/// every row of it (or the whole text of a `.txt` file) in the JSON format.
+pub(crate) fn show_file(filename: &str) -> anyhow::Result<CommandOutput> {
+    if output_format() == OutputFormat::Human {
+        render_file_preview!(filename);
+        return Ok(CommandOutput::empty());
+    }
+    if get_extension_from_filename(filename) == Some("txt") {
+        return CommandOutput::data(&serde_json::json!({
+            "file": filename,
+            "contents": std::fs::read_to_string(filename)?,
+        }));
+    }
+    let entries: Vec<FileEntry> = make_address_vector(filename)
+        .into_iter()
+        .zip(make_value_vector(filename))
+        .map(|(address, value)| FileEntry { address, value })
+        .collect();
+    CommandOutput::data(&serde_json::json!({ "file": filename, "entries": entries }))
+}
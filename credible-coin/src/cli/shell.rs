@@ -0,0 +1,436 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result, anyhow};
+use clap::builder::ValueParser;
+use clap::{ArgMatches, ColorChoice};
+use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
+use nu_ansi_term::{Color, Style};
+use reedline::{
+    ColumnarMenu, DefaultHinter, DefaultPrompt, DefaultValidator, Emacs, ExampleHighlighter,
+    FileBackedHistory, HISTORY_SIZE, KeyCode, KeyModifiers, Reedline, ReedlineEvent, ReedlineMenu,
+    Signal, default_emacs_keybindings,
+};
+use serde::Serialize;
+
+use crate::cli::completer::ShellCompleter;
+use crate::cli::output::{CommandOutput, emit};
+use crate::cli::transcript::record;
+use crate::utils::address_utils::normalize;
+use crate::utils::duplicates::normalize_key;
+
+/// What a shell should do once a command has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    /// Read the next command
+    Continue,
+    /// Clear the screen, then read the next command. This is a no-op outside of the interactive
+    /// shell
+    Clear,
+    /// Stop reading commands
+    Exit,
+}
+/// The type of a shell command argument, which decides how it is parsed (and so which errors
+/// the user gets for a bad value)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Not every kind is taken by a command yet
+#[allow(dead_code)]
+pub(crate) enum ArgKind {
+    /// An address, normalized and checked against the active network
+    Address,
+    /// An address, or the disambiguated key (`<ADDRESS>#<N>`) of a repeated one
+    Key,
+    /// A signed value, e.g. a delta
+    I64,
+    /// An unsigned value, e.g. a balance or a seed
+    U64,
+    /// A path to a file
+    Path,
+    /// Any other word, e.g. a search prefix
+    Text,
+}
+impl ArgKind {
+    fn value_parser(self) -> ValueParser {
+        match self {
+            ArgKind::Address => {
+                ValueParser::new(|value: &str| normalize(value).map_err(|err| err.to_string()))
+            }
+            ArgKind::Key => {
+                ValueParser::new(|value: &str| normalize_key(value).map_err(|err| err.to_string()))
+            }
+            ArgKind::I64 => clap::value_parser!(i64).into(),
+            ArgKind::U64 => clap::value_parser!(u64).into(),
+            ArgKind::Path => clap::value_parser!(PathBuf),
+            ArgKind::Text => clap::value_parser!(String),
+        }
+    }
+}
+/// A positional argument of a shell command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ArgSpec {
+    name: &'static str,
+    kind: ArgKind,
+}
+impl ArgSpec {
+    /// An argument called `name` (e.g. `ADDRESS`), used in the usage string and to get its value
+    /// back out of the [`Args`] given to the handler
+    pub(crate) const fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind }
+    }
+}
+impl fmt::Display for ArgSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.name)?;
+        if self.kind == ArgKind::Key {
+            f.write_str("[#N]")?;
+        }
+        Ok(())
+    }
+}
+/// The parsed arguments of a shell command, handed to its [`Handler`]
+#[derive(Debug)]
+pub(crate) struct Args(ArgMatches);
+impl Args {
+    /// The value of the argument called `name`, of the type its [`ArgKind`] parses to: a
+    /// [`String`] for addresses and keys, [`i64`], [`u64`], or [`PathBuf`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if the command doesn't declare an argument called `name` of that type, which is a
+    /// bug in the command's declaration rather than a user error.
+    pub(crate) fn get<T: Clone + Send + Sync + 'static>(&self, name: &str) -> T {
+        self.0
+            .get_one::<T>(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("The command has no argument called {}", name))
+    }
+}
+/// Runs a command on a shell given its parsed arguments
+pub(crate) type Handler<S> = fn(&mut S, &Args) -> Result<CommandOutput>;
+
+/// A command of a shell, as declared in its [`Registry`]
+pub(crate) struct Command<S> {
+    name: &'static str,
+    args: Vec<ArgSpec>,
+    help: &'static str,
+    handler: Handler<S>,
+}
+impl<S> Command<S> {
+    /// How the command is called, e.g. `updateCoin <ADDRESS>[#N] <NEW VALUE>`
+    fn usage(&self) -> String {
+        std::iter::once(self.name.to_owned())
+            .chain(self.args.iter().map(ToString::to_string))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+    /// Parse `args` with clap against the command's declared arguments
+    fn parse_args(&self, args: &[String]) -> Result<Args> {
+        let parser = self.args.iter().fold(
+            clap::Command::new(self.name)
+                .no_binary_name(true)
+                .disable_help_flag(true)
+                .color(ColorChoice::Never)
+                .override_usage(self.usage()),
+            |parser, spec| {
+                parser.arg(
+                    clap::Arg::new(spec.name)
+                        .value_name(spec.name)
+                        .required(true)
+                        .allow_negative_numbers(true)
+                        .value_parser(spec.kind.value_parser()),
+                )
+            },
+        );
+        match parser.try_get_matches_from(args) {
+            Ok(matches) => Ok(Args(matches)),
+            Err(err) => {
+                let message = err.render().to_string();
+                let message = message.trim().trim_start_matches("error: ");
+                Err(anyhow!("{}", message))
+            }
+        }
+    }
+}
+impl<S> fmt::Debug for Command<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .field("help", &self.help)
+            .finish_non_exhaustive()
+    }
+}
+/// The commands every shell has. They are run by the shell itself rather than by a [`Handler`].
+const BUILTINS: [(&str, &str); 4] = [
+    ("?", "Print this command table"),
+    ("clear", "Clear the screen"),
+    ("exit", "Exit the shell"),
+    ("help", "Print this command table"),
+];
+/// A row of the command table printed by `help`
+#[derive(Debug, Serialize)]
+struct CommandHelp {
+    command: &'static str,
+    description: &'static str,
+    usage: String,
+}
+/// The command table printed by `help`, or reported as a list of commands in the JSON format
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+struct HelpTable(Vec<CommandHelp>);
+impl fmt::Display for HelpTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_width(80)
+            .set_header(vec![
+                Cell::new("Command").add_attribute(Attribute::Bold),
+                Cell::new("Description").add_attribute(Attribute::Bold),
+                Cell::new("Usage").add_attribute(Attribute::Bold),
+            ]);
+        for row in &self.0 {
+            table.add_row(vec![
+                Cell::new(row.command).add_attribute(Attribute::Bold),
+                Cell::new(row.description),
+                Cell::new(format!("Usage: `{}`", row.usage)),
+            ]);
+        }
+        write!(f, "{table}")
+    }
+}
+/// Every command a shell understands. The completion word list, the `help` table, argument
+/// checking, and dispatch are all generated from it, so adding a command to a shell only means
+/// declaring it here.
+pub(crate) struct Registry<S> {
+    commands: Vec<Command<S>>,
+}
+impl<S> Registry<S> {
+    /// A registry holding only the builtin commands
+    pub(crate) fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+        }
+    }
+    /// Declare a command called `name` taking the arguments `args`, described by `help` and run
+    /// by `handler`
+    pub(crate) fn command(
+        mut self,
+        name: &'static str,
+        args: &[ArgSpec],
+        help: &'static str,
+        handler: Handler<S>,
+    ) -> Self {
+        debug_assert!(
+            !self.names().iter().any(|existing| existing == name),
+            "{} is declared twice",
+            name
+        );
+        self.commands.push(Command {
+            name,
+            args: args.to_vec(),
+            help,
+            handler,
+        });
+        self
+    }
+    /// The name of every command, including the builtin ones, sorted for completion
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BUILTINS
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.commands.iter().map(|command| command.name))
+            .map(str::to_owned)
+            .collect();
+        names.sort();
+        names
+    }
+    /// The name of every command, including the builtin ones, with the kinds of its arguments,
+    /// for completion
+    pub(crate) fn signatures(&self) -> Vec<(String, Vec<ArgKind>)> {
+        let mut signatures: Vec<(String, Vec<ArgKind>)> = BUILTINS
+            .iter()
+            .map(|(name, _)| ((*name).to_owned(), Vec::new()))
+            .chain(self.commands.iter().map(|command| {
+                (
+                    command.name.to_owned(),
+                    command.args.iter().map(|spec| spec.kind).collect(),
+                )
+            }))
+            .collect();
+        signatures.sort_by(|(a, _), (b, _)| a.cmp(b));
+        signatures
+    }
+    /// The table of commands, descriptions, and usage
+    fn help(&self) -> HelpTable {
+        let mut rows: Vec<CommandHelp> = BUILTINS
+            .iter()
+            .map(|&(command, description)| CommandHelp {
+                command,
+                description,
+                usage: command.to_owned(),
+            })
+            .chain(self.commands.iter().map(|command| CommandHelp {
+                command: command.name,
+                description: command.help,
+                usage: command.usage(),
+            }))
+            .collect();
+        rows.sort_by_key(|row| row.command);
+        HelpTable(rows)
+    }
+    /// Run the command `args[0]` on `shell` with the rest of `args` as its arguments
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no such command, if its arguments don't parse, or if the
+    /// command itself failed.
+    pub(crate) fn dispatch(&self, shell: &mut S, args: &[String]) -> Result<CommandOutput> {
+        match args[0].as_str() {
+            "help" | "?" => CommandOutput::new(&self.help()),
+            name => {
+                let command = self
+                    .commands
+                    .iter()
+                    .find(|command| command.name == name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Unknown command {:?}, run `help` to list the commands",
+                            name
+                        )
+                    })?;
+                let parsed = command.parse_args(&args[1..])?;
+                (command.handler)(shell, &parsed)
+            }
+        }
+    }
+}
+impl<S> fmt::Debug for Registry<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("commands", &self.commands)
+            .finish()
+    }
+}
+/// A shell whose commands can be run interactively or from a script
+pub(crate) trait Shell: Sized {
+    /// The name of the shell, `publisher` or `exchange`, which keeps the history and transcript
+    /// entries of each shell apart
+    const ROLE: &'static str;
+    /// The commands of the shell
+    fn registry() -> Registry<Self>;
+    /// The hex encoded root of the Merkle tree the shell works on, if it has one
+    fn root(&self) -> Option<String>;
+    /// The keys of the file the shell works on, offered when completing address arguments
+    fn addresses(&self) -> Vec<String>;
+    /// Run a single command line and report its result in the active output format, returning
+    /// an error if the command failed. `exit` and `clear` are handled here for every shell.
+    fn execute(&mut self, line: &str) -> Result<Flow> {
+        match tokenize(line) {
+            Ok(args) if args.is_empty() => Ok(Flow::Continue),
+            Ok(args) => self.execute_args(&args),
+            Err(err) => {
+                let result = Err(err);
+                emit(line.split_whitespace().next().unwrap_or_default(), &result);
+                result.map(|_: CommandOutput| Flow::Continue)
+            }
+        }
+    }
+    /// Run a command that has already been split into words (the command name first), report
+    /// its result and record it in the session transcript if one was started
+    fn execute_args(&mut self, args: &[String]) -> Result<Flow> {
+        let root_before = self.root();
+        let (flow, result) = match args[0].as_str() {
+            "exit" => {
+                log::info!("Exiting Shell");
+                (Flow::Exit, Ok(CommandOutput::empty()))
+            }
+            "clear" => (Flow::Clear, Ok(CommandOutput::empty())),
+            _ => (Flow::Continue, Self::registry().dispatch(self, args)),
+        };
+        emit(&args[0], &result);
+        record(args, &result, root_before, self.root());
+        result.map(|_| flow)
+    }
+    /// Run the interactive reedline loop until the user exits
+    fn start(&mut self) -> Result<()> {
+        println!("Ctrl-D or Ctrl-C to quit");
+        let addresses = Arc::new(RwLock::new(self.addresses()));
+        let mut line_editor = line_editor(&Self::registry(), Self::ROLE, Arc::clone(&addresses));
+        let prompt = DefaultPrompt::default();
+        // Ctrl-D and Ctrl-C both end the loop
+        while let Signal::Success(buffer) = line_editor.read_line(&prompt)? {
+            let root = self.root();
+            let flow = self.execute(&buffer);
+            // Only commands which change the tree can change the addresses to complete
+            if self.root() != root {
+                *addresses.write().unwrap() = self.addresses();
+            }
+            match flow {
+                Ok(Flow::Exit) => break,
+                Ok(Flow::Clear) => line_editor.clear_scrollback()?,
+                Ok(Flow::Continue) => {}
+                Err(err) => log::error!("{:#}", err),
+            }
+        }
+        println!();
+        Ok(())
+    }
+}
+/// Split a command line into words the way a POSIX shell would: words are separated by any
+/// amount of whitespace, and can be quoted with `'` or `"` (or escaped with `\`) to hold spaces
+///
+/// # Errors
+///
+/// Returns an error if a quote is never closed, or the line ends with a lone `\`.
+pub(crate) fn tokenize(line: &str) -> Result<Vec<String>> {
+    shlex::split(line).with_context(|| format!("Unterminated quote or escape in {:?}", line.trim()))
+}
+/// The file the interactive history of the `role` shell is kept in, next to `credible.log`
+fn history_file(role: &str) -> PathBuf {
+    PathBuf::from(format!("credible_{}_history.txt", role))
+}
+/// The reedline editor both shells read their commands with, highlighting the commands of
+/// `registry` and completing them along with their arguments (from `addresses` for addresses),
+/// and remembering them across sessions in the history file of `role`
+fn line_editor<S>(
+    registry: &Registry<S>,
+    role: &str,
+    addresses: Arc<RwLock<Vec<String>>>,
+) -> Reedline {
+    let completer = Box::new(ShellCompleter::new(registry.signatures(), addresses));
+    // Use the interactive menu to select options from the completer
+    let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
+    // Set up the required keybindings
+    let mut keybindings = default_emacs_keybindings();
+    keybindings.add_binding(
+        KeyModifiers::NONE,
+        KeyCode::Tab,
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu("completion_menu".to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+    );
+    let edit_mode = Box::new(Emacs::new(keybindings));
+
+    let mut editor = Reedline::create();
+    // A shell without history is still usable, so a file we can't open is only worth a warning
+    match FileBackedHistory::with_file(HISTORY_SIZE, history_file(role)) {
+        Ok(history) => editor = editor.with_history(Box::new(history)),
+        Err(err) => log::warn!("Shell history is disabled: {}", err),
+    }
+    editor
+        .with_highlighter(Box::new(ExampleHighlighter::new(registry.names())))
+        .with_completer(completer)
+        .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
+        .with_ansi_colors(true)
+        .with_quick_completions(true)
+        .with_partial_completions(true)
+        .with_hinter(Box::new(
+            DefaultHinter::default().with_style(Style::new().italic().fg(Color::LightGray)),
+        ))
+        .with_validator(Box::new(DefaultValidator))
+        .with_edit_mode(edit_mode)
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use anyhow::{Result, ensure};
+use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
+use rs_merkle::{MerkleTree, algorithms::Sha256};
+use serde::Serialize;
+
+use crate::cli::output::CommandOutput;
+use crate::utils::merkle_utils::load_merkle_leaves_from_csv;
+use crate::utils::snapshots::{Snapshot, list_snapshots, restore_snapshot, take_snapshot};
+
+/// A snapshot which was just taken, as reported by `snapshot`
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+struct TakenSnapshot(Snapshot);
+impl fmt::Display for TakenSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Snapshot:{}\nRoot:{}\nFile hash:{}",
+            self.0.label,
+            self.0.root.as_deref().unwrap_or_default(),
+            self.0.file_hash
+        )
+    }
+}
+/// The snapshots of a file, as listed by `snapshots`
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+struct SnapshotTable(Vec<Snapshot>);
+impl fmt::Display for SnapshotTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("No snapshots taken");
+        }
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Label").add_attribute(Attribute::Bold),
+                Cell::new("Taken at (Unix time)").add_attribute(Attribute::Bold),
+                Cell::new("Root").add_attribute(Attribute::Bold),
+            ]);
+        for snapshot in &self.0 {
+            table.add_row(vec![
+                Cell::new(&snapshot.label).add_attribute(Attribute::Bold),
+                Cell::new(snapshot.timestamp),
+                Cell::new(snapshot.root.as_deref().unwrap_or_default()),
+            ]);
+        }
+        write!(f, "{table}")
+    }
+}
+/// A rollback, as reported by `rollback`
+#[derive(Debug, Serialize)]
+struct Rollback {
+    /// The label of the snapshot the file was rolled back to
+    label: String,
+    /// The hex encoded merkle root of the restored tree
+    root: Option<String>,
+}
+impl fmt::Display for Rollback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rolled back to {}\nRoot:{}",
+            self.label,
+            self.root.as_deref().unwrap_or_default()
+        )
+    }
+}
+/// Snapshot `filename` under `label`
+pub(crate) fn take(filename: &str, label: &str) -> Result<CommandOutput> {
+    CommandOutput::new(&TakenSnapshot(take_snapshot(filename, label)?))
+}
+/// List the snapshots of `filename`
+pub(crate) fn list(filename: &str) -> Result<CommandOutput> {
+    CommandOutput::new(&SnapshotTable(list_snapshots(filename)?))
+}
+/// Roll `filename` back to the snapshot called `label`, returning the tree rebuilt from the
+/// restored file for the shell to replace its own with.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot can't be restored, or if the rebuilt tree doesn't have the
+/// root recorded in the snapshot (e.g. because the duplicate policy the shell was loaded with
+/// differs from the one the snapshot was taken under).
+pub(crate) fn rollback(filename: &str, label: &str) -> Result<(MerkleTree<Sha256>, CommandOutput)> {
+    let snapshot = restore_snapshot(filename, label)?;
+    let tree = MerkleTree::<Sha256>::from_leaves(&load_merkle_leaves_from_csv(filename));
+    ensure!(
+        tree.root_hex() == snapshot.root,
+        "The file was restored but its root {} differs from the one recorded in snapshot {}",
+        tree.root_hex().unwrap_or_default(),
+        label
+    );
+    let output = CommandOutput::new(&Rollback {
+        label: snapshot.label,
+        root: snapshot.root,
+    })?;
+    Ok((tree, output))
+}
@@ -0,0 +1,121 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::output::{CommandOutput, CommandReport};
+
+lazy_static! {
+    static ref TRANSCRIPT: Mutex<Option<Transcript>> = Mutex::new(None);
+}
+
+/// One command of a session transcript, as written on its own line of the transcript file.
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::cli::output::Status;
+/// use credible_coin::cli::transcript::TranscriptEntry;
+///
+/// let line = r#"{"timestamp":1700000000,"role":"publisher","command":"updateCoin",
+///     "args":["FIRST","5"],"status":"ok","data":{"value":5},"error":null,
+///     "root_before":"aa","root_after":"bb"}"#;
+/// let entry: TranscriptEntry = serde_json::from_str(line).unwrap();
+/// assert_eq!(entry.report.status, Status::Ok);
+/// assert_eq!(entry.args, ["FIRST", "5"]);
+/// assert_eq!(entry.root_after.as_deref(), Some("bb"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// When the command finished, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// The shell the command was run in, `publisher` or `exchange`
+    pub role: String,
+    /// The command, whether it succeeded and its result, exactly as `--output json` reports it
+    #[serde(flatten)]
+    pub report: CommandReport,
+    /// The arguments the command was given, after tokenizing
+    pub args: Vec<String>,
+    /// The hex encoded Merkle root before the command ran, `null` if the shell had no tree
+    pub root_before: Option<String>,
+    /// The hex encoded Merkle root after the command ran, `null` if the shell had no tree
+    pub root_after: Option<String>,
+}
+/// An open transcript file and the shell whose commands are written to it
+#[derive(Debug)]
+struct Transcript {
+    file: File,
+    role: &'static str,
+}
+
+/// Record every command the `role` shell runs from now on to the end of the transcript at `path`,
+/// which is created if it doesn't exist.
+///
+/// # Errors
+///
+/// Returns an error if the transcript can't be opened for appending.
+pub(crate) fn start_transcript(path: &Path, role: &'static str) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open transcript {}", path.display()))?;
+    *TRANSCRIPT.lock().unwrap() = Some(Transcript { file, role });
+    Ok(())
+}
+/// Append a command and its result to the transcript, if one was started.
+///
+/// Every entry is flushed as soon as it is written so a session that is killed still leaves a
+/// complete record of the commands it ran. A transcript that can't be written to is logged, it
+/// never fails the command itself.
+pub(crate) fn record(
+    args: &[String],
+    result: &Result<CommandOutput>,
+    root_before: Option<String>,
+    root_after: Option<String>,
+) {
+    let mut transcript = TRANSCRIPT.lock().unwrap();
+    let Some(transcript) = transcript.as_mut() else {
+        return;
+    };
+    let entry = TranscriptEntry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        role: transcript.role.to_owned(),
+        report: CommandReport::new(&args[0], result),
+        args: args[1..].to_vec(),
+        root_before,
+        root_after,
+    };
+    let written = serde_json::to_string(&entry)
+        .map_err(anyhow::Error::from)
+        .and_then(|line| Ok(writeln!(transcript.file, "{}", line)?))
+        .and_then(|()| Ok(transcript.file.flush()?));
+    if let Err(err) = written {
+        log::error!("Failed to record {} in the transcript: {:#}", args[0], err);
+    }
+}
+/// Read back every entry of the transcript at `path`, in the order the commands were run
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read, or a line isn't a [`TranscriptEntry`].
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEntry>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open transcript {}", path.display()))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line = line.context("Failed to read the transcript")?;
+            serde_json::from_str(&line)
+                .with_context(|| format!("line {} of the transcript is not an entry", index + 1))
+        })
+        .collect()
+}
@@ -1,11 +1,19 @@
 //! A type holding all of our configuration properties. Namely:
 //! 1. emp_path: The path to the emp project test directory where we put generated c++ files for membership proofs
 //! 2. emp_root_path: The path to the emp project root directory
+//! 3. keystore: Where the exchange keeps its secret keys (optional, defaults to Redis)
+//! 4. `deposit_address_type`: The script type of the exchange's deposit addresses (optional,
+//!    defaults to p2wpkh)
+//! 5. redis: How to reach the exchange's and the proofs' Redis instances, and the namespace of
+//!    the exchange's keys in them (optional, defaults to the instances of `redis-conf`)
+use std::path::PathBuf;
 use std::sync::RwLock;
 
 use config::{Config, ConfigError, File, FileFormat};
 use serde::Deserialize;
 
+use crate::utils::bitcoin_utils::ScriptType;
+
 #[derive(Debug, Deserialize, Clone)]
 struct CredibleConfig {
     emp_path: String,
@@ -79,3 +87,165 @@ pub fn get_emp_root_path() -> String {
 
     path
 }
+/// Where the exchange keeps its secret keys, set by the optional `keystore` section of the
+/// configuration:
+///
+/// ```yaml
+/// keystore:
+///   backend: file
+///   path: exchange_keys.json
+/// ```
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum KeystoreConfig {
+    /// The exchange's Redis instance, which stores the keys as they are
+    Redis {
+        /// Whether storing the keys in plaintext was explicitly allowed, without which the
+        /// keystore isn't opened
+        #[serde(default)]
+        allow_plaintext: bool,
+    },
+    /// A file holding the keys encrypted with a passphrase
+    File {
+        /// The path of the file, created on first use
+        path: PathBuf,
+    },
+}
+impl Default for KeystoreConfig {
+    /// `exchange_keys.json`, encrypted with a passphrase
+    fn default() -> Self {
+        Self::File {
+            path: PathBuf::from("exchange_keys.json"),
+        }
+    }
+}
+/// Retrieves the keystore the exchange should use from the configuration.
+///
+/// Unlike the emp paths the keystore is optional: if the configuration file or its `keystore`
+/// section is missing, the keys are encrypted in `exchange_keys.json`.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file can't be parsed or its `keystore` section is
+/// invalid.
+pub fn get_keystore_config() -> Result<KeystoreConfig, ConfigError> {
+    optional_setting("keystore")
+}
+/// Retrieves the script type of the deposit addresses `addCoinToDB` derives, set by the optional
+/// `deposit_address_type` property of the configuration (`p2pkh`, `p2sh-p2wpkh`, `p2wpkh` or
+/// `p2tr`). Native segwit is used if it is missing.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file can't be parsed or the script type is unknown.
+pub fn get_deposit_script_type() -> Result<ScriptType, ConfigError> {
+    let Some(script_type) = optional_setting::<Option<String>>("deposit_address_type")? else {
+        return Ok(ScriptType::P2wpkh);
+    };
+    script_type
+        .parse()
+        .map_err(|err| ConfigError::Message(format!("deposit_address_type: {}", err)))
+}
+/// How to reach one Redis instance
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct RedisInstanceConfig {
+    /// The URL of the instance, e.g. `redis://127.0.0.1:6380/` or `rediss://` for TLS
+    pub url: String,
+    /// The user to authenticate as, if the instance uses ACLs
+    #[serde(default)]
+    pub username: Option<String>,
+    /// The password to authenticate with, overriding any password in the URL
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The database index to select, overriding any index in the URL
+    #[serde(default)]
+    pub db: Option<i64>,
+    /// The most connections the process keeps open to the instance
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+impl RedisInstanceConfig {
+    /// An instance on the local machine, without authentication
+    fn local(port: u16) -> Self {
+        Self {
+            url: format!("redis://127.0.0.1:{port}/"),
+            username: None,
+            password: None,
+            db: None,
+            pool_size: default_pool_size(),
+        }
+    }
+}
+fn default_pool_size() -> u32 {
+    8
+}
+fn default_exchange_id() -> String {
+    "default".to_owned()
+}
+fn default_exchange_instance() -> RedisInstanceConfig {
+    RedisInstanceConfig::local(6380)
+}
+fn default_proofs_instance() -> RedisInstanceConfig {
+    RedisInstanceConfig::local(6381)
+}
+/// The Redis instances of the system, set by the optional `redis` section of the configuration:
+///
+/// ```yaml
+/// redis:
+///   exchange_id: eu-prod
+///   exchange:
+///     url: redis://10.0.0.5:6380/
+///     password: s3cret
+///     db: 2
+///   proofs:
+///     url: redis://10.0.0.5:6381/
+/// ```
+///
+/// Every key of an exchange is stored under `exchange:{exchange_id}:`, and every proof under
+/// `proofs:{root}:`, so several exchanges or environments can share one instance.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct RedisConfig {
+    /// The namespace of the exchange's keys, which sets it apart from the other exchanges
+    /// sharing its instance
+    #[serde(default = "default_exchange_id")]
+    pub exchange_id: String,
+    /// The instance holding the exchange's keys, by default the one of
+    /// `redis-conf/redis-exchange.conf`
+    #[serde(default = "default_exchange_instance")]
+    pub exchange: RedisInstanceConfig,
+    /// The instance holding the proofs, by default the one of `redis-conf/redis-proof-db.conf`
+    #[serde(default = "default_proofs_instance")]
+    pub proofs: RedisInstanceConfig,
+}
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            exchange_id: default_exchange_id(),
+            exchange: default_exchange_instance(),
+            proofs: default_proofs_instance(),
+        }
+    }
+}
+/// Retrieves the Redis instances to connect to from the configuration, see [`RedisConfig`].
+///
+/// If the configuration file or its `redis` section is missing, the local instances started from
+/// `redis-conf` are used.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file can't be parsed or its `redis` section is invalid.
+pub fn get_redis_config() -> Result<RedisConfig, ConfigError> {
+    optional_setting("redis")
+}
+/// Read a property which may be missing, along with the whole configuration file, returning its
+/// default value in that case
+fn optional_setting<T: for<'de> Deserialize<'de> + Default>(key: &str) -> Result<T, ConfigError> {
+    let config = Config::builder()
+        .add_source(File::new("credible_config", FileFormat::Yaml).required(false))
+        .build()?;
+    match config.get::<T>(key) {
+        Ok(value) => Ok(value),
+        Err(ConfigError::NotFound(_)) => Ok(T::default()),
+        Err(err) => Err(err),
+    }
+}
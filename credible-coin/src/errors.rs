@@ -10,35 +10,18 @@ pub enum DBConnectorError {
     #[error("Redis error: {0}")]
     /// A specialization error for redis::RedisError representing a Connection Error
     RedisConnectorError(#[from] redis::RedisError),
-}
-#[derive(Error, Debug)]
-/// Errors that can occur while processing command-line interface (CLI) inputs.
-pub enum CliError {
-    /// An unexpected number of arguments was provided.
-    ///
-    /// This error is triggered when the number of provided arguments does not match
-    /// the expected number.
-    ///
-    /// - `expected`: The expected number of arguments.
-    /// - `actual`: The actual number of arguments provided by the user.
-    #[error("Unexpected: `{expected:?}` arguments found `{actual:?}` arguments")]
-    UnexpectedNumberOfArguments {
-        /// The expected number of arguments
-        expected: usize,
-        /// The actual number of arguments provided by the user
-        actual: usize,
-    },
-
-    /// An empty argument was provided at a specific position.
-    ///
-    /// This error indicates that one of the expected arguments was provided as empty.
-    ///
-    /// - `arg_position`: The position of the empty argument in the argument list.
-    #[error("Empty argument provided at position {arg_position:?}")]
-    EmptyArgument {
-        /// The position of the empty argument in the argument list.
-        arg_position: usize,
-    },
+    #[error("Redis pool error: {0}")]
+    /// No connection of the pool could be established in time
+    PoolError(#[from] r2d2::Error),
+    #[error("Redis configuration error: {0}")]
+    /// The `redis` section of the configuration is invalid
+    ConfigError(#[from] config::ConfigError),
+    #[error("the pool of {0} must hold at least one connection")]
+    /// The pool size of an instance is 0
+    InvalidPoolSize(String),
+    #[error("invalid exchange id {0:?}, use letters, digits, '-', '_' and '.'")]
+    /// The exchange id can't be used as a namespace
+    InvalidNamespace(String),
 }
 /// Errors that can occur when trying to resolve the position of an address from a file
 #[derive(Error, Debug)]
@@ -64,6 +47,124 @@ pub enum AddressPositionError {
         /// The specific value for which the indices were not found
         value: String,
     },
+
+    /// Indicates that the address appears on several rows which are all kept as their own
+    /// leaves, so the row has to be chosen with a disambiguated key (`<ADDRESS>#<N>`).
+    #[error(
+        "address {address} appears {occurrences} times, use {address}#1 to {address}#{occurrences} to pick one"
+    )]
+    AmbiguousAddress {
+        /// The repeated address
+        address: String,
+        /// How many rows the address appears on
+        occurrences: usize,
+    },
+
+    /// Indicates that a disambiguated key asked for an occurrence past the last row of the address.
+    #[error(
+        "address {address} only appears {occurrences} times, occurrence {requested} does not exist"
+    )]
+    NoSuchOccurrence {
+        /// The repeated address
+        address: String,
+        /// How many rows the address appears on
+        occurrences: usize,
+        /// The (1-based) occurrence that was asked for
+        requested: usize,
+    },
+}
+/// Errors that can occur while reading or writing a binary ledger file
+#[derive(Error, Debug)]
+pub enum LedgerFormatError {
+    /// Represents I/O errors encountered while reading, writing, or mapping the file.
+    #[error("ledger i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The file does not start with the binary ledger magic bytes.
+    #[error("not a binary ledger file (bad magic bytes)")]
+    BadMagic,
+
+    /// The file was written with a format version this build can't read.
+    ///
+    /// - `u16`: The version found in the header.
+    #[error("unsupported binary ledger version {0}")]
+    UnsupportedVersion(u16),
+
+    /// The header or one of the sections it describes doesn't fit in the file.
+    #[error("binary ledger is truncated or corrupt: {0}")]
+    Corrupt(String),
+
+    /// The ledger has more distinct addresses than the dictionary can index.
+    #[error("too many distinct addresses for a binary ledger: {0}")]
+    TooManyAddresses(usize),
+}
+/// Errors that can occur while applying a duplicate address policy to a file
+#[derive(Error, Debug)]
+pub enum DuplicateAddressError {
+    /// The `reject` policy is in effect and the file repeats at least one address.
+    ///
+    /// - `String`: A report of the repeated addresses and the lines they appear on.
+    #[error("duplicate addresses are rejected, found {0}")]
+    Rejected(String),
+
+    /// Summing the values of a repeated address overflowed.
+    ///
+    /// - `String`: The address whose values could not be summed.
+    #[error("the values of address {0} overflow when summed")]
+    Overflow(String),
+}
+/// Errors that can occur while building an address mix for synthetic address generation
+#[derive(Error, Debug)]
+pub enum AddressMixError {
+    /// The provided script type is not one that we can generate addresses for.
+    ///
+    /// - `String`: The unrecognized script type.
+    #[error("unknown script type: {0}, expected one of p2pkh, p2sh-p2wpkh, p2wpkh or p2tr")]
+    UnknownScriptType(String),
+
+    /// A weight in the mix was not of the form `type=weight` with a non-negative integer weight.
+    ///
+    /// - `String`: The malformed `type=weight` pair.
+    #[error("malformed weight `{0}`, expected `type=weight`")]
+    MalformedWeight(String),
+
+    /// Every script type in the mix has a weight of zero so nothing could ever be generated.
+    #[error("at least one script type must have a non-zero weight")]
+    NoWeights,
+}
+/// Errors that can occur while validating and normalizing an address
+#[derive(Error, Debug)]
+pub enum AddressValidationError {
+    /// The address could not be parsed as a bitcoin address.
+    #[error("invalid address `{address}`: {reason}")]
+    Malformed {
+        /// The address which failed to parse
+        address: String,
+        /// Why the address failed to parse
+        reason: String,
+    },
+
+    /// The address is valid but it belongs to a different network than the one expected.
+    #[error("address `{address}` is a {found} address but {expected} addresses are expected")]
+    WrongNetwork {
+        /// The address which is for the wrong network
+        address: String,
+        /// The network the address belongs to
+        found: bitcoin::Network,
+        /// The network that was expected
+        expected: bitcoin::Network,
+    },
+
+    /// An address read from a file failed validation.
+    #[error("{file}, line {line}: {source}")]
+    InFile {
+        /// The file the address was read from
+        file: String,
+        /// The line of the file the address is on (the header is line 1)
+        line: usize,
+        /// Why the address failed validation
+        source: Box<AddressValidationError>,
+    },
 }
 /// Errors that can occur while copying a file.
 #[derive(Debug, Error)]
@@ -142,3 +243,324 @@ impl From<io::Error> for CommandError {
         CommandError::CommandError(error)
     }
 }
+/// Errors that can occur while taking, listing, or restoring snapshots of a ledger
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    /// Represents I/O errors encountered while copying the ledger or its snapshots.
+    #[error("snapshot i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The list of snapshots could not be read or written.
+    #[error("the snapshot index is corrupt: {0}")]
+    Index(#[from] serde_json::Error),
+
+    /// The label can't be used as a file name.
+    ///
+    /// - `String`: The rejected label.
+    #[error("invalid snapshot label {0:?}, use letters, digits, '-', '_' and '.'")]
+    InvalidLabel(String),
+
+    /// The label is the name of the snapshot index, which its copy would overwrite.
+    ///
+    /// - `String`: The rejected label.
+    #[error("snapshot label {0:?} is reserved")]
+    ReservedLabel(String),
+
+    /// A snapshot with this label was already taken.
+    ///
+    /// - `String`: The label of the existing snapshot.
+    #[error("a snapshot labelled {0:?} already exists")]
+    Exists(String),
+
+    /// No snapshot with this label was taken.
+    ///
+    /// - `String`: The label which was asked for.
+    #[error("no snapshot labelled {0:?}")]
+    NotFound(String),
+
+    /// The stored copy of the ledger doesn't hash to what was recorded when it was taken.
+    ///
+    /// - `String`: The label of the damaged snapshot.
+    #[error("the snapshot labelled {0:?} was modified since it was taken")]
+    Corrupt(String),
+}
+/// Errors that can occur while creating, loading, or using the keys of the exchange
+#[derive(Error, Debug)]
+pub enum KeyError {
+    /// The database holding the keys could not be reached.
+    #[error("key storage error: {0}")]
+    Storage(#[from] DBConnectorError),
+
+    /// The `keystore` section of the configuration is invalid.
+    #[error("keystore configuration error: {0}")]
+    Config(#[from] config::ConfigError),
+
+    /// Represents I/O errors encountered while reading or writing a keystore file.
+    #[error("keystore i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A keystore file could not be parsed or written.
+    #[error("the keystore file is corrupt: {0}")]
+    Format(#[from] serde_json::Error),
+
+    /// A keystore file was written by a newer version of credible-coin.
+    ///
+    /// - `u32`: The version of the file.
+    #[error("unsupported keystore file version {0}")]
+    UnsupportedVersion(u32),
+
+    /// There is no passphrase to open the encrypted keystore with.
+    #[error("no passphrase for the encrypted keystore, set CREDIBLE_KEYSTORE_PASSPHRASE")]
+    NoPassphrase,
+
+    /// The encrypted keystore was created with another passphrase.
+    #[error("wrong passphrase for the encrypted keystore")]
+    WrongPassphrase,
+
+    /// The encryption key could not be derived from the passphrase.
+    #[error("key derivation error: {0}")]
+    Kdf(#[from] argon2::Error),
+
+    /// The key derivation parameters of the encrypted keystore ask for more memory or time than
+    /// allowed, so the key isn't derived.
+    ///
+    /// - `String`: The parameter which is out of bounds.
+    #[error("the keystore file's key derivation parameters are out of bounds: {0}")]
+    KdfOutOfBounds(String),
+
+    /// A key could not be encrypted.
+    #[error("encryption error: {0}")]
+    Encryption(chacha20poly1305::Error),
+
+    /// A key could not be derived from one of the exchange's keys.
+    #[error("key derivation error: {0}")]
+    Derivation(#[from] bitcoin::bip32::Error),
+
+    /// The purpose to derive addresses under is not one of BIP44, BIP49, BIP84 or BIP86.
+    ///
+    /// - `String`: The rejected purpose.
+    #[error("unknown derivation purpose {0:?}, use bip44, bip49, bip84 or bip86")]
+    UnknownPurpose(String),
+
+    /// A derivation counter handed out every index it can.
+    ///
+    /// - `String`: The name of the counter.
+    #[error("the derivation counter {0:?} is exhausted")]
+    IndicesExhausted(String),
+
+    /// The derivation indices of the encrypted keystore were tampered with, e.g. rolled back to
+    /// hand out an address again.
+    #[error("the derivation indices of the keystore file were tampered with")]
+    TamperedIndices,
+
+    /// The Redis keystore, which holds the keys in plaintext, was selected without allowing it.
+    #[error(
+        "the redis keystore stores keys in plaintext, set `allow_plaintext: true` in the keystore section to use it"
+    )]
+    PlaintextKeystore,
+
+    /// A key in the encrypted keystore was tampered with or moved to another name.
+    ///
+    /// - `String`: The name of the key.
+    #[error("the key named {0:?} could not be decrypted")]
+    Undecryptable(String),
+
+    /// No key was stored under this name.
+    ///
+    /// - `String`: The name which was asked for.
+    #[error("no key named {0:?}, create one with `createPrivateKey {0}`")]
+    Missing(String),
+
+    /// A key was already stored under this name.
+    ///
+    /// - `String`: The name of the existing key.
+    #[error("a key named {0:?} already exists")]
+    Exists(String),
+
+    /// The name can't be used for a key.
+    ///
+    /// - `String`: The rejected name.
+    #[error("invalid key name {0:?}, use letters, digits, '-', '_' and '.'")]
+    InvalidName(String),
+
+    /// The bytes stored under this name aren't a valid secret key.
+    ///
+    /// - `String`: The name of the damaged key.
+    /// - `secp256k1::Error`: Why the bytes were rejected.
+    #[error("the key named {0:?} is not a valid secret key: {1}")]
+    Corrupt(String, #[source] bitcoin::secp256k1::Error),
+
+    /// An address could not be derived from the key.
+    #[error("address error: {0}")]
+    Address(#[from] bitcoin::address::Error),
+
+    /// The address to check a signature against could not be parsed.
+    #[error("invalid address: {0}")]
+    InvalidAddress(#[from] bitcoin::address::ParseError),
+
+    /// A signature, or a value of a keystore file, is not valid base64.
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    /// A signature could not be decoded or checked.
+    #[error("signature error: {0}")]
+    Signature(#[from] bitcoin::sign_message::MessageSignatureError),
+
+    /// The key can't sign for the address, it isn't one of the key's addresses.
+    ///
+    /// - `String`: The address which was asked for.
+    #[error("the key is not the key of {0}")]
+    NotOwned(String),
+
+    /// An address was proven more than once in a proof of reserves.
+    ///
+    /// - `String`: The repeated address.
+    #[error("{0} is proven more than once, its balance only counts once")]
+    Duplicate(String),
+
+    /// The balances of a proof of reserves add up to more than an `i64` holds.
+    ///
+    /// - `String`: The balance or total which overflowed.
+    #[error("{0} overflows")]
+    Overflow(String),
+
+    /// Messages can't be signed for, or checked against, this kind of address.
+    ///
+    /// - `String`: The address which was asked for.
+    #[error("BIP-322 signatures are only supported for P2WPKH, P2SH-P2WPKH and P2TR addresses, not {0}")]
+    UnsupportedAddress(String),
+
+    /// The transaction a BIP-322 signature commits to could not be hashed.
+    #[error("sighash error: {0}")]
+    Sighash(#[from] bitcoin::sighash::Error),
+
+    /// A BIP-322 signature is neither a witness nor a transaction.
+    #[error("invalid BIP-322 signature: {0}")]
+    Encoding(#[from] bitcoin::consensus::encode::Error),
+
+    /// The ledger whose addresses are signed for could not be read.
+    #[error("ledger error: {0}")]
+    Ledger(#[from] LedgerStoreError),
+}
+/// Errors that can occur while saving or fetching the proofs of an attestation run
+#[derive(Error, Debug)]
+pub enum ProofStoreError {
+    /// The instance holding the proofs could not be reached or refused the command.
+    #[error("proof storage error: {0}")]
+    Storage(#[from] DBConnectorError),
+
+    /// A proof could not be serialized, or a stored proof is corrupt.
+    #[error("invalid stored proof: {0}")]
+    Format(#[from] serde_json::Error),
+
+    /// Roots are hex encoded merkle roots.
+    ///
+    /// - `String`: The rejected root.
+    #[error("invalid merkle root {0:?}, expected a hex string")]
+    InvalidRoot(String),
+
+    /// The ledger whose accounts are proven could not be read.
+    #[error("ledger error: {0}")]
+    Ledger(#[from] LedgerStoreError),
+}
+/// Errors that can occur while serving proof requests, or sending them to the server
+#[derive(Error, Debug)]
+pub enum ProofServerError {
+    /// The connection failed.
+    #[error("connection error: {0}")]
+    Io(#[from] io::Error),
+
+    /// A message could not be serialized or parsed.
+    #[error("invalid message: {0}")]
+    Format(#[from] serde_json::Error),
+
+    /// A frame is longer than [`MAX_FRAME_LEN`](crate::proof_server::MAX_FRAME_LEN).
+    ///
+    /// - `usize`: The length of the frame.
+    #[error("frame of {0} bytes is too large")]
+    FrameTooLarge(usize),
+
+    /// The connection sent nothing for
+    /// [`READ_TIMEOUT`](crate::proof_server::READ_TIMEOUT).
+    #[error("connection timed out")]
+    TimedOut,
+
+    /// The connection was closed before the response arrived.
+    #[error("connection closed before the response arrived")]
+    Closed,
+
+    /// The server was started without a proof store, so it has no solvency reports.
+    #[error("this server doesn't serve solvency reports")]
+    NoReports,
+
+    /// The server was started without the secret account IDs are derived with, so it can't
+    /// look accounts up by ID.
+    #[error("this server doesn't serve account IDs")]
+    NoAccountIds,
+
+    /// No account has the ID.
+    ///
+    /// - `String`: The ID which was asked for.
+    #[error("no account has the ID {0}")]
+    UnknownAccount(String),
+
+    /// The solvency reports could not be read.
+    #[error("solvency report error: {0}")]
+    Reports(#[from] ProofStoreError),
+
+    /// The work answering the request panicked.
+    #[error("the request could not be answered")]
+    Aborted,
+}
+/// Errors that can occur while reading or changing the accounts of a ledger
+#[derive(Error, Debug)]
+pub enum LedgerStoreError {
+    /// The instance holding the ledger could not be reached or refused the command.
+    #[error("ledger storage error: {0}")]
+    Storage(#[from] DBConnectorError),
+
+    /// A stored row is corrupt.
+    #[error("invalid stored row: {0}")]
+    Format(#[from] serde_json::Error),
+
+    /// The file holding the ledger could not be read or rewritten.
+    #[error("{0}")]
+    File(#[from] anyhow::Error),
+
+    /// An address is not valid for the active network.
+    #[error(transparent)]
+    Address(#[from] AddressValidationError),
+
+    /// No leaf, or more than one, matches the address.
+    #[error(transparent)]
+    Position(#[from] AddressPositionError),
+
+    /// The active duplicate policy refuses the repeated addresses of the ledger.
+    #[error(transparent)]
+    Duplicates(#[from] DuplicateAddressError),
+
+    /// No row matches the address.
+    ///
+    /// - `String`: The (possibly disambiguated) address which was asked for.
+    #[error("No record for address {0}")]
+    NotFound(String),
+
+    /// The ledger already has a row for the address.
+    ///
+    /// - `String`: The address which was appended.
+    #[error("Record for address {0}, already exists")]
+    Exists(String),
+
+    /// The SQLite database holding the ledger could not be opened, read or changed.
+    #[error("account database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// The SQLite database was made by a newer version with a schema this one can't read.
+    #[error("account database schema version {found} is newer than the supported {supported}")]
+    UnsupportedSchema {
+        /// The schema version of the database
+        found: i32,
+        /// The newest schema version this version can read
+        supported: i32,
+    },
+}
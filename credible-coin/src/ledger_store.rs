@@ -0,0 +1,559 @@
+//! Where the accounts of a ledger are kept, behind the [`LedgerStore`] trait:
+//! 1. [`CsvLedgerStore`]: A CSV file or [binary ledger](crate::utils::binary_ledger)
+//! 2. [`MemoryLedgerStore`]: Memory, for tests
+//! 3. [`RedisLedgerStore`]: A list in the exchange's Redis instance
+//! 4. [`SqliteLedgerStore`](crate::account_database::SqliteLedgerStore): An embedded SQLite
+//!    database, which also keeps the history of every account
+//!
+//! A store holds the raw rows of the ledger, in order. The merkle leaves are made from the rows
+//! once the [`active_duplicate_policy`] has been applied to them, exactly as they are for a file,
+//! so every store of the same rows has the same root.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use redis::{Commands, LposOptions};
+use rs_merkle::MerkleTree;
+use rs_merkle::algorithms::Sha256;
+
+use crate::account_database::{SQLITE_EXTENSIONS, SqliteLedgerStore};
+use crate::cli::exchange::db_connector::{
+    ExchangeNamespace, PooledConnection, RedisPool, exchange_pool, list_items,
+};
+use crate::errors::{DBConnectorError, LedgerStoreError};
+use crate::merkle_tree_entry::MerkleTreeEntry;
+use crate::utils::address_utils::normalize;
+use crate::utils::csv_utils::{
+    append_entries, append_to_file, first_existing, position_in_columns, read_columns,
+    remove_entries, rewrite_columns, update_entry, validate_csv_addresses,
+};
+use crate::utils::duplicates::{active_duplicate_policy, apply_duplicate_policy, split_key};
+use crate::utils::merkle_utils::leaves_from_vectors;
+
+/// The accounts of a ledger: address and value rows, in order.
+///
+/// Implementations only provide access to the raw rows; lookups, the merkle tree and its root
+/// are derived from them. Addresses are stored normalized against the active network, and keys
+/// may be disambiguated (`<ADDRESS>#<N>`) to pick one row of a repeated address.
+pub trait LedgerStore: fmt::Debug + Send + Sync {
+    /// The address and value columns of every row, in order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read.
+    fn columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError>;
+    /// Set the value of the row of `key`. A bare address which is repeated keeps only its first
+    /// row, as [`update_csv_entry`](crate::utils::csv_utils::update_csv_entry) does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no row matches `key` or the ledger can't be written.
+    fn update(&self, key: &str, value: i64) -> Result<(), LedgerStoreError>;
+    /// Append rows to the end of the ledger, in order, returning the number of rows appended.
+    /// Either every row is appended or none is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an address is invalid, given twice or already in the ledger, or if
+    /// the ledger can't be written.
+    fn append(&self, addresses: Vec<String>, values: Vec<i64>) -> Result<usize, LedgerStoreError>;
+    /// Remove every row of `key`, or only the Nth row of a disambiguated key, returning the
+    /// number of rows removed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no row matches `key` or the ledger can't be written.
+    fn remove(&self, key: &str) -> Result<usize, LedgerStoreError>;
+    /// The CSV file or binary ledger the ledger is kept in, if it is kept in one
+    fn file(&self) -> Option<&str> {
+        None
+    }
+    /// The address and value columns of the merkle leaves, after the [`active_duplicate_policy`]
+    /// is applied to the rows
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read or the policy refuses its repeated
+    /// addresses.
+    fn leaf_columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError> {
+        let (addresses, values) = self.columns()?;
+        let (addresses, values, _) =
+            apply_duplicate_policy(addresses, values, active_duplicate_policy())?;
+        Ok((addresses, values))
+    }
+    /// The entry of every merkle leaf, in order
+    ///
+    /// # Errors
+    ///
+    /// See [`LedgerStore::leaf_columns`].
+    fn entries(&self) -> Result<Vec<MerkleTreeEntry>, LedgerStoreError> {
+        let (addresses, values) = self.leaf_columns()?;
+        Ok(MerkleTreeEntry::create_entries_vector(addresses, values))
+    }
+    /// The entry of the leaf at `index`, `None` past the last leaf
+    ///
+    /// # Errors
+    ///
+    /// See [`LedgerStore::leaf_columns`].
+    fn get_by_index(&self, index: usize) -> Result<Option<MerkleTreeEntry>, LedgerStoreError> {
+        let (addresses, values) = self.leaf_columns()?;
+        Ok(addresses
+            .into_iter()
+            .zip(values)
+            .nth(index)
+            .map(|(address, value)| MerkleTreeEntry::new(address, value)))
+    }
+    /// The index of the leaf of `key`, as
+    /// [`get_address_position`](crate::utils::csv_utils::get_address_position) finds it in a file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no leaf matches, or if a bare address matches several leaves and no
+    /// value tells them apart.
+    fn position(&self, key: &str, value: Option<i64>) -> Result<usize, LedgerStoreError> {
+        let (addresses, values) = self.leaf_columns()?;
+        Ok(position_in_columns(&addresses, &values, key, value)?)
+    }
+    /// The index and entry of the leaf of `key`
+    ///
+    /// # Errors
+    ///
+    /// See [`LedgerStore::position`].
+    fn get_by_address(&self, key: &str) -> Result<(usize, MerkleTreeEntry), LedgerStoreError> {
+        let (addresses, values) = self.leaf_columns()?;
+        let index = position_in_columns(&addresses, &values, key, None)?;
+        Ok((
+            index,
+            MerkleTreeEntry::new(addresses[index].clone(), values[index]),
+        ))
+    }
+    /// The merkle tree of the leaves
+    ///
+    /// # Errors
+    ///
+    /// See [`LedgerStore::leaf_columns`].
+    fn tree(&self) -> Result<MerkleTree<Sha256>, LedgerStoreError> {
+        let (addresses, values) = self.leaf_columns()?;
+        Ok(MerkleTree::<Sha256>::from_leaves(&leaves_from_vectors(
+            addresses, values,
+        )))
+    }
+    /// The hex encoded merkle root, `None` if the ledger is empty
+    ///
+    /// # Errors
+    ///
+    /// See [`LedgerStore::leaf_columns`].
+    fn root(&self) -> Result<Option<String>, LedgerStoreError> {
+        Ok(self.tree()?.root_hex())
+    }
+}
+/// Open the ledger in `filename`, picking the store from its extension: a
+/// [`SqliteLedgerStore`](crate::account_database::SqliteLedgerStore) for a `.db` or `.sqlite`
+/// account database, and a [`CsvLedgerStore`] for anything else. Every address is checked against the active
+/// network first.
+///
+/// # Errors
+///
+/// Returns an error if the ledger can't be read or has an invalid address.
+pub fn open_ledger(filename: &str) -> anyhow::Result<Box<dyn LedgerStore>> {
+    let is_database = std::path::Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| SQLITE_EXTENSIONS.contains(&extension));
+    if is_database {
+        let ledger = SqliteLedgerStore::open(filename)?;
+        ledger.validate_addresses()?;
+        return Ok(Box::new(ledger));
+    }
+    validate_csv_addresses(filename)?;
+    Ok(Box::new(CsvLedgerStore::new(filename)))
+}
+/// Normalize the addresses of rows about to be appended
+pub(crate) fn normalized(addresses: &[String]) -> Result<Vec<String>, LedgerStoreError> {
+    Ok(addresses
+        .iter()
+        .map(|address| normalize(address))
+        .collect::<Result<Vec<String>, _>>()?)
+}
+/// A ledger kept in a CSV file or a [binary ledger](crate::utils::binary_ledger). Rows are
+/// appended to the end of a CSV file in place, and any other change rewrites the file atomically,
+/// always under its [`FileLock`](crate::utils::atomic_file::FileLock).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvLedgerStore {
+    filename: String,
+}
+impl CsvLedgerStore {
+    /// The ledger in `filename`
+    pub fn new(filename: &str) -> Self {
+        Self {
+            filename: filename.to_owned(),
+        }
+    }
+}
+impl LedgerStore for CsvLedgerStore {
+    fn columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError> {
+        Ok(read_columns(&self.filename)?)
+    }
+    fn update(&self, key: &str, value: i64) -> Result<(), LedgerStoreError> {
+        let mut updated = false;
+        rewrite_columns(&self.filename, |addresses, values| {
+            updated = update_entry(addresses, values, key, value);
+            updated
+        })?;
+        if !updated {
+            return Err(LedgerStoreError::NotFound(key.to_owned()));
+        }
+        Ok(())
+    }
+    fn append(&self, addresses: Vec<String>, values: Vec<i64>) -> Result<usize, LedgerStoreError> {
+        let addresses = normalized(&addresses)?;
+        match append_to_file(&self.filename, &addresses, &values)? {
+            Some(address) => Err(LedgerStoreError::Exists(address)),
+            None => Ok(addresses.len()),
+        }
+    }
+    fn remove(&self, key: &str) -> Result<usize, LedgerStoreError> {
+        let mut removed = 0;
+        rewrite_columns(&self.filename, |addresses, values| {
+            removed = remove_entries(addresses, values, key);
+            removed > 0
+        })?;
+        if removed == 0 {
+            return Err(LedgerStoreError::NotFound(key.to_owned()));
+        }
+        Ok(removed)
+    }
+    fn file(&self) -> Option<&str> {
+        Some(&self.filename)
+    }
+}
+/// A ledger kept in memory, gone with the store, so it is meant for tests
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::ledger_store::{LedgerStore, MemoryLedgerStore};
+///
+/// let ledger = MemoryLedgerStore::new();
+/// ledger
+///     .append(vec!["17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned()], vec![10])
+///     .unwrap();
+/// ledger.update("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC", 20).unwrap();
+/// let (index, entry) = ledger.get_by_address("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC").unwrap();
+/// assert_eq!((index, entry.entry_value()), (0, 20));
+/// assert!(ledger.root().unwrap().is_some());
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryLedgerStore {
+    columns: Mutex<(Vec<String>, Vec<i64>)>,
+}
+impl MemoryLedgerStore {
+    /// An empty ledger
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A ledger of the provided rows, which may repeat addresses
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the addresses is invalid for the active network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the columns have different lengths.
+    pub fn from_columns(
+        addresses: Vec<String>,
+        values: Vec<i64>,
+    ) -> Result<Self, LedgerStoreError> {
+        assert_eq!(addresses.len(), values.len());
+        Ok(Self {
+            columns: Mutex::new((normalized(&addresses)?, values)),
+        })
+    }
+}
+impl LedgerStore for MemoryLedgerStore {
+    fn columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError> {
+        Ok(self.columns.lock().unwrap().clone())
+    }
+    fn update(&self, key: &str, value: i64) -> Result<(), LedgerStoreError> {
+        let (addresses, values) = &mut *self.columns.lock().unwrap();
+        if !update_entry(addresses, values, key, value) {
+            return Err(LedgerStoreError::NotFound(key.to_owned()));
+        }
+        Ok(())
+    }
+    fn append(&self, addresses: Vec<String>, values: Vec<i64>) -> Result<usize, LedgerStoreError> {
+        let addresses = normalized(&addresses)?;
+        let (rows, row_values) = &mut *self.columns.lock().unwrap();
+        if let Some(address) = append_entries(rows, row_values, &addresses, &values) {
+            return Err(LedgerStoreError::Exists(address));
+        }
+        Ok(addresses.len())
+    }
+    fn remove(&self, key: &str) -> Result<usize, LedgerStoreError> {
+        let (addresses, values) = &mut *self.columns.lock().unwrap();
+        match remove_entries(addresses, values, key) {
+            0 => Err(LedgerStoreError::NotFound(key.to_owned())),
+            removed => Ok(removed),
+        }
+    }
+}
+/// A ledger kept as a list in the exchange's Redis instance, under
+/// `exchange:{exchange_id}:ledgers:{name}`. Every row is an `[address, value]` JSON array.
+///
+/// A hash under `exchange:{exchange_id}:ledger-indices:{name}` holds the values of the rows of
+/// each address, so a change only touches the rows it is about: appended rows are pushed to the
+/// end of the list, and a row is found with `LPOS` to be set or removed in place. This needs
+/// Redis 6.0.6 or later. Changes are made in a transaction watching the list and the index, so
+/// concurrent writers can't lose each other's rows.
+#[derive(Debug, Clone)]
+pub struct RedisLedgerStore {
+    pool: RedisPool,
+    key: String,
+    index: String,
+}
+/// What a row is replaced with before it is removed with `LREM`, which removes rows by value
+const REMOVED_ROW: &str = "null";
+
+impl RedisLedgerStore {
+    /// The ledger called `name` of the exchange set in `credible_config`. The instance is only
+    /// connected to when the ledger is read or changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `redis` section of the configuration is invalid.
+    pub fn new(name: &str) -> Result<Self, LedgerStoreError> {
+        Ok(Self::with_pool(
+            exchange_pool()?,
+            &ExchangeNamespace::from_config()?,
+            name,
+        ))
+    }
+    /// The ledger called `name` of `namespace`, in the instance of `pool`
+    pub fn with_pool(pool: RedisPool, namespace: &ExchangeNamespace, name: &str) -> Self {
+        Self {
+            pool,
+            key: namespace.ledger(name),
+            index: namespace.ledger_index(name),
+        }
+    }
+    fn connection(&self) -> Result<PooledConnection, LedgerStoreError> {
+        Ok(self.pool.get().map_err(DBConnectorError::from)?)
+    }
+    /// Run `change` in a transaction watching the list and its index. `change` reads what it
+    /// needs with the connection and queues its writes on the pipeline, which are only sent if
+    /// it succeeds. It is run again if another client changed the ledger in the meantime.
+    fn transaction<T>(
+        &self,
+        mut change: impl FnMut(
+            &mut redis::Connection,
+            &mut redis::Pipeline,
+        ) -> Result<T, LedgerStoreError>,
+    ) -> Result<T, LedgerStoreError> {
+        let mut outcome = None;
+        redis::transaction(
+            &mut *self.connection()?,
+            &[&self.key, &self.index],
+            |conn, pipe| {
+                let result = change(conn, pipe);
+                if result.is_ok() && pipe.query::<Option<()>>(conn)?.is_none() {
+                    return Ok(None);
+                }
+                outcome = Some(result);
+                Ok(Some(()))
+            },
+        )
+        .map_err(DBConnectorError::from)?;
+        outcome.expect("the transaction ran to completion")
+    }
+    /// The values of the rows of each of `addresses`, in order. The index of a ledger written
+    /// before it had one is built from its rows, and the writes doing so are queued on `pipe`.
+    fn indexed_values(
+        &self,
+        conn: &mut redis::Connection,
+        pipe: &mut redis::Pipeline,
+        addresses: &[String],
+    ) -> Result<Vec<Vec<i64>>, LedgerStoreError> {
+        if !conn
+            .exists::<_, bool>(&self.index)
+            .map_err(DBConnectorError::from)?
+        {
+            let (rows, values) = decode_rows(&list_items(conn, &self.key)?)?;
+            let mut index: HashMap<String, Vec<i64>> = HashMap::new();
+            for (address, value) in rows.into_iter().zip(values) {
+                index.entry(address).or_default().push(value);
+            }
+            if !index.is_empty() {
+                let fields = index
+                    .iter()
+                    .map(|(address, values)| Ok((address.clone(), serde_json::to_string(values)?)))
+                    .collect::<Result<Vec<_>, serde_json::Error>>()?;
+                pipe.hset_multiple(&self.index, &fields).ignore();
+            }
+            return Ok(addresses
+                .iter()
+                .map(|address| index.get(address).cloned().unwrap_or_default())
+                .collect());
+        }
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fields: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(&self.index)
+            .arg(addresses)
+            .query(conn)
+            .map_err(DBConnectorError::from)?;
+        Ok(fields
+            .into_iter()
+            .map(|field| field.map_or(Ok(Vec::new()), |field| serde_json::from_str(&field)))
+            .collect::<Result<_, _>>()?)
+    }
+    /// Queue writing the values of the rows of `address` to the index, or removing it from the
+    /// index if it has no rows left
+    fn queue_index(
+        &self,
+        pipe: &mut redis::Pipeline,
+        address: &str,
+        values: &[i64],
+    ) -> Result<(), LedgerStoreError> {
+        if values.is_empty() {
+            pipe.hdel(&self.index, address).ignore();
+        } else {
+            pipe.hset(&self.index, address, serde_json::to_string(values)?)
+                .ignore();
+        }
+        Ok(())
+    }
+    /// The positions in the list of the rows of `address` whose values are `values`, in order
+    fn row_positions(
+        &self,
+        conn: &mut redis::Connection,
+        address: &str,
+        values: &[i64],
+    ) -> Result<Vec<isize>, LedgerStoreError> {
+        let mut positions = Vec::with_capacity(values.len());
+        for (row, value) in values.iter().enumerate() {
+            // Rows with the same address and value are told apart by their rank among them
+            let rank = values[..=row]
+                .iter()
+                .filter(|other| *other == value)
+                .count();
+            let position: Option<isize> = conn
+                .lpos(
+                    &self.key,
+                    encode_row(address, *value),
+                    LposOptions::default().rank(isize::try_from(rank).unwrap_or(isize::MAX)),
+                )
+                .map_err(DBConnectorError::from)?;
+            // A row missing from the list means the index is out of date, so the row is gone
+            positions.push(position.ok_or_else(|| LedgerStoreError::NotFound(address.to_owned()))?);
+        }
+        Ok(positions)
+    }
+}
+/// The columns of the `[address, value]` rows of a stored list
+fn decode_rows(items: &[String]) -> Result<(Vec<String>, Vec<i64>), serde_json::Error> {
+    let rows = items
+        .iter()
+        .map(|item| serde_json::from_str::<(String, i64)>(item))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows.into_iter().unzip())
+}
+/// An `[address, value]` row, as it is stored in a list
+fn encode_row(address: &str, value: i64) -> String {
+    serde_json::json!((address, value)).to_string()
+}
+impl LedgerStore for RedisLedgerStore {
+    fn columns(&self) -> Result<(Vec<String>, Vec<i64>), LedgerStoreError> {
+        Ok(decode_rows(&list_items(
+            &mut *self.connection()?,
+            &self.key,
+        )?)?)
+    }
+    fn update(&self, key: &str, value: i64) -> Result<(), LedgerStoreError> {
+        let (address, occurrence) = split_key(key);
+        self.transaction(|conn, pipe| {
+            let mut values = self
+                .indexed_values(conn, pipe, &[address.to_owned()])?
+                .remove(0);
+            let positions = self.row_positions(conn, address, &values)?;
+            let not_found = || LedgerStoreError::NotFound(key.to_owned());
+            if let Some(occurrence) = occurrence {
+                let row = occurrence.checked_sub(1).ok_or_else(not_found)?;
+                let position = *positions.get(row).ok_or_else(not_found)?;
+                pipe.lset(&self.key, position, encode_row(address, value))
+                    .ignore();
+                values[row] = value;
+            } else {
+                // A bare address keeps only its first row, as `update_entry` does
+                let (&first, rest) = positions.split_first().ok_or_else(not_found)?;
+                pipe.lset(&self.key, first, encode_row(address, value))
+                    .ignore();
+                for &position in rest {
+                    pipe.lset(&self.key, position, REMOVED_ROW).ignore();
+                }
+                if !rest.is_empty() {
+                    pipe.lrem(&self.key, 0, REMOVED_ROW).ignore();
+                }
+                values = vec![value];
+            }
+            self.queue_index(pipe, address, &values)
+        })
+    }
+    fn append(&self, addresses: Vec<String>, values: Vec<i64>) -> Result<usize, LedgerStoreError> {
+        let addresses = normalized(&addresses)?;
+        self.transaction(|conn, pipe| {
+            let existing = self.indexed_values(conn, pipe, &addresses)?;
+            let taken: Vec<String> = addresses
+                .iter()
+                .zip(&existing)
+                .filter(|(_, rows)| !rows.is_empty())
+                .map(|(address, _)| address.clone())
+                .collect();
+            if let Some(address) = first_existing(&taken, &addresses) {
+                return Err(LedgerStoreError::Exists(address));
+            }
+            if addresses.is_empty() {
+                return Ok(0);
+            }
+            let rows: Vec<String> = addresses
+                .iter()
+                .zip(&values)
+                .map(|(address, value)| encode_row(address, *value))
+                .collect();
+            pipe.rpush(&self.key, rows).ignore();
+            for (address, value) in addresses.iter().zip(&values) {
+                self.queue_index(pipe, address, &[*value])?;
+            }
+            Ok(addresses.len())
+        })
+    }
+    fn remove(&self, key: &str) -> Result<usize, LedgerStoreError> {
+        let (address, occurrence) = split_key(key);
+        self.transaction(|conn, pipe| {
+            let mut values = self
+                .indexed_values(conn, pipe, &[address.to_owned()])?
+                .remove(0);
+            let positions = self.row_positions(conn, address, &values)?;
+            let removed: Vec<usize> = match occurrence {
+                Some(occurrence) if (1..=positions.len()).contains(&occurrence) => {
+                    vec![occurrence - 1]
+                }
+                Some(_) => Vec::new(),
+                None => (0..positions.len()).collect(),
+            };
+            if removed.is_empty() {
+                return Err(LedgerStoreError::NotFound(key.to_owned()));
+            }
+            for &row in &removed {
+                pipe.lset(&self.key, positions[row], REMOVED_ROW).ignore();
+            }
+            pipe.lrem(&self.key, 0, REMOVED_ROW).ignore();
+            for &row in removed.iter().rev() {
+                values.remove(row);
+            }
+            self.queue_index(pipe, address, &values)?;
+            Ok(removed.len())
+        })
+    }
+}
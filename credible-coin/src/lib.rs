@@ -86,12 +86,17 @@ use std::io::{stdin, stdout, Read, Write};
 #[macro_use]
 extern crate lazy_static;
 
+pub mod account_database;
 pub mod accumulator;
 pub mod cli;
 pub mod credible_config;
 pub mod emp;
 pub mod errors;
+pub mod ledger_store;
 pub mod merkle_tree_entry;
+pub mod proof_api;
+pub mod proof_server;
+pub mod proof_store;
 pub mod utils;
 pub(crate) fn _pause() {
     let mut stdout = stdout();
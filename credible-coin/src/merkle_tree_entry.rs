@@ -112,7 +112,7 @@ impl MerkleTreeEntry {
             // println!("Address= {}, Value= {}", a, v);
             entries.push(MerkleTreeEntry::new(a.to_string(), *v));
         }
-        println!("Address Count: {:?}", entries.len());
+        log::debug!("Address Count: {:?}", entries.len());
         entries
     }
 
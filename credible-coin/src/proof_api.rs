@@ -0,0 +1,147 @@
+//! An HTTP API customers use to check their balances are committed to by the exchange's root,
+//! served by `exchange serve`.
+//!
+//! Every endpoint answers a `GET` with JSON, from the same snapshot of the ledger as the
+//! [`ProofServer`] it wraps:
+//! 1. `/root`: The hex encoded merkle root, as a [`Root`]
+//! 2. `/proof/address/{address}`: The [`InclusionProof`] of the account of `address`, which may
+//!    be a disambiguated key (`<ADDRESS>%23<N>`, the `#` percent-encoded)
+//! 3. `/proof/account/{id}`: The [`InclusionProof`] of the account with the ID the exchange
+//!    gave its customer, see [`account_id`](crate::proof_server::account_id). Only served if
+//!    the server was given the secret the IDs are derived with.
+//! 4. `/solvency-report`: The [`SolvencyReport`](crate::proof_store::SolvencyReport) the
+//!    exchange published for the root `/root` returns
+//!
+//! Accounts are only looked up by address or by an ID derived from it with the exchange's
+//! secret, so nobody can walk the leaves to list every customer's balance.
+//!
+//! A request that can't be answered gets an [`ApiError`], with a `404` status if there is
+//! nothing to answer with and a `400` one if the request is invalid.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use credible_coin::ledger_store::{LedgerStore, MemoryLedgerStore};
+//! use credible_coin::proof_api::serve_http;
+//! use credible_coin::proof_server::{MembershipProver, ProofServer};
+//! use tokio::net::TcpListener;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let ledger: Arc<dyn LedgerStore> = Arc::new(
+//!     MemoryLedgerStore::from_columns(
+//!         vec!["17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned()],
+//!         vec![10],
+//!     )
+//!     .unwrap(),
+//! );
+//! let server = Arc::new(ProofServer::new(ledger, MembershipProver::MerklePath, None).unwrap());
+//! let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+//! println!("Customers can fetch their proofs from http://{}", listener.local_addr().unwrap());
+//! tokio::spawn(serve_http(server, listener));
+//! # });
+//! ```
+use std::io;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::errors::{AddressPositionError, LedgerStoreError, ProofServerError};
+use crate::proof_server::ProofServer;
+use crate::proof_store::InclusionProof;
+
+/// The address the API listens on unless told otherwise
+pub const DEFAULT_HTTP_ADDRESS: &str = "127.0.0.1:8080";
+
+/// The body of `/root`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Root {
+    /// The hex encoded merkle root, `None` if the ledger is empty
+    pub root: Option<String>,
+}
+/// The body of every failed request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    /// Why the request could not be answered
+    pub error: String,
+}
+impl ApiError {
+    fn reply(status: StatusCode, err: impl ToString) -> Response {
+        let body = Self {
+            error: err.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+/// The status a failed ledger lookup is answered with
+fn ledger_status(err: &LedgerStoreError) -> StatusCode {
+    match err {
+        LedgerStoreError::NotFound(_)
+        | LedgerStoreError::Position(AddressPositionError::NoMatchingAddress(_)) => {
+            StatusCode::NOT_FOUND
+        }
+        LedgerStoreError::Address(_) | LedgerStoreError::Position(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+fn proof_response(proof: Result<InclusionProof, LedgerStoreError>) -> Response {
+    match proof {
+        Ok(proof) => Json(proof).into_response(),
+        Err(err) => ApiError::reply(ledger_status(&err), err),
+    }
+}
+async fn root(State(server): State<Arc<ProofServer>>) -> Json<Root> {
+    Json(Root {
+        root: server.root(),
+    })
+}
+async fn address_proof(
+    State(server): State<Arc<ProofServer>>,
+    Path(address): Path<String>,
+) -> Response {
+    proof_response(server.inclusion_proof(&address))
+}
+async fn account_proof(State(server): State<Arc<ProofServer>>, Path(id): Path<String>) -> Response {
+    match server.account_proof(&id) {
+        Ok(proof) => Json(proof).into_response(),
+        Err(err @ (ProofServerError::NoAccountIds | ProofServerError::UnknownAccount(_))) => {
+            ApiError::reply(StatusCode::NOT_FOUND, err)
+        }
+        Err(err) => ApiError::reply(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+async fn solvency_report(State(server): State<Arc<ProofServer>>) -> Response {
+    match server.solvency_report().await {
+        Ok(Some(report)) => Json(report).into_response(),
+        Ok(None) => ApiError::reply(
+            StatusCode::NOT_FOUND,
+            "No solvency report has been published for the root",
+        ),
+        Err(err @ ProofServerError::NoReports) => ApiError::reply(StatusCode::NOT_FOUND, err),
+        Err(err) => ApiError::reply(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+/// The routes of the API, answered by `server`
+pub fn router(server: Arc<ProofServer>) -> Router {
+    Router::new()
+        .route("/root", get(root))
+        .route("/proof/address/:address", get(address_proof))
+        .route("/proof/account/:id", get(account_proof))
+        .route("/solvency-report", get(solvency_report))
+        .with_state(server)
+}
+/// Answer the requests made to `listener` with the API of `server`
+///
+/// # Errors
+///
+/// Only returns if the listener fails.
+pub async fn serve_http(server: Arc<ProofServer>, listener: TcpListener) -> io::Result<()> {
+    axum::serve(listener, router(server)).await
+}
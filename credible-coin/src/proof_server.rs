@@ -0,0 +1,556 @@
+//! A server answering the questions of verifiers and customers about the exchange's ledger over
+//! TCP, and the client side of its protocol.
+//!
+//! Every message is a frame: its length as a big-endian `u32`, followed by that many bytes of
+//! JSON. A client sends a [`Request`] frame and reads back one [`Response`] frame, and may send
+//! any number of requests over the same connection, but a connection which sends nothing for
+//! [`READ_TIMEOUT`] is closed. Requests are answered from a snapshot of the ledger and its merkle
+//! tree, taken when the server starts and on every [`ProofServer::reload`], so every answer
+//! matches the root [`Request::GetRoot`] returns.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use credible_coin::ledger_store::{LedgerStore, MemoryLedgerStore};
+//! use credible_coin::proof_server::{
+//!     MembershipProver, ProofServer, Request, Response, send_request,
+//! };
+//! use tokio::net::{TcpListener, TcpStream};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let ledger: Arc<dyn LedgerStore> = Arc::new(
+//!     MemoryLedgerStore::from_columns(
+//!         vec!["17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned()],
+//!         vec![10],
+//!     )
+//!     .unwrap(),
+//! );
+//! let server = Arc::new(ProofServer::new(ledger, MembershipProver::MerklePath, None).unwrap());
+//! let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+//! let address = listener.local_addr().unwrap();
+//! tokio::spawn(Arc::clone(&server).serve(listener));
+//!
+//! let mut stream = TcpStream::connect(address).await.unwrap();
+//! let response = send_request(&mut stream, &Request::GetRoot).await.unwrap();
+//! assert_eq!(response, Response::Root { root: server.root() });
+//! # });
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{Hash, HashEngine, sha256};
+use rs_merkle::MerkleTree;
+use rs_merkle::algorithms::Sha256;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Semaphore, oneshot};
+
+use crate::accumulator::merkle_path::path_proves;
+use crate::accumulator::{AbstractAccumulator, MembershipProof};
+use crate::errors::{AddressPositionError, LedgerStoreError, ProofServerError};
+use crate::ledger_store::LedgerStore;
+use crate::merkle_tree_entry::MerkleTreeEntry;
+use crate::proof_store::{InclusionProof, ProofStore, SolvencyReport};
+use crate::utils::csv_utils::position_in_columns;
+use crate::utils::duplicates::{account_keys, normalize_key};
+use crate::utils::merkle_utils::leaves_from_vectors;
+
+/// The address the server listens on unless told otherwise
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:2300";
+/// The longest frame either side accepts, in bytes
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+/// How long the server waits for the next frame of a connection before closing it
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// The most membership proofs an accumulator works on at once, each on a thread of its own
+pub const MAX_CONCURRENT_PROOFS: usize = 4;
+/// The environment variable holding the secret the exchange derives its customers' account IDs
+/// with, see [`account_id`]
+pub const ACCOUNT_ID_SECRET_VAR: &str = "CREDIBLE_ACCOUNT_ID_SECRET";
+
+/// The ID of the account with `key` (its address, or its disambiguated key if the address is
+/// repeated): the hex encoded HMAC-SHA256 of the key under the exchange's `secret`.
+///
+/// The exchange hands every customer the ID of their account. Without the secret, nobody can
+/// tell which account an ID belongs to, or guess the IDs of the other accounts.
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::proof_server::account_id;
+///
+/// let id = account_id("secret", "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC");
+/// assert_eq!(id.len(), 64);
+/// assert_ne!(id, account_id("other secret", "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC"));
+/// ```
+pub fn account_id(secret: &str, key: &str) -> String {
+    let mut engine = HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(key.as_bytes());
+    Hmac::<sha256::Hash>::from_engine(engine).to_string()
+}
+/// The secret account IDs are derived with, read from [`ACCOUNT_ID_SECRET_VAR`], or `None` if
+/// it isn't set
+pub fn account_id_secret() -> Option<String> {
+    std::env::var(ACCOUNT_ID_SECRET_VAR)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+/// A question about the exchange's ledger. Addresses may be disambiguated keys
+/// (`<ADDRESS>#<N>`) to pick one row of a repeated address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    /// The hex encoded merkle root of the ledger
+    GetRoot,
+    /// Whether the account of an address is a member of the ledger, as the server's
+    /// [`MembershipProver`] proves it
+    ProveMembership {
+        /// The address of the account
+        address: String,
+    },
+    /// The merkle path proving the balance of an account is committed to by the root
+    GetProof {
+        /// The address of the account
+        address: String,
+    },
+    /// The merkle path of the account with an ID, see [`account_id`]
+    GetAccountProof {
+        /// The ID of the account
+        id: String,
+    },
+    /// The solvency report the exchange published for the root [`Request::GetRoot`] returns
+    GetSolvencyReport,
+}
+/// The answer to a [`Request`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    /// The answer to [`Request::GetRoot`], `None` if the ledger is empty
+    Root {
+        /// The hex encoded merkle root
+        root: Option<String>,
+    },
+    /// The answer to [`Request::ProveMembership`]
+    Membership {
+        /// The normalized address which was asked about
+        address: String,
+        /// The index of the account's leaf, `None` if no account has the address
+        index: Option<usize>,
+        /// The prover's proof
+        proof: MembershipProof,
+    },
+    /// The answer to [`Request::GetProof`] and [`Request::GetAccountProof`]
+    Proof(InclusionProof),
+    /// The answer to [`Request::GetSolvencyReport`], `None` if no report was published for the
+    /// root
+    SolvencyReport(Option<SolvencyReport>),
+    /// The request could not be answered
+    Error {
+        /// Why the request could not be answered
+        message: String,
+    },
+}
+impl Response {
+    fn error(err: impl fmt::Display) -> Self {
+        Self::Error {
+            message: err.to_string(),
+        }
+    }
+}
+/// Read one frame, returning `None` if the connection was closed before it started
+///
+/// # Errors
+///
+/// Returns an error if the connection fails or is closed in the middle of the frame, or if the
+/// frame is longer than [`MAX_FRAME_LEN`].
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<Vec<u8>>, ProofServerError> {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let length = usize::try_from(u32::from_be_bytes(length)).unwrap_or(usize::MAX);
+    if length > MAX_FRAME_LEN {
+        return Err(ProofServerError::FrameTooLarge(length));
+    }
+    // The frame grows as its bytes arrive, so a header alone can't make the reader allocate
+    let mut frame = Vec::new();
+    (&mut *reader)
+        .take(u64::from(u32::try_from(length).unwrap_or(u32::MAX)))
+        .read_to_end(&mut frame)
+        .await?;
+    if frame.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(Some(frame))
+}
+/// Write one frame
+///
+/// # Errors
+///
+/// Returns an error if the connection fails or the frame is longer than [`MAX_FRAME_LEN`].
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    frame: &[u8],
+) -> Result<(), ProofServerError> {
+    let length = u32::try_from(frame.len())
+        .ok()
+        .filter(|_| frame.len() <= MAX_FRAME_LEN)
+        .ok_or(ProofServerError::FrameTooLarge(frame.len()))?;
+    writer.write_all(&length.to_be_bytes()).await?;
+    writer.write_all(frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+/// Send `request` to the server at the other end of `stream` and wait for its response
+///
+/// # Errors
+///
+/// Returns an error if the connection fails or is closed before the response arrives, or if the
+/// response can't be parsed.
+pub async fn send_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    request: &Request,
+) -> Result<Response, ProofServerError> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+    let frame = read_frame(stream).await?.ok_or(ProofServerError::Closed)?;
+    Ok(serde_json::from_slice(&frame)?)
+}
+/// Run `work` on a thread of its own and wait for it. The accumulators and stores block, and the
+/// emp-zk accumulator starts a runtime of its own, which it can't do on one of tokio's threads,
+/// blocking ones included. Returns `None` if `work` panicked.
+async fn off_runtime<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+    receiver.await.ok()
+}
+/// How a [`ProofServer`] proves membership
+#[derive(Clone)]
+pub enum MembershipProver {
+    /// With the merkle path of the account's leaf in the server's snapshot of the ledger
+    MerklePath,
+    /// With an accumulator over the exchange's secret set, e.g. the emp-zk
+    /// [`DeltaAccumulator`](crate::accumulator::value_delta::DeltaAccumulator), given the
+    /// account's entry and index in the snapshot. At most [`MAX_CONCURRENT_PROOFS`] are worked
+    /// on at once.
+    Accumulator(Arc<dyn AbstractAccumulator + Send + Sync>),
+}
+impl fmt::Debug for MembershipProver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MerklePath => f.write_str("MerklePath"),
+            Self::Accumulator(_) => f.write_str("Accumulator"),
+        }
+    }
+}
+/// The leaves of the ledger and their tree, as of the last reload
+struct LedgerSnapshot {
+    addresses: Vec<String>,
+    values: Vec<i64>,
+    leaves: Vec<[u8; 32]>,
+    tree: MerkleTree<Sha256>,
+    /// The index and key of the account of every ID, empty if the server has no secret to
+    /// derive them with
+    accounts: HashMap<String, (usize, String)>,
+}
+impl LedgerSnapshot {
+    fn new(ledger: &dyn LedgerStore, secret: Option<&str>) -> Result<Self, LedgerStoreError> {
+        let (addresses, values) = ledger.leaf_columns()?;
+        // The tree is built from the same read as the columns, so they can't disagree
+        let leaves = leaves_from_vectors(addresses.clone(), values.clone());
+        let accounts = match secret {
+            Some(secret) => account_keys(&addresses)
+                .into_iter()
+                .enumerate()
+                .map(|(index, key)| (account_id(secret, &key), (index, key)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            tree: MerkleTree::<Sha256>::from_leaves(&leaves),
+            addresses,
+            values,
+            leaves,
+            accounts,
+        })
+    }
+    fn position(&self, key: &str) -> Result<usize, AddressPositionError> {
+        position_in_columns(&self.addresses, &self.values, key, None)
+    }
+    /// The inclusion proof of the account at `index`, under `key`
+    fn proof(&self, key: String, index: usize) -> InclusionProof {
+        InclusionProof {
+            root: self.tree.root_hex().unwrap_or_default(),
+            address: key,
+            value: self.values[index],
+            index,
+            leaf_count: self.addresses.len(),
+            proof_hashes: self.tree.proof(&[index]).proof_hashes_hex(),
+        }
+    }
+}
+/// Answers [`Request`]s about a ledger, see the [module documentation](self) for the protocol
+pub struct ProofServer {
+    ledger: Arc<dyn LedgerStore>,
+    prover: MembershipProver,
+    /// Bounds the accumulator proofs worked on at once
+    proofs: Arc<Semaphore>,
+    /// The secret account IDs are derived with, if the server serves them
+    account_id_secret: Option<String>,
+    /// Where the solvency reports are published, if the server serves them
+    reports: Option<Arc<dyn ProofStore>>,
+    snapshot: RwLock<Arc<LedgerSnapshot>>,
+}
+impl fmt::Debug for ProofServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProofServer")
+            .field("ledger", &self.ledger)
+            .field("prover", &self.prover)
+            .field("reports", &self.reports)
+            .field("root", &self.root())
+            .finish_non_exhaustive()
+    }
+}
+impl ProofServer {
+    /// A server answering from `ledger`, proving membership with `prover` and serving the
+    /// solvency reports published to `reports`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read.
+    pub fn new(
+        ledger: Arc<dyn LedgerStore>,
+        prover: MembershipProver,
+        reports: Option<Arc<dyn ProofStore>>,
+    ) -> Result<Self, LedgerStoreError> {
+        let snapshot = LedgerSnapshot::new(ledger.as_ref(), None)?;
+        Ok(Self {
+            ledger,
+            prover,
+            proofs: Arc::new(Semaphore::new(MAX_CONCURRENT_PROOFS)),
+            account_id_secret: None,
+            reports,
+            snapshot: RwLock::new(Arc::new(snapshot)),
+        })
+    }
+    /// Serve the proofs of accounts by their ID too, deriving the IDs with `secret`, see
+    /// [`account_id`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read.
+    pub fn with_account_ids(mut self, secret: String) -> Result<Self, LedgerStoreError> {
+        self.account_id_secret = Some(secret);
+        self.reload()?;
+        Ok(self)
+    }
+    fn snapshot(&self) -> Arc<LedgerSnapshot> {
+        Arc::clone(&self.snapshot.read().unwrap())
+    }
+    /// The merkle root requests are answered against
+    pub fn root(&self) -> Option<String> {
+        self.snapshot().tree.root_hex()
+    }
+    /// Take a new snapshot of the ledger, returning its root. Requests being answered keep the
+    /// snapshot they started with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read, in which case the old snapshot is kept.
+    pub fn reload(&self) -> Result<Option<String>, LedgerStoreError> {
+        let snapshot =
+            LedgerSnapshot::new(self.ledger.as_ref(), self.account_id_secret.as_deref())?;
+        let root = snapshot.tree.root_hex();
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+        Ok(root)
+    }
+    /// Reload the ledger every `period`, on a task of its own. Failed reloads are logged and
+    /// the old snapshot is kept until the next one.
+    pub fn reload_every(self: &Arc<Self>, period: Duration) -> tokio::task::JoinHandle<()> {
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let reloading = Arc::clone(&server);
+                match tokio::task::spawn_blocking(move || reloading.reload()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(err)) => log::warn!("Failed to reload the ledger: {err}"),
+                    Err(err) => log::warn!("Failed to reload the ledger: {err}"),
+                }
+            }
+        })
+    }
+    /// The inclusion proof of the account of `address`, which may be a disambiguated key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is invalid, or no account or more than one has it.
+    pub fn inclusion_proof(&self, address: &str) -> Result<InclusionProof, LedgerStoreError> {
+        let snapshot = self.snapshot();
+        let key = normalize_key(address)?;
+        let index = snapshot.position(&key)?;
+        Ok(snapshot.proof(key, index))
+    }
+    /// The inclusion proof of the account with the ID `id`, see [`account_id`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server doesn't serve account IDs, or no account has the ID.
+    pub fn account_proof(&self, id: &str) -> Result<InclusionProof, ProofServerError> {
+        if self.account_id_secret.is_none() {
+            return Err(ProofServerError::NoAccountIds);
+        }
+        let snapshot = self.snapshot();
+        let (index, key) = snapshot
+            .accounts
+            .get(id)
+            .ok_or_else(|| ProofServerError::UnknownAccount(id.to_owned()))?;
+        Ok(snapshot.proof(key.clone(), *index))
+    }
+    /// The solvency report the exchange published for the [`root`](Self::root) requests are
+    /// answered against, `None` if no report was published for it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server doesn't serve solvency reports, or they can't be read.
+    pub async fn solvency_report(&self) -> Result<Option<SolvencyReport>, ProofServerError> {
+        let reports = self.reports.clone().ok_or(ProofServerError::NoReports)?;
+        let Some(root) = self.root() else {
+            return Ok(None);
+        };
+        off_runtime(move || reports.get_report(&root))
+            .await
+            .ok_or(ProofServerError::Aborted)?
+            .map_err(ProofServerError::from)
+    }
+    /// Answer one request
+    pub async fn respond(&self, request: Request) -> Response {
+        match request {
+            Request::GetRoot => Response::Root { root: self.root() },
+            Request::ProveMembership { address } => {
+                self.prove_membership(&self.snapshot(), &address).await
+            }
+            Request::GetProof { address } => match self.inclusion_proof(&address) {
+                Ok(proof) => Response::Proof(proof),
+                Err(err) => Response::error(err),
+            },
+            Request::GetAccountProof { id } => match self.account_proof(&id) {
+                Ok(proof) => Response::Proof(proof),
+                Err(err) => Response::error(err),
+            },
+            Request::GetSolvencyReport => match self.solvency_report().await {
+                Ok(report) => Response::SolvencyReport(report),
+                Err(err) => Response::error(err),
+            },
+        }
+    }
+    async fn prove_membership(&self, snapshot: &LedgerSnapshot, address: &str) -> Response {
+        let key = match normalize_key(address) {
+            Ok(key) => key,
+            Err(err) => return Response::error(err),
+        };
+        let index = match snapshot.position(&key) {
+            Ok(index) => index,
+            Err(AddressPositionError::NoMatchingAddress(_)) => {
+                return Response::Membership {
+                    address: key,
+                    index: None,
+                    proof: MembershipProof::new(false),
+                };
+            }
+            Err(err) => return Response::error(err),
+        };
+        let entry = MerkleTreeEntry::new(snapshot.addresses[index].clone(), snapshot.values[index]);
+        let proof = match &self.prover {
+            MembershipProver::MerklePath => {
+                MembershipProof::new(path_proves(&snapshot.tree, &snapshot.leaves, &entry, index))
+            }
+            MembershipProver::Accumulator(accumulator) => {
+                match self.prove_with(Arc::clone(accumulator), entry, index).await {
+                    Ok(proof) => proof,
+                    Err(response) => return response,
+                }
+            }
+        };
+        Response::Membership {
+            address: key,
+            index: Some(index),
+            proof,
+        }
+    }
+    /// Prove `entry` is a member with `accumulator`, once one of the
+    /// [`MAX_CONCURRENT_PROOFS`] permits is free
+    async fn prove_with(
+        &self,
+        accumulator: Arc<dyn AbstractAccumulator + Send + Sync>,
+        entry: MerkleTreeEntry,
+        index: usize,
+    ) -> Result<MembershipProof, Response> {
+        let permit = Arc::clone(&self.proofs)
+            .acquire_owned()
+            .await
+            .map_err(Response::error)?;
+        // The permit is only released when the proof is done, even if the request is dropped
+        let proof = off_runtime(move || {
+            let _permit = permit;
+            accumulator.prove_member(&entry, Some(index))
+        })
+        .await;
+        match proof {
+            Some(Ok(proof)) => Ok(proof),
+            Some(Err(err)) => Err(Response::error(format!("{err:#}"))),
+            None => Err(Response::error(ProofServerError::Aborted)),
+        }
+    }
+    /// Answer requests from every connection made to `listener`, each on a task of its own
+    ///
+    /// # Errors
+    ///
+    /// Only returns if the listener fails. Failed connections are logged and dropped.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(err) = server.handle_connection(stream).await {
+                    log::warn!("Connection from {peer} failed: {err}");
+                }
+            });
+        }
+    }
+    async fn handle_connection(&self, mut stream: TcpStream) -> Result<(), ProofServerError> {
+        loop {
+            let frame = tokio::time::timeout(READ_TIMEOUT, read_frame(&mut stream))
+                .await
+                .map_err(|_elapsed| ProofServerError::TimedOut)?;
+            let frame = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(()),
+                Err(err @ ProofServerError::FrameTooLarge(_)) => {
+                    // The rest of the frame can't be skipped safely, so the connection is closed
+                    let response = serde_json::to_vec(&Response::error(&err))?;
+                    write_frame(&mut stream, &response).await?;
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            };
+            let response = match serde_json::from_slice::<Request>(&frame) {
+                Ok(request) => self.respond(request).await,
+                Err(err) => Response::error(format!("Invalid request: {err}")),
+            };
+            write_frame(&mut stream, &serde_json::to_vec(&response)?).await?;
+        }
+    }
+}
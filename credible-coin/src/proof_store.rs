@@ -0,0 +1,558 @@
+//! The proofs published after an attestation run, and where they are kept for customers to
+//! fetch:
+//! 1. [`InclusionProof`]: The merkle path proving an account's balance is committed to by a root
+//! 2. [`SolvencyReport`]: The totals the exchange attested to for a root
+//!
+//! Both are stored by root (and address) in a [`ProofStore`]: the proof Redis instance
+//! ([`RedisProofStore`]), or memory ([`MemoryProofStore`]) in tests.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitcoin::hex::FromHex;
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::{MerkleProof, MerkleTree};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::exchange::db_connector::{
+    PROOF_KEY_PREFIX, PooledConnection, RedisPool, expire_key, get_value, proof_key, proofs_pool,
+    scan_keys, set_value,
+};
+use crate::errors::{DBConnectorError, LedgerStoreError, ProofStoreError};
+use crate::ledger_store::LedgerStore;
+use crate::merkle_tree_entry::MerkleTreeEntry;
+use crate::utils::duplicates::{account_keys, split_key};
+
+/// The hash of the leaf of an account
+fn leaf_hash(address: &str, value: i64) -> [u8; 32] {
+    MerkleTreeEntry::hash_bytes(MerkleTreeEntry::new(address.to_owned(), value).serialize_entry())
+}
+/// The proof that the balance of an account is one of the leaves committed to by a merkle root.
+///
+/// Customers check it with [`InclusionProof::verify`], needing nothing but the root the exchange
+/// published.
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::ledger_store::MemoryLedgerStore;
+/// use credible_coin::proof_store::InclusionProof;
+///
+/// let ledger = MemoryLedgerStore::from_columns(
+///     vec![
+///         "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned(),
+///         "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_owned(),
+///     ],
+///     vec![10, 20],
+/// )
+/// .unwrap();
+/// let proofs = InclusionProof::for_ledger(&ledger).unwrap();
+/// assert_eq!(proofs.len(), 2);
+/// assert!(proofs.iter().all(InclusionProof::verify));
+///
+/// let mut forged = proofs[0].clone();
+/// forged.value = 1_000;
+/// assert!(!forged.verify());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    /// The hex encoded merkle root the account is proven against
+    pub root: String,
+    /// The address of the account, or its disambiguated key if the address is repeated
+    pub address: String,
+    /// The balance of the account
+    pub value: i64,
+    /// The index of the account's leaf
+    pub index: usize,
+    /// The number of leaves committed to by the root
+    pub leaf_count: usize,
+    /// The hex encoded hashes of the merkle path from the leaf to the root
+    pub proof_hashes: Vec<String>,
+}
+impl InclusionProof {
+    /// The proof of every account of `ledger`, in the order of its leaves.
+    ///
+    /// The rows of a repeated address are proven separately, under their disambiguated keys
+    /// (`<ADDRESS>#<N>`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger can't be read.
+    pub fn for_ledger(ledger: &dyn LedgerStore) -> Result<Vec<InclusionProof>, LedgerStoreError> {
+        let (addresses, values) = ledger.leaf_columns()?;
+        let leaves: Vec<[u8; 32]> = addresses
+            .iter()
+            .zip(&values)
+            .map(|(address, value)| leaf_hash(address, *value))
+            .collect();
+        let tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+        let Some(root) = tree.root_hex() else {
+            return Ok(Vec::new());
+        };
+        let mut proofs = Vec::with_capacity(addresses.len());
+        for (index, (key, value)) in account_keys(&addresses)
+            .into_iter()
+            .zip(&values)
+            .enumerate()
+        {
+            proofs.push(InclusionProof {
+                root: root.clone(),
+                address: key,
+                value: *value,
+                index,
+                leaf_count: leaves.len(),
+                proof_hashes: tree.proof(&[index]).proof_hashes_hex(),
+            });
+        }
+        Ok(proofs)
+    }
+    /// Check that the leaf of the account's address and balance is committed to by the root
+    pub fn verify(&self) -> bool {
+        let Ok(root) = <[u8; 32]>::from_hex(&self.root) else {
+            return false;
+        };
+        let Ok(hashes) = self
+            .proof_hashes
+            .iter()
+            .map(|hash| <[u8; 32]>::from_hex(hash))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return false;
+        };
+        let (address, _) = split_key(&self.address);
+        MerkleProof::<Sha256>::new(hashes).verify(
+            root,
+            &[self.index],
+            &[leaf_hash(address, self.value)],
+            self.leaf_count,
+        )
+    }
+}
+impl fmt::Display for InclusionProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Address:{:?}\nValue:{}\nLeaf:{} of {}\nRoot:{}",
+            self.address, self.value, self.index, self.leaf_count, self.root
+        )
+    }
+}
+/// What the exchange attested to for a merkle root: the balances it owes, and the reserves it
+/// proved it controls
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolvencyReport {
+    /// The hex encoded merkle root of the accounts
+    pub root: String,
+    /// The number of accounts committed to by the root
+    pub accounts: usize,
+    /// The sum of the balances of the accounts
+    pub liabilities: i64,
+    /// The reserves verified by the exchange's proof of reserves
+    pub reserves: i64,
+    /// The challenge the proof of reserves signed
+    pub challenge: String,
+    /// Whether the reserves cover the liabilities
+    pub solvent: bool,
+    /// When the report was made, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+impl SolvencyReport {
+    /// A report made now of the accounts proven by `proofs`, covered by `reserves` as verified
+    /// for `challenge`
+    pub fn new(proofs: &[InclusionProof], reserves: i64, challenge: &str) -> Self {
+        let liabilities = proofs.iter().map(|proof| proof.value).sum();
+        Self {
+            root: proofs
+                .first()
+                .map(|proof| proof.root.clone())
+                .unwrap_or_default(),
+            accounts: proofs.len(),
+            liabilities,
+            reserves,
+            challenge: challenge.to_owned(),
+            solvent: reserves >= liabilities,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+impl fmt::Display for SolvencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Root:{}\nAccounts:{}\nLiabilities:{}\nReserves:{}\nSolvent:{}",
+            self.root, self.accounts, self.liabilities, self.reserves, self.solvent
+        )
+    }
+}
+/// Roots are hex encoded, which also keeps them from matching more than their own keys
+fn check_root(root: &str) -> Result<(), ProofStoreError> {
+    if root.is_empty() || !root.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ProofStoreError::InvalidRoot(root.to_owned()));
+    }
+    Ok(())
+}
+/// The field of the proof of `address` among the proofs of a root
+fn address_field(address: &str) -> String {
+    format!("address:{address}")
+}
+/// The field of the solvency report of a root
+const SOLVENCY_FIELD: &str = "solvency";
+
+/// Somewhere the proofs of attestation runs are kept, by root and address.
+///
+/// Anything saved can be given a time to live, after which it is gone as if it was never saved,
+/// and everything saved for a root can be expired at once with [`ProofStore::expire_root`].
+pub trait ProofStore: fmt::Debug + Send + Sync {
+    /// Save `proof` under its root and address, replacing any proof already saved there
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be written.
+    fn save_proof(
+        &self,
+        proof: &InclusionProof,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError>;
+    /// The proof of `address` against `root`, `None` if none was saved (or it expired)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be read.
+    fn get_proof(
+        &self,
+        root: &str,
+        address: &str,
+    ) -> Result<Option<InclusionProof>, ProofStoreError>;
+    /// The addresses with a proof against `root`, sorted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be read.
+    fn list_proofs(&self, root: &str) -> Result<Vec<String>, ProofStoreError>;
+    /// Save `report` under its root, replacing any report already saved there
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be written.
+    fn save_report(
+        &self,
+        report: &SolvencyReport,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError>;
+    /// The solvency report of `root`, `None` if none was saved (or it expired)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be read.
+    fn get_report(&self, root: &str) -> Result<Option<SolvencyReport>, ProofStoreError>;
+    /// Every root with a proof or a report saved, sorted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be read.
+    fn list_roots(&self) -> Result<Vec<String>, ProofStoreError>;
+    /// Drop everything saved for `root` after `ttl`, or right away if `ttl` is zero, returning
+    /// the number of proofs and reports affected
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is invalid or the store can't be written.
+    fn expire_root(&self, root: &str, ttl: Duration) -> Result<usize, ProofStoreError>;
+    /// The most recent solvency report of any root, `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be read.
+    fn latest_report(&self) -> Result<Option<SolvencyReport>, ProofStoreError> {
+        let mut latest: Option<SolvencyReport> = None;
+        for root in self.list_roots()? {
+            if let Some(report) = self.get_report(&root)? {
+                if latest
+                    .as_ref()
+                    .is_none_or(|latest| report.timestamp >= latest.timestamp)
+                {
+                    latest = Some(report);
+                }
+            }
+        }
+        Ok(latest)
+    }
+}
+/// Save the proof of every account of `ledger`, and the solvency report of its root, so
+/// customers can fetch and check them. Everything is dropped after `ttl`, if any.
+///
+/// # Errors
+///
+/// Returns an error if the ledger is empty or can't be read, or the store can't be written.
+pub fn publish_proofs(
+    store: &dyn ProofStore,
+    ledger: &dyn LedgerStore,
+    reserves: i64,
+    challenge: &str,
+    ttl: Option<Duration>,
+) -> Result<SolvencyReport, ProofStoreError> {
+    let proofs = InclusionProof::for_ledger(ledger)?;
+    let report = SolvencyReport::new(&proofs, reserves, challenge);
+    check_root(&report.root)?;
+    for proof in &proofs {
+        store.save_proof(proof, ttl)?;
+    }
+    store.save_report(&report, ttl)?;
+    Ok(report)
+}
+/// A value of a [`MemoryProofStore`], along with when it expires
+#[derive(Debug, Clone)]
+struct Entry {
+    json: String,
+    expires_at: Option<Instant>,
+}
+impl Entry {
+    fn is_live(&self) -> bool {
+        self.expires_at.is_none_or(|at| Instant::now() < at)
+    }
+}
+/// Proofs kept in memory, under the same keys and in the same format as the proof Redis
+/// instance. Nothing outlives the store, so it is meant for tests.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use credible_coin::proof_store::{InclusionProof, MemoryProofStore, ProofStore};
+///
+/// let proof = InclusionProof {
+///     root: "ab12".to_owned(),
+///     address: "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned(),
+///     value: 10,
+///     index: 0,
+///     leaf_count: 1,
+///     proof_hashes: Vec::new(),
+/// };
+/// let store = MemoryProofStore::new();
+/// store.save_proof(&proof, None).unwrap();
+/// assert_eq!(store.get_proof("ab12", &proof.address).unwrap(), Some(proof.clone()));
+/// assert_eq!(store.list_roots().unwrap(), vec!["ab12".to_owned()]);
+///
+/// store.expire_root("ab12", Duration::ZERO).unwrap();
+/// assert_eq!(store.get_proof("ab12", &proof.address).unwrap(), None);
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryProofStore {
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+impl MemoryProofStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn set(&self, key: String, json: String, ttl: Option<Duration>) {
+        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, Entry { json, expires_at });
+    }
+    fn get(&self, key: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|entry| entry.is_live())
+            .map(|entry| entry.json.clone())
+    }
+    /// The live keys starting with `prefix`, without the prefix
+    fn suffixes(&self, prefix: &str) -> Vec<String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.is_live())
+            .filter_map(|(key, _)| key.strip_prefix(prefix))
+            .map(str::to_owned)
+            .collect()
+    }
+}
+impl ProofStore for MemoryProofStore {
+    fn save_proof(
+        &self,
+        proof: &InclusionProof,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError> {
+        check_root(&proof.root)?;
+        let key = proof_key(&proof.root, &address_field(&proof.address));
+        self.set(key, serde_json::to_string(proof)?, ttl);
+        Ok(())
+    }
+    fn get_proof(
+        &self,
+        root: &str,
+        address: &str,
+    ) -> Result<Option<InclusionProof>, ProofStoreError> {
+        check_root(root)?;
+        self.get(&proof_key(root, &address_field(address)))
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(ProofStoreError::from)
+    }
+    fn list_proofs(&self, root: &str) -> Result<Vec<String>, ProofStoreError> {
+        check_root(root)?;
+        Ok(self.suffixes(&proof_key(root, &address_field(""))))
+    }
+    fn save_report(
+        &self,
+        report: &SolvencyReport,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError> {
+        check_root(&report.root)?;
+        let key = proof_key(&report.root, SOLVENCY_FIELD);
+        self.set(key, serde_json::to_string(report)?, ttl);
+        Ok(())
+    }
+    fn get_report(&self, root: &str) -> Result<Option<SolvencyReport>, ProofStoreError> {
+        check_root(root)?;
+        self.get(&proof_key(root, SOLVENCY_FIELD))
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(ProofStoreError::from)
+    }
+    fn list_roots(&self) -> Result<Vec<String>, ProofStoreError> {
+        Ok(roots_of(&self.suffixes(PROOF_KEY_PREFIX)))
+    }
+    fn expire_root(&self, root: &str, ttl: Duration) -> Result<usize, ProofStoreError> {
+        check_root(root)?;
+        let prefix = proof_key(root, "");
+        let expires_at = Instant::now() + ttl;
+        let mut entries = self.entries.lock().unwrap();
+        let mut expired = 0;
+        for (_, entry) in entries
+            .iter_mut()
+            .filter(|(key, entry)| key.starts_with(&prefix) && entry.is_live())
+        {
+            entry.expires_at = Some(expires_at);
+            expired += 1;
+        }
+        entries.retain(|_, entry| entry.is_live());
+        Ok(expired)
+    }
+}
+/// The distinct roots of keys stripped of their `proofs:` prefix, sorted
+fn roots_of(keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .filter_map(|key| key.split_once(':').map(|(root, _)| root.to_owned()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+/// The proofs in the proof Redis instance, as JSON under `proofs:{root}:address:{address}` and
+/// `proofs:{root}:solvency`
+#[derive(Debug, Clone)]
+pub struct RedisProofStore {
+    pool: RedisPool,
+}
+impl RedisProofStore {
+    /// A store for the proof Redis instance set in `credible_config`. The instance is only
+    /// connected to when a proof is saved or fetched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `redis` section of the configuration is invalid.
+    pub fn new() -> Result<Self, ProofStoreError> {
+        Ok(Self::with_pool(proofs_pool()?))
+    }
+    /// A store for the instance of `pool`
+    pub fn with_pool(pool: RedisPool) -> Self {
+        Self { pool }
+    }
+    fn connection(&self) -> Result<PooledConnection, ProofStoreError> {
+        Ok(self.pool.get().map_err(DBConnectorError::from)?)
+    }
+}
+impl ProofStore for RedisProofStore {
+    fn save_proof(
+        &self,
+        proof: &InclusionProof,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError> {
+        check_root(&proof.root)?;
+        let key = proof_key(&proof.root, &address_field(&proof.address));
+        set_value(
+            &mut *self.connection()?,
+            &key,
+            &serde_json::to_string(proof)?,
+            ttl,
+        )?;
+        Ok(())
+    }
+    fn get_proof(
+        &self,
+        root: &str,
+        address: &str,
+    ) -> Result<Option<InclusionProof>, ProofStoreError> {
+        check_root(root)?;
+        let key = proof_key(root, &address_field(address));
+        get_value(&mut *self.connection()?, &key)?
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(ProofStoreError::from)
+    }
+    fn list_proofs(&self, root: &str) -> Result<Vec<String>, ProofStoreError> {
+        check_root(root)?;
+        let prefix = proof_key(root, &address_field(""));
+        let mut addresses: Vec<String> =
+            scan_keys(&mut *self.connection()?, &format!("{prefix}*"))?
+                .iter()
+                .filter_map(|key| key.strip_prefix(&prefix))
+                .map(str::to_owned)
+                .collect();
+        addresses.sort();
+        Ok(addresses)
+    }
+    fn save_report(
+        &self,
+        report: &SolvencyReport,
+        ttl: Option<Duration>,
+    ) -> Result<(), ProofStoreError> {
+        check_root(&report.root)?;
+        let key = proof_key(&report.root, SOLVENCY_FIELD);
+        set_value(
+            &mut *self.connection()?,
+            &key,
+            &serde_json::to_string(report)?,
+            ttl,
+        )?;
+        Ok(())
+    }
+    fn get_report(&self, root: &str) -> Result<Option<SolvencyReport>, ProofStoreError> {
+        check_root(root)?;
+        let key = proof_key(root, SOLVENCY_FIELD);
+        get_value(&mut *self.connection()?, &key)?
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(ProofStoreError::from)
+    }
+    fn list_roots(&self) -> Result<Vec<String>, ProofStoreError> {
+        let keys: Vec<String> =
+            scan_keys(&mut *self.connection()?, &format!("{PROOF_KEY_PREFIX}*"))?
+                .iter()
+                .filter_map(|key| key.strip_prefix(PROOF_KEY_PREFIX))
+                .map(str::to_owned)
+                .collect();
+        Ok(roots_of(&keys))
+    }
+    fn expire_root(&self, root: &str, ttl: Duration) -> Result<usize, ProofStoreError> {
+        check_root(root)?;
+        let mut conn = self.connection()?;
+        let keys = scan_keys(&mut conn, &proof_key(root, "*"))?;
+        let mut expired = 0;
+        for key in keys {
+            if expire_key(&mut conn, &key, ttl)? {
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+}
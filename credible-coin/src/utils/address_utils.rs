@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::sync::RwLock;
+
+use bitcoin::address::{NetworkUnchecked, ParseError};
+use bitcoin::{Address, Network};
+
+use crate::errors::AddressValidationError;
+
+lazy_static! {
+    static ref ACTIVE_NETWORK: RwLock<Network> = RwLock::new(Network::Bitcoin);
+}
+
+/// Set the network that addresses are validated against when they are read from a file or
+/// provided to one of the shells.
+///
+/// The publisher and exchange CLIs call this once with the `--network` they were started with.
+/// If it is never called, mainnet ([`Network::Bitcoin`]) is used.
+pub fn set_active_network(network: Network) {
+    *ACTIVE_NETWORK.write().unwrap() = network;
+}
+/// The network that addresses are currently validated against. See [`set_active_network`].
+pub fn active_network() -> Network {
+    *ACTIVE_NETWORK.read().unwrap()
+}
+/// The bitcoin parse errors only describe their own layer ("base58 error") when displayed, so
+/// walk the source chain to get at the actual reason ("invalid checksum")
+fn error_chain(err: &dyn Error) -> String {
+    let mut reason = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        reason.push_str(": ");
+        reason.push_str(&err.to_string());
+        source = err.source();
+    }
+    reason
+}
+/// Parse an address and check that it is valid for the provided network.
+///
+/// # Errors
+///
+/// - [`AddressValidationError::Malformed`] if the address can't be parsed (bad checksum,
+///   unknown encoding, mixed case bech32, ...).
+/// - [`AddressValidationError::WrongNetwork`] if the address is valid but belongs to another
+///   network.
+///
+/// # Examples
+///
+/// ```
+/// # use bitcoin::Network;
+/// # use credible_coin::utils::address_utils::validate_address;
+/// assert!(validate_address("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC", Network::Bitcoin).is_ok());
+/// assert!(validate_address("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC", Network::Testnet).is_err());
+/// assert!(validate_address("not an address", Network::Bitcoin).is_err());
+/// ```
+pub fn validate_address(
+    address: &str,
+    network: Network,
+) -> Result<Address, AddressValidationError> {
+    let unchecked: Address<NetworkUnchecked> =
+        address
+            .trim()
+            .parse()
+            .map_err(|err: ParseError| AddressValidationError::Malformed {
+                address: address.to_owned(),
+                reason: error_chain(&err),
+            })?;
+    if !unchecked.is_valid_for_network(network) {
+        return Err(AddressValidationError::WrongNetwork {
+            address: address.to_owned(),
+            found: *unchecked.network(),
+            expected: network,
+        });
+    }
+    Ok(unchecked.assume_checked())
+}
+/// Validate an address for the provided network and return it in its canonical form.
+///
+/// Base58 addresses are case sensitive so they are returned as is (minus surrounding
+/// whitespace), while bech32 addresses, which may be written entirely in upper or lower case,
+/// are always returned in lower case. Two spellings of the same address therefore always
+/// normalize to the same string, and so end up as the same merkle leaf.
+///
+/// # Examples
+///
+/// ```
+/// # use bitcoin::Network;
+/// # use credible_coin::utils::address_utils::normalize_address;
+/// let normalized =
+///     normalize_address("BC1QUSHQA4NWPZ2J0YFTNPW08C5LJ2U92MNAH79Q2K", Network::Bitcoin).unwrap();
+/// assert_eq!(normalized, "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k");
+/// ```
+pub fn normalize_address(
+    address: &str,
+    network: Network,
+) -> Result<String, AddressValidationError> {
+    validate_address(address, network).map(|address| address.to_string())
+}
+/// Normalize an address against the [`active_network`]
+pub fn normalize(address: &str) -> Result<String, AddressValidationError> {
+    normalize_address(address, active_network())
+}
@@ -0,0 +1,134 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+use tempfile::Builder;
+
+/// The directory `path` lives in, which is where its temporary and lock files are created so
+/// that renaming over it stays on the same filesystem
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+/// The path of the lock file guarding `path`, i.e. `<path>.lock`
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    parent_dir(path).join(name)
+}
+/// An advisory, exclusive lock over a data file, released when dropped.
+///
+/// The lock is taken on a sibling `<file>.lock` file rather than on the data file itself,
+/// because [`write_atomically`] replaces the data file (and so its inode) on every write. The
+/// lock file is deliberately left behind when the lock is released: removing it would let a
+/// waiting process lock a file that no longer has a name.
+///
+/// Every read-modify-write of a ledger should hold this lock from before the read until after
+/// the new contents have been renamed into place, so that two shells working on the same file
+/// can't lose each other's changes.
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+}
+impl FileLock {
+    /// Block until the exclusive lock over `path` is acquired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file can't be created or locked.
+    pub fn exclusive<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path(path.as_ref()))?;
+        file.lock_exclusive()?;
+        Ok(Self { file })
+    }
+    /// Try to acquire the exclusive lock over `path` without blocking, returning `Ok(None)` if
+    /// another process (or another handle in this one) already holds it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file can't be created, or locking fails for any reason other
+    /// than the lock being held.
+    pub fn try_exclusive<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path(path.as_ref()))?;
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(Self { file })),
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway, this only makes it explicit
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+/// Replace the contents of `path` with whatever `write` writes, without ever leaving a partially
+/// written file behind.
+///
+/// The new contents are written to a temporary file in the same directory as `path`, which is
+/// flushed and fsynced before being atomically renamed over `path`. The directory is then
+/// fsynced too so the rename itself survives a crash. If `write` fails, or the process dies at any
+/// point, `path` is left untouched and the temporary file is cleaned up (or, after a crash, left
+/// behind as a hidden `.credible-*.tmp` file). If `path` already exists, the new file keeps its
+/// permissions.
+///
+/// This does not lock `path`, callers doing a read-modify-write should hold a [`FileLock`].
+///
+/// # Errors
+///
+/// Returns any error from `write`, or from creating, syncing, or renaming the temporary file.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use credible_coin::utils::atomic_file::write_atomically;
+/// let path = std::env::temp_dir().join("write_atomically_doctest.csv");
+/// write_atomically(&path, |file| file.write_all(b"addresses,value\n")).unwrap();
+/// assert_eq!(std::fs::read_to_string(&path).unwrap(), "addresses,value\n");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn write_atomically<P, F>(path: P, write: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let path = path.as_ref();
+    let dir = parent_dir(path);
+    let mut temp = Builder::new()
+        .prefix(".credible-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    write(temp.as_file_mut())?;
+    temp.as_file_mut().flush()?;
+    // The temporary file is only readable by its owner, which the file it replaces may not be
+    match std::fs::metadata(path) {
+        Ok(metadata) => temp.as_file().set_permissions(metadata.permissions())?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|err| err.error)?;
+    sync_dir(dir)
+}
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    // Directories can't be opened (and so synced) like files on other platforms
+    Ok(())
+}
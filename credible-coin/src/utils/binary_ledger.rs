@@ -0,0 +1,399 @@
+//! A versioned, columnar binary format for publisher and exchange ledgers.
+//!
+//! Re-parsing a multi-million row CSV file for every operation is slow, so a ledger can instead
+//! be stored in this format (conventionally with the `.ccl` extension) and memory-mapped, which
+//! makes opening it a matter of validating a few offsets.
+//!
+//! # Layout
+//!
+//! Every integer is little-endian. The file is made of a fixed 64 byte header followed by four
+//! sections, each starting on an 8 byte boundary:
+//!
+//! | Offset | Size | Field                                                     |
+//! |--------|------|-----------------------------------------------------------|
+//! | 0      | 8    | Magic bytes, `CCLEDGER`                                   |
+//! | 8      | 2    | Format version ([`FORMAT_VERSION`])                       |
+//! | 10     | 6    | Reserved, must be zero                                    |
+//! | 16     | 8    | Entry count (`n`)                                         |
+//! | 24     | 8    | Dictionary length, the number of distinct addresses (`d`) |
+//! | 32     | 8    | Offset of the dictionary offsets section                  |
+//! | 40     | 8    | Offset of the dictionary bytes section                    |
+//! | 48     | 8    | Offset of the address column                              |
+//! | 56     | 8    | Offset of the value column                                |
+//!
+//! 1. Dictionary offsets: `d + 1` `u64`s. Address `i` of the dictionary is the UTF-8 string
+//!    between offsets `i` and `i + 1` of the dictionary bytes section.
+//! 2. Dictionary bytes: every distinct address, back to back, in order of first appearance.
+//! 3. Address column: `n` `u32`s, the dictionary index of the address of each entry.
+//! 4. Value column: `n` `i64`s, the value of each entry.
+//!
+//! Entries are kept in file order, so entry `i` of a binary ledger is leaf `i` of the merkle tree
+//! built from it, exactly as it would be for row `i` of the CSV file it was converted from.
+//! Repeated addresses are stored once in the dictionary and then referenced by every entry that
+//! uses them.
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use indexmap::IndexMap;
+use memmap2::Mmap;
+
+use crate::errors::LedgerFormatError;
+use crate::utils::atomic_file::write_atomically;
+
+/// The magic bytes every binary ledger starts with
+pub const MAGIC: [u8; 8] = *b"CCLEDGER";
+/// The version of the format written by [`write_ledger`]
+pub const FORMAT_VERSION: u16 = 1;
+/// The conventional file extension of a binary ledger
+pub const LEDGER_EXTENSION: &str = "ccl";
+/// The size of the fixed header at the start of every binary ledger
+const HEADER_LEN: u64 = 64;
+
+/// Round `offset` up to the next 8 byte boundary
+fn align(offset: u64) -> u64 {
+    (offset + 7) & !7
+}
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+/// The header of a binary ledger, see the [module documentation](self) for its layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedgerHeader {
+    /// The format version the file was written with
+    pub version: u16,
+    /// The number of entries (rows) in the ledger
+    pub entry_count: u64,
+    /// The number of distinct addresses in the ledger
+    pub dictionary_len: u64,
+    /// The offset of the dictionary offsets section
+    pub dictionary_offsets_start: u64,
+    /// The offset of the dictionary bytes section
+    pub dictionary_bytes_start: u64,
+    /// The offset of the address column
+    pub address_column_start: u64,
+    /// The offset of the value column
+    pub value_column_start: u64,
+}
+impl LedgerHeader {
+    fn new(entry_count: u64, dictionary_len: u64, dictionary_bytes_len: u64) -> Self {
+        let dictionary_offsets_start = HEADER_LEN;
+        let dictionary_bytes_start = dictionary_offsets_start + 8 * (dictionary_len + 1);
+        let address_column_start = align(dictionary_bytes_start + dictionary_bytes_len);
+        let value_column_start = align(address_column_start + 4 * entry_count);
+        Self {
+            version: FORMAT_VERSION,
+            entry_count,
+            dictionary_len,
+            dictionary_offsets_start,
+            dictionary_bytes_start,
+            address_column_start,
+            value_column_start,
+        }
+    }
+    fn to_bytes(self) -> [u8; HEADER_LEN as usize] {
+        let mut bytes = [0u8; HEADER_LEN as usize];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..10].copy_from_slice(&self.version.to_le_bytes());
+        for (at, field) in [
+            (16, self.entry_count),
+            (24, self.dictionary_len),
+            (32, self.dictionary_offsets_start),
+            (40, self.dictionary_bytes_start),
+            (48, self.address_column_start),
+            (56, self.value_column_start),
+        ] {
+            bytes[at..at + 8].copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+    /// Parse the header at the start of `bytes` and check that every section it describes fits
+    /// in `bytes`
+    fn parse(bytes: &[u8]) -> Result<Self, LedgerFormatError> {
+        if bytes.len() < MAGIC.len() || bytes[0..8] != MAGIC {
+            return Err(LedgerFormatError::BadMagic);
+        }
+        if bytes.len() < HEADER_LEN as usize {
+            return Err(LedgerFormatError::Corrupt(format!(
+                "the header is {} bytes but the file is only {} bytes long",
+                HEADER_LEN,
+                bytes.len()
+            )));
+        }
+        let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version != FORMAT_VERSION {
+            return Err(LedgerFormatError::UnsupportedVersion(version));
+        }
+        let header = Self {
+            version,
+            entry_count: read_u64(bytes, 16),
+            dictionary_len: read_u64(bytes, 24),
+            dictionary_offsets_start: read_u64(bytes, 32),
+            dictionary_bytes_start: read_u64(bytes, 40),
+            address_column_start: read_u64(bytes, 48),
+            value_column_start: read_u64(bytes, 56),
+        };
+        let corrupt = |what: &str| LedgerFormatError::Corrupt(what.to_owned());
+        let offsets_end = header
+            .dictionary_len
+            .checked_add(1)
+            .and_then(|len| len.checked_mul(8))
+            .and_then(|len| len.checked_add(header.dictionary_offsets_start))
+            .ok_or_else(|| corrupt("dictionary length overflows"))?;
+        let addresses_end = header
+            .entry_count
+            .checked_mul(4)
+            .and_then(|len| len.checked_add(header.address_column_start))
+            .ok_or_else(|| corrupt("entry count overflows"))?;
+        let file_end = header
+            .entry_count
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(header.value_column_start))
+            .ok_or_else(|| corrupt("entry count overflows"))?;
+        if header.dictionary_offsets_start < HEADER_LEN
+            || header.dictionary_bytes_start < offsets_end
+            || header.address_column_start < header.dictionary_bytes_start
+            || header.value_column_start < addresses_end
+        {
+            return Err(corrupt("sections overlap"));
+        }
+        if file_end > bytes.len() as u64 {
+            return Err(LedgerFormatError::Corrupt(format!(
+                "expected at least {} bytes but the file is only {} bytes long",
+                file_end,
+                bytes.len()
+            )));
+        }
+        Ok(header)
+    }
+}
+/// Check whether the file at `path` is a binary ledger by looking at its magic bytes
+pub fn is_binary_ledger<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == MAGIC
+}
+/// Write the provided address and value columns to `path` as a binary ledger, replacing the
+/// file if it already exists. The file is replaced atomically, see
+/// [`write_atomically`](crate::utils::atomic_file::write_atomically).
+///
+/// # Errors
+///
+/// Returns [`LedgerFormatError::Io`] if the file can't be written, or
+/// [`LedgerFormatError::TooManyAddresses`] if there are more distinct addresses than fit in a
+/// `u32` dictionary index.
+///
+/// # Panics
+///
+/// This function will panic if `addresses` and `values` have different lengths.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::binary_ledger::{write_ledger, MappedLedger};
+/// let path = std::env::temp_dir().join("write_ledger_doctest.ccl");
+/// let addresses = vec!["a".to_owned(), "b".to_owned(), "a".to_owned()];
+/// write_ledger(&path, &addresses, &[1, 2, 3]).unwrap();
+///
+/// let ledger = MappedLedger::open(&path).unwrap();
+/// assert_eq!(ledger.len(), 3);
+/// assert_eq!(ledger.address_count(), 2);
+/// assert_eq!(ledger.entry(2), ("a", 3));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn write_ledger<P: AsRef<Path>>(
+    path: P,
+    addresses: &[String],
+    values: &[i64],
+) -> Result<(), LedgerFormatError> {
+    assert_eq!(addresses.len(), values.len());
+    let mut dictionary: IndexMap<&str, u32> = IndexMap::new();
+    let mut address_column = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let next = dictionary.len();
+        let index = *dictionary.entry(address.as_str()).or_insert_with(|| {
+            // Saturate so the error below is raised instead of wrapping around
+            u32::try_from(next).unwrap_or(u32::MAX)
+        });
+        if index == u32::MAX {
+            return Err(LedgerFormatError::TooManyAddresses(dictionary.len()));
+        }
+        address_column.push(index);
+    }
+    let dictionary_bytes_len: usize = dictionary.keys().map(|address| address.len()).sum();
+    let header = LedgerHeader::new(
+        addresses.len() as u64,
+        dictionary.len() as u64,
+        dictionary_bytes_len as u64,
+    );
+
+    write_atomically(path, |file| {
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&header.to_bytes())?;
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_le_bytes())?;
+        for address in dictionary.keys() {
+            offset += address.len() as u64;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for address in dictionary.keys() {
+            writer.write_all(address.as_bytes())?;
+        }
+        let padding = (header.address_column_start - header.dictionary_bytes_start) as usize
+            - dictionary_bytes_len;
+        writer.write_all(&vec![0u8; padding])?;
+        for index in address_column {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+        let padding =
+            header.value_column_start - header.address_column_start - 4 * header.entry_count;
+        writer.write_all(&vec![0u8; padding as usize])?;
+        for value in values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    })?;
+    Ok(())
+}
+/// A read-only, memory-mapped binary ledger.
+///
+/// Opening a ledger validates its header, dictionary, and address column once, after which
+/// every entry can be read in constant time without copying the file into memory.
+#[derive(Debug)]
+pub struct MappedLedger {
+    mmap: Mmap,
+    header: LedgerHeader,
+}
+impl MappedLedger {
+    /// Memory-map and validate the binary ledger at `path`.
+    ///
+    /// # Errors
+    ///
+    /// - [`LedgerFormatError::Io`] if the file can't be opened or mapped.
+    /// - [`LedgerFormatError::BadMagic`] if the file is not a binary ledger.
+    /// - [`LedgerFormatError::UnsupportedVersion`] if the file was written by a newer version.
+    /// - [`LedgerFormatError::Corrupt`] if any section is truncated or inconsistent.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LedgerFormatError> {
+        let file = File::open(path)?;
+        // SAFETY: The map is read-only and every read goes through bounds-checked slices. The
+        // ledger must not be truncated by another process while it is mapped, which is the same
+        // contract every other reader of these files already relies on.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header = LedgerHeader::parse(&mmap)?;
+        let ledger = Self { mmap, header };
+        ledger.validate()?;
+        Ok(ledger)
+    }
+    /// Check that the dictionary offsets are in bounds and point at valid UTF-8, and that every
+    /// entry references an address in the dictionary
+    fn validate(&self) -> Result<(), LedgerFormatError> {
+        let dictionary_bytes_len =
+            self.header.address_column_start - self.header.dictionary_bytes_start;
+        let mut previous = 0;
+        for i in 0..=self.header.dictionary_len as usize {
+            let offset = self.dictionary_offset(i);
+            if offset < previous || offset > dictionary_bytes_len {
+                return Err(LedgerFormatError::Corrupt(format!(
+                    "dictionary offset {} is out of bounds",
+                    i
+                )));
+            }
+            previous = offset;
+        }
+        for i in 0..self.header.dictionary_len as usize {
+            std::str::from_utf8(self.dictionary_bytes(i))
+                .ok()
+                .ok_or_else(|| {
+                    LedgerFormatError::Corrupt(format!(
+                        "dictionary address {} is not valid UTF-8",
+                        i
+                    ))
+                })?;
+        }
+        if let Some(row) = (0..self.len())
+            .find(|row| u64::from(self.address_index(*row)) >= self.header.dictionary_len)
+        {
+            return Err(LedgerFormatError::Corrupt(format!(
+                "entry {} references an address outside of the dictionary",
+                row
+            )));
+        }
+        Ok(())
+    }
+    fn dictionary_offset(&self, i: usize) -> u64 {
+        read_u64(
+            &self.mmap,
+            self.header.dictionary_offsets_start as usize + 8 * i,
+        )
+    }
+    fn dictionary_bytes(&self, i: usize) -> &[u8] {
+        let start = (self.header.dictionary_bytes_start + self.dictionary_offset(i)) as usize;
+        let end = (self.header.dictionary_bytes_start + self.dictionary_offset(i + 1)) as usize;
+        &self.mmap[start..end]
+    }
+    fn address_index(&self, row: usize) -> u32 {
+        let at = self.header.address_column_start as usize + 4 * row;
+        u32::from_le_bytes(self.mmap[at..at + 4].try_into().unwrap())
+    }
+    /// The header of the ledger
+    pub fn header(&self) -> &LedgerHeader {
+        &self.header
+    }
+    /// The number of entries in the ledger
+    pub fn len(&self) -> usize {
+        self.header.entry_count as usize
+    }
+    /// Whether the ledger has no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The number of distinct addresses in the ledger
+    pub fn address_count(&self) -> usize {
+        self.header.dictionary_len as usize
+    }
+    /// The address of entry `row`
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `row` is out of bounds.
+    pub fn address(&self, row: usize) -> &str {
+        assert!(row < self.len(), "entry {} is out of bounds", row);
+        let bytes = self.dictionary_bytes(self.address_index(row) as usize);
+        // Every dictionary entry was checked to be valid UTF-8 when the ledger was opened
+        std::str::from_utf8(bytes).unwrap()
+    }
+    /// The value of entry `row`
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `row` is out of bounds.
+    pub fn value(&self, row: usize) -> i64 {
+        assert!(row < self.len(), "entry {} is out of bounds", row);
+        let at = self.header.value_column_start as usize + 8 * row;
+        i64::from_le_bytes(self.mmap[at..at + 8].try_into().unwrap())
+    }
+    /// The address and value of entry `row`
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `row` is out of bounds.
+    pub fn entry(&self, row: usize) -> (&str, i64) {
+        (self.address(row), self.value(row))
+    }
+    /// Iterate over every entry of the ledger in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i64)> + '_ {
+        (0..self.len()).map(|row| self.entry(row))
+    }
+    /// Copy the address column out of the ledger
+    pub fn addresses(&self) -> Vec<String> {
+        (0..self.len())
+            .map(|row| self.address(row).to_owned())
+            .collect()
+    }
+    /// Copy the value column out of the ledger
+    pub fn values(&self) -> Vec<i64> {
+        (0..self.len()).map(|row| self.value(row)).collect()
+    }
+}
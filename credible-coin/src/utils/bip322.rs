@@ -0,0 +1,307 @@
+use std::str::FromStr;
+
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use bitcoin::absolute::LockTime;
+use bitcoin::address::AddressType;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{Hash, HashEngine, sha256};
+use bitcoin::key::{TapTweak, XOnlyPublicKey};
+use bitcoin::opcodes::all::{OP_PUSHBYTES_0, OP_RETURN};
+use bitcoin::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::secp256k1::{Keypair, Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, OutPoint, PublicKey, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Txid, Witness, ecdsa, taproot,
+};
+
+use crate::errors::KeyError;
+
+/// The tag of the hash BIP-322 commits to the message with
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// The BIP-322 hash of `message`, the tagged hash `to_spend` commits to
+///
+/// # Examples
+///
+/// ```
+/// use bitcoin::hex::DisplayHex;
+/// use credible_coin::utils::bip322::message_hash;
+///
+/// assert_eq!(
+///     message_hash("Hello World").to_lower_hex_string(),
+///     "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+/// );
+/// ```
+pub fn message_hash(message: &str) -> [u8; 32] {
+    let tag = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+/// The virtual transaction paying to `script_pubkey` whose only input commits to `message`
+fn to_spend(script_pubkey: ScriptBuf, message: &str) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFF_FFFF),
+            script_sig: Builder::new()
+                .push_opcode(OP_PUSHBYTES_0)
+                .push_slice(message_hash(message))
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey,
+        }],
+    }
+}
+/// The virtual transaction spending `to_spend`, whose input carries the signature
+fn to_sign(to_spend: &Transaction, script_sig: ScriptBuf, witness: Witness) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.txid(), 0),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+/// The P2WPKH script the key of `public_key` is spent with, either as the output itself or as
+/// the redeem script of a P2SH-P2WPKH output
+fn p2wpkh_script(public_key: &PublicKey) -> Option<ScriptBuf> {
+    public_key
+        .wpubkey_hash()
+        .map(|hash| ScriptBuf::new_p2wpkh(&hash))
+}
+/// Sign the P2WPKH spend of `to_spend` through `script_code` (the P2WPKH script itself)
+fn p2wpkh_witness(
+    tx: &Transaction,
+    script_code: &Script,
+    secret_key: &SecretKey,
+) -> Result<Witness, KeyError> {
+    let secp = Secp256k1::signing_only();
+    let sighash = SighashCache::new(tx).p2wpkh_signature_hash(
+        0,
+        script_code,
+        Amount::ZERO,
+        EcdsaSighashType::All,
+    )?;
+    let signature = ecdsa::Signature::sighash_all(
+        secp.sign_ecdsa_low_r(&Message::from_digest(sighash.to_byte_array()), secret_key),
+    );
+    Ok(Witness::p2wpkh(&signature, &secret_key.public_key(&secp)))
+}
+/// Sign `message` for `address` with the BIP-322 generic signed message format.
+///
+/// P2WPKH and P2TR (key path) addresses get a "simple" signature, the base64 encoded witness of
+/// `to_sign`. P2SH-P2WPKH addresses need a redeem script as well, so they get a "full"
+/// signature, the base64 encoded `to_sign` transaction.
+///
+/// # Errors
+///
+/// Returns an error if `address` can't be parsed, if it is of any other type, or if
+/// `secret_key` isn't the key of the address.
+///
+/// # Examples
+///
+/// ```
+/// use bitcoin::Network;
+/// use bitcoin::secp256k1::SecretKey;
+/// use credible_coin::utils::bip322::{sign, verify};
+/// use credible_coin::utils::bitcoin_utils::{ScriptType, address_from_public_key};
+///
+/// let secret_key = SecretKey::new(&mut rand::thread_rng());
+/// let public_key = bitcoin::PublicKey::new(secret_key.public_key(&secp256k1::Secp256k1::new()));
+/// let address = address_from_public_key(&public_key, ScriptType::P2tr, Network::Bitcoin).unwrap();
+/// let signature = sign(&secret_key, &address, "Proof of reserves").unwrap();
+/// assert!(verify(&address, "Proof of reserves", &signature).unwrap());
+/// assert!(!verify(&address, "Something else", &signature).unwrap());
+/// ```
+pub fn sign(secret_key: &SecretKey, address: &str, message: &str) -> Result<String, KeyError> {
+    let address = Address::from_str(address)?.assume_checked();
+    let script_pubkey = address.script_pubkey();
+    let secp = Secp256k1::new();
+    let public_key = PublicKey::new(secret_key.public_key(&secp));
+    let to_spend = to_spend(script_pubkey.clone(), message);
+    let not_owned = || KeyError::NotOwned(address.to_string());
+    match address.address_type() {
+        Some(AddressType::P2wpkh) => {
+            let script = p2wpkh_script(&public_key).ok_or_else(not_owned)?;
+            if script != script_pubkey {
+                return Err(not_owned());
+            }
+            let unsigned = to_sign(&to_spend, ScriptBuf::new(), Witness::new());
+            let witness = p2wpkh_witness(&unsigned, &script, secret_key)?;
+            Ok(BASE64_STANDARD.encode(serialize(&witness)))
+        }
+        Some(AddressType::P2sh) => {
+            let redeem_script = p2wpkh_script(&public_key).ok_or_else(not_owned)?;
+            if redeem_script.to_p2sh() != script_pubkey {
+                return Err(not_owned());
+            }
+            // A P2WPKH script is 22 bytes long, so it always fits in a push
+            let push =
+                PushBytesBuf::try_from(redeem_script.to_bytes()).expect("P2WPKH scripts are short");
+            let script_sig = Builder::new().push_slice(push).into_script();
+            let mut signed = to_sign(&to_spend, script_sig, Witness::new());
+            signed.input[0].witness = p2wpkh_witness(&signed, &redeem_script, secret_key)?;
+            Ok(BASE64_STANDARD.encode(serialize(&signed)))
+        }
+        Some(AddressType::P2tr) => {
+            let keypair = Keypair::from_secret_key(&secp, secret_key).tap_tweak(&secp, None);
+            let (output_key, _) = keypair.to_inner().x_only_public_key();
+            if output_key.serialize() != script_pubkey.as_bytes()[2..] {
+                return Err(not_owned());
+            }
+            let unsigned = to_sign(&to_spend, ScriptBuf::new(), Witness::new());
+            let sighash = SighashCache::new(&unsigned).taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&to_spend.output),
+                TapSighashType::Default,
+            )?;
+            let signature = secp.sign_schnorr_with_rng(
+                &Message::from_digest(sighash.to_byte_array()),
+                &keypair.to_inner(),
+                &mut rand::thread_rng(),
+            );
+            let witness = Witness::from_slice(&[signature.as_ref()]);
+            Ok(BASE64_STANDARD.encode(serialize(&witness)))
+        }
+        _ => Err(KeyError::UnsupportedAddress(address.to_string())),
+    }
+}
+/// The redeem script pushed by the `scriptSig` of a P2SH-P2WPKH spend
+fn redeem_script(script_sig: &Script) -> Option<ScriptBuf> {
+    let mut instructions = script_sig.instructions();
+    match (instructions.next(), instructions.next()) {
+        (Some(Ok(Instruction::PushBytes(push))), None) => {
+            Some(ScriptBuf::from_bytes(push.as_bytes().to_vec()))
+        }
+        _ => None,
+    }
+}
+/// Check the witness of a P2WPKH spend of `tx` through `script_code`
+fn verify_p2wpkh(tx: &Transaction, script_code: &Script) -> Result<bool, KeyError> {
+    let witness = &tx.input[0].witness;
+    if witness.len() != 2 {
+        return Ok(false);
+    }
+    let (Some(Ok(signature)), Some(Ok(public_key))) = (
+        witness.nth(0).map(ecdsa::Signature::from_slice),
+        witness.nth(1).map(PublicKey::from_slice),
+    ) else {
+        return Ok(false);
+    };
+    if p2wpkh_script(&public_key).as_deref() != Some(script_code) {
+        return Ok(false);
+    }
+    let sighash = SighashCache::new(tx).p2wpkh_signature_hash(
+        0,
+        script_code,
+        Amount::ZERO,
+        signature.hash_ty,
+    )?;
+    Ok(Secp256k1::verification_only()
+        .verify_ecdsa(
+            &Message::from_digest(sighash.to_byte_array()),
+            &signature.sig,
+            &public_key.inner,
+        )
+        .is_ok())
+}
+/// Check the witness of a P2TR key path spend of the only output of `to_spend`
+fn verify_p2tr(tx: &Transaction, to_spend: &Transaction) -> Result<bool, KeyError> {
+    let witness = &tx.input[0].witness;
+    let Some(Ok(signature)) = witness
+        .nth(0)
+        .filter(|_| witness.len() == 1)
+        .map(taproot::Signature::from_slice)
+    else {
+        return Ok(false);
+    };
+    let Ok(output_key) =
+        XOnlyPublicKey::from_slice(&to_spend.output[0].script_pubkey.as_bytes()[2..])
+    else {
+        return Ok(false);
+    };
+    let sighash = SighashCache::new(tx).taproot_key_spend_signature_hash(
+        0,
+        &Prevouts::All(&to_spend.output),
+        signature.hash_ty,
+    )?;
+    Ok(Secp256k1::verification_only()
+        .verify_schnorr(
+            &signature.sig,
+            &Message::from_digest(sighash.to_byte_array()),
+            &output_key,
+        )
+        .is_ok())
+}
+/// Check that `signature` is a BIP-322 signature of `message` by the key of `address`, either
+/// "simple" (a base64 encoded witness) or "full" (a base64 encoded `to_sign` transaction).
+///
+/// Returns `false` for a well formed signature which doesn't prove `message` for `address`,
+/// e.g. one over another message, for another address, or of a malformed `to_sign`.
+///
+/// # Errors
+///
+/// Returns an error if `address` can't be parsed, if it isn't a P2WPKH, P2SH-P2WPKH or P2TR
+/// address, or if `signature` can't be decoded.
+pub fn verify(address: &str, message: &str, signature: &str) -> Result<bool, KeyError> {
+    let address = Address::from_str(address)?.assume_checked();
+    let bytes = BASE64_STANDARD.decode(signature)?;
+    let to_spend = to_spend(address.script_pubkey(), message);
+    // A "full" signature starts with the version of `to_sign`, 0, which no witness of a
+    // "simple" signature can be followed by
+    let tx = if let Ok(witness) = deserialize::<Witness>(&bytes) {
+        to_sign(&to_spend, ScriptBuf::new(), witness)
+    } else {
+        let tx: Transaction = deserialize(&bytes)?;
+        let expected = to_sign(
+            &to_spend,
+            tx.input
+                .first()
+                .map(|input| input.script_sig.clone())
+                .unwrap_or_default(),
+            tx.input
+                .first()
+                .map(|input| input.witness.clone())
+                .unwrap_or_default(),
+        );
+        if tx != expected {
+            return Ok(false);
+        }
+        tx
+    };
+    let script_pubkey = &to_spend.output[0].script_pubkey;
+    match address.address_type() {
+        Some(AddressType::P2wpkh) => {
+            Ok(tx.input[0].script_sig.is_empty() && verify_p2wpkh(&tx, script_pubkey)?)
+        }
+        Some(AddressType::P2sh) => match redeem_script(&tx.input[0].script_sig) {
+            Some(redeem_script)
+                if redeem_script.is_p2wpkh() && &redeem_script.to_p2sh() == script_pubkey =>
+            {
+                verify_p2wpkh(&tx, &redeem_script)
+            }
+            _ => Ok(false),
+        },
+        Some(AddressType::P2tr) => {
+            Ok(tx.input[0].script_sig.is_empty() && verify_p2tr(&tx, &to_spend)?)
+        }
+        _ => Err(KeyError::UnsupportedAddress(address.to_string())),
+    }
+}
@@ -1,7 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+
 use bitcoin::secp256k1::{rand, Secp256k1};
 use bitcoin::{Address, Network, PublicKey};
+use rand::distributions::{Distribution, WeightedIndex};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
+use crate::errors::AddressMixError;
+
+/// The output script types we know how to synthesize addresses for.
+///
+/// These cover the single-key script types that make up the overwhelming majority of the
+/// addresses seen on chain today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptType {
+    /// Legacy pay-to-pubkey-hash (`1...` on mainnet)
+    P2pkh,
+    /// Pay-to-witness-pubkey-hash nested in pay-to-script-hash (`3...` on mainnet)
+    P2shP2wpkh,
+    /// Native segwit v0 pay-to-witness-pubkey-hash (`bc1q...` on mainnet)
+    P2wpkh,
+    /// Segwit v1 pay-to-taproot (`bc1p...` on mainnet)
+    P2tr,
+}
+impl ScriptType {
+    /// Every script type, in the order they are listed in an [`AddressMix`]
+    pub const ALL: [ScriptType; 4] = [
+        ScriptType::P2pkh,
+        ScriptType::P2shP2wpkh,
+        ScriptType::P2wpkh,
+        ScriptType::P2tr,
+    ];
+}
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScriptType::P2pkh => "p2pkh",
+            ScriptType::P2shP2wpkh => "p2sh-p2wpkh",
+            ScriptType::P2wpkh => "p2wpkh",
+            ScriptType::P2tr => "p2tr",
+        })
+    }
+}
+impl FromStr for ScriptType {
+    type Err = AddressMixError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "p2pkh" => Ok(ScriptType::P2pkh),
+            "p2sh-p2wpkh" | "p2shwpkh" => Ok(ScriptType::P2shP2wpkh),
+            "p2wpkh" => Ok(ScriptType::P2wpkh),
+            "p2tr" => Ok(ScriptType::P2tr),
+            other => Err(AddressMixError::UnknownScriptType(other.to_owned())),
+        }
+    }
+}
+/// A weighted distribution of [`ScriptType`]s on a single [`Network`].
+///
+/// Synthetic databases use a mix so that generated addresses look like the ones found in real
+/// ledgers rather than being all legacy P2PKH addresses. A mix can be parsed from a comma
+/// separated list of `type=weight` pairs, where the weights are relative to each other:
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{AddressMix, ScriptType};
+/// let mix: AddressMix = "p2pkh=3,p2wpkh=1".parse().unwrap();
+/// assert_eq!(mix.weight(ScriptType::P2pkh), 3);
+/// assert_eq!(mix.weight(ScriptType::P2tr), 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressMix {
+    network: Network,
+    weights: Vec<(ScriptType, u32)>,
+}
+impl AddressMix {
+    /// Construct a new [`AddressMix`] on the given network from `(script type, weight)` pairs.
+    ///
+    /// Script types which are not listed get a weight of zero. Listing a script type more than
+    /// once adds the weights together.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AddressMixError::NoWeights`] if every weight is zero.
+    pub fn new(network: Network, weights: &[(ScriptType, u32)]) -> Result<Self, AddressMixError> {
+        let weights: Vec<(ScriptType, u32)> = ScriptType::ALL
+            .iter()
+            .map(|script_type| {
+                let weight = weights
+                    .iter()
+                    .filter(|(t, _)| t == script_type)
+                    .map(|(_, w)| *w)
+                    .sum();
+                (*script_type, weight)
+            })
+            .collect();
+        if weights.iter().all(|(_, w)| *w == 0) {
+            return Err(AddressMixError::NoWeights);
+        }
+        Ok(Self { network, weights })
+    }
+    /// A mix which only ever produces the provided script type
+    pub fn only(script_type: ScriptType, network: Network) -> Self {
+        Self::new(network, &[(script_type, 1)]).unwrap()
+    }
+    /// Return the same mix of script types on a different network
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+    /// The network the generated addresses are encoded for
+    pub fn network(&self) -> Network {
+        self.network
+    }
+    /// The relative weight given to the provided script type
+    pub fn weight(&self, script_type: ScriptType) -> u32 {
+        self.weights
+            .iter()
+            .find(|(t, _)| *t == script_type)
+            .map_or(0, |(_, w)| *w)
+    }
+    /// Pick a script type at random according to the weights of the mix
+    pub fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> ScriptType {
+        // `new` guarantees that at least one weight is non-zero
+        let distribution = WeightedIndex::new(self.weights.iter().map(|(_, w)| *w)).unwrap();
+        self.weights[distribution.sample(rng)].0
+    }
+}
+/// The default mix is a mainnet mix which loosely follows the share of each script type among
+/// the addresses in recent blocks: mostly native segwit and legacy addresses, with a smaller
+/// share of nested segwit and taproot.
+impl Default for AddressMix {
+    fn default() -> Self {
+        Self::new(
+            Network::Bitcoin,
+            &[
+                (ScriptType::P2pkh, 40),
+                (ScriptType::P2shP2wpkh, 15),
+                (ScriptType::P2wpkh, 40),
+                (ScriptType::P2tr, 5),
+            ],
+        )
+        .unwrap()
+    }
+}
+impl FromStr for AddressMix {
+    type Err = AddressMixError;
+
+    /// Parse a mainnet mix from a string like `p2pkh=40,p2wpkh=60`. Use
+    /// [`AddressMix::with_network`] to move it to another network.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Vec::new();
+        for pair in s.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (script_type, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| AddressMixError::MalformedWeight(pair.trim().to_owned()))?;
+            let weight = weight
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .ok_or_else(|| AddressMixError::MalformedWeight(pair.trim().to_owned()))?;
+            weights.push((script_type.parse::<ScriptType>()?, weight));
+        }
+        Self::new(Network::Bitcoin, &weights)
+    }
+}
+impl fmt::Display for AddressMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .weights
+            .iter()
+            .filter(|(_, w)| *w != 0)
+            .map(|(t, w)| format!("{}={}", t, w))
+            .collect();
+        write!(f, "{} ({})", pairs.join(","), self.network)
+    }
+}
+
 /// Generates a valid random Bitcoin address.
 ///
 /// This function creates a new random key pair using the `Secp256k1` elliptic curve. It then
@@ -54,6 +227,91 @@ pub fn generate_address_with_provided_public_key(public_key: PublicKey) -> Strin
     // Generate pay-to-pubkey-hash address.
     Address::p2pkh(&public_key, Network::Bitcoin).to_string()
 }
+/// Encodes the provided public key as an address of the given script type on the given network.
+///
+/// # Errors
+///
+/// The segwit script types (everything except [`ScriptType::P2pkh`]) require a compressed public
+/// key, so an error is returned when an uncompressed key is provided for one of them.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{address_from_public_key, ScriptType};
+/// # use bitcoin::{Network, PublicKey};
+/// # use secp256k1::Secp256k1;
+/// let s = Secp256k1::new();
+/// let public_key = PublicKey::new(s.generate_keypair(&mut rand::thread_rng()).1);
+/// let address = address_from_public_key(&public_key, ScriptType::P2wpkh, Network::Bitcoin).unwrap();
+/// assert!(address.starts_with("bc1q"));
+/// ```
+pub fn address_from_public_key(
+    public_key: &PublicKey,
+    script_type: ScriptType,
+    network: Network,
+) -> Result<String, bitcoin::address::Error> {
+    let address = match script_type {
+        ScriptType::P2pkh => Address::p2pkh(public_key, network),
+        ScriptType::P2shP2wpkh => Address::p2shwpkh(public_key, network)?,
+        ScriptType::P2wpkh => Address::p2wpkh(public_key, network)?,
+        ScriptType::P2tr => {
+            let secp = Secp256k1::verification_only();
+            let (internal_key, _) = public_key.inner.x_only_public_key();
+            Address::p2tr(&secp, internal_key, None, network)
+        }
+    };
+    Ok(address.to_string())
+}
+/// Generates a valid random address of the given script type on the given network, drawing the
+/// key pair from the provided random number generator.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{generate_address_with_rng, ScriptType};
+/// # use bitcoin::Network;
+/// let mut rng = rand::thread_rng();
+/// let address = generate_address_with_rng(&mut rng, ScriptType::P2tr, Network::Testnet);
+/// assert!(address.starts_with("tb1p"));
+/// ```
+pub fn generate_address_with_rng<R: rand::Rng + ?Sized>(
+    rng: &mut R,
+    script_type: ScriptType,
+    network: Network,
+) -> String {
+    let s = Secp256k1::new();
+    let public_key = PublicKey::new(s.generate_keypair(rng).1);
+    // Freshly generated keys are always compressed, so none of the script types can fail here
+    address_from_public_key(&public_key, script_type, network).unwrap()
+}
+/// Generates a valid random address of the given script type on the given network.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{generate_address_of_type, ScriptType};
+/// # use bitcoin::Network;
+/// let address = generate_address_of_type(ScriptType::P2shP2wpkh, Network::Bitcoin);
+/// assert!(address.starts_with("3"));
+/// ```
+pub fn generate_address_of_type(script_type: ScriptType, network: Network) -> String {
+    generate_address_with_rng(&mut rand::thread_rng(), script_type, network)
+}
+/// Generates a valid random address whose script type is drawn from the provided
+/// [`AddressMix`].
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{generate_mixed_address, AddressMix};
+/// let address = generate_mixed_address(&AddressMix::default());
+/// assert!(address.starts_with("1") || address.starts_with("3") || address.starts_with("bc1"));
+/// ```
+pub fn generate_mixed_address(mix: &AddressMix) -> String {
+    let mut rng = rand::thread_rng();
+    let script_type = mix.sample(&mut rng);
+    generate_address_with_rng(&mut rng, script_type, mix.network())
+}
 /// Generates a random `n` digit number representing a Bitcoin value.
 ///
 /// This function is useful for generating random Bitcoin transaction values, for example, when
@@ -120,3 +378,33 @@ pub fn generate_n_address_value_pairs(n: u32) -> (Vec<String>, Vec<u32>) {
 
     (addresses, values)
 }
+/// Generates `n` pairs of addresses and associated values, drawing the script type of each
+/// address from the provided [`AddressMix`].
+///
+/// This is the same as [`generate_n_address_value_pairs`] except that the addresses follow the
+/// script type and network distribution of the mix instead of being mainnet P2PKH addresses.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::bitcoin_utils::{generate_n_address_value_pairs_with_mix, AddressMix};
+///
+/// let (addresses, values) = generate_n_address_value_pairs_with_mix(10, &AddressMix::default());
+/// assert_eq!(addresses.len(), 10);
+/// assert_eq!(values.len(), 10);
+/// ```
+pub fn generate_n_address_value_pairs_with_mix(
+    n: u32,
+    mix: &AddressMix,
+) -> (Vec<String>, Vec<u32>) {
+    let mut rng = rand::thread_rng();
+    let addresses: Vec<String> = (0..n)
+        .into_par_iter()
+        .map(|_| generate_mixed_address(mix))
+        .collect();
+    let values = (0..n)
+        .map(|_| generate_bitcoin_value(&mut rng, 6))
+        .collect();
+
+    (addresses, values)
+}
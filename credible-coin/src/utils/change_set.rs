@@ -0,0 +1,270 @@
+use anyhow::{Context, Result, bail, ensure};
+use indexmap::IndexMap;
+use rs_merkle::{MerkleTree, algorithms::Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::exchange::key_manager::{
+    KeyManager, decode_signature, encode_signature, verify_message,
+};
+use crate::cli::publisher::entry_map::EntryMap;
+use crate::errors::KeyError;
+use crate::utils::address_utils::active_network;
+use crate::utils::bitcoin_utils::ScriptType;
+use crate::utils::csv_utils::replace_records;
+use crate::utils::duplicates::split_key;
+use crate::utils::merkle_utils::leaves_from_vectors;
+
+/// An entry which is only in one of the two files of a [`ChangeSet`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryChange {
+    /// The index of the entry's leaf in the file it is in
+    pub index: usize,
+    /// The address of the entry, or its disambiguated key if the address is repeated
+    pub address: String,
+    /// The value of the entry
+    pub value: i64,
+}
+/// An entry which is in both files of a [`ChangeSet`] with different values
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueChange {
+    /// The index of the entry's leaf in the new file
+    pub index: usize,
+    /// The address of the entry, or its disambiguated key if the address is repeated
+    pub address: String,
+    /// The value of the entry in the old file
+    pub old_value: i64,
+    /// The value of the entry in the new file
+    pub new_value: i64,
+    /// How much the value changed by, negative if it went down
+    pub delta: i128,
+}
+/// The entries added, removed, and modified between two versions of a ledger, along with the
+/// merkle roots of both. It can be serialized to be reviewed, and re-applied to a copy of the old
+/// file with [`apply_change_set`].
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::utils::change_set::{apply_change_set, diff_ledgers};
+///
+/// let dir = std::env::temp_dir().join("change_set_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+/// std::fs::write(path("old.csv"), "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,10\n").unwrap();
+/// std::fs::write(path("new.csv"), "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,4\n").unwrap();
+///
+/// let changes = diff_ledgers(&path("old.csv"), &path("new.csv")).unwrap();
+/// assert_eq!(changes.modified[0].delta, -6);
+/// assert_eq!(changes.net_change, -6);
+///
+/// std::fs::copy(path("old.csv"), path("copy.csv")).unwrap();
+/// apply_change_set(&path("copy.csv"), &changes).unwrap();
+/// assert_eq!(std::fs::read(path("copy.csv")).unwrap(), std::fs::read(path("new.csv")).unwrap());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    /// The file the changes were made from
+    pub old_file: String,
+    /// The file the changes lead to
+    pub new_file: String,
+    /// The hex encoded merkle root of the old file, `None` if it has no entries
+    pub old_root: Option<String>,
+    /// The hex encoded merkle root of the new file, `None` if it has no entries
+    pub new_root: Option<String>,
+    /// The entries only in the new file, in the order of the new file
+    pub added: Vec<EntryChange>,
+    /// The entries only in the old file, in the order of the old file
+    pub removed: Vec<EntryChange>,
+    /// The entries whose value changed, in the order of the new file
+    pub modified: Vec<ValueChange>,
+    /// The total value of the new file minus the total value of the old one
+    pub net_change: i128,
+}
+impl ChangeSet {
+    /// Whether the two files hold the same entries
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+    /// Sign the change set's JSON serialization with the key stored under `name`, see
+    /// [`KeyManager::sign_message`]. The signature names the P2PKH address of the key on the
+    /// [`active_network`], which is what it is checked against.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Missing`] if there is no such key, or an error if it can't be loaded.
+    pub fn sign(self, keys: &KeyManager, name: &str) -> Result<SignedChangeSet, KeyError> {
+        let message = serde_json::to_string(&self)?;
+        Ok(SignedChangeSet {
+            address: keys.address(name, ScriptType::P2pkh, active_network())?,
+            signature: encode_signature(&keys.sign_message(name, &message)?),
+            changes: self,
+        })
+    }
+}
+/// A [`ChangeSet`] signed by one of the exchange's keys with [`ChangeSet::sign`], so whoever
+/// reviews or applies it can check that the exchange made it and that it wasn't altered since.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedChangeSet {
+    /// The signed change set
+    pub changes: ChangeSet,
+    /// The P2PKH address of the key which signed the change set
+    pub address: String,
+    /// The base64 `signmessage` signature of the change set's JSON serialization
+    pub signature: String,
+}
+/// Check that `signed` was signed by the key of `address`, which should be an address the
+/// verifier knows belongs to the exchange rather than the one the change set names.
+///
+/// # Errors
+///
+/// Returns an error if the signature can't be decoded, or if `address` isn't a P2PKH address.
+pub fn verify_change_set(signed: &SignedChangeSet, address: &str) -> Result<bool, KeyError> {
+    if signed.address != address {
+        return Ok(false);
+    }
+    let message = serde_json::to_string(&signed.changes)?;
+    verify_message(address, &message, &decode_signature(&signed.signature)?)
+}
+/// The root of the tree made from the entries of a map, in order
+fn root_of(entries: &[(String, i64)]) -> Option<String> {
+    let (addresses, values): (Vec<String>, Vec<i64>) = entries
+        .iter()
+        .map(|(key, value)| (split_key(key).0.to_owned(), *value))
+        .unzip();
+    MerkleTree::<Sha256>::from_leaves(&leaves_from_vectors(addresses, values)).root_hex()
+}
+fn entries_of(file: &str) -> Vec<(String, i64)> {
+    EntryMap::generate_address_value_map(file)
+        .inner
+        .into_iter()
+        .collect()
+}
+/// Compare two versions of a ledger (CSV files or binary ledgers, in any combination).
+///
+/// Entries are matched by address, or by disambiguated key (`<ADDRESS>#<N>`) for the rows of a
+/// repeated address, after the
+/// [`active_duplicate_policy`](crate::utils::duplicates::active_duplicate_policy) is applied to
+/// both files.
+///
+/// # Errors
+///
+/// Returns an error if either file doesn't exist.
+///
+/// # Panics
+///
+/// This function will panic if either file can't be parsed, like when it is loaded into one of
+/// the shells.
+pub fn diff_ledgers(old_file: &str, new_file: &str) -> Result<ChangeSet> {
+    for file in [old_file, new_file] {
+        ensure!(std::path::Path::new(file).exists(), "{} not found", file);
+    }
+    let old = EntryMap::generate_address_value_map(old_file).inner;
+    let new = EntryMap::generate_address_value_map(new_file).inner;
+    let change = |(index, (address, value)): (usize, (&String, &i64))| EntryChange {
+        index,
+        address: address.clone(),
+        value: *value,
+    };
+    let added = new
+        .iter()
+        .enumerate()
+        .filter(|(_, (address, _))| !old.contains_key(*address))
+        .map(change)
+        .collect();
+    let removed = old
+        .iter()
+        .enumerate()
+        .filter(|(_, (address, _))| !new.contains_key(*address))
+        .map(change)
+        .collect();
+    let modified = new
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (address, &new_value))| {
+            let &old_value = old.get(address)?;
+            (old_value != new_value).then(|| ValueChange {
+                index,
+                address: address.clone(),
+                old_value,
+                new_value,
+                delta: i128::from(new_value) - i128::from(old_value),
+            })
+        })
+        .collect();
+    let total = |entries: &IndexMap<String, i64>| -> i128 {
+        entries.values().map(|&value| i128::from(value)).sum()
+    };
+    Ok(ChangeSet {
+        old_file: old_file.to_owned(),
+        new_file: new_file.to_owned(),
+        old_root: root_of(&old.clone().into_iter().collect::<Vec<_>>()),
+        new_root: root_of(&new.clone().into_iter().collect::<Vec<_>>()),
+        net_change: total(&new) - total(&old),
+        added,
+        removed,
+        modified,
+    })
+}
+/// Apply a change set to `file`, which must hold the same entries as the change set's old file.
+///
+/// Removed entries are dropped, modified ones take their new value, and added ones are inserted at
+/// their index in the new file. The file is only rewritten if the result has the change set's new
+/// root, which isn't the case if the new file also reordered the entries the two files share.
+///
+/// # Errors
+///
+/// Returns an error if the root of `file` isn't the change set's old root, if the changes don't
+/// match its entries, if the result doesn't have the new root, or if the file can't be rewritten.
+///
+/// # Panics
+///
+/// This function will panic if the file can't be parsed, like when it is loaded into one of the
+/// shells.
+pub fn apply_change_set(file: &str, changes: &ChangeSet) -> Result<()> {
+    let mut entries = entries_of(file);
+    ensure!(
+        root_of(&entries) == changes.old_root,
+        "{} is not the file the change set was made from, its root differs",
+        file
+    );
+    for removed in &changes.removed {
+        let position = entries
+            .iter()
+            .position(|(address, _)| *address == removed.address)
+            .with_context(|| format!("{} has no entry {} to remove", file, removed.address))?;
+        entries.remove(position);
+    }
+    for modified in &changes.modified {
+        let Some((_, value)) = entries
+            .iter_mut()
+            .find(|(address, _)| *address == modified.address)
+        else {
+            bail!("{} has no entry {} to modify", file, modified.address);
+        };
+        ensure!(
+            *value == modified.old_value,
+            "{} has the value {} for {}, not {}",
+            file,
+            value,
+            modified.address,
+            modified.old_value
+        );
+        *value = modified.new_value;
+    }
+    for added in &changes.added {
+        let index = added.index.min(entries.len());
+        entries.insert(index, (added.address.clone(), added.value));
+    }
+    ensure!(
+        root_of(&entries) == changes.new_root,
+        "Applying the change set to {} doesn't reproduce its new root",
+        file
+    );
+    let (addresses, values) = entries
+        .into_iter()
+        .map(|(key, value)| (split_key(&key).0.to_owned(), value))
+        .unzip();
+    replace_records(file, addresses, values)
+}
@@ -1,10 +1,20 @@
 use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
 
-use csv::{Reader, Writer};
+use csv::{Reader, Writer, WriterBuilder};
 use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::{errors::AddressPositionError, merkle_tree_entry::MerkleTreeEntry};
+use crate::{
+    errors::{AddressPositionError, AddressValidationError},
+    merkle_tree_entry::MerkleTreeEntry,
+    utils::address_utils::normalize,
+    utils::atomic_file::{FileLock, write_atomically},
+    utils::binary_ledger::{MappedLedger, is_binary_ledger, write_ledger},
+    utils::duplicates::{
+        DuplicateReport, active_duplicate_policy, apply_duplicate_policy, split_key,
+    },
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 /// Represents a record from a CSV file.
@@ -25,25 +35,114 @@ pub struct CSVRecord {
     value: i64,
 }
 
-fn find_matching_indices<T: PartialEq + ToString + Sync, U: PartialEq + ToString + Sync>(
-    first_vector: &[T],
-    val1: &T,
-    second_vector: &[U],
-    val2: &U,
-) -> Result<usize, AddressPositionError> {
-    assert_eq!(first_vector.len(), second_vector.len());
-    first_vector
-        .par_iter()
-        .zip(second_vector.par_iter())
-        .position_first(|(x, y)| x == val1 && y == val2)
-        .ok_or_else(|| AddressPositionError::NoMatchingIndices {
-            address: val1.to_string(),
-            value: val2.to_string(),
+impl CSVRecord {
+    /// The address of the record normalized against the active network, with the file and
+    /// line it came from attached to the error if it fails validation. `row` is the 0-based
+    /// index of the record (not counting the header).
+    fn normalized_address(
+        &self,
+        file_name: &str,
+        row: usize,
+    ) -> Result<String, AddressValidationError> {
+        normalize_in_file(&self.addresses, file_name, row)
+    }
+}
+/// Normalize an address read from row `row` (0-based, not counting the header) of a file,
+/// attaching the file and line to the error if it fails validation
+fn normalize_in_file(
+    address: &str,
+    file_name: &str,
+    row: usize,
+) -> Result<String, AddressValidationError> {
+    normalize(address).map_err(|err| AddressValidationError::InFile {
+        file: file_name.to_owned(),
+        line: row + 2,
+        source: Box::new(err),
+    })
+}
+/// Memory-map the file if it is a [binary ledger](crate::utils::binary_ledger) rather than a
+/// CSV file
+///
+/// # Panics
+///
+/// This function will panic if the file looks like a binary ledger but is corrupt.
+fn open_binary_ledger(file_name: &str) -> Option<MappedLedger> {
+    if !is_binary_ledger(file_name) {
+        return None;
+    }
+    Some(MappedLedger::open(file_name).unwrap_or_else(|err| panic!("{}: {}", file_name, err)))
+}
+/// Read the address and value columns of a CSV file or binary ledger in one pass, with the
+/// addresses normalized against the active network, as [`make_address_vector`] and
+/// [`make_value_vector`] do without panicking.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or parsed, or if one of its addresses is invalid.
+pub(crate) fn read_columns(file_name: &str) -> anyhow::Result<(Vec<String>, Vec<i64>)> {
+    if is_binary_ledger(file_name) {
+        let ledger = MappedLedger::open(file_name)?;
+        return ledger
+            .iter()
+            .enumerate()
+            .map(|(row, (address, value))| Ok((normalize_in_file(address, file_name, row)?, value)))
+            .collect();
+    }
+    let mut rdr = csv::Reader::from_path(file_name)?;
+    rdr.deserialize::<CSVRecord>()
+        .enumerate()
+        .map(|(row, result)| {
+            let record = result?;
+            Ok((record.normalized_address(file_name, row)?, record.value))
         })
+        .collect()
+}
+/// Read-modify-write the address and value columns of a CSV file or binary ledger.
+///
+/// The file's [`FileLock`] is held for the whole operation, and if `update` returns `true` the
+/// columns are written back atomically (see [`write_atomically`]) in the file's own format.
+/// Addresses are written back in their normalized form.
+pub(crate) fn rewrite_columns(
+    file_name: &str,
+    update: impl FnOnce(&mut Vec<String>, &mut Vec<i64>) -> bool,
+) -> anyhow::Result<()> {
+    let _lock = FileLock::exclusive(file_name)?;
+    let (mut addresses, mut values) = read_columns(file_name)?;
+    if !update(&mut addresses, &mut values) {
+        return Ok(());
+    }
+    if is_binary_ledger(file_name) {
+        write_ledger(file_name, &addresses, &values)?;
+    } else {
+        write_csv_columns(file_name, &addresses, &values)?;
+    }
+    Ok(())
+}
+/// Write the address and value columns to `file_name` as a CSV file with the usual
+/// `addresses,value` header, replacing the file atomically (see [`write_atomically`]) if it
+/// already exists
+pub(crate) fn write_csv_columns(
+    file_name: &str,
+    addresses: &[String],
+    values: &[i64],
+) -> anyhow::Result<()> {
+    write_atomically(file_name, |file| {
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(file);
+        writer.write_record(["addresses", "value"])?;
+        for (address, value) in addresses.iter().zip(values) {
+            writer.write_record([address, &value.to_string()])?;
+        }
+        writer.flush()
+    })?;
+    Ok(())
 }
 /// Given a filename as input return the value
 /// column as a `Vec<i64>`
+/// The file may be a CSV file or a [binary ledger](crate::utils::binary_ledger)
 pub fn make_value_vector(filename: &str) -> Vec<i64> {
+    if let Some(ledger) = open_binary_ledger(filename) {
+        return ledger.values();
+    }
     let mut rdr = csv::Reader::from_path(filename).unwrap();
     let records: Vec<CSVRecord> = rdr
         .deserialize()
@@ -51,67 +150,234 @@ pub fn make_value_vector(filename: &str) -> Vec<i64> {
         .collect();
     records.par_iter().map(|record| record.value).collect()
 }
+/// Check that every address in the provided file is valid for the active network, returning the
+/// first address that isn't
+///
+/// This is called when a file is first loaded into one of the shells so that a bad address is
+/// reported up front instead of when it is first used.
+pub fn validate_csv_addresses(file_name: &str) -> anyhow::Result<()> {
+    if is_binary_ledger(file_name) {
+        let ledger = MappedLedger::open(file_name)?;
+        for (row, (address, _)) in ledger.iter().enumerate() {
+            normalize_in_file(address, file_name, row)?;
+        }
+        return Ok(());
+    }
+    let mut rdr = csv::Reader::from_path(file_name)?;
+    for (row, result) in rdr.deserialize::<CSVRecord>().enumerate() {
+        result?.normalized_address(file_name, row)?;
+    }
+    Ok(())
+}
 /// Given a filename as input return the address column as a `Vec<String>`
+/// Every address is normalized against the active network (see
+/// [`normalize`](crate::utils::address_utils::normalize)) so that different spellings of the
+/// same address always produce the same merkle leaf.
 /// NOTE 1: The current implementation forces the returned Vec to be a
 /// `Vec<String>`. If you need the value column call the `make_value_vector`
 /// function
 /// NOTE 2: We cannot parallelize this function because doing par_bridge when
 /// deserializing messes with element order guarantees vector gives
+/// NOTE 3: The file may also be a [binary ledger](crate::utils::binary_ledger), in which case
+/// it is memory-mapped instead of parsed
 // TODO: Cache the vector?
 pub fn make_address_vector(file_name: &str) -> Vec<String> {
+    if let Some(ledger) = open_binary_ledger(file_name) {
+        return ledger
+            .iter()
+            .enumerate()
+            .map(|(row, (address, _))| {
+                normalize_in_file(address, file_name, row).unwrap_or_else(|err| panic!("{}", err))
+            })
+            .collect();
+    }
     let mut rdr = csv::Reader::from_path(file_name).unwrap();
 
     let records: Vec<String> = rdr
         .deserialize::<CSVRecord>()
-        .filter_map(|result| match result {
-            Ok(record) => Some(record.addresses.clone()),
-            Err(_) => unreachable!(),
+        .enumerate()
+        .map(|(row, result)| {
+            let record = result.expect("Error parsing CSV record");
+            record
+                .normalized_address(file_name, row)
+                .unwrap_or_else(|err| panic!("{}", err))
         })
         .collect();
 
     records
 }
-/// Retrieve the address and value columns in the dataframe as vectors
+/// Retrieve the address and value columns in the dataframe as vectors, after applying the
+/// [`active_duplicate_policy`] to any repeated addresses.
+///
+/// # Panics
+///
+/// This function will panic if the file can't be read, if one of its addresses is not valid for
+/// the active network, or if the active policy refuses the file's repeated addresses. Files are
+/// checked with [`check_duplicates`] when they are loaded so the last case should only happen if
+/// the file is changed from under the shell.
 pub fn addresses_and_values_as_vectors(file_name: &str) -> (Vec<String>, Vec<i64>) {
     let address_vec = make_address_vector(file_name);
     let value_vec = make_value_vector(file_name);
+    let (address_vec, value_vec, _) =
+        apply_duplicate_policy(address_vec, value_vec, active_duplicate_policy())
+            .unwrap_or_else(|err| panic!("{}: {}", file_name, err));
     (address_vec, value_vec)
 }
-/// Given a filename, a public address, and optional unique in that file, find its position within the address vector
+/// Check the provided file for repeated addresses under the [`active_duplicate_policy`],
+/// returning a report of every repeated address.
+///
+/// This is called when a file is first loaded into one of the shells so that the repeated
+/// addresses are reported, and refused if the policy says so, before any tree is built.
+pub fn check_duplicates(file_name: &str) -> anyhow::Result<DuplicateReport> {
+    let address_vec = make_address_vector(file_name);
+    let value_vec = make_value_vector(file_name);
+    let (_, _, report) = apply_duplicate_policy(address_vec, value_vec, active_duplicate_policy())?;
+    Ok(report)
+}
+/// Given a filename, a public address, and an optional value, find the position of the
+/// address's merkle leaf once the [`active_duplicate_policy`] has been applied to the file.
+///
+/// The address may be a disambiguated key (`<ADDRESS>#<N>`, see
+/// [`disambiguated_key`](crate::utils::duplicates::disambiguated_key)) to pick the Nth row of a
+/// repeated address. A bare address which appears on several rows is only accepted when a value
+/// is provided to tell the rows apart, in which case the first row with that value is used.
 pub fn get_address_position(
     filename: &str,
     public_address: String,
     value: Option<i64>,
 ) -> Result<usize, AddressPositionError> {
-    let address_vec = make_address_vector(filename); // This function should properly handle errors and possibly return Result
-
-    if let Some(val) = value {
-        let values = make_value_vector(filename); // This function should properly handle errors and possibly return Result
-        find_matching_indices(&address_vec, &public_address, &values, &val)
-            .map_err(|_| AddressPositionError::NoMatchingIndexForValue(public_address, val))
-    } else {
-        address_vec
-            .par_iter()
-            .position_first(|r| r == &public_address)
-            .ok_or_else(|| AddressPositionError::NoMatchingAddress(public_address))
+    let (address_vec, values) = addresses_and_values_as_vectors(filename);
+    position_in_columns(&address_vec, &values, &public_address, value)
+}
+/// Find the position of the leaf of a (possibly disambiguated) key among address and value
+/// columns the [`active_duplicate_policy`] was already applied to, as [`get_address_position`]
+/// does for a file
+///
+/// # Errors
+///
+/// Returns an error if no leaf matches, or if a bare address matches several leaves and no value
+/// tells them apart.
+pub fn position_in_columns(
+    address_vec: &[String],
+    values: &[i64],
+    public_address: &str,
+    value: Option<i64>,
+) -> Result<usize, AddressPositionError> {
+    let (address, occurrence) = split_key(public_address);
+    let positions: Vec<usize> = address_vec
+        .par_iter()
+        .enumerate()
+        .filter(|(_, a)| *a == address)
+        .map(|(pos, _)| pos)
+        .collect();
+    if positions.is_empty() {
+        return Err(AddressPositionError::NoMatchingAddress(address.to_owned()));
+    }
+    let position = match (occurrence, value) {
+        (Some(occurrence), _) => *positions.get(occurrence.wrapping_sub(1)).ok_or_else(|| {
+            AddressPositionError::NoSuchOccurrence {
+                address: address.to_owned(),
+                occurrences: positions.len(),
+                requested: occurrence,
+            }
+        })?,
+        (None, Some(val)) => positions
+            .iter()
+            .copied()
+            .find(|pos| values[*pos] == val)
+            .ok_or_else(|| {
+                AddressPositionError::NoMatchingIndexForValue(public_address.to_owned(), val)
+            })?,
+        (None, None) if positions.len() > 1 => {
+            return Err(AddressPositionError::AmbiguousAddress {
+                address: address.to_owned(),
+                occurrences: positions.len(),
+            });
+        }
+        (None, None) => positions[0],
+    };
+    match value {
+        Some(val) if values[position] != val => Err(AddressPositionError::NoMatchingIndexForValue(
+            public_address.to_owned(),
+            val,
+        )),
+        _ => Ok(position),
     }
 }
 /// Update the value for the given address in a provided dataset file
 /// with the provided value
-/// This function works by updating the record in the old csv file, creating a new temporary one
-/// and renaming it to the same name as the old file
-pub fn update_csv_value(filename: &str, address: String, value: i64) {
-    let mut rdr = Reader::from_path(filename).unwrap();
-    let mut writer = Writer::from_path("temp.csv").unwrap();
-    for result in rdr.deserialize() {
-        let mut record: CSVRecord = result.unwrap();
-        if record.addresses == address {
-            record.value = value;
+/// This function works by writing the updated records to a temporary file next to the old one
+/// and atomically renaming it over the old file, while holding the file's [`FileLock`]
+/// The addresses are written back in their normalized form
+pub fn update_csv_value(filename: &str, address: String, value: i64) -> anyhow::Result<()> {
+    rewrite_columns(filename, |addresses, values| {
+        for (pos, a) in addresses.iter().enumerate() {
+            if *a == address {
+                values[pos] = value;
+            }
         }
-        let _ = writer.serialize(record);
+        true
+    })
+}
+/// Update the value of a single leaf in a provided dataset file, given the (possibly
+/// disambiguated) key the leaf is known by.
+///
+/// - A disambiguated key (`<ADDRESS>#<N>`) only updates the Nth row of the address.
+/// - A bare address updates its first row and drops any later rows of the same address, so that
+///   the address ends up with the new value under every
+///   [`DuplicatePolicy`](crate::utils::duplicates::DuplicatePolicy).
+///
+/// Like [`update_csv_value`], the file is rewritten with its addresses in their normalized form.
+///
+/// # Errors
+///
+/// Returns an error if no row matches the key, or if the file can't be rewritten.
+pub fn update_csv_entry(filename: &str, key: &str, value: i64) -> anyhow::Result<()> {
+    let mut updated = false;
+    rewrite_columns(filename, |addresses, values| {
+        updated = update_entry(addresses, values, key, value);
+        updated
+    })?;
+    if !updated {
+        anyhow::bail!("No record for address {}", key);
     }
-    std::fs::remove_file(filename).unwrap();
-    std::fs::rename("temp.csv", filename).unwrap();
+    Ok(())
+}
+/// Update the value of the row(s) of a (possibly disambiguated) key in the raw address and value
+/// columns of a ledger, as [`update_csv_entry`] does for a file, returning whether a row matched
+pub fn update_entry(
+    addresses: &mut Vec<String>,
+    values: &mut Vec<i64>,
+    key: &str,
+    value: i64,
+) -> bool {
+    let (address, occurrence) = split_key(key);
+    let mut updated = false;
+    let mut seen = 0;
+    let rows: Vec<(String, i64)> = addresses
+        .drain(..)
+        .zip(values.drain(..))
+        .filter_map(|(a, v)| {
+            if a != address {
+                return Some((a, v));
+            }
+            seen += 1;
+            match occurrence {
+                Some(occurrence) if occurrence == seen => {
+                    updated = true;
+                    Some((a, value))
+                }
+                Some(_) => Some((a, v)),
+                None if seen == 1 => {
+                    updated = true;
+                    Some((a, value))
+                }
+                None => None,
+            }
+        })
+        .collect();
+    (*addresses, *values) = rows.into_iter().unzip();
+    updated
 }
 /// Extracts exchange addresses and their associated values from a given CSV file.
 ///
@@ -133,39 +399,232 @@ pub fn update_csv_value(filename: &str, address: String, value: i64) {
 /// This function will panic if:
 /// - The provided file path is not found or there's an error in reading the file.
 /// - There's a problem in deserializing the CSV records into the `CSVRecord` struct.
+/// - One of the addresses is not valid for the active network.
 pub fn get_exchange_addresses_and_values_from_file(file_name: &str) -> (Vec<String>, Vec<i64>) {
+    if is_binary_ledger(file_name) {
+        return (make_address_vector(file_name), make_value_vector(file_name));
+    }
     let mut rdr: Reader<std::fs::File> = csv::Reader::from_path(file_name).unwrap();
     let mut address_col = Vec::new();
     let mut val_col = Vec::new();
-    for result in rdr.deserialize() {
+    for (row, result) in rdr.deserialize().enumerate() {
         let record: CSVRecord = result.unwrap();
-        address_col.push(record.addresses);
+        address_col.push(
+            record
+                .normalized_address(file_name, row)
+                .unwrap_or_else(|err| panic!("{}", err)),
+        );
         val_col.push(record.value);
     }
     (address_col, val_col)
 }
 /// Given a file, and an address and value, write it as a record
 /// to the end of the file
-pub fn append_record(file: &str, address: String, value: u64) {
-    let mut rdr: Reader<std::fs::File> = csv::Reader::from_path(file).unwrap();
-    for result in rdr.deserialize() {
-        let record: CSVRecord = result.unwrap();
-        if record.addresses == address {
-            log::error!("Record for address {}, already exists", address);
-            return;
+/// Like every other mutation it is done while holding the file's [`FileLock`], so concurrent
+/// shells appending to the same file can't lose each other's records
+///
+/// # Errors
+///
+/// Returns an error if the address is not valid for the active network, if the file already has
+/// a record for it, or if the file can't be rewritten.
+pub fn append_record(file: &str, address: String, value: u64) -> anyhow::Result<()> {
+    append_records(file, vec![address], vec![i64::try_from(value)?])?;
+    Ok(())
+}
+/// Append several records to the end of a file at once, in order, returning the number of
+/// records appended. Either every record is appended or none is.
+///
+/// The records are written to the end of a CSV file in place and fsynced, while holding its
+/// [`FileLock`], rather than rewriting the whole file. A binary ledger is rewritten atomically,
+/// as its header and dictionary change with every row.
+///
+/// # Errors
+///
+/// Returns an error if one of the addresses is not valid for the active network, if an address
+/// is given twice or the file already has a record for it, or if the file can't be written.
+pub fn append_records(
+    file: &str,
+    new_addresses: Vec<String>,
+    new_values: Vec<i64>,
+) -> anyhow::Result<usize> {
+    let new_addresses = new_addresses
+        .iter()
+        .map(|address| normalize(address))
+        .collect::<Result<Vec<String>, _>>()?;
+    if let Some(address) = append_to_file(file, &new_addresses, &new_values)? {
+        anyhow::bail!("Record for address {}, already exists", address);
+    }
+    Ok(new_addresses.len())
+}
+/// Append already normalized rows to a file as [`append_records`] does, except that an address
+/// which is given twice or already in the file is returned instead of failing
+pub(crate) fn append_to_file(
+    file: &str,
+    new_addresses: &[String],
+    new_values: &[i64],
+) -> anyhow::Result<Option<String>> {
+    if is_binary_ledger(file) {
+        let mut existing = None;
+        rewrite_columns(file, |addresses, values| {
+            existing = append_entries(addresses, values, new_addresses, new_values);
+            existing.is_none()
+        })?;
+        return Ok(existing);
+    }
+    let _lock = FileLock::exclusive(file)?;
+    let existing = first_existing(&read_columns(file)?.0, new_addresses);
+    if existing.is_none() {
+        append_csv_rows(file, new_addresses, new_values)?;
+    }
+    Ok(existing)
+}
+/// Append already normalized rows to the raw address and value columns of a ledger, as
+/// [`append_records`] does for a file.
+///
+/// Nothing is appended if one of the new addresses is given twice or is already in the columns,
+/// in which case that address is returned.
+pub fn append_entries(
+    addresses: &mut Vec<String>,
+    values: &mut Vec<i64>,
+    new_addresses: &[String],
+    new_values: &[i64],
+) -> Option<String> {
+    let existing = first_existing(addresses, new_addresses);
+    if existing.is_none() {
+        addresses.extend(new_addresses.iter().cloned());
+        values.extend(new_values.iter().copied());
+    }
+    existing
+}
+/// The first of `new_addresses` which is already in `addresses`, or given twice
+pub(crate) fn first_existing(addresses: &[String], new_addresses: &[String]) -> Option<String> {
+    new_addresses
+        .iter()
+        .enumerate()
+        .find(|(index, address)| {
+            addresses.contains(address) || new_addresses[..*index].contains(address)
+        })
+        .map(|(_, address)| address.clone())
+}
+/// Write rows to the end of a CSV file and fsync it. The caller must hold the file's
+/// [`FileLock`]. If the write fails the file is truncated back to its old length, so it is never
+/// left with half a row.
+fn append_csv_rows(file_name: &str, addresses: &[String], values: &[i64]) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new().read(true).append(true).open(file_name)?;
+    let length = file.metadata()?.len();
+    let mut rows = Vec::new();
+    if length > 0 {
+        // A file whose last line has no newline would have its first new row joined to it
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            rows.push(b'\n');
         }
     }
-    let file_handle = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(file)
-        .unwrap();
-    let mut writer = Writer::from_writer(file_handle);
-    if writer.write_record(&[address, value.to_string()]).is_ok() {
-    } else {
-        log::error!("Failed to write record");
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(rows);
+    if length == 0 {
+        writer.write_record(["addresses", "value"])?;
+    }
+    for (address, value) in addresses.iter().zip(values) {
+        writer.write_record([address, &value.to_string()])?;
+    }
+    let rows = writer.into_inner()?;
+    if let Err(err) = file.write_all(&rows).and_then(|()| file.sync_data()) {
+        let _ = file.set_len(length);
+        return Err(err.into());
+    }
+    Ok(())
+}
+/// Replace every record of a file with the provided addresses and values, in order, keeping the
+/// file's format
+///
+/// # Errors
+///
+/// Returns an error if one of the addresses is not valid for the active network, or if the file
+/// can't be rewritten.
+pub fn replace_records(
+    file: &str,
+    new_addresses: Vec<String>,
+    new_values: Vec<i64>,
+) -> anyhow::Result<()> {
+    let new_addresses = new_addresses
+        .iter()
+        .map(|address| normalize(address))
+        .collect::<Result<Vec<String>, _>>()?;
+    rewrite_columns(file, |addresses, values| {
+        *addresses = new_addresses;
+        *values = new_values;
+        true
+    })
+}
+/// Remove the records of an account from a file, given the (possibly disambiguated) key it is
+/// known by, returning the number of rows removed.
+///
+/// - A disambiguated key (`<ADDRESS>#<N>`) only removes the Nth row of the address.
+/// - A bare address removes every row of the address.
+///
+/// # Errors
+///
+/// Returns an error if no row matches the key, or if the file can't be rewritten.
+pub fn remove_records(file: &str, key: &str) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    rewrite_columns(file, |addresses, values| {
+        removed = remove_entries(addresses, values, key);
+        removed > 0
+    })?;
+    if removed == 0 {
+        anyhow::bail!("No record for address {}", key);
+    }
+    Ok(removed)
+}
+/// Remove the row(s) of a (possibly disambiguated) key from the raw address and value columns
+/// of a ledger, as [`remove_records`] does for a file, returning the number of rows removed
+pub fn remove_entries(addresses: &mut Vec<String>, values: &mut Vec<i64>, key: &str) -> usize {
+    let (address, occurrence) = split_key(key);
+    let mut removed = 0;
+    let mut seen = 0;
+    let rows: Vec<(String, i64)> = addresses
+        .drain(..)
+        .zip(values.drain(..))
+        .filter(|(a, _)| {
+            if a != address {
+                return true;
+            }
+            seen += 1;
+            let remove = occurrence.is_none_or(|occurrence| occurrence == seen);
+            if remove {
+                removed += 1;
+            }
+            !remove
+        })
+        .collect();
+    (*addresses, *values) = rows.into_iter().unzip();
+    removed
+}
+/// Convert a CSV file into a [binary ledger](crate::utils::binary_ledger), returning the number
+/// of entries written.
+///
+/// The addresses are validated and normalized against the active network on the way, and the
+/// rows are kept in order so the binary ledger produces the same merkle tree as the CSV file.
+pub fn convert_csv_to_ledger(csv_file: &str, ledger_file: &str) -> anyhow::Result<usize> {
+    validate_csv_addresses(csv_file)?;
+    let addresses = make_address_vector(csv_file);
+    let values = make_value_vector(csv_file);
+    write_ledger(ledger_file, &addresses, &values)?;
+    Ok(addresses.len())
+}
+/// Convert a [binary ledger](crate::utils::binary_ledger) back into a CSV file with an
+/// `addresses,value` header, returning the number of rows written.
+pub fn convert_ledger_to_csv(ledger_file: &str, csv_file: &str) -> anyhow::Result<usize> {
+    let ledger = MappedLedger::open(ledger_file)?;
+    let mut writer = Writer::from_path(csv_file)?;
+    writer.write_record(["addresses", "value"])?;
+    for (address, value) in ledger.iter() {
+        writer.write_record([address, &value.to_string()])?;
     }
-    let _ = writer.flush();
+    writer.flush()?;
+    Ok(ledger.len())
 }
 /// Transforms paired vectors of strings and integers into a vector of `MerkleTreeEntry` structs.
 ///
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
+
+use clap::ValueEnum;
+use indexmap::IndexMap;
+
+use crate::errors::{AddressValidationError, DuplicateAddressError};
+use crate::utils::address_utils::normalize;
+
+lazy_static! {
+    static ref ACTIVE_POLICY: RwLock<DuplicatePolicy> = RwLock::new(DuplicatePolicy::default());
+}
+
+/// The separator between an address and its occurrence number in a disambiguated key. It can't
+/// collide with an address because it is not part of the base58 or bech32 alphabets.
+const OCCURRENCE_SEPARATOR: char = '#';
+
+/// What to do when the same address appears on more than one row of a publisher or exchange file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Refuse to load a file which contains a repeated address
+    Reject,
+    /// Collapse every row of a repeated address into a single entry (at the position of its first
+    /// row) whose value is the sum of the rows
+    Sum,
+    /// Keep every row as its own merkle leaf. Rows of a repeated address are told apart by their
+    /// occurrence number, i.e. `<ADDRESS>#1`, `<ADDRESS>#2`, ...
+    #[default]
+    KeepAll,
+    /// Collapse every row of a repeated address into a single entry (at the position of its first
+    /// row) holding the value of its last row
+    LastWins,
+}
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DuplicatePolicy::Reject => "reject",
+            DuplicatePolicy::Sum => "sum",
+            DuplicatePolicy::KeepAll => "keep-all",
+            DuplicatePolicy::LastWins => "last-wins",
+        })
+    }
+}
+/// Set the policy applied to repeated addresses whenever a file is read.
+///
+/// The publisher and exchange CLIs call this once with the `--duplicates` policy they were
+/// started with. If it is never called, [`DuplicatePolicy::KeepAll`] is used.
+pub fn set_active_duplicate_policy(policy: DuplicatePolicy) {
+    *ACTIVE_POLICY.write().unwrap() = policy;
+}
+/// The policy currently applied to repeated addresses. See [`set_active_duplicate_policy`].
+pub fn active_duplicate_policy() -> DuplicatePolicy {
+    *ACTIVE_POLICY.read().unwrap()
+}
+/// The addresses which appear more than once in a file, along with the (0-based, header
+/// excluded) rows each of them appears on
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DuplicateReport {
+    /// Every repeated address mapped to the rows it appears on, in file order
+    pub rows: IndexMap<String, Vec<usize>>,
+}
+impl DuplicateReport {
+    /// Whether every address in the file is unique
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    /// The number of distinct addresses which are repeated
+    pub fn address_count(&self) -> usize {
+        self.rows.len()
+    }
+    /// The number of rows which repeat an address seen on an earlier row
+    pub fn extra_row_count(&self) -> usize {
+        self.rows.values().map(|rows| rows.len() - 1).sum()
+    }
+}
+impl fmt::Display for DuplicateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no repeated addresses");
+        }
+        write!(
+            f,
+            "{} repeated address(es) over {} extra row(s):",
+            self.address_count(),
+            self.extra_row_count()
+        )?;
+        for (address, rows) in &self.rows {
+            // +2 to turn the row index into a line number, skipping the header
+            let lines: Vec<String> = rows.iter().map(|row| (row + 2).to_string()).collect();
+            write!(f, "\n  {} (lines {})", address, lines.join(", "))?;
+        }
+        Ok(())
+    }
+}
+/// Find every address which appears more than once in the provided address column
+pub fn find_duplicates(addresses: &[String]) -> DuplicateReport {
+    let mut seen: IndexMap<&str, Vec<usize>> = IndexMap::new();
+    for (row, address) in addresses.iter().enumerate() {
+        seen.entry(address.as_str()).or_default().push(row);
+    }
+    DuplicateReport {
+        rows: seen
+            .into_iter()
+            .filter(|(_, rows)| rows.len() > 1)
+            .map(|(address, rows)| (address.to_owned(), rows))
+            .collect(),
+    }
+}
+/// Apply a [`DuplicatePolicy`] to the address and value columns of a file, returning the
+/// columns that should be turned into merkle leaves along with a report of the repeated
+/// addresses.
+///
+/// # Errors
+///
+/// Returns [`DuplicateAddressError::Rejected`] if the policy is [`DuplicatePolicy::Reject`] and
+/// an address is repeated, or [`DuplicateAddressError::Overflow`] if summing the values of a
+/// repeated address overflows.
+///
+/// # Examples
+///
+/// ```
+/// # use credible_coin::utils::duplicates::{apply_duplicate_policy, DuplicatePolicy};
+/// let addresses = vec!["a".to_owned(), "b".to_owned(), "a".to_owned()];
+/// let values = vec![1, 2, 3];
+/// let (addresses, values, report) =
+///     apply_duplicate_policy(addresses, values, DuplicatePolicy::Sum).unwrap();
+/// assert_eq!(addresses, vec!["a".to_owned(), "b".to_owned()]);
+/// assert_eq!(values, vec![4, 2]);
+/// assert_eq!(report.extra_row_count(), 1);
+/// ```
+pub fn apply_duplicate_policy(
+    addresses: Vec<String>,
+    values: Vec<i64>,
+    policy: DuplicatePolicy,
+) -> Result<(Vec<String>, Vec<i64>, DuplicateReport), DuplicateAddressError> {
+    assert_eq!(addresses.len(), values.len());
+    let report = find_duplicates(&addresses);
+    if report.is_empty() {
+        return Ok((addresses, values, report));
+    }
+    match policy {
+        DuplicatePolicy::Reject => Err(DuplicateAddressError::Rejected(report.to_string())),
+        DuplicatePolicy::KeepAll => Ok((addresses, values, report)),
+        DuplicatePolicy::Sum | DuplicatePolicy::LastWins => {
+            let mut collapsed: IndexMap<String, i64> = IndexMap::with_capacity(addresses.len());
+            for (address, value) in addresses.into_iter().zip(values) {
+                match collapsed.get_mut(&address) {
+                    Some(total) if policy == DuplicatePolicy::Sum => {
+                        *total = total
+                            .checked_add(value)
+                            .ok_or_else(|| DuplicateAddressError::Overflow(address.clone()))?;
+                    }
+                    Some(last) => *last = value,
+                    None => {
+                        collapsed.insert(address, value);
+                    }
+                }
+            }
+            let (addresses, values) = collapsed.into_iter().unzip();
+            Ok((addresses, values, report))
+        }
+    }
+}
+/// Build the key used to refer to the `occurrence`th (1-based) row of a repeated address when
+/// every row is kept
+///
+/// ```
+/// # use credible_coin::utils::duplicates::disambiguated_key;
+/// assert_eq!(disambiguated_key("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC", 2), "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC#2");
+/// ```
+pub fn disambiguated_key(address: &str, occurrence: usize) -> String {
+    format!("{}{}{}", address, OCCURRENCE_SEPARATOR, occurrence)
+}
+/// The key of every row of a ledger's `addresses`: its address, or its disambiguated key if the
+/// address is repeated
+///
+/// ```
+/// # use credible_coin::utils::duplicates::account_keys;
+/// let addresses = ["abc", "def", "abc"].map(str::to_owned);
+/// assert_eq!(account_keys(&addresses), ["abc#1", "def", "abc#2"]);
+/// ```
+pub fn account_keys(addresses: &[String]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for address in addresses {
+        *counts.entry(address).or_default() += 1;
+    }
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    addresses
+        .iter()
+        .map(|address| {
+            let occurrence = occurrences.entry(address).or_default();
+            *occurrence += 1;
+            if counts[address.as_str()] > 1 {
+                disambiguated_key(address, *occurrence)
+            } else {
+                address.clone()
+            }
+        })
+        .collect()
+}
+/// Split a (possibly disambiguated) key into its address and its 1-based occurrence number, if
+/// it has one
+///
+/// ```
+/// # use credible_coin::utils::duplicates::split_key;
+/// assert_eq!(split_key("abc#2"), ("abc", Some(2)));
+/// assert_eq!(split_key("abc"), ("abc", None));
+/// ```
+pub fn split_key(key: &str) -> (&str, Option<usize>) {
+    match key.rsplit_once(OCCURRENCE_SEPARATOR) {
+        Some((address, occurrence)) => match occurrence.parse::<usize>() {
+            Ok(occurrence) => (address, Some(occurrence)),
+            Err(_) => (key, None),
+        },
+        None => (key, None),
+    }
+}
+/// Normalize the address part of a (possibly disambiguated) key against the active network,
+/// keeping its occurrence number
+pub fn normalize_key(key: &str) -> Result<String, AddressValidationError> {
+    let (address, occurrence) = split_key(key.trim());
+    let address = normalize(address)?;
+    Ok(match occurrence {
+        Some(occurrence) => disambiguated_key(&address, occurrence),
+        None => address,
+    })
+}
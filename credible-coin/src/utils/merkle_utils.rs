@@ -1,15 +1,20 @@
 use rs_merkle::{algorithms::Sha256, MerkleTree};
 
 use crate::{
-    cli::publisher::entry_map::EntryMap, merkle_tree_entry::MerkleTreeEntry,
-    utils::csv_utils::addresses_and_values_as_vectors, utils::csv_utils::get_address_position,
+    ledger_store::LedgerStore, merkle_tree_entry::MerkleTreeEntry,
+    utils::csv_utils::addresses_and_values_as_vectors, utils::csv_utils::position_in_columns,
+    utils::duplicates::split_key,
 };
 use anyhow::{anyhow, Result};
 
 /// Creates leaves from coin vectors
 pub fn load_merkle_leaves_from_csv(file_name: &str) -> Vec<[u8; 32]> {
     let (v1, v2) = addresses_and_values_as_vectors(file_name);
-    let vec_entries = MerkleTreeEntry::create_entries_vector(v1, v2);
+    leaves_from_vectors(v1, v2)
+}
+/// Creates the leaves of the entries made from the address and value vectors, in order
+pub fn leaves_from_vectors(addresses: Vec<String>, values: Vec<i64>) -> Vec<[u8; 32]> {
+    let vec_entries = MerkleTreeEntry::create_entries_vector(addresses, values);
 
     let mut serialized_entries: Vec<Vec<u8>> = Vec::new();
 
@@ -25,10 +30,12 @@ pub fn load_merkle_leaves_from_csv(file_name: &str) -> Vec<[u8; 32]> {
 
 /// Prove that a coin is a member of the merkle tree given its public address and an optional value
 /// Note that the value is only needed by the publisher shell because ledger
-/// entries can use the same address multiple times, so we use the value to distinguish them
-// TODO: What is the best way to handle duplicate delta values in the ledger file?
+/// entries can use the same address multiple times, so we use the value to distinguish them.
+/// A repeated address can also be picked by its disambiguated key (`<ADDRESS>#<N>`), see
+/// [`DuplicatePolicy`](crate::utils::duplicates::DuplicatePolicy) for how repeated addresses
+/// are turned into leaves.
 pub fn prove_membership(
-    filename: &str,
+    ledger: &dyn LedgerStore,
     _public_address: &str,
     value: Option<i64>,
     tree: &MerkleTree<Sha256>,
@@ -36,34 +43,13 @@ pub fn prove_membership(
     let tree_leaves = tree
         .leaves()
         .ok_or_else(|| anyhow!("Could not get leaves to prove"))?;
-    let map = EntryMap::generate_address_value_map(filename);
-
-    let map_value = map
-        .inner
-        .get(_public_address)
-        .ok_or_else(|| anyhow!("Could not find public address {:?}", _public_address))?;
+    let (addresses, values) = ledger.leaf_columns()?;
 
-    let (generated_entry, address_index) = if let Some(value) = value {
-        let index = get_address_position(filename, _public_address.to_string(), Some(value))
-            .map_err(|e| {
-                anyhow!(
-                    "Could not get address position with provided value: {:?}",
-                    e
-                )
-            })?;
-        (
-            MerkleTreeEntry::new(_public_address.to_owned(), value),
-            index,
-        )
-    } else {
-        let index: usize =
-            get_address_position(filename, _public_address.to_string(), Some(*map_value))
-                .map_err(|e| anyhow!("Could not get address position with map value: {:?}", e))?;
-        (
-            MerkleTreeEntry::new(_public_address.to_owned(), *map_value),
-            index,
-        )
-    };
+    let address_index = position_in_columns(&addresses, &values, _public_address, value)
+        .map_err(|e| anyhow!("Could not get address position: {}", e))?;
+    let map_value = values[address_index];
+    let (address, _) = split_key(_public_address);
+    let generated_entry = MerkleTreeEntry::new(address.to_owned(), value.unwrap_or(map_value));
 
     let indices = vec![address_index];
     let proof = tree.proof(&indices);
@@ -77,6 +63,6 @@ pub fn prove_membership(
         return Err(anyhow!("Verification failed"));
     }
 
-    log::info!("Address {:?} found in merkle tree", _public_address);
+    log::debug!("Address {:?} found in merkle tree", _public_address);
     Ok(())
 }
@@ -5,17 +5,29 @@ use std::io::{self};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
-/// A simple binary serializer
-pub mod binary_serializer;
+/// Address validation and normalization
+pub mod address_utils;
+/// Crash-safe file replacement and advisory file locks
+pub mod atomic_file;
+/// A versioned, columnar binary format for large ledgers
+pub mod binary_ledger;
+/// Signing messages for, and checking them against, segwit addresses as BIP-322 describes
+pub mod bip322;
 /// Helper functions for bitcoin
 pub mod bitcoin_utils;
+/// Comparing two versions of a ledger, and re-applying the differences
+pub mod change_set;
 /// A set of csv helper functions
 pub mod csv_utils;
+/// Policies for addresses repeated within a file
+pub mod duplicates;
 /// A helper trait to convert vector and slice types
 /// to Vec<&[u8]> to be hashed by the sha crate
 pub mod hashable;
 /// Helper functions to work with the merkle tree from `rs::merkle`
 pub mod merkle_utils;
+/// Labelled copies of a ledger which it can be rolled back to
+pub mod snapshots;
 lazy_static! {
     static ref PROJECT_ROOT: Mutex<Option<String>> = Mutex::new(None);
 }
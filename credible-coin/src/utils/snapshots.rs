@@ -0,0 +1,155 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rs_merkle::utils::collections::to_hex_string;
+use rs_merkle::{Hasher, MerkleTree, algorithms::Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SnapshotError;
+use crate::utils::atomic_file::{FileLock, write_atomically};
+use crate::utils::merkle_utils::load_merkle_leaves_from_csv;
+
+/// The file listing the snapshots of a ledger, in its snapshot directory
+const INDEX_FILE: &str = "snapshots.json";
+
+/// A copy of a ledger saved under a label, which it can be rolled back to
+///
+/// # Examples
+///
+/// ```
+/// use credible_coin::utils::snapshots::{list_snapshots, restore_snapshot, take_snapshot};
+///
+/// let dir = std::env::temp_dir().join("snapshot_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let file = dir.join("ledger.csv");
+/// let file = file.to_str().unwrap();
+/// std::fs::write(file, "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,10\n").unwrap();
+///
+/// let snapshot = take_snapshot(file, "before-edit").unwrap();
+/// std::fs::write(file, "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,99\n").unwrap();
+/// assert_eq!(restore_snapshot(file, "before-edit").unwrap(), snapshot);
+/// assert!(std::fs::read_to_string(file).unwrap().ends_with(",10\n"));
+/// assert_eq!(list_snapshots(file).unwrap(), vec![snapshot]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The name the snapshot was taken under
+    pub label: String,
+    /// The hex encoded SHA-256 hash of the ledger's contents
+    pub file_hash: String,
+    /// The hex encoded merkle root of the ledger, `None` if it had no entries
+    pub root: Option<String>,
+    /// When the snapshot was taken, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+/// The directory the snapshots of `file` are kept in, i.e. `<file>.snapshots`
+pub fn snapshot_dir(file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.snapshots", file))
+}
+/// Labels become file names in the snapshot directory, so they are kept to a safe set of
+/// characters and can't be the name of the index
+fn check_label(label: &str) -> Result<(), SnapshotError> {
+    if label == INDEX_FILE {
+        return Err(SnapshotError::ReservedLabel(label.to_owned()));
+    }
+    let valid = !label.is_empty()
+        && !label.starts_with('.')
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(SnapshotError::InvalidLabel(label.to_owned()));
+    }
+    Ok(())
+}
+fn read_index(dir: &Path) -> Result<Vec<Snapshot>, SnapshotError> {
+    match fs::read(dir.join(INDEX_FILE)) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+fn write_index(dir: &Path, snapshots: &[Snapshot]) -> Result<(), SnapshotError> {
+    let contents = serde_json::to_vec_pretty(snapshots)?;
+    write_atomically(dir.join(INDEX_FILE), |file| file.write_all(&contents))?;
+    Ok(())
+}
+fn hash_contents(contents: &[u8]) -> String {
+    to_hex_string(&Sha256::hash(contents))
+}
+/// Save a copy of `file` (a CSV file or binary ledger) under `label`, recording the hash of its
+/// contents, its merkle root, and the time.
+///
+/// The file's [`FileLock`] is held while it is copied so the snapshot can't catch a shell halfway
+/// through rewriting it.
+///
+/// # Errors
+///
+/// Returns an error if the label is invalid, reserved or already taken, or if the file or its
+/// snapshot can't be read or written.
+///
+/// # Panics
+///
+/// This function will panic if the file can't be parsed into a merkle tree, like when it is
+/// first loaded into one of the shells.
+pub fn take_snapshot(file: &str, label: &str) -> Result<Snapshot, SnapshotError> {
+    check_label(label)?;
+    let _lock = FileLock::exclusive(file)?;
+    let dir = snapshot_dir(file);
+    let mut snapshots = read_index(&dir)?;
+    if snapshots.iter().any(|snapshot| snapshot.label == label) {
+        return Err(SnapshotError::Exists(label.to_owned()));
+    }
+    let contents = fs::read(file)?;
+    fs::create_dir_all(&dir)?;
+    write_atomically(dir.join(label), |copy| copy.write_all(&contents))?;
+    let snapshot = Snapshot {
+        label: label.to_owned(),
+        file_hash: hash_contents(&contents),
+        root: MerkleTree::<Sha256>::from_leaves(&load_merkle_leaves_from_csv(file)).root_hex(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+    };
+    snapshots.push(snapshot.clone());
+    write_index(&dir, &snapshots)?;
+    Ok(snapshot)
+}
+/// Every snapshot taken of `file`, oldest first
+///
+/// # Errors
+///
+/// Returns an error if the snapshot index can't be read.
+pub fn list_snapshots(file: &str) -> Result<Vec<Snapshot>, SnapshotError> {
+    read_index(&snapshot_dir(file))
+}
+/// Replace the contents of `file` with the copy saved under `label`, returning the snapshot so
+/// the caller can rebuild its tree and check it against the recorded root.
+///
+/// The copy is checked against the hash recorded when it was taken before it is written back,
+/// atomically and under the file's [`FileLock`]. Later snapshots are kept, so a rollback can
+/// itself be undone.
+///
+/// # Errors
+///
+/// Returns an error if there is no snapshot called `label`, if its copy was modified, or if the
+/// file can't be rewritten.
+pub fn restore_snapshot(file: &str, label: &str) -> Result<Snapshot, SnapshotError> {
+    check_label(label)?;
+    let _lock = FileLock::exclusive(file)?;
+    let dir = snapshot_dir(file);
+    let snapshot = read_index(&dir)?
+        .into_iter()
+        .find(|snapshot| snapshot.label == label)
+        .ok_or_else(|| SnapshotError::NotFound(label.to_owned()))?;
+    let contents = fs::read(dir.join(label))?;
+    if hash_contents(&contents) != snapshot.file_hash {
+        return Err(SnapshotError::Corrupt(label.to_owned()));
+    }
+    write_atomically(file, |original| original.write_all(&contents))?;
+    Ok(snapshot)
+}
@@ -0,0 +1,153 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use credible_coin::account_database::{
+        ChangeKind, SCHEMA_VERSION, SqliteLedgerStore, is_sqlite_database,
+    };
+    use credible_coin::cli::output::{CommandReport, Status};
+    use credible_coin::errors::LedgerStoreError;
+    use credible_coin::ledger_store::{CsvLedgerStore, LedgerStore};
+
+    use crate::common::{self, path};
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const THIRD: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(
+            name,
+            &[(
+                "exchange.csv",
+                &format!("addresses,value\n{FIRST},10\n{SECOND},20\n{FIRST},5\n"),
+            )],
+        )
+    }
+    fn exchange(dir: &Path, args: &[&str], script: &str) -> Vec<CommandReport> {
+        common::exchange(dir, args, &[], script)
+    }
+    #[test]
+    pub fn databases_match_their_csv_file() {
+        let dir = temp_dir("account_database_csv");
+        let csv = CsvLedgerStore::new(&path(&dir, "exchange.csv"));
+        let database = SqliteLedgerStore::open(&path(&dir, "exchange.db")).unwrap();
+        assert_eq!(database.import_csv(&path(&dir, "exchange.csv")).unwrap(), 3);
+        assert!(is_sqlite_database(database.path()));
+        assert_eq!(database.root().unwrap(), csv.root().unwrap());
+
+        for ledger in [&csv as &dyn LedgerStore, &database] {
+            ledger.update(&format!("{FIRST}#2"), 7).unwrap();
+            ledger.append(vec![THIRD.to_owned()], vec![30]).unwrap();
+            assert_eq!(ledger.remove(SECOND).unwrap(), 1);
+        }
+        assert_eq!(database.columns().unwrap(), csv.columns().unwrap());
+        assert_eq!(database.root().unwrap(), csv.root().unwrap());
+
+        // Exporting writes the same file, and importing it back replaces every account
+        assert_eq!(database.export_csv(&path(&dir, "export.csv")).unwrap(), 3);
+        assert_eq!(
+            fs::read_to_string(dir.join("export.csv")).unwrap(),
+            fs::read_to_string(dir.join("exchange.csv")).unwrap()
+        );
+        fs::write(
+            dir.join("import.csv"),
+            format!("addresses,value\n{SECOND},1\n"),
+        )
+        .unwrap();
+        assert_eq!(database.import_csv(&path(&dir, "import.csv")).unwrap(), 1);
+        assert_eq!(
+            database.columns().unwrap(),
+            (vec![SECOND.to_owned()], vec![1])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn changes_are_recorded_and_all_or_nothing() {
+        let ledger = SqliteLedgerStore::in_memory().unwrap();
+        ledger
+            .append(vec![FIRST.to_owned(), SECOND.to_owned()], vec![10, 20])
+            .unwrap();
+        // A closed account keeps its history
+        ledger.append(vec![THIRD.to_owned()], vec![1]).unwrap();
+        ledger.remove(THIRD).unwrap();
+        ledger.update(FIRST, 15).unwrap();
+
+        let before = ledger.columns().unwrap();
+        assert!(matches!(
+            ledger.append(vec![THIRD.to_owned(), FIRST.to_owned()], vec![1, 2]),
+            Err(LedgerStoreError::Exists(address)) if address == FIRST
+        ));
+        assert!(matches!(
+            ledger.update(&format!("{FIRST}#2"), 1),
+            Err(LedgerStoreError::NotFound(_))
+        ));
+        assert_eq!(ledger.columns().unwrap(), before);
+
+        let history: Vec<(ChangeKind, Option<i64>, Option<i64>)> = ledger
+            .history(FIRST)
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.change, change.old_value, change.new_value))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (ChangeKind::Open, None, Some(10)),
+                (ChangeKind::Update, Some(10), Some(15)),
+            ]
+        );
+        let closed = ledger.history(THIRD).unwrap();
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[1].change, ChangeKind::Close);
+        assert_eq!(closed[1].old_value, Some(1));
+    }
+    #[test]
+    pub fn newer_schemas_are_refused() {
+        let dir = temp_dir("account_database_schema");
+        let file = path(&dir, "exchange.db");
+        drop(SqliteLedgerStore::open(&file).unwrap());
+        rusqlite::Connection::open(&file)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(matches!(
+            SqliteLedgerStore::open(&file),
+            Err(LedgerStoreError::UnsupportedSchema { found, .. }) if found == SCHEMA_VERSION + 1
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn exchange_loads_databases() {
+        let dir = temp_dir("account_database_load");
+        let reports = exchange(&dir, &["convert", "exchange.csv", "exchange.db"], "");
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[0].data["rows"], 3);
+        assert_eq!(reports[0].data["to"], "sqlite");
+
+        let reports = exchange(
+            &dir,
+            &["load", "exchange.db", "--keep-going"],
+            &format!("setBalance {SECOND} 25\nremoveAccount {FIRST}#1\nsnapshot before\n"),
+        );
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[1].data["rows"], 1);
+        assert_eq!(reports[2].status, Status::Error);
+
+        let database = SqliteLedgerStore::open(&path(&dir, "exchange.db")).unwrap();
+        assert_eq!(
+            database.columns().unwrap(),
+            (vec![SECOND.to_owned(), FIRST.to_owned()], vec![25, 5])
+        );
+        assert_eq!(
+            database.root().unwrap().as_deref(),
+            reports[1].data["root"].as_str()
+        );
+        assert_eq!(database.history(FIRST).unwrap().len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
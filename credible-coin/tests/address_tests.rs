@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bitcoin::{Address, Network};
+    use credible_coin::errors::AddressValidationError;
+    use credible_coin::utils::address_utils::normalize_address;
+    use credible_coin::utils::bitcoin_utils::{
+        generate_address_of_type, generate_n_address_value_pairs_with_mix, AddressMix, ScriptType,
+    };
+    use credible_coin::utils::csv_utils::{make_address_vector, validate_csv_addresses};
+
+    #[test]
+    pub fn script_type_prefixes() {
+        let expected = [
+            (ScriptType::P2pkh, Network::Bitcoin, vec!["1"]),
+            (ScriptType::P2shP2wpkh, Network::Bitcoin, vec!["3"]),
+            (ScriptType::P2wpkh, Network::Bitcoin, vec!["bc1q"]),
+            (ScriptType::P2tr, Network::Bitcoin, vec!["bc1p"]),
+            (ScriptType::P2pkh, Network::Testnet, vec!["m", "n"]),
+            (ScriptType::P2shP2wpkh, Network::Signet, vec!["2"]),
+            (ScriptType::P2wpkh, Network::Signet, vec!["tb1q"]),
+            (ScriptType::P2tr, Network::Testnet, vec!["tb1p"]),
+            (ScriptType::P2wpkh, Network::Regtest, vec!["bcrt1q"]),
+            (ScriptType::P2tr, Network::Regtest, vec!["bcrt1p"]),
+        ];
+        for (script_type, network, prefixes) in expected {
+            let address = generate_address_of_type(script_type, network);
+            assert!(
+                prefixes.iter().any(|p| address.starts_with(p)),
+                "{} address {} on {} has an unexpected prefix",
+                script_type,
+                address,
+                network
+            );
+            let parsed: Address<_> = address.parse().unwrap();
+            assert!(parsed.is_valid_for_network(network));
+        }
+    }
+    #[test]
+    pub fn single_type_mix_only_generates_that_type() {
+        let mix = AddressMix::only(ScriptType::P2wpkh, Network::Regtest);
+        let (addresses, values) = generate_n_address_value_pairs_with_mix(50, &mix);
+        assert_eq!(addresses.len(), values.len());
+        assert!(addresses.iter().all(|a| a.starts_with("bcrt1q")));
+    }
+    #[test]
+    pub fn parse_mix() {
+        let mix: AddressMix = "p2pkh=1, p2sh-p2wpkh=2,p2tr=3".parse().unwrap();
+        assert_eq!(mix.network(), Network::Bitcoin);
+        assert_eq!(mix.weight(ScriptType::P2pkh), 1);
+        assert_eq!(mix.weight(ScriptType::P2shP2wpkh), 2);
+        assert_eq!(mix.weight(ScriptType::P2wpkh), 0);
+        assert_eq!(mix.weight(ScriptType::P2tr), 3);
+
+        assert!("p2pkh=0".parse::<AddressMix>().is_err());
+        assert!("p2wsh=1".parse::<AddressMix>().is_err());
+        assert!("p2pkh".parse::<AddressMix>().is_err());
+        assert!("p2pkh=-1".parse::<AddressMix>().is_err());
+    }
+    #[test]
+    pub fn bech32_case_variants_normalize_to_the_same_address() {
+        let lower = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+        let upper = lower.to_uppercase();
+        assert_eq!(normalize_address(lower, Network::Bitcoin).unwrap(), lower);
+        assert_eq!(normalize_address(&upper, Network::Bitcoin).unwrap(), lower);
+        // Mixed case bech32 is invalid
+        assert!(normalize_address("bc1qUSHQA4nwpz2j0yftnpw08c5lj2u92mnah79q2k", Network::Bitcoin)
+            .is_err());
+    }
+    #[test]
+    pub fn invalid_addresses_are_reported() {
+        match normalize_address("17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC", Network::Testnet) {
+            Err(AddressValidationError::WrongNetwork {
+                address,
+                found,
+                expected,
+            }) => {
+                assert_eq!(address, "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC");
+                assert_eq!(found, Network::Bitcoin);
+                assert_eq!(expected, Network::Testnet);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        // Last character changed so the checksum no longer matches
+        let err = normalize_address("17wNSD33wQFDwMnzUHRPCsXseWctUZVQED", Network::Bitcoin)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("17wNSD33wQFDwMnzUHRPCsXseWctUZVQED"));
+        assert!(err.contains("checksum"));
+    }
+    #[test]
+    pub fn csv_addresses_are_normalized_and_validated() {
+        let filename = "address_validation_test.csv";
+        fs::write(
+            filename,
+            "addresses,value\n\
+             17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,1\n\
+             BC1QUSHQA4NWPZ2J0YFTNPW08C5LJ2U92MNAH79Q2K,2\n",
+        )
+        .unwrap();
+        validate_csv_addresses(filename).unwrap();
+        assert_eq!(
+            make_address_vector(filename),
+            vec![
+                "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned(),
+                "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k".to_owned()
+            ]
+        );
+
+        fs::write(
+            filename,
+            "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,1\nnot-an-address,2\n",
+        )
+        .unwrap();
+        let err = validate_csv_addresses(filename).unwrap_err();
+        let err = err.downcast_ref::<AddressValidationError>().unwrap();
+        assert!(matches!(err, AddressValidationError::InFile { line: 3, .. }));
+        assert!(err.to_string().contains("not-an-address"));
+        fs::remove_file(filename).unwrap();
+    }
+}
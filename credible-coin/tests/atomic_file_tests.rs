@@ -0,0 +1,123 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Write};
+    use std::thread;
+
+    use bitcoin::Network;
+    use credible_coin::utils::atomic_file::{FileLock, write_atomically};
+    use credible_coin::utils::bitcoin_utils::{ScriptType, generate_address_of_type};
+    use credible_coin::utils::csv_utils::{append_record, append_records, make_address_vector};
+
+    use crate::common;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        common::temp_dir(name, &[])
+    }
+    #[test]
+    pub fn failed_writes_leave_the_file_untouched() {
+        let dir = temp_dir("atomic_failed_write");
+        let path = dir.join("ledger.csv");
+        fs::write(&path, "addresses,value\n").unwrap();
+
+        let result = write_atomically(&path, |file| {
+            file.write_all(b"half written")?;
+            Err(io::Error::new(io::ErrorKind::Other, "crashed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "addresses,value\n");
+        // Only the original file is left, the temporary file was cleaned up
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn locks_are_exclusive() {
+        let dir = temp_dir("atomic_lock");
+        let path = dir.join("ledger.csv");
+        let lock = FileLock::exclusive(&path).unwrap();
+        assert!(FileLock::try_exclusive(&path).unwrap().is_none());
+        drop(lock);
+        assert!(FileLock::try_exclusive(&path).unwrap().is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn concurrent_appends_are_not_lost() {
+        let dir = temp_dir("atomic_concurrent_appends");
+        let path = dir.join("exchange.csv");
+        fs::write(&path, "addresses,value\n").unwrap();
+        let path = path.to_str().unwrap().to_owned();
+
+        let addresses: Vec<String> = (0..16)
+            .map(|_| generate_address_of_type(ScriptType::P2wpkh, Network::Bitcoin))
+            .collect();
+        let handles: Vec<_> = addresses
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(value, address)| {
+                let path = path.clone();
+                thread::spawn(move || append_record(&path, address, value as u64).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut written = make_address_vector(&path);
+        let mut expected = addresses;
+        written.sort();
+        expected.sort();
+        assert_eq!(written, expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[cfg(unix)]
+    #[test]
+    pub fn rewrites_keep_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("atomic_permissions");
+        let path = dir.join("ledger.csv");
+        fs::write(&path, "addresses,value\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomically(&path, |file| file.write_all(b"addresses,value\n")).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[cfg(unix)]
+    #[test]
+    pub fn appends_write_in_place() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = temp_dir("atomic_append_in_place");
+        let path = dir.join("exchange.csv");
+        // The last row has no newline, the appended one mustn't be joined to it
+        fs::write(
+            &path,
+            "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,10",
+        )
+        .unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+        let file = path.to_str().unwrap();
+
+        append_record(file, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_owned(), 20).unwrap();
+        assert!(
+            append_records(
+                file,
+                vec!["17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC".to_owned()],
+                vec![1]
+            )
+            .is_err()
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "addresses,value\n17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC,10\n1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2,20\n"
+        );
+        // The file was written to, not replaced
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Output;
+
+    use credible_coin::cli::output::{CommandReport, Status};
+    use credible_coin::cli::transcript::read_transcript;
+
+    use crate::common;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(
+            name,
+            &[(
+                "publisher.csv",
+                &format!("addresses,value\n{FIRST},10\n{SECOND},20\n"),
+            )],
+        )
+    }
+    fn publisher(dir: &Path, args: &[&str], stdin: &str) -> Output {
+        publisher_with(dir, &[], args, stdin)
+    }
+    fn publisher_with(dir: &Path, global: &[&str], args: &[&str], stdin: &str) -> Output {
+        let args = [global, &["load", "publisher.csv"], args].concat();
+        common::run(env!("CARGO_BIN_EXE_publisher"), dir, &args, &[], stdin)
+    }
+    #[test]
+    pub fn scripts_stop_at_the_first_failure() {
+        let dir = temp_dir("batch_first_failure");
+        fs::write(
+            dir.join("script.txt"),
+            format!("# comment\n\ngetCoinInfo {FIRST}\nbogus\ngetCoinInfo {SECOND}\n"),
+        )
+        .unwrap();
+        let output = publisher(&dir, &["--script", "script.txt"], "");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stdout.contains("Value:10"));
+        assert!(!stdout.contains("Value:20"));
+        assert!(stderr.contains("line 4: `bogus` failed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn keep_going_runs_every_command() {
+        let dir = temp_dir("batch_keep_going");
+        let script = format!("bogus\ngetCoinInfo {SECOND}\nupdateCoin\n");
+        let output = publisher(&dir, &["--script", "-", "--keep-going"], &script);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stdout.contains("Value:20"));
+        assert!(stderr.contains("2 command(s) failed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn piped_stdin_runs_without_a_tty() {
+        let dir = temp_dir("batch_stdin");
+        let output = publisher(&dir, &[], &format!("getCoinInfo {FIRST}\nexit\nbogus\n"));
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Value:10"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn json_output_emits_one_report_per_command() {
+        let dir = temp_dir("batch_json");
+        let script = format!("getCoinInfo {SECOND}\nbogus\nupdateCoin {FIRST} 15\nexit\n");
+        let output = publisher_with(&dir, &["--output", "json"], &["--keep-going"], &script);
+        assert!(!output.status.success());
+        let reports: Vec<CommandReport> = common::reports(&output);
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].command, "getCoinInfo");
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[0].data["address"], SECOND);
+        assert_eq!(reports[0].data["value"], 20);
+        assert_eq!(reports[1].status, Status::Error);
+        assert!(reports[1].data.is_null());
+        assert!(
+            reports[1]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("Unknown command")
+        );
+        assert_eq!(reports[2].data["value"], 15);
+        assert_ne!(reports[2].data["root"], reports[0].data["root"]);
+        assert_eq!(reports[3].command, "exit");
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn commands_are_checked_against_the_registry() {
+        let dir = temp_dir("batch_registry");
+        let output = publisher_with(
+            &dir,
+            &["--output", "json"],
+            &["--keep-going"],
+            "help\ngetCoinInfo\nshowFile extra\n",
+        );
+        let reports: Vec<CommandReport> = common::reports(&output);
+        let commands: Vec<&str> = reports[0]
+            .data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["command"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                "?",
+                "clear",
+                "exit",
+                "find",
+                "getCoinInfo",
+                "help",
+                "proveMembership",
+                "rollback",
+                "root",
+                "showFile",
+                "snapshot",
+                "snapshots",
+                "stats",
+                "top",
+                "updateCoin"
+            ]
+        );
+        let error = |index: usize| reports[index].error.clone().unwrap();
+        assert!(error(1).contains("<ADDRESS>"));
+        assert!(error(1).contains("Usage: getCoinInfo <ADDRESS>[#N]"));
+        assert!(error(2).starts_with("unexpected argument 'extra' found"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn arguments_are_tokenized_and_typed() {
+        let dir = temp_dir("batch_arguments");
+        let script = format!(
+            "getCoinInfo   '{SECOND}'\nupdateCoin {FIRST} ten\nproveMembership \"{FIRST}\" -3\ngetCoinInfo \"{FIRST}\n"
+        );
+        let output = publisher_with(&dir, &["--output", "json"], &["--keep-going"], &script);
+        let reports: Vec<CommandReport> = common::reports(&output);
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[0].data["value"], 20);
+        assert_eq!(
+            reports[1].error.as_deref(),
+            Some("invalid value 'ten' for '<NEW VALUE>': invalid digit found in string")
+        );
+        // Negative values are values, not flags, but -3 is not the value of the first coin
+        assert!(
+            reports[2]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("no matching index found")
+        );
+        assert!(
+            reports[3]
+                .error
+                .as_ref()
+                .unwrap()
+                .starts_with("Unterminated quote")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn transcripts_record_and_replay_sessions() {
+        let dir = temp_dir("batch_transcript");
+        let original = fs::read_to_string(dir.join("publisher.csv")).unwrap();
+        let script = format!("updateCoin {FIRST} 15\nbogus\ngetCoinInfo {FIRST}\n");
+        let output = publisher(
+            &dir,
+            &["--keep-going", "--transcript", "session.jsonl"],
+            &script,
+        );
+        assert!(!output.status.success());
+        let entries = read_transcript(&dir.join("session.jsonl")).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|entry| entry.role == "publisher"));
+        assert_eq!(entries[0].report.command, "updateCoin");
+        assert_eq!(entries[0].args, [FIRST, "15"]);
+        assert_ne!(entries[0].root_before, entries[0].root_after);
+        assert_eq!(entries[1].report.status, Status::Error);
+        assert_eq!(entries[1].root_before, entries[1].root_after);
+        assert_eq!(entries[2].report.data["value"], 15);
+
+        // Replaying against the file the session started from reproduces every root
+        fs::write(dir.join("publisher.csv"), &original).unwrap();
+        let output = publisher(&dir, &["--replay", "session.jsonl"], "");
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Value:15"));
+        // The file has moved on since, so a second replay can't start from the recorded root
+        let output = publisher(&dir, &["--replay", "session.jsonl"], "");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("entry 1: `updateCoin` failed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn publishers_can_inspect_the_loaded_file() {
+        let dir = temp_dir("batch_inspect");
+        let script =
+            format!("root\nstats\nfind bc1\nfind nothing\ntop 1\ntop 5\ngetCoinInfo {FIRST}\n");
+        let output = publisher_with(&dir, &["--output", "json"], &[], &script);
+        assert!(output.status.success());
+        let reports: Vec<CommandReport> = common::reports(&output);
+        let root = &reports[6].data["root"];
+        assert_eq!(reports[0].data["root"], *root);
+        assert_eq!(reports[0].data["leaf_count"], 2);
+        let stats = &reports[1].data;
+        assert_eq!(stats["count"], 2);
+        assert_eq!(stats["sum"], 30);
+        assert_eq!(stats["min"], 10);
+        assert_eq!(stats["max"], 20);
+        assert_eq!(stats["duplicate_addresses"], 0);
+        assert_eq!(stats["root"], *root);
+        assert_eq!(
+            reports[2].data,
+            serde_json::json!([{ "index": 1, "address": SECOND, "value": 20 }])
+        );
+        assert_eq!(reports[3].data, serde_json::json!([]));
+        assert_eq!(reports[4].data[0]["address"], SECOND);
+        assert_eq!(reports[4].data.as_array().unwrap().len(), 1);
+        assert_eq!(reports[5].data[1]["address"], FIRST);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn rollback_restores_the_file_and_the_tree() {
+        let dir = temp_dir("batch_snapshots");
+        let original = fs::read_to_string(dir.join("publisher.csv")).unwrap();
+        let script = format!(
+            "snapshot before\nupdateCoin {FIRST} 15\nsnapshot before\nsnapshot after\nrollback before\ngetCoinInfo {FIRST}\nsnapshots\nrollback missing\nsnapshot ../escape\nsnapshot snapshots.json\nsnapshots\n"
+        );
+        let output = publisher_with(&dir, &["--output", "json"], &["--keep-going"], &script);
+        let reports: Vec<CommandReport> = common::reports(&output);
+        let before = &reports[0].data;
+        assert_eq!(before["label"], "before");
+        assert_ne!(reports[1].data["root"], before["root"]);
+        assert!(
+            reports[2]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("already exists")
+        );
+        assert_eq!(reports[3].data["root"], reports[1].data["root"]);
+        assert_eq!(reports[4].data["root"], before["root"]);
+        assert_eq!(reports[5].data["value"], 10);
+        assert_eq!(reports[5].data["root"], before["root"]);
+        assert_eq!(reports[6].data.as_array().unwrap().len(), 2);
+        assert_eq!(reports[6].data[1]["label"], "after");
+        assert!(reports[7].error.as_ref().unwrap().contains("no snapshot"));
+        assert!(
+            reports[8]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("invalid snapshot label")
+        );
+        // The index can't be overwritten by a copy of the ledger
+        assert!(reports[9].error.as_ref().unwrap().contains("reserved"));
+        assert_eq!(reports[10].data.as_array().unwrap().len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join("publisher.csv")).unwrap(),
+            original
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
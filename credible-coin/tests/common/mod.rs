@@ -0,0 +1,79 @@
+//! Fixtures shared by the integration tests. Every test file only uses some of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use credible_coin::cli::output::CommandReport;
+
+/// A fresh, empty directory `name` in the system's temporary directory, holding `files` as
+/// `(name, contents)` pairs
+pub fn temp_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+/// The path of `file` in `dir`, as a string
+pub fn path(dir: &Path, file: &str) -> String {
+    dir.join(file).to_str().unwrap().to_owned()
+}
+/// Run `binary` in `dir` with `args`, writing `stdin` to it and waiting for it to exit. Every
+/// `(variable, value)` of `env` is set, or removed if its value is `None`.
+pub fn run(
+    binary: &str,
+    dir: &Path,
+    args: &[&str],
+    env: &[(&str, Option<&str>)],
+    stdin: &str,
+) -> Output {
+    let mut command = Command::new(binary);
+    command
+        .current_dir(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for (variable, value) in env {
+        match value {
+            Some(value) => command.env(variable, value),
+            None => command.env_remove(variable),
+        };
+    }
+    let mut child = command.spawn().unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+/// The command reports a cli printed with `--output json`
+pub fn reports(output: &Output) -> Vec<CommandReport> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+/// Run the exchange cli in `dir` with `--output json` and `args`, see [`run`]
+pub fn exchange(
+    dir: &Path,
+    args: &[&str],
+    env: &[(&str, Option<&str>)],
+    script: &str,
+) -> Vec<CommandReport> {
+    let args = [&["--output", "json"], args].concat();
+    reports(&run(
+        env!("CARGO_BIN_EXE_exchange"),
+        dir,
+        &args,
+        env,
+        script,
+    ))
+}
@@ -1,11 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use bitcoin::PublicKey;
     use credible_coin::cli::exchange::asset_database::create_exchange_database;
     use credible_coin::cli::exchange::db_connector::{
-        insert_key_or_update, retrieve_public_key_bytes,
+        ExchangeNamespace, connection_info, exchange_pool, insert_secret_key, list_key_names,
+        peek_index, pool, proof_key, remove_secret_key, reserve_index, retrieve_secret_key,
     };
     use credible_coin::cli::publisher::database::{create_db, load_db};
+    use credible_coin::credible_config::{RedisConfig, RedisInstanceConfig};
+    use credible_coin::errors::DBConnectorError;
     use credible_coin::utils::merkle_utils::load_merkle_leaves_from_csv;
     use rs_merkle::{algorithms::Sha256, MerkleProof};
     use std::fs;
@@ -87,17 +89,106 @@ mod tests {
         assert_eq!(a, b);
     }
     #[test]
+    fn redis_settings_override_the_url() {
+        let defaults = RedisConfig::default();
+        assert_eq!(defaults.exchange_id, "default");
+        assert_eq!(defaults.exchange.url, "redis://127.0.0.1:6380/");
+        assert_eq!(defaults.proofs.url, "redis://127.0.0.1:6381/");
+
+        let config: RedisConfig = serde_json::from_value(serde_json::json!({
+            "exchange_id": "eu-prod",
+            "exchange": {"url": "redis://:old@10.0.0.5:6390/3", "password": "s3cret", "db": 2},
+        }))
+        .unwrap();
+        assert_eq!(config.exchange_id, "eu-prod");
+        assert_eq!(config.exchange.pool_size, 8);
+        // The proofs instance keeps its default
+        assert_eq!(config.proofs, defaults.proofs);
+        let info = connection_info(&config.exchange).unwrap();
+        assert_eq!(info.redis.password.as_deref(), Some("s3cret"));
+        assert_eq!(info.redis.db, 2);
+        // Pools are shared, and connect lazily
+        let first = pool(&config.exchange).unwrap();
+        let second = pool(&config.exchange).unwrap();
+        assert_eq!(first.max_size(), 8);
+        assert_eq!(first.state().connections, 0);
+        assert_eq!(second.state().connections, 0);
+        let empty = RedisInstanceConfig {
+            pool_size: 0,
+            ..config.exchange
+        };
+        assert!(matches!(
+            pool(&empty),
+            Err(DBConnectorError::InvalidPoolSize(_))
+        ));
+    }
+    #[test]
+    fn keys_are_namespaced() {
+        let eu = ExchangeNamespace::new("eu-prod").unwrap();
+        let us = ExchangeNamespace::new("us-prod").unwrap();
+        assert_eq!(eu.secret_key("default"), "exchange:eu-prod:keys:default");
+        assert_ne!(eu.secret_key("default"), us.secret_key("default"));
+        assert_eq!(
+            eu.index("default:m/84'/0'/0'/0"),
+            "exchange:eu-prod:indices:default:m/84'/0'/0'/0"
+        );
+        assert_eq!(proof_key("ab12", "solvency"), "proofs:ab12:solvency");
+        for id in ["", "eu:prod", "eu*", "eu prod"] {
+            assert!(matches!(
+                ExchangeNamespace::new(id),
+                Err(DBConnectorError::InvalidNamespace(_))
+            ));
+        }
+    }
+    #[test]
     #[ignore = "Only run when connected to the redis server"]
-    fn bytes_to_public_key() {
-        let s = secp256k1::Secp256k1::new();
-        let key = bitcoin::PublicKey::new(s.generate_keypair(&mut rand::thread_rng()).1);
-        let _ = insert_key_or_update(key.to_bytes());
-        let retrieved_bytes = retrieve_public_key_bytes();
-        let retrieved_key: PublicKey = PublicKey::from_slice(&retrieved_bytes.unwrap()).unwrap();
-        assert_eq!(key, retrieved_key);
-        let client = redis::Client::open("redis://127.0.0.1:6380/").unwrap();
+    fn secret_key_round_trip() {
+        let key = secp256k1::SecretKey::new(&mut rand::thread_rng());
+        let mut conn = exchange_pool().unwrap().get().unwrap();
+        let ns = ExchangeNamespace::new("db_tests").unwrap();
+        let other_ns = ExchangeNamespace::new("db_tests_other").unwrap();
+        assert!(insert_secret_key(&mut conn, &ns, "db_test_key", &key.secret_bytes()).unwrap());
+        // Keys are never overwritten
+        let other = secp256k1::SecretKey::new(&mut rand::thread_rng());
+        assert!(!insert_secret_key(&mut conn, &ns, "db_test_key", &other.secret_bytes()).unwrap());
+        let retrieved_bytes = retrieve_secret_key(&mut conn, &ns, "db_test_key").unwrap();
+        assert_eq!(retrieved_bytes.unwrap(), key.secret_bytes());
+        // Other exchanges sharing the instance don't see the key
+        assert_eq!(
+            retrieve_secret_key(&mut conn, &other_ns, "db_test_key").unwrap(),
+            None
+        );
+        assert!(
+            !list_key_names(&mut conn, &other_ns)
+                .unwrap()
+                .contains(&"db_test_key".to_owned())
+        );
+        assert!(
+            list_key_names(&mut conn, &ns)
+                .unwrap()
+                .contains(&"db_test_key".to_owned())
+        );
         // Remove the key
-        let mut conn = client.get_connection().unwrap();
-        redis::cmd("del").arg("private_key").execute(&mut conn);
+        assert!(remove_secret_key(&mut conn, &ns, "db_test_key").unwrap());
+        assert_eq!(
+            retrieve_secret_key(&mut conn, &ns, "db_test_key").unwrap(),
+            None
+        );
+    }
+    #[test]
+    #[ignore = "Only run when connected to the redis server"]
+    fn derivation_counters() {
+        let mut conn = exchange_pool().unwrap().get().unwrap();
+        let ns = ExchangeNamespace::new("db_tests").unwrap();
+        redis::cmd("del")
+            .arg(ns.index("db_test_counter"))
+            .execute(&mut *conn);
+        assert_eq!(peek_index(&mut conn, &ns, "db_test_counter").unwrap(), 0);
+        assert_eq!(reserve_index(&mut conn, &ns, "db_test_counter").unwrap(), 0);
+        assert_eq!(reserve_index(&mut conn, &ns, "db_test_counter").unwrap(), 1);
+        assert_eq!(peek_index(&mut conn, &ns, "db_test_counter").unwrap(), 2);
+        redis::cmd("del")
+            .arg(ns.index("db_test_counter"))
+            .execute(&mut *conn);
     }
 }
@@ -0,0 +1,230 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use bitcoin::Network;
+    use credible_coin::cli::exchange::key_manager::KeyManager;
+    use credible_coin::cli::exchange::keystore::{EncryptedFileKeystore, PASSPHRASE_VAR};
+    use credible_coin::cli::output::{CommandReport, Status};
+    use credible_coin::utils::bitcoin_utils::ScriptType;
+    use credible_coin::utils::change_set::{
+        ChangeSet, SignedChangeSet, apply_change_set, diff_ledgers, verify_change_set,
+    };
+    use credible_coin::utils::csv_utils::convert_csv_to_ledger;
+
+    use crate::common::{self, path};
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+    const THIRD: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(
+            name,
+            &[
+                (
+                    "old.csv",
+                    &format!("addresses,value\n{FIRST},10\n{SECOND},20\n"),
+                ),
+                (
+                    "new.csv",
+                    &format!("addresses,value\n{THIRD},5\n{FIRST},10\n{FIRST},1\n"),
+                ),
+            ],
+        )
+    }
+    #[test]
+    pub fn change_sets_list_every_difference() {
+        let dir = temp_dir("diff_changes");
+        let changes = diff_ledgers(&path(&dir, "old.csv"), &path(&dir, "new.csv")).unwrap();
+        let added: Vec<(&str, i64)> = changes
+            .added
+            .iter()
+            .map(|entry| (entry.address.as_str(), entry.value))
+            .collect();
+        // The repeated address is told apart by its disambiguated keys, so its first row reads
+        // as a new entry rather than the same one
+        assert_eq!(
+            added,
+            [
+                (THIRD, 5),
+                (format!("{FIRST}#1").as_str(), 10),
+                (format!("{FIRST}#2").as_str(), 1)
+            ]
+        );
+        assert_eq!(changes.removed.len(), 2);
+        assert!(changes.modified.is_empty());
+        assert_eq!(changes.net_change, -14);
+        assert_ne!(changes.old_root, changes.new_root);
+
+        // Diffing a file against itself, even in another format, is empty
+        convert_csv_to_ledger(&path(&dir, "old.csv"), &path(&dir, "old.ledger")).unwrap();
+        let same = diff_ledgers(&path(&dir, "old.csv"), &path(&dir, "old.ledger")).unwrap();
+        assert!(same.is_empty());
+        assert_eq!(same.old_root, same.new_root);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn change_sets_can_be_reapplied() {
+        let dir = temp_dir("diff_apply");
+        fs::write(
+            dir.join("new.csv"),
+            format!("addresses,value\n{THIRD},5\n{FIRST},12\n"),
+        )
+        .unwrap();
+        let changes = diff_ledgers(&path(&dir, "old.csv"), &path(&dir, "new.csv")).unwrap();
+        assert_eq!(changes.modified[0].delta, 2);
+        // A change set survives a round trip through JSON
+        let changes: ChangeSet =
+            serde_json::from_str(&serde_json::to_string(&changes).unwrap()).unwrap();
+
+        fs::copy(dir.join("old.csv"), dir.join("copy.csv")).unwrap();
+        apply_change_set(&path(&dir, "copy.csv"), &changes).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("copy.csv")).unwrap(),
+            fs::read_to_string(dir.join("new.csv")).unwrap()
+        );
+        // The change set only applies to the file it was made from
+        let err = apply_change_set(&path(&dir, "copy.csv"), &changes).unwrap_err();
+        assert!(err.to_string().contains("root differs"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn change_sets_are_signed_by_the_exchange() {
+        let dir = temp_dir("diff_signed");
+        let keys = KeyManager::new(Box::new(
+            EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap(),
+        ));
+        keys.create_key("default").unwrap();
+        keys.create_key("other").unwrap();
+        let exchange = keys
+            .address("default", ScriptType::P2pkh, Network::Bitcoin)
+            .unwrap();
+        let changes = diff_ledgers(&path(&dir, "old.csv"), &path(&dir, "new.csv")).unwrap();
+        let signed = changes.clone().sign(&keys, "default").unwrap();
+        assert_eq!(signed.address, exchange);
+        // The signature survives a round trip through JSON
+        let signed: SignedChangeSet =
+            serde_json::from_str(&serde_json::to_string(&signed).unwrap()).unwrap();
+        assert!(verify_change_set(&signed, &exchange).unwrap());
+
+        // Altering the changes breaks the signature
+        let mut altered = signed.clone();
+        altered.changes.net_change += 1;
+        assert!(!verify_change_set(&altered, &exchange).unwrap());
+        // So does signing with a key other than the exchange's
+        let forged = changes.sign(&keys, "other").unwrap();
+        assert!(!verify_change_set(&forged, &exchange).unwrap());
+        let relabeled = SignedChangeSet {
+            address: exchange.clone(),
+            ..forged
+        };
+        assert!(!verify_change_set(&relabeled, &exchange).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn signed_change_sets_are_applied_from_the_cli() {
+        let dir = temp_dir("diff_apply_signed");
+        fs::write(
+            dir.join("credible_config.yaml"),
+            "keystore:\n  backend: file\n  path: keys.json\n",
+        )
+        .unwrap();
+        let keys = KeyManager::new(Box::new(
+            EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap(),
+        ));
+        keys.create_key("default").unwrap();
+        keys.create_key("other").unwrap();
+        let exchange = keys
+            .address("default", ScriptType::P2pkh, Network::Bitcoin)
+            .unwrap();
+        let other = keys
+            .address("other", ScriptType::P2pkh, Network::Bitcoin)
+            .unwrap();
+        let env = [(PASSPHRASE_VAR, Some("hunter2"))];
+
+        let reports = common::exchange(
+            &dir,
+            &["diff", "old.csv", "new.csv", "--sign", "default"],
+            &env,
+            "",
+        );
+        assert_eq!(reports[0].data["address"], exchange.as_str());
+        let reports = common::exchange(
+            &dir,
+            &[
+                "diff",
+                "old.csv",
+                "new.csv",
+                "--sign",
+                "default",
+                "--save",
+                "changes.json",
+            ],
+            &env,
+            "",
+        );
+        assert_eq!(reports[0].status, Status::Ok);
+        let saved: SignedChangeSet =
+            serde_json::from_slice(&fs::read(dir.join("changes.json")).unwrap()).unwrap();
+        assert!(verify_change_set(&saved, &exchange).unwrap());
+
+        // A change set is only applied if it is signed by the expected address
+        fs::copy(dir.join("old.csv"), dir.join("copy.csv")).unwrap();
+        let apply = |address: &str| {
+            common::exchange(
+                &dir,
+                &["apply", "copy.csv", "changes.json", "--address", address],
+                &[],
+                "",
+            )
+        };
+        let reports = apply(&other);
+        assert!(
+            reports[0]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("is not signed by")
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("copy.csv")).unwrap(),
+            fs::read_to_string(dir.join("old.csv")).unwrap()
+        );
+        let reports = apply(&exchange);
+        assert_eq!(reports[0].status, Status::Ok);
+        assert_eq!(reports[0].data["changes"], 5);
+        assert_eq!(
+            fs::read_to_string(dir.join("copy.csv")).unwrap(),
+            fs::read_to_string(dir.join("new.csv")).unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn diff_reports_the_change_set() {
+        let dir = temp_dir("diff_cli");
+        let output = Command::new(env!("CARGO_BIN_EXE_publisher"))
+            .current_dir(&dir)
+            .args(["--output", "json", "diff", "old.csv", "new.csv"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let report: CommandReport = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report.command, "diff");
+        assert_eq!(report.data["added"][0]["address"], THIRD);
+        assert_eq!(report.data["net_change"], -14);
+
+        let output = Command::new(env!("CARGO_BIN_EXE_exchange"))
+            .current_dir(&dir)
+            .args(["diff", "old.csv", "new.csv"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("3 added, 2 removed, 0 modified, net change -14"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
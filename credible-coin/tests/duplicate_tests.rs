@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use credible_coin::cli::publisher::entry_map::EntryMap;
+    use credible_coin::errors::{AddressPositionError, DuplicateAddressError};
+    use credible_coin::utils::csv_utils::{
+        addresses_and_values_as_vectors, get_address_position, update_csv_entry,
+    };
+    use credible_coin::utils::duplicates::{
+        DuplicatePolicy, apply_duplicate_policy, find_duplicates, normalize_key,
+    };
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+
+    fn columns() -> (Vec<String>, Vec<i64>) {
+        (
+            vec![FIRST.to_owned(), SECOND.to_owned(), FIRST.to_owned()],
+            vec![10, 20, 30],
+        )
+    }
+    #[test]
+    pub fn duplicates_are_reported() {
+        let (addresses, _) = columns();
+        let report = find_duplicates(&addresses);
+        assert_eq!(report.address_count(), 1);
+        assert_eq!(report.extra_row_count(), 1);
+        assert_eq!(report.rows.get(FIRST), Some(&vec![0, 2]));
+        // Rows are reported as line numbers, counting the header
+        assert!(report.to_string().contains("lines 2, 4"));
+        assert!(find_duplicates(&[FIRST.to_owned(), SECOND.to_owned()]).is_empty());
+    }
+    #[test]
+    pub fn each_policy_resolves_duplicates() {
+        let (addresses, values) = columns();
+        let err =
+            apply_duplicate_policy(addresses.clone(), values.clone(), DuplicatePolicy::Reject)
+                .unwrap_err();
+        assert!(matches!(err, DuplicateAddressError::Rejected(_)));
+        assert!(err.to_string().contains(FIRST));
+
+        let (a, v, _) =
+            apply_duplicate_policy(addresses.clone(), values.clone(), DuplicatePolicy::Sum)
+                .unwrap();
+        assert_eq!(
+            (a, v),
+            (vec![FIRST.to_owned(), SECOND.to_owned()], vec![40, 20])
+        );
+
+        let (a, v, _) =
+            apply_duplicate_policy(addresses.clone(), values.clone(), DuplicatePolicy::LastWins)
+                .unwrap();
+        assert_eq!(
+            (a, v),
+            (vec![FIRST.to_owned(), SECOND.to_owned()], vec![30, 20])
+        );
+
+        let (a, v, report) =
+            apply_duplicate_policy(addresses.clone(), values.clone(), DuplicatePolicy::KeepAll)
+                .unwrap();
+        assert_eq!((a, v), (addresses, values));
+        assert_eq!(report.extra_row_count(), 1);
+
+        let overflow = apply_duplicate_policy(
+            vec![FIRST.to_owned(), FIRST.to_owned()],
+            vec![i64::MAX, 1],
+            DuplicatePolicy::Sum,
+        );
+        assert!(matches!(overflow, Err(DuplicateAddressError::Overflow(_))));
+    }
+    #[test]
+    pub fn keep_all_entries_are_disambiguated() {
+        let (addresses, values) = columns();
+        let map = EntryMap::from_vectors(addresses, values);
+        assert_eq!(map.inner.len(), 3);
+        assert_eq!(map.inner.get_index_of(&format!("{FIRST}#1")), Some(0));
+        assert_eq!(map.inner.get_index_of(SECOND), Some(1));
+        assert_eq!(map.inner.get_index_of(&format!("{FIRST}#2")), Some(2));
+
+        assert_eq!(
+            normalize_key(&format!("{}#2", SECOND.to_uppercase())).unwrap(),
+            format!("{SECOND}#2")
+        );
+    }
+    #[test]
+    pub fn keep_all_lookups_and_updates() {
+        let path = std::env::temp_dir().join("duplicate_policy_test.csv");
+        let filename = path.to_str().unwrap();
+        fs::write(
+            filename,
+            format!("addresses,value\n{FIRST},10\n{SECOND},20\n{FIRST},30\n"),
+        )
+        .unwrap();
+        let position = |key: &str, value| get_address_position(filename, key.to_owned(), value);
+
+        assert!(matches!(
+            position(FIRST, None),
+            Err(AddressPositionError::AmbiguousAddress { occurrences: 2, .. })
+        ));
+        assert_eq!(position(&format!("{FIRST}#2"), None).unwrap(), 2);
+        assert_eq!(position(FIRST, Some(30)).unwrap(), 2);
+        assert_eq!(position(SECOND, None).unwrap(), 1);
+        assert!(matches!(
+            position(&format!("{FIRST}#3"), None),
+            Err(AddressPositionError::NoSuchOccurrence { requested: 3, .. })
+        ));
+        assert!(matches!(
+            position(&format!("{FIRST}#1"), Some(30)),
+            Err(AddressPositionError::NoMatchingIndexForValue(_, 30))
+        ));
+
+        update_csv_entry(filename, &format!("{FIRST}#2"), 31).unwrap();
+        assert_eq!(
+            addresses_and_values_as_vectors(filename).1,
+            vec![10, 20, 31]
+        );
+        // A bare address collapses every row of the address into its first one
+        update_csv_entry(filename, FIRST, 5).unwrap();
+        assert_eq!(
+            addresses_and_values_as_vectors(filename),
+            (vec![FIRST.to_owned(), SECOND.to_owned()], vec![5, 20])
+        );
+        assert_eq!(position(FIRST, None).unwrap(), 0);
+        fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use credible_coin::cli::exchange::keystore::PASSPHRASE_VAR;
+    use credible_coin::cli::output::{CommandReport, Status};
+    use credible_coin::proof_server::{ACCOUNT_ID_SECRET_VAR, account_id};
+
+    use crate::common;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+    const THIRD: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(
+            name,
+            &[(
+                "exchange.csv",
+                &format!("addresses,value\n{FIRST},10\n{SECOND},20\n"),
+            )],
+        )
+    }
+    fn exchange(dir: &Path, script: &str) -> Vec<CommandReport> {
+        common::exchange(
+            dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(PASSPHRASE_VAR, None)],
+            script,
+        )
+    }
+    #[test]
+    pub fn accounts_can_be_changed_from_the_shell() {
+        let dir = temp_dir("exchange_accounts");
+        fs::write(
+            dir.join("import.csv"),
+            format!("addresses,value\n{THIRD},30\n"),
+        )
+        .unwrap();
+        let reports = exchange(
+            &dir,
+            &format!(
+                "setBalance {FIRST} 15\nremoveAccount {SECOND}\nimportAccounts import.csv\nremoveAccount {SECOND}\n"
+            ),
+        );
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].data["value"], 15);
+        assert_eq!(reports[1].data["rows"], 1);
+        assert_eq!(reports[2].data["count"], 1);
+        let roots: Vec<&str> = reports[..3]
+            .iter()
+            .map(|report| report.data["root"].as_str().unwrap())
+            .collect();
+        assert_ne!(roots[0], roots[1]);
+        assert_ne!(roots[1], roots[2]);
+        assert_eq!(reports[3].status, Status::Error);
+        assert_eq!(
+            fs::read_to_string(dir.join("exchange.csv")).unwrap(),
+            format!("addresses,value\n{FIRST},15\n{THIRD},30\n")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn account_ids_are_derived_with_the_secret() {
+        let dir = temp_dir("exchange_account_ids");
+        let script = format!("accountId {SECOND}\naccountId {THIRD}\n");
+        let reports = common::exchange(
+            &dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(ACCOUNT_ID_SECRET_VAR, Some("secret"))],
+            &script,
+        );
+        assert_eq!(reports[0].data["id"], account_id("secret", SECOND));
+        assert_eq!(reports[1].status, Status::Error);
+
+        // Without the secret there are no IDs to show
+        let reports = common::exchange(
+            &dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(ACCOUNT_ID_SECRET_VAR, None)],
+            &script,
+        );
+        assert_eq!(reports[0].status, Status::Error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn imports_are_all_or_nothing() {
+        let dir = temp_dir("exchange_import_conflict");
+        fs::write(
+            dir.join("import.csv"),
+            format!("addresses,value\n{THIRD},30\n{FIRST},40\n"),
+        )
+        .unwrap();
+        let reports = exchange(
+            &dir,
+            &format!(
+                "importAccounts import.csv\nsetBalance {THIRD} 5\nimportAccounts missing.csv\n"
+            ),
+        );
+        assert!(
+            reports[0]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains(&format!("Record for address {FIRST}, already exists"))
+        );
+        assert!(
+            reports[1]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("No record for address")
+        );
+        assert!(reports[2].error.as_ref().unwrap().contains("not found"));
+        assert_eq!(
+            fs::read_to_string(dir.join("exchange.csv")).unwrap(),
+            format!("addresses,value\n{FIRST},10\n{SECOND},20\n")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn coins_are_never_paid_to_throwaway_keys() {
+        let dir = temp_dir("exchange_missing_key");
+        // Without the passphrase of the exchange's key store there is no key to derive the
+        // address from, and the coin must not be added to an address nobody can sign for
+        let reports = exchange(&dir, "addCoinToDB 5\nsignMessage default hello\n");
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.status == Status::Error));
+        assert!(reports[0].error.as_ref().unwrap().contains("no passphrase"));
+        assert_eq!(
+            fs::read_to_string(dir.join("exchange.csv")).unwrap(),
+            format!("addresses,value\n{FIRST},10\n{SECOND},20\n")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use bitcoin::bip32::ChildNumber;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::{Network, PublicKey};
+    use credible_coin::cli::exchange::hd_wallet::{HdWallet, Purpose};
+    use credible_coin::cli::exchange::key_manager::{KeyManager, ManagedKey};
+    use credible_coin::cli::exchange::keystore::{EncryptedFileKeystore, PASSPHRASE_VAR};
+    use credible_coin::cli::output::CommandReport;
+    use credible_coin::utils::bitcoin_utils::address_from_public_key;
+
+    use crate::common;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+
+    fn temp_dir(name: &str, config: &str) -> PathBuf {
+        common::temp_dir(
+            name,
+            &[
+                ("exchange.csv", &format!("addresses,value\n{FIRST},10\n")),
+                (
+                    "credible_config.yaml",
+                    &format!("keystore:\n  backend: file\n  path: keys.json\n{config}"),
+                ),
+            ],
+        )
+    }
+    fn keys(dir: &Path) -> KeyManager {
+        KeyManager::new(Box::new(
+            EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap(),
+        ))
+    }
+    fn exchange(dir: &Path, script: &str) -> Vec<CommandReport> {
+        common::exchange(
+            dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(PASSPHRASE_VAR, Some("hunter2"))],
+            script,
+        )
+    }
+    #[test]
+    pub fn addresses_follow_the_standard_paths() {
+        let wallet = HdWallet::new(&ManagedKey::generate("default"), Network::Bitcoin).unwrap();
+        let prefixes = ["1", "3", "bc1q", "bc1p"];
+        for (purpose, prefix) in Purpose::ALL.into_iter().zip(prefixes) {
+            let derived = wallet.derive(purpose, 2, 5).unwrap();
+            assert_eq!(
+                derived.path.to_string(),
+                format!("m/{}'/0'/2'/0/5", purpose.number())
+            );
+            assert!(derived.address.starts_with(prefix));
+            assert_eq!(purpose.to_string().parse::<Purpose>().unwrap(), purpose);
+            assert_eq!(Purpose::for_script_type(purpose.script_type()), purpose);
+
+            // Watch-only wallets derive the same addresses from the account's xpub
+            let xpub = wallet.account_xpub(purpose, 2).unwrap();
+            let child = xpub
+                .derive_pub(
+                    &Secp256k1::verification_only(),
+                    &[
+                        ChildNumber::from_normal_idx(0).unwrap(),
+                        ChildNumber::from_normal_idx(5).unwrap(),
+                    ],
+                )
+                .unwrap();
+            assert_eq!(
+                address_from_public_key(
+                    &PublicKey::new(child.public_key),
+                    purpose.script_type(),
+                    Network::Bitcoin
+                )
+                .unwrap(),
+                derived.address
+            );
+        }
+        assert_eq!("p2wpkh".parse::<Purpose>().unwrap(), Purpose::Bip84);
+        assert!("bip32".parse::<Purpose>().is_err());
+        // Test networks use coin type 1
+        let testnet = HdWallet::new(&ManagedKey::generate("default"), Network::Testnet).unwrap();
+        let derived = testnet.derive(Purpose::Bip84, 0, 0).unwrap();
+        assert_eq!(derived.path.to_string(), "m/84'/1'/0'/0/0");
+        assert!(derived.address.starts_with("tb1q"));
+        // The derivation only depends on the key
+        let key = ManagedKey::generate("default");
+        let first = HdWallet::new(&key, Network::Bitcoin).unwrap();
+        let second = HdWallet::new(&key, Network::Bitcoin).unwrap();
+        assert_eq!(
+            first.derive(Purpose::Bip86, 0, 3).unwrap(),
+            second.derive(Purpose::Bip86, 0, 3).unwrap()
+        );
+    }
+    #[test]
+    pub fn next_indices_are_kept_in_the_keystore() {
+        let dir = temp_dir("hd_wallet_indices", "");
+        let manager = keys(&dir);
+        manager.create_key("default").unwrap();
+        let first = manager
+            .next_address("default", Purpose::Bip84, 0, Network::Bitcoin)
+            .unwrap();
+        let second = manager
+            .next_address("default", Purpose::Bip84, 0, Network::Bitcoin)
+            .unwrap();
+        assert_eq!((first.index, second.index), (0, 1));
+        assert_ne!(first.address, second.address);
+        // Each account and purpose has its own counter
+        let other = manager
+            .next_address("default", Purpose::Bip44, 0, Network::Bitcoin)
+            .unwrap();
+        assert_eq!(other.index, 0);
+
+        let reopened = keys(&dir);
+        let third = reopened
+            .next_address("default", Purpose::Bip84, 0, Network::Bitcoin)
+            .unwrap();
+        assert_eq!(third.index, 2);
+        let issued: Vec<String> = reopened
+            .issued_addresses("default", Purpose::Bip84, 0, Network::Bitcoin)
+            .unwrap()
+            .into_iter()
+            .map(|derived| derived.address)
+            .collect();
+        assert_eq!(issued, [first.address, second.address, third.address]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn every_coin_gets_its_own_deposit_address() {
+        let dir = temp_dir("hd_wallet_deposits", "deposit_address_type: p2tr\n");
+        let manager = keys(&dir);
+        manager.create_key("default").unwrap();
+        // The address at index 0 is already in the ledger, so it is skipped
+        let taken = manager
+            .wallet("default", Network::Bitcoin)
+            .unwrap()
+            .derive(Purpose::Bip86, 0, 0)
+            .unwrap();
+        fs::write(
+            dir.join("exchange.csv"),
+            format!("addresses,value\n{FIRST},10\n{},20\n", taken.address),
+        )
+        .unwrap();
+
+        let reports = exchange(&dir, "addCoinToDB 5\naddCoinToDB 6\n");
+        let paths: Vec<&str> = reports
+            .iter()
+            .map(|report| report.data["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, ["m/86'/0'/0'/0/1", "m/86'/0'/0'/0/2"]);
+        assert_ne!(reports[0].data["address"], reports[1].data["address"]);
+        assert!(
+            reports[0].data["address"]
+                .as_str()
+                .unwrap()
+                .starts_with("bc1p")
+        );
+        // The next session carries on from the persisted index
+        let reports = exchange(&dir, "addCoinToDB 7\n");
+        assert_eq!(reports[0].data["path"], "m/86'/0'/0'/0/3");
+        let contents = fs::read_to_string(dir.join("exchange.csv")).unwrap();
+        assert_eq!(contents.lines().count(), 6);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn deposit_addresses_follow_the_network() {
+        let dir = temp_dir("hd_wallet_testnet", "");
+        fs::write(dir.join("exchange.csv"), "addresses,value\n").unwrap();
+        let reports = common::exchange(
+            &dir,
+            &[
+                "load",
+                "exchange.csv",
+                "--network",
+                "testnet",
+                "--keep-going",
+            ],
+            &[(PASSPHRASE_VAR, Some("hunter2"))],
+            "createPrivateKey default\naddCoinToDB 5\nsignMessage default hello\n",
+        );
+        assert!(
+            reports[0].data["address"]
+                .as_str()
+                .unwrap()
+                .starts_with(['m', 'n'])
+        );
+        assert_eq!(reports[1].data["path"], "m/84'/1'/0'/0/0");
+        assert!(
+            reports[1].data["address"]
+                .as_str()
+                .unwrap()
+                .starts_with("tb1q")
+        );
+        assert_eq!(reports[2].data["address"], reports[0].data["address"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+    use credible_coin::cli::exchange::db_connector::{
+        ExchangeNamespace, exchange_pool, remove_secret_key,
+    };
+    use credible_coin::cli::exchange::key_manager::{
+        KeyManager, ManagedKey, decode_signature, encode_signature, verify_message,
+    };
+    use credible_coin::cli::exchange::keystore::RedisKeystore;
+    use credible_coin::errors::KeyError;
+    use credible_coin::utils::bitcoin_utils::ScriptType;
+
+    #[test]
+    pub fn keys_derive_from_their_secret() {
+        let key = ManagedKey::generate("hot");
+        let restored = ManagedKey::from_slice("hot", &key.secret_key().secret_bytes()).unwrap();
+        assert_eq!(key, restored);
+        assert_eq!(key.public_key(), restored.public_key());
+        assert!(key.public_key().compressed);
+        for script_type in ScriptType::ALL {
+            assert_eq!(
+                key.address(script_type, Network::Bitcoin).unwrap(),
+                restored.address(script_type, Network::Bitcoin).unwrap()
+            );
+        }
+        // The secret key is never printed
+        let debug = format!("{:?}", key);
+        assert!(!debug.contains(&key.secret_key().display_secret().to_string()));
+        assert!(matches!(
+            ManagedKey::from_slice("broken", &[0; 32]),
+            Err(KeyError::Corrupt(name, _)) if name == "broken"
+        ));
+    }
+    #[test]
+    pub fn signatures_check_against_the_address_of_the_key() {
+        let key = ManagedKey::generate("hot");
+        let other = ManagedKey::generate("cold");
+        let address = key.address(ScriptType::P2pkh, Network::Bitcoin).unwrap();
+        let signature = key.sign_message("proof of reserves");
+        let encoded = encode_signature(&signature);
+        assert_eq!(decode_signature(&encoded).unwrap(), signature);
+        assert!(verify_message(&address, "proof of reserves", &signature).unwrap());
+        let forged = other.sign_message("proof of reserves");
+        assert!(!verify_message(&address, "proof of reserves", &forged).unwrap());
+        // signmessage signatures can only be checked against P2PKH addresses
+        let segwit = key.address(ScriptType::P2wpkh, Network::Bitcoin).unwrap();
+        assert!(verify_message(&segwit, "proof of reserves", &signature).is_err());
+        assert!(decode_signature("not base64!").is_err());
+    }
+    #[test]
+    #[ignore = "Only run when connected to the redis server"]
+    pub fn keys_are_stored_by_name() {
+        let manager = KeyManager::new(Box::new(RedisKeystore::new().unwrap()));
+        let key = manager.create_key("key_test").unwrap();
+        assert_eq!(manager.key("key_test").unwrap(), key);
+        assert!(
+            manager
+                .list_keys()
+                .unwrap()
+                .contains(&"key_test".to_owned())
+        );
+        assert!(matches!(
+            manager.create_key("key_test"),
+            Err(KeyError::Exists(_))
+        ));
+        assert!(matches!(
+            manager.key("key_test_missing"),
+            Err(KeyError::Missing(_))
+        ));
+        assert!(matches!(
+            manager.create_key("no spaces"),
+            Err(KeyError::InvalidName(_))
+        ));
+        let mut conn = exchange_pool().unwrap().get().unwrap();
+        let namespace = ExchangeNamespace::from_config().unwrap();
+        remove_secret_key(&mut conn, &namespace, "key_test").unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use bitcoin::Network;
+    use credible_coin::cli::exchange::key_manager::{KeyManager, decode_signature, verify_message};
+    use credible_coin::cli::exchange::keystore::{EncryptedFileKeystore, Keystore, PASSPHRASE_VAR};
+    use credible_coin::cli::output::{CommandReport, Status};
+    use credible_coin::errors::KeyError;
+    use credible_coin::utils::bitcoin_utils::ScriptType;
+
+    use crate::common;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(name, &[])
+    }
+    fn exchange(dir: &Path, passphrase: Option<&str>, script: &str) -> Vec<CommandReport> {
+        common::exchange(
+            dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(PASSPHRASE_VAR, passphrase)],
+            script,
+        )
+    }
+    #[test]
+    pub fn encrypted_keystores_only_open_with_their_passphrase() {
+        let dir = temp_dir("keystore_file");
+        let path = dir.join("keys.json");
+        let secret = [42; 32];
+        let keystore = EncryptedFileKeystore::open(&path, "hunter2").unwrap();
+        assert!(keystore.insert("hot", &secret).unwrap());
+        assert!(!keystore.insert("hot", &[1; 32]).unwrap());
+        assert!(keystore.insert("cold", &[2; 32]).unwrap());
+        // The key material never sits in the file in plaintext
+        let contents = fs::read(&path).unwrap();
+        assert!(!contents.windows(32).any(|bytes| bytes == secret));
+
+        let reopened = EncryptedFileKeystore::open(&path, "hunter2").unwrap();
+        assert_eq!(reopened.get("hot").unwrap().unwrap().as_slice(), secret);
+        assert_eq!(reopened.names().unwrap(), ["cold", "hot"]);
+        assert!(reopened.get("warm").unwrap().is_none());
+        assert!(matches!(
+            EncryptedFileKeystore::open(&path, "hunter3"),
+            Err(KeyError::WrongPassphrase)
+        ));
+
+        // A ciphertext moved to another name doesn't decrypt
+        let mut file: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+        file["keys"]["cold"] = file["keys"]["hot"].clone();
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(
+            reopened.get("cold"),
+            Err(KeyError::Undecryptable(name)) if name == "cold"
+        ));
+        assert!(reopened.remove("cold").unwrap());
+        assert!(!reopened.remove("cold").unwrap());
+        assert_eq!(reopened.names().unwrap(), ["hot"]);
+
+        // The KDF parameters and the derivation indices can't be edited without the passphrase
+        assert_eq!(reopened.reserve_index("deposits").unwrap(), 0);
+        assert_eq!(reopened.reserve_index("deposits").unwrap(), 1);
+        let contents: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let mut file = contents.clone();
+        file["indices"]["deposits"] = 0.into();
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(
+            reopened.reserve_index("deposits"),
+            Err(KeyError::TamperedIndices)
+        ));
+        let mut file = contents.clone();
+        file["kdf"]["iterations"] = 1.into();
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(
+            EncryptedFileKeystore::open(&path, "hunter2"),
+            Err(KeyError::WrongPassphrase)
+        ));
+        // Costs too high to derive a key with are refused before anything is derived
+        let mut file = contents;
+        file["kdf"]["memory_kib"] = u32::MAX.into();
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(
+            EncryptedFileKeystore::open(&path, "hunter2"),
+            Err(KeyError::KdfOutOfBounds(param)) if param.starts_with("memory_kib")
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn key_managers_work_on_any_keystore() {
+        let dir = temp_dir("keystore_manager");
+        let keystore = EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap();
+        let manager = KeyManager::new(Box::new(keystore));
+        let key = manager.create_key("default").unwrap();
+        assert_eq!(manager.key("default").unwrap(), key);
+        assert_eq!(manager.list_keys().unwrap(), ["default"]);
+        assert!(matches!(
+            manager.create_key("default"),
+            Err(KeyError::Exists(_))
+        ));
+        assert!(matches!(manager.key("other"), Err(KeyError::Missing(_))));
+        let address = manager
+            .address("default", ScriptType::P2pkh, Network::Bitcoin)
+            .unwrap();
+        let signature = manager.sign_message("default", "solvent").unwrap();
+        assert!(verify_message(&address, "solvent", &signature).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn the_exchange_uses_the_configured_keystore() {
+        let dir = temp_dir("keystore_config");
+        fs::write(
+            dir.join("exchange.csv"),
+            format!("addresses,value\n{FIRST},10\n"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("credible_config.yaml"),
+            "keystore:\n  backend: file\n  path: keys.json\n",
+        )
+        .unwrap();
+        let reports = exchange(
+            &dir,
+            Some("hunter2"),
+            "createPrivateKey default\naddCoinToDB 5\nsignMessage default \"proof of reserves\"\nlistKeys\n",
+        );
+        assert!(reports.iter().all(|report| report.status == Status::Ok));
+        let address = reports[0].data["address"].as_str().unwrap();
+        assert_eq!(reports[1].data["path"], "m/84'/0'/0'/0/0");
+        let signature = decode_signature(reports[2].data["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_message(address, "proof of reserves", &signature).unwrap());
+        assert_eq!(reports[3].data[0]["name"], "default");
+
+        // Without the passphrase the keys can't be used
+        let reports = exchange(&dir, None, "addCoinToDB 5\n");
+        assert!(reports[0].error.as_ref().unwrap().contains("no passphrase"));
+        let reports = exchange(&dir, Some("hunter3"), "listKeys\n");
+        assert!(
+            reports[0]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("wrong passphrase")
+        );
+        // Redis stores the keys in plaintext, so it has to be allowed explicitly
+        fs::write(
+            dir.join("credible_config.yaml"),
+            "keystore:\n  backend: redis\n",
+        )
+        .unwrap();
+        let reports = exchange(&dir, None, "listKeys\n");
+        assert!(reports[0].error.as_ref().unwrap().contains("plaintext"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use redis::Commands;
+
+    use credible_coin::cli::exchange::db_connector::{ExchangeNamespace, exchange_pool};
+    use credible_coin::cli::exchange::exchange_functions::create_new_tree_from_file;
+    use credible_coin::errors::LedgerStoreError;
+    use credible_coin::ledger_store::{
+        CsvLedgerStore, LedgerStore, MemoryLedgerStore, RedisLedgerStore,
+    };
+    use credible_coin::merkle_tree_entry::MerkleTreeEntry;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const THIRD: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+
+    fn csv_ledger(name: &str, contents: &str) -> CsvLedgerStore {
+        let file = std::env::temp_dir().join(name);
+        fs::write(&file, contents).unwrap();
+        CsvLedgerStore::new(file.to_str().unwrap())
+    }
+    /// Run the same changes against `ledger`, checking what every backend must agree on
+    fn exercise(ledger: &dyn LedgerStore) {
+        assert_eq!(ledger.position(SECOND, None).unwrap(), 1);
+        let (index, entry) = ledger.get_by_address(&format!("{FIRST}#2")).unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(entry, MerkleTreeEntry::new(FIRST.to_owned(), 5));
+        assert_eq!(ledger.get_by_index(3).unwrap(), None);
+
+        ledger.update(SECOND, 25).unwrap();
+        assert_eq!(ledger.get_by_index(1).unwrap().unwrap().entry_value(), 25);
+        assert!(matches!(
+            ledger.update(THIRD, 1),
+            Err(LedgerStoreError::NotFound(_))
+        ));
+
+        assert_eq!(ledger.append(vec![THIRD.to_owned()], vec![7]).unwrap(), 1);
+        assert!(matches!(
+            ledger.append(vec![THIRD.to_owned()], vec![8]),
+            Err(LedgerStoreError::Exists(_))
+        ));
+        assert_eq!(ledger.columns().unwrap().0.len(), 4);
+
+        // Removing a repeated address drops all of its rows
+        assert_eq!(ledger.remove(FIRST).unwrap(), 2);
+        assert_eq!(
+            ledger.columns().unwrap(),
+            (vec![SECOND.to_owned(), THIRD.to_owned()], vec![25, 7])
+        );
+        assert!(matches!(
+            ledger.remove(FIRST),
+            Err(LedgerStoreError::NotFound(_))
+        ));
+    }
+    #[test]
+    pub fn csv_and_memory_ledgers_agree() {
+        let csv = csv_ledger(
+            "ledger_store_agree.csv",
+            &format!("addresses,value\n{FIRST},10\n{SECOND},20\n{FIRST},5\n"),
+        );
+        let memory = MemoryLedgerStore::from_columns(
+            vec![FIRST.to_owned(), SECOND.to_owned(), FIRST.to_owned()],
+            vec![10, 20, 5],
+        )
+        .unwrap();
+        let tree = create_new_tree_from_file(csv.file().unwrap());
+        assert_eq!(csv.root().unwrap(), tree.root_hex());
+        assert_eq!(memory.root().unwrap(), tree.root_hex());
+        assert_eq!(memory.entries().unwrap(), csv.entries().unwrap());
+
+        exercise(&csv);
+        exercise(&memory);
+        assert_eq!(memory.root().unwrap(), csv.root().unwrap());
+        let tree = create_new_tree_from_file(csv.file().unwrap());
+        assert_eq!(csv.root().unwrap(), tree.root_hex());
+    }
+    #[test]
+    pub fn empty_ledgers_have_no_root() {
+        let ledger = MemoryLedgerStore::new();
+        assert_eq!(ledger.root().unwrap(), None);
+        assert_eq!(ledger.get_by_index(0).unwrap(), None);
+        assert!(matches!(
+            ledger.get_by_address(FIRST),
+            Err(LedgerStoreError::Position(_))
+        ));
+        assert!(
+            MemoryLedgerStore::from_columns(vec!["not an address".to_owned()], vec![1]).is_err()
+        );
+    }
+    #[test]
+    pub fn malformed_files_are_errors() {
+        for (name, contents) in [
+            (
+                "ledger_store_bad_address.csv",
+                "addresses,value\nnot an address,1\n",
+            ),
+            (
+                "ledger_store_bad_value.csv",
+                &format!("addresses,value\n{FIRST},ten\n"),
+            ),
+        ] {
+            let ledger = csv_ledger(name, contents);
+            assert!(matches!(ledger.columns(), Err(LedgerStoreError::File(_))));
+            assert!(ledger.root().is_err());
+            assert!(ledger.update(FIRST, 1).is_err());
+            fs::remove_file(ledger.file().unwrap()).unwrap();
+        }
+    }
+    #[test]
+    #[ignore = "Only run when connected to the redis server"]
+    pub fn redis_ledger_round_trip() {
+        let pool = exchange_pool().unwrap();
+        let namespace = ExchangeNamespace::new("ledger-store-tests").unwrap();
+        // Rows written without an index, which is built on the first change
+        let mut conn = pool.get().unwrap();
+        let _: () = conn
+            .del(&[
+                namespace.ledger("round-trip"),
+                namespace.ledger_index("round-trip"),
+            ])
+            .unwrap();
+        let _: () = conn
+            .rpush(
+                namespace.ledger("round-trip"),
+                &[
+                    format!("[\"{FIRST}\",10]"),
+                    format!("[\"{SECOND}\",20]"),
+                    format!("[\"{FIRST}\",5]"),
+                ],
+            )
+            .unwrap();
+        let ledger = RedisLedgerStore::with_pool(pool, &namespace, "round-trip");
+        exercise(&ledger);
+        // Single rows are changed in place
+        ledger.append(vec![FIRST.to_owned()], vec![10]).unwrap();
+        ledger.update(&format!("{FIRST}#1"), 11).unwrap();
+        assert_eq!(ledger.get_by_index(2).unwrap().unwrap().entry_value(), 11);
+        assert_eq!(ledger.remove(&format!("{FIRST}#1")).unwrap(), 1);
+        assert_eq!(ledger.columns().unwrap().0, [SECOND, THIRD]);
+        ledger.remove(SECOND).unwrap();
+        ledger.remove(THIRD).unwrap();
+        assert_eq!(ledger.root().unwrap(), None);
+    }
+}
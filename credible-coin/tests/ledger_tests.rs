@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use credible_coin::errors::LedgerFormatError;
+    use credible_coin::utils::binary_ledger::{
+        FORMAT_VERSION, MappedLedger, is_binary_ledger, write_ledger,
+    };
+    use credible_coin::utils::csv_utils::{
+        convert_csv_to_ledger, convert_ledger_to_csv, get_address_position, update_csv_entry,
+    };
+    use credible_coin::utils::merkle_utils::load_merkle_leaves_from_csv;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "bc1qushqa4nwpz2j0yftnpw08c5lj2u92mnah79q2k";
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(name).to_str().unwrap().to_owned()
+    }
+    #[test]
+    pub fn csv_and_binary_ledgers_round_trip() {
+        let csv = temp_path("ledger_round_trip.csv");
+        let ledger = temp_path("ledger_round_trip.ccl");
+        let back = temp_path("ledger_round_trip_back.csv");
+        let contents = format!("addresses,value\n{FIRST},10\n{SECOND},-20\n{FIRST},30\n");
+        fs::write(&csv, &contents).unwrap();
+
+        assert_eq!(convert_csv_to_ledger(&csv, &ledger).unwrap(), 3);
+        assert!(is_binary_ledger(&ledger));
+        assert!(!is_binary_ledger(&csv));
+        let mapped = MappedLedger::open(&ledger).unwrap();
+        assert_eq!(mapped.header().version, FORMAT_VERSION);
+        assert_eq!(mapped.len(), 3);
+        assert_eq!(mapped.address_count(), 2);
+        assert_eq!(
+            mapped.iter().collect::<Vec<_>>(),
+            vec![(FIRST, 10), (SECOND, -20), (FIRST, 30)]
+        );
+        drop(mapped);
+        // Both formats must produce the same tree
+        assert_eq!(
+            load_merkle_leaves_from_csv(&csv),
+            load_merkle_leaves_from_csv(&ledger)
+        );
+
+        assert_eq!(convert_ledger_to_csv(&ledger, &back).unwrap(), 3);
+        assert_eq!(fs::read_to_string(&back).unwrap(), contents);
+        for file in [csv, ledger, back] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+    #[test]
+    pub fn binary_ledgers_can_be_updated() {
+        let ledger = temp_path("ledger_update.ccl");
+        write_ledger(
+            &ledger,
+            &[FIRST.to_owned(), SECOND.to_owned(), FIRST.to_owned()],
+            &[10, 20, 30],
+        )
+        .unwrap();
+        assert_eq!(
+            get_address_position(&ledger, format!("{FIRST}#2"), None).unwrap(),
+            2
+        );
+        update_csv_entry(&ledger, &format!("{FIRST}#2"), 31).unwrap();
+        assert_eq!(
+            MappedLedger::open(&ledger).unwrap().values(),
+            vec![10, 20, 31]
+        );
+        update_csv_entry(&ledger, FIRST, 5).unwrap();
+        let mapped = MappedLedger::open(&ledger).unwrap();
+        assert_eq!(
+            mapped.iter().collect::<Vec<_>>(),
+            vec![(FIRST, 5), (SECOND, 20)]
+        );
+        drop(mapped);
+        fs::remove_file(ledger).unwrap();
+    }
+    #[test]
+    pub fn corrupt_ledgers_are_rejected() {
+        let ledger = temp_path("ledger_corrupt.ccl");
+        write_ledger(&ledger, &[FIRST.to_owned(), SECOND.to_owned()], &[1, 2]).unwrap();
+        let bytes = fs::read(&ledger).unwrap();
+
+        fs::write(&ledger, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            MappedLedger::open(&ledger),
+            Err(LedgerFormatError::Corrupt(_))
+        ));
+
+        let mut newer = bytes.clone();
+        newer[8] = 0xff;
+        fs::write(&ledger, &newer).unwrap();
+        assert!(matches!(
+            MappedLedger::open(&ledger),
+            Err(LedgerFormatError::UnsupportedVersion(_))
+        ));
+
+        let mut bad_index = bytes.clone();
+        let address_column = u64::from_le_bytes(bytes[48..56].try_into().unwrap()) as usize;
+        bad_index[address_column] = 7;
+        fs::write(&ledger, &bad_index).unwrap();
+        assert!(matches!(
+            MappedLedger::open(&ledger),
+            Err(LedgerFormatError::Corrupt(_))
+        ));
+
+        fs::write(&ledger, "addresses,value\n").unwrap();
+        assert!(matches!(
+            MappedLedger::open(&ledger),
+            Err(LedgerFormatError::BadMagic)
+        ));
+        fs::remove_file(ledger).unwrap();
+    }
+}
@@ -1,5 +1,10 @@
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use credible_coin::accumulator::AbstractAccumulator;
+    use credible_coin::accumulator::merkle_path::MerklePathAccumulator;
+    use credible_coin::ledger_store::MemoryLedgerStore;
     use credible_coin::{merkle_tree_entry::MerkleTreeEntry, utils::hashable::ToHashable};
     use rs_merkle::{algorithms::Sha256, Hasher, MerkleProof, MerkleTree};
     #[test]
@@ -314,4 +319,28 @@ mod tests {
         ));
         assert_eq!(tree.depth(), 2);
     }
+    #[test]
+    pub fn merkle_path_aggregates_count_only_the_secret_set() {
+        let first = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+        let second = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+        let third = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+        let secrets = MemoryLedgerStore::from_columns(
+            vec![first.to_owned(), second.to_owned()],
+            vec![10, 20],
+        )
+        .unwrap();
+        // The ledger inflates one balance and adds an account the exchange never had
+        let ledger = MemoryLedgerStore::from_columns(
+            vec![first.to_owned(), second.to_owned(), third.to_owned()],
+            vec![10, 25, 30],
+        )
+        .unwrap();
+        let accumulator = MerklePathAccumulator::new(Arc::new(secrets));
+        let entries = vec![
+            MerkleTreeEntry::new(first.to_owned(), 10),
+            MerkleTreeEntry::new(second.to_owned(), 25),
+            MerkleTreeEntry::new(third.to_owned(), 30),
+        ];
+        assert_eq!(accumulator.aggregate(&ledger, entries).unwrap(), 10);
+    }
 }
@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::Arc;
+
+    use credible_coin::ledger_store::{LedgerStore, MemoryLedgerStore};
+    use credible_coin::proof_api::{ApiError, Root, serve_http};
+    use credible_coin::proof_server::{MembershipProver, ProofServer, account_id};
+    use credible_coin::proof_store::{
+        InclusionProof, MemoryProofStore, ProofStore, SolvencyReport, publish_proofs,
+    };
+    use serde::de::DeserializeOwned;
+    use tokio::net::TcpListener;
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const THIRD: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+    const SECRET: &str = "account ID secret";
+
+    fn ledger() -> Arc<dyn LedgerStore> {
+        Arc::new(
+            MemoryLedgerStore::from_columns(
+                vec![FIRST.to_owned(), SECOND.to_owned(), FIRST.to_owned()],
+                vec![10, 20, 5],
+            )
+            .unwrap(),
+        )
+    }
+    /// Serve the API of `ledger` on a thread of its own, returning its base URL
+    fn start(ledger: Arc<dyn LedgerStore>, reports: Option<Arc<dyn ProofStore>>) -> String {
+        serve(ProofServer::new(ledger, MembershipProver::MerklePath, reports).unwrap())
+    }
+    /// Serve the API of `ledger` with accounts served by the IDs derived with [`SECRET`]
+    fn start_with_ids(ledger: Arc<dyn LedgerStore>) -> String {
+        serve(
+            ProofServer::new(ledger, MembershipProver::MerklePath, None)
+                .unwrap()
+                .with_account_ids(SECRET.to_owned())
+                .unwrap(),
+        )
+    }
+    fn serve(server: ProofServer) -> String {
+        let server = Arc::new(server);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || runtime.block_on(serve_http(server, listener)));
+        base
+    }
+    fn get<T: DeserializeOwned>(base: &str, path: &str) -> T {
+        ureq::get(&format!("{base}{path}"))
+            .call()
+            .unwrap()
+            .into_json()
+            .unwrap()
+    }
+    /// The status and error of a request which must fail
+    fn get_error(base: &str, path: &str) -> (u16, ApiError) {
+        match ureq::get(&format!("{base}{path}")).call() {
+            Err(ureq::Error::Status(status, response)) => (status, response.into_json().unwrap()),
+            other => panic!("expected an error status, got {other:?}"),
+        }
+    }
+    #[test]
+    pub fn endpoints_serve_the_ledger() {
+        let ledger = ledger();
+        let reports = Arc::new(MemoryProofStore::new());
+        let report = publish_proofs(reports.as_ref(), ledger.as_ref(), 50, "audit", None).unwrap();
+        // A report published later for another ledger is not the one of the served root
+        let other = MemoryLedgerStore::from_columns(vec![SECOND.to_owned()], vec![1]).unwrap();
+        publish_proofs(reports.as_ref(), &other, 50, "audit", None).unwrap();
+        let base = start(Arc::clone(&ledger), Some(reports));
+
+        let root: Root = get(&base, "/root");
+        assert_eq!(root.root, ledger.root().unwrap());
+
+        // Every account is proven as it is published
+        let published = InclusionProof::for_ledger(ledger.as_ref()).unwrap();
+        for expected in &published {
+            let address = expected.address.replace('#', "%23");
+            let by_address: InclusionProof = get(&base, &format!("/proof/address/{address}"));
+            assert!(by_address.verify());
+            assert_eq!(&by_address, expected);
+        }
+        assert_eq!(published[2].address, format!("{FIRST}#2"));
+
+        let served: SolvencyReport = get(&base, "/solvency-report");
+        assert_eq!(served, report);
+    }
+    #[test]
+    pub fn accounts_are_served_by_id() {
+        let ledger = ledger();
+        let base = start_with_ids(Arc::clone(&ledger));
+        let published = InclusionProof::for_ledger(ledger.as_ref()).unwrap();
+        for expected in &published {
+            let id = account_id(SECRET, &expected.address);
+            let by_id: InclusionProof = get(&base, &format!("/proof/account/{id}"));
+            assert_eq!(&by_id, expected);
+        }
+        // Neither IDs derived with another secret nor leaf indices are accounts
+        let other = account_id("another secret", SECOND);
+        let (status, error) = get_error(&base, &format!("/proof/account/{other}"));
+        assert_eq!(status, 404);
+        assert!(error.error.contains(&other));
+        assert_eq!(get_error(&base, "/proof/account/0").0, 404);
+
+        // Servers without the secret don't serve accounts by ID
+        let base = start(ledger, None);
+        let id = account_id(SECRET, SECOND);
+        let (status, error) = get_error(&base, &format!("/proof/account/{id}"));
+        assert_eq!(status, 404);
+        assert!(error.error.contains("account IDs"));
+    }
+    #[test]
+    pub fn failed_requests_get_errors() {
+        let base = start(ledger(), Some(Arc::new(MemoryProofStore::new())));
+        assert_eq!(get_error(&base, &format!("/proof/address/{THIRD}")).0, 404);
+        // Accounts can't be walked by the index of their leaf
+        assert!(matches!(
+            ureq::get(&format!("{base}/proof/account/0")).call(),
+            Err(ureq::Error::Status(404, _))
+        ));
+        assert_eq!(get_error(&base, "/solvency-report").0, 404);
+        // A bare repeated address is ambiguous, and an invalid one is refused
+        let (status, error) = get_error(&base, &format!("/proof/address/{FIRST}"));
+        assert_eq!(status, 400);
+        assert!(error.error.contains(FIRST));
+        assert_eq!(get_error(&base, "/proof/address/not-an-address").0, 400);
+
+        let base = start(ledger(), None);
+        let (status, error) = get_error(&base, "/solvency-report");
+        assert_eq!(status, 404);
+        assert!(error.error.contains("solvency reports"));
+    }
+    #[test]
+    pub fn exchange_serves_csv_ledgers() {
+        let dir = std::env::temp_dir().join("proof_api_serve");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("exchange.csv"),
+            format!("addresses,value\n{FIRST},10\n{SECOND},20\n"),
+        )
+        .unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_exchange"))
+            .current_dir(&dir)
+            .args(["serve", "exchange.csv", "--listen", "127.0.0.1:0"])
+            .args(["--no-reports", "--refresh", "0"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let base = line
+            .trim()
+            .strip_prefix("Listening on ")
+            .unwrap()
+            .to_owned();
+
+        let root: Root = get(&base, "/root");
+        let proof: InclusionProof = get(&base, &format!("/proof/address/{SECOND}"));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(proof.verify());
+        assert_eq!((proof.index, proof.value), (1, 20));
+        assert_eq!(root.root, Some(proof.root));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
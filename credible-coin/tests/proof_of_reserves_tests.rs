@@ -0,0 +1,220 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use bitcoin::{Network, PrivateKey};
+    use credible_coin::cli::exchange::hd_wallet::Purpose;
+    use credible_coin::cli::exchange::key_manager::{DEFAULT_KEY, KeyManager, ManagedKey};
+    use credible_coin::cli::exchange::keystore::{EncryptedFileKeystore, PASSPHRASE_VAR};
+    use credible_coin::cli::exchange::proof_of_reserves::{
+        OwnershipProof, ReserveProof, SignatureFormat, prove_reserves, verify_reserves,
+    };
+    use credible_coin::cli::output::Status;
+    use credible_coin::errors::KeyError;
+    use credible_coin::ledger_store::{CsvLedgerStore, MemoryLedgerStore};
+    use credible_coin::utils::bip322::{sign, verify};
+    use credible_coin::utils::bitcoin_utils::ScriptType;
+
+    use crate::common;
+
+    /// The key and address of the test vectors of BIP-322
+    const VECTOR_KEY: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+    const VECTOR_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        common::temp_dir(name, &[])
+    }
+
+    #[test]
+    pub fn bip322_signatures_match_the_test_vectors() {
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(verify(VECTOR_ADDRESS, "", empty).unwrap());
+        assert!(verify(VECTOR_ADDRESS, "Hello World", hello).unwrap());
+        assert!(!verify(VECTOR_ADDRESS, "Hello World", empty).unwrap());
+        // Signing is deterministic for P2WPKH, so our own signatures are the vectors
+        let key = PrivateKey::from_wif(VECTOR_KEY).unwrap().inner;
+        assert_eq!(sign(&key, VECTOR_ADDRESS, "").unwrap(), empty);
+        assert_eq!(sign(&key, VECTOR_ADDRESS, "Hello World").unwrap(), hello);
+        // Keys can only sign for their own addresses
+        assert!(matches!(
+            sign(&key, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq", ""),
+            Err(KeyError::NotOwned(_))
+        ));
+        assert!(matches!(
+            sign(&key, FIRST, ""),
+            Err(KeyError::UnsupportedAddress(_))
+        ));
+    }
+    #[test]
+    pub fn reserves_are_proven_for_every_controlled_address() {
+        let dir = temp_dir("proof_of_reserves");
+        let keys = KeyManager::new(Box::new(
+            EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap(),
+        ));
+        let key = keys.create_key(DEFAULT_KEY).unwrap();
+        let mut rows = vec![
+            (key.address(ScriptType::P2pkh, Network::Bitcoin).unwrap(), 5),
+            (FIRST.to_owned(), 7),
+        ];
+        for purpose in [Purpose::Bip49, Purpose::Bip84, Purpose::Bip86] {
+            let deposit = keys
+                .next_address(DEFAULT_KEY, purpose, 0, Network::Bitcoin)
+                .unwrap();
+            rows.push((deposit.address, 10));
+        }
+        // The rows of a repeated address are proven together
+        rows.push((rows[2].0.clone(), 1));
+        let ledger = dir.join("exchange.csv");
+        let mut contents = String::from("addresses,value\n");
+        for (address, value) in &rows {
+            contents.push_str(&format!("{address},{value}\n"));
+        }
+        fs::write(&ledger, contents).unwrap();
+
+        let proof = prove_reserves(
+            &keys,
+            &CsvLedgerStore::new(ledger.to_str().unwrap()),
+            "audit 2024-Q1",
+        )
+        .unwrap();
+        assert_eq!(proof.proofs.len(), 4);
+        assert_eq!(proof.unproven, vec![FIRST.to_owned()]);
+        assert_eq!(proof.total, 5 + 11 + 10 + 10);
+        assert_eq!(proof.proofs[0].format, SignatureFormat::Legacy);
+        assert!(
+            proof.proofs[1..]
+                .iter()
+                .all(|ownership| ownership.format == SignatureFormat::Bip322)
+        );
+        let verification = verify_reserves(&proof);
+        assert!(verification.valid, "{verification}");
+        assert_eq!(verification.verified, 4);
+        assert_eq!(verification.verified_total, proof.total);
+
+        // Inflating a balance breaks the total
+        let mut inflated = proof.clone();
+        inflated.proofs[0].value += 100;
+        assert!(!verify_reserves(&inflated).valid);
+        // A signature moved to another address doesn't hold
+        let mut swapped = proof.clone();
+        swapped.proofs[2].signature = proof.proofs[3].signature.clone();
+        let verification = verify_reserves(&swapped);
+        assert!(!verification.valid);
+        assert_eq!(verification.failures.len(), 1);
+        assert_eq!(verification.failures[0].address, proof.proofs[2].address);
+        // An address counted twice only counts once
+        let mut doubled = proof.clone();
+        doubled.proofs.push(proof.proofs[1].clone());
+        doubled.total += proof.proofs[1].value;
+        assert!(!verify_reserves(&doubled).valid);
+    }
+    #[test]
+    pub fn overflowing_balances_are_failures() {
+        let challenge = "audit 2024-Q1";
+        let proofs = ["first", "second"].map(|name| {
+            let key = ManagedKey::generate(name);
+            let address = key.address(ScriptType::P2wpkh, Network::Bitcoin).unwrap();
+            OwnershipProof::sign(&key, &address, i64::MAX, challenge).unwrap()
+        });
+        let proof = ReserveProof {
+            challenge: challenge.to_owned(),
+            root: String::new(),
+            total: i64::MAX,
+            proofs: proofs.to_vec(),
+            unproven: Vec::new(),
+        };
+        // The second balance would wrap the total around, so it isn't counted
+        let verification = verify_reserves(&proof);
+        assert!(!verification.valid);
+        assert_eq!(verification.verified_total, i64::MAX);
+        assert_eq!(verification.failures.len(), 1);
+        assert!(verification.failures[0].reason.contains("overflows"));
+
+        // Nor are balances which overflow proven
+        let dir = temp_dir("proof_of_reserves_overflow");
+        let keys = KeyManager::new(Box::new(
+            EncryptedFileKeystore::open(dir.join("keys.json"), "hunter2").unwrap(),
+        ));
+        let key = keys.create_key(DEFAULT_KEY).unwrap();
+        let address = key.address(ScriptType::P2pkh, Network::Bitcoin).unwrap();
+        let ledger = MemoryLedgerStore::from_columns(
+            vec![address.clone(), address],
+            vec![i64::MAX, i64::MAX],
+        )
+        .unwrap();
+        assert!(matches!(
+            prove_reserves(&keys, &ledger, challenge),
+            Err(KeyError::Overflow(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    pub fn the_exchange_proves_and_verifies_its_reserves() {
+        let dir = temp_dir("proof_of_reserves_shell");
+        fs::write(
+            dir.join("exchange.csv"),
+            format!("addresses,value\n{FIRST},10\n"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("credible_config.yaml"),
+            "keystore:\n  backend: file\n  path: keys.json\n",
+        )
+        .unwrap();
+        let reports = common::exchange(
+            &dir,
+            &["load", "exchange.csv", "--keep-going"],
+            &[(PASSPHRASE_VAR, Some("hunter2"))],
+            "createPrivateKey default\naddCoinToDB 25\nproveReserves \"audit 2024-Q1\" proof.json\nverifyReserves proof.json\n",
+        );
+        assert_eq!(reports.len(), 4);
+        assert!(
+            reports.iter().all(|report| report.status == Status::Ok),
+            "{reports:?}"
+        );
+        let proof = &reports[2].data;
+        assert_eq!(proof["total"], 25);
+        assert_eq!(proof["unproven"][0], FIRST);
+        assert!(dir.join("proof.json").exists());
+        let verification = &reports[3].data;
+        assert_eq!(verification["valid"], true);
+        assert_eq!(verification["verified_total"], 25);
+    }
+    #[test]
+    pub fn reserves_are_proven_on_the_exchanges_network() {
+        let dir = temp_dir("proof_of_reserves_testnet");
+        fs::write(dir.join("exchange.csv"), "addresses,value\n").unwrap();
+        fs::write(
+            dir.join("credible_config.yaml"),
+            "keystore:\n  backend: file\n  path: keys.json\n",
+        )
+        .unwrap();
+        let reports = common::exchange(
+            &dir,
+            &[
+                "load",
+                "exchange.csv",
+                "--network",
+                "testnet",
+                "--keep-going",
+            ],
+            &[(PASSPHRASE_VAR, Some("hunter2"))],
+            "createPrivateKey default\naddCoinToDB 25\nproveReserves challenge proof.json\n",
+        );
+        let proof = &reports[2].data;
+        assert_eq!(proof["total"], 25, "{reports:?}");
+        assert_eq!(proof["unproven"].as_array().unwrap().len(), 0);
+        assert!(
+            proof["proofs"][0]["address"]
+                .as_str()
+                .unwrap()
+                .starts_with("tb1q")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::Arc;
+
+    use credible_coin::errors::ProofServerError;
+    use credible_coin::ledger_store::{LedgerStore, MemoryLedgerStore};
+    use credible_coin::proof_server::{
+        ACCOUNT_ID_SECRET_VAR, MAX_FRAME_LEN, MembershipProver, ProofServer, Request, Response,
+        account_id, read_frame, send_request, write_frame,
+    };
+    use credible_coin::proof_store::{MemoryProofStore, ProofStore, publish_proofs};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const THIRD: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+
+    fn ledger() -> Arc<dyn LedgerStore> {
+        Arc::new(
+            MemoryLedgerStore::from_columns(
+                vec![FIRST.to_owned(), SECOND.to_owned(), FIRST.to_owned()],
+                vec![10, 20, 5],
+            )
+            .unwrap(),
+        )
+    }
+    async fn start(
+        ledger: Arc<dyn LedgerStore>,
+        reports: Option<Arc<dyn ProofStore>>,
+    ) -> (Arc<ProofServer>, TcpStream) {
+        let server =
+            Arc::new(ProofServer::new(ledger, MembershipProver::MerklePath, reports).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(Arc::clone(&server).serve(listener));
+        (server, TcpStream::connect(address).await.unwrap())
+    }
+    #[tokio::test]
+    pub async fn requests_are_answered_from_the_ledger() {
+        let ledger = ledger();
+        let reports = Arc::new(MemoryProofStore::new());
+        let report = publish_proofs(reports.as_ref(), ledger.as_ref(), 50, "audit", None).unwrap();
+        // A report published later for another ledger is not the one of the served root
+        let other = MemoryLedgerStore::from_columns(vec![THIRD.to_owned()], vec![1]).unwrap();
+        publish_proofs(reports.as_ref(), &other, 50, "audit", None).unwrap();
+        let (server, mut stream) = start(Arc::clone(&ledger), Some(reports)).await;
+
+        let root = ledger.root().unwrap();
+        assert_eq!(server.root(), root);
+        assert_eq!(
+            send_request(&mut stream, &Request::GetRoot).await.unwrap(),
+            Response::Root { root: root.clone() }
+        );
+
+        let Response::Membership {
+            address,
+            index,
+            proof,
+        } = send_request(
+            &mut stream,
+            &Request::ProveMembership {
+                address: format!("{FIRST}#2"),
+            },
+        )
+        .await
+        .unwrap()
+        else {
+            panic!("expected a membership proof");
+        };
+        assert_eq!(
+            (address.as_str(), index),
+            (format!("{FIRST}#2").as_str(), Some(2))
+        );
+        assert!(proof.is_member());
+
+        let Response::Proof(proof) = send_request(
+            &mut stream,
+            &Request::GetProof {
+                address: SECOND.to_owned(),
+            },
+        )
+        .await
+        .unwrap() else {
+            panic!("expected an inclusion proof");
+        };
+        assert!(proof.verify());
+        assert_eq!((proof.index, proof.value), (1, 20));
+        assert_eq!(Some(proof.root), root);
+
+        assert_eq!(
+            send_request(&mut stream, &Request::GetSolvencyReport)
+                .await
+                .unwrap(),
+            Response::SolvencyReport(Some(report))
+        );
+    }
+    #[tokio::test]
+    pub async fn bad_requests_get_errors() {
+        let (_server, mut stream) = start(ledger(), None).await;
+
+        // An unknown address is not a member, a bare repeated one is ambiguous
+        let response = send_request(
+            &mut stream,
+            &Request::ProveMembership {
+                address: THIRD.to_owned(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            response,
+            Response::Membership { index: None, proof, .. } if !proof.is_member()
+        ));
+        for request in [
+            Request::GetProof {
+                address: THIRD.to_owned(),
+            },
+            Request::GetProof {
+                address: FIRST.to_owned(),
+            },
+            // The server has no secret to derive account IDs with
+            Request::GetAccountProof {
+                id: account_id("secret", SECOND),
+            },
+            Request::GetSolvencyReport,
+        ] {
+            let response = send_request(&mut stream, &request).await.unwrap();
+            assert!(matches!(response, Response::Error { .. }), "{response:?}");
+        }
+
+        // Invalid JSON is answered, and the connection stays open
+        write_frame(&mut stream, b"{\"GetRoot\"").await.unwrap();
+        let frame = read_frame(&mut stream).await.unwrap().unwrap();
+        let response: Response = serde_json::from_slice(&frame).unwrap();
+        assert!(matches!(response, Response::Error { message } if message.starts_with("Invalid")));
+        assert!(matches!(
+            send_request(&mut stream, &Request::GetRoot).await.unwrap(),
+            Response::Root { root: Some(_) }
+        ));
+
+        // A frame that is too long is refused, and the connection is closed
+        let length = u32::try_from(MAX_FRAME_LEN + 1).unwrap();
+        stream.write_all(&length.to_be_bytes()).await.unwrap();
+        let frame = read_frame(&mut stream).await.unwrap().unwrap();
+        assert!(matches!(
+            serde_json::from_slice(&frame).unwrap(),
+            Response::Error { .. }
+        ));
+        assert_eq!(read_frame(&mut stream).await.unwrap(), None);
+    }
+    #[tokio::test]
+    pub async fn truncated_frames_are_errors() {
+        // A header promising the longest frame, followed by a few bytes and the end of the stream
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let length = u32::try_from(MAX_FRAME_LEN).unwrap();
+        client.write_all(&length.to_be_bytes()).await.unwrap();
+        client.write_all(b"{}").await.unwrap();
+        drop(client);
+        assert!(matches!(
+            read_frame(&mut server).await,
+            Err(ProofServerError::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+    }
+    #[tokio::test]
+    pub async fn reloads_pick_up_changes() {
+        let ledger = ledger();
+        let (server, mut stream) = start(Arc::clone(&ledger), None).await;
+        let before = server.root();
+
+        ledger.append(vec![THIRD.to_owned()], vec![30]).unwrap();
+        let request = Request::GetProof {
+            address: THIRD.to_owned(),
+        };
+        assert!(matches!(
+            send_request(&mut stream, &request).await.unwrap(),
+            Response::Error { .. }
+        ));
+
+        let after = server.reload().unwrap();
+        assert_ne!(after, before);
+        assert_eq!(after, ledger.root().unwrap());
+        let Response::Proof(proof) = send_request(&mut stream, &request).await.unwrap() else {
+            panic!("expected an inclusion proof");
+        };
+        assert!(proof.verify());
+        assert_eq!(Some(proof.root), after);
+    }
+    #[test]
+    pub fn daemon_serves_csv_ledgers() {
+        let dir = std::env::temp_dir().join("proof_server_daemon");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("exchange.csv");
+        fs::write(&file, format!("addresses,value\n{FIRST},10\n{SECOND},20\n")).unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tcp_daemon"))
+            .arg(&file)
+            .args(["--listen", "127.0.0.1:0", "--no-reports", "--refresh", "0"])
+            .env(ACCOUNT_ID_SECRET_VAR, "secret")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let address = line
+            .trim()
+            .strip_prefix("Listening on ")
+            .unwrap()
+            .to_owned();
+
+        let responses = tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let root = send_request(&mut stream, &Request::GetRoot).await.unwrap();
+            let membership = send_request(
+                &mut stream,
+                &Request::ProveMembership {
+                    address: SECOND.to_owned(),
+                },
+            )
+            .await
+            .unwrap();
+            let by_id = send_request(
+                &mut stream,
+                &Request::GetAccountProof {
+                    id: account_id("secret", SECOND),
+                },
+            )
+            .await
+            .unwrap();
+            (root, membership, by_id)
+        });
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let Response::Root { root: Some(root) } = responses.0 else {
+            panic!("expected a root");
+        };
+        assert_eq!(root.len(), 64);
+        assert!(matches!(
+            responses.1,
+            Response::Membership { index: Some(1), proof, .. } if proof.is_member()
+        ));
+        let Response::Proof(proof) = responses.2 else {
+            panic!("expected an inclusion proof");
+        };
+        assert!(proof.verify());
+        assert_eq!((proof.address.as_str(), proof.value), (SECOND, 20));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
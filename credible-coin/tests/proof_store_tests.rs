@@ -0,0 +1,142 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use credible_coin::cli::exchange::db_connector::proofs_pool;
+    use credible_coin::errors::ProofStoreError;
+    use credible_coin::ledger_store::CsvLedgerStore;
+    use credible_coin::proof_store::{
+        InclusionProof, MemoryProofStore, ProofStore, RedisProofStore, publish_proofs,
+    };
+
+    const FIRST: &str = "17wNSD33wQFDwMnzUHRPCsXseWctUZVQEC";
+    const SECOND: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const THIRD: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+
+    fn ledger(name: &str, rows: &[(&str, i64)]) -> CsvLedgerStore {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut contents = String::from("addresses,value\n");
+        for (address, value) in rows {
+            contents.push_str(&format!("{address},{value}\n"));
+        }
+        let file = dir.join("exchange.csv");
+        fs::write(&file, contents).unwrap();
+        CsvLedgerStore::new(file.to_str().unwrap())
+    }
+
+    #[test]
+    pub fn customers_fetch_and_check_their_proof() {
+        let file = ledger(
+            "proof_store_customers",
+            &[(FIRST, 10), (SECOND, 20), (FIRST, 5), (THIRD, 7)],
+        );
+        let store = MemoryProofStore::new();
+        let report = publish_proofs(&store, &file, 50, "audit 2024-Q1", None).unwrap();
+        assert_eq!(report.accounts, 4);
+        assert_eq!(report.liabilities, 42);
+        assert!(report.solvent);
+        assert_eq!(
+            store.get_report(&report.root).unwrap(),
+            Some(report.clone())
+        );
+        assert_eq!(store.latest_report().unwrap(), Some(report.clone()));
+
+        // The rows of a repeated address are proven under their disambiguated keys
+        let mut addresses = vec![
+            format!("{FIRST}#1"),
+            format!("{FIRST}#2"),
+            SECOND.to_owned(),
+            THIRD.to_owned(),
+        ];
+        addresses.sort();
+        assert_eq!(store.list_proofs(&report.root).unwrap(), addresses);
+        for address in &addresses {
+            let proof = store.get_proof(&report.root, address).unwrap().unwrap();
+            assert!(proof.verify(), "{proof}");
+        }
+        let proof = store
+            .get_proof(&report.root, &format!("{FIRST}#2"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.value, 5);
+        assert_eq!(proof.index, 2);
+        // A proof doesn't hold against another root, or for another balance
+        let mut moved = proof.clone();
+        moved.root = "00".repeat(32);
+        assert!(!moved.verify());
+        let mut inflated = proof;
+        inflated.value += 1;
+        assert!(!inflated.verify());
+
+        assert_eq!(store.get_proof(&report.root, "unknown").unwrap(), None);
+        assert!(matches!(
+            store.get_proof("proofs:*", FIRST),
+            Err(ProofStoreError::InvalidRoot(_))
+        ));
+    }
+    #[test]
+    pub fn proofs_expire_by_root() {
+        let older = ledger("proof_store_older", &[(FIRST, 10)]);
+        let newer = ledger("proof_store_newer", &[(FIRST, 10), (SECOND, 20)]);
+        let store = MemoryProofStore::new();
+        let short = publish_proofs(
+            &store,
+            &older,
+            10,
+            "audit 2023-Q4",
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+        let kept = publish_proofs(&store, &newer, 10, "audit 2024-Q1", None).unwrap();
+        assert!(!kept.solvent);
+        let mut roots = vec![short.root.clone(), kept.root.clone()];
+        roots.sort();
+        assert_eq!(store.list_roots().unwrap(), roots);
+
+        sleep(Duration::from_millis(100));
+        assert_eq!(store.list_roots().unwrap(), vec![kept.root.clone()]);
+        assert_eq!(store.get_report(&short.root).unwrap(), None);
+        assert_eq!(store.get_proof(&short.root, FIRST).unwrap(), None);
+        assert_eq!(store.latest_report().unwrap(), Some(kept.clone()));
+
+        // Expiring a root drops its proofs and its report
+        assert_eq!(store.expire_root(&kept.root, Duration::ZERO).unwrap(), 3);
+        assert!(store.list_roots().unwrap().is_empty());
+        assert_eq!(store.latest_report().unwrap(), None);
+    }
+    #[test]
+    #[ignore = "Only run when connected to the redis server"]
+    pub fn redis_store_round_trip() {
+        let file = ledger("proof_store_redis", &[(FIRST, 10), (SECOND, 20)]);
+        let store = RedisProofStore::with_pool(proofs_pool().unwrap());
+        let report = publish_proofs(
+            &store,
+            &file,
+            30,
+            "audit 2024-Q1",
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        assert_eq!(
+            store.get_report(&report.root).unwrap(),
+            Some(report.clone())
+        );
+        assert!(store.list_roots().unwrap().contains(&report.root));
+        let proofs = InclusionProof::for_ledger(&file).unwrap();
+        for proof in &proofs {
+            assert_eq!(
+                store
+                    .get_proof(&report.root, &proof.address)
+                    .unwrap()
+                    .as_ref(),
+                Some(proof)
+            );
+        }
+        assert_eq!(store.expire_root(&report.root, Duration::ZERO).unwrap(), 3);
+        assert_eq!(store.get_report(&report.root).unwrap(), None);
+    }
+}